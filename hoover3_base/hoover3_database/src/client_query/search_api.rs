@@ -2,17 +2,22 @@
 use std::collections::BTreeMap;
 use std::time::Instant;
 
+use futures::TryStreamExt;
 use hoover3_types::{
     db_schema::{
         DatabaseColumnType, DatabaseServiceType, DatabaseValue, DynamicQueryResponse,
-        DynamicQueryResult, GraphEdgeSchemaDynamic,
+        DynamicQueryResult, GraphDegreeStatistics, GraphEdgeId, GraphEdgeSchemaDynamic,
+        GraphNodeEdgeCount, GraphTraversalPath, GraphTraversalQuery,
     },
     identifier::CollectionId,
 };
 use meilisearch_sdk::search::Selectors;
 
 use crate::db_management::{DatabaseSpaceManager, MeilisearchDatabaseHandle};
-use crate::models::collection::get_graph_edges_types_from_inventory;
+use crate::models::collection::{
+    get_graph_edges_types_from_inventory, graph_degree_statistics, graph_node_edge_counts,
+    graph_traverse,
+};
 
 use super::database_explorer::{json_value_to_database_type, json_value_to_database_value};
 
@@ -356,8 +361,36 @@ fn trim_response_value(value: String) -> String {
 
 /// Get the graph schema from the inventory of edge types.
 /// Returns a GraphEdgeSchemaDynamic object containing information about all edge types.
-pub async fn get_graph_schema(_:()) -> anyhow::Result<GraphEdgeSchemaDynamic> {
+pub async fn get_graph_schema(_: ()) -> anyhow::Result<GraphEdgeSchemaDynamic> {
     let graph_schema = get_graph_edges_types_from_inventory();
     Ok((*graph_schema).clone())
 }
 
+/// Run a multi-hop graph traversal from a node, and return the paths found.
+/// The number of paths is capped by the limits of the query.
+pub async fn graph_traversal_paths(
+    (collection_id, query): (CollectionId, GraphTraversalQuery),
+) -> anyhow::Result<Vec<GraphTraversalPath>> {
+    graph_traverse(&collection_id, query)
+        .await?
+        .try_collect()
+        .await
+}
+
+/// Get the number of stored edges of a node, by edge type and direction,
+/// from the edge counters - shown in the document preview.
+pub async fn graph_node_degrees(
+    (collection_id, pk_hash): (CollectionId, String),
+) -> anyhow::Result<Vec<GraphNodeEdgeCount>> {
+    graph_node_edge_counts(&collection_id, &pk_hash).await
+}
+
+/// Get the degree distributions and the `top_n` most connected nodes of the stored edge types
+/// of a collection; all the edge types if none are given. `top_n` is capped to
+/// [crate::models::collection::GRAPH_DEGREE_TOP_N_MAX]. The statistics are those saved by the
+/// last run of the degree statistics workflow; empty if it never ran.
+pub async fn graph_degree_distributions(
+    (collection_id, edge_types, top_n): (CollectionId, Vec<GraphEdgeId>, u64),
+) -> anyhow::Result<Vec<GraphDegreeStatistics>> {
+    graph_degree_statistics(&collection_id, &edge_types, top_n as usize).await
+}
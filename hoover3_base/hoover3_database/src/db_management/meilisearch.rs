@@ -14,7 +14,7 @@ use tracing::info;
 
 use hoover3_types::db_schema::MeilisearchDatabaseSchema;
 
-use crate::db_management::redis::with_redis_cache;
+use crate::db_management::redis::{drop_redis_cache, with_redis_cache};
 use crate::models::collection::get_scylla_schema_from_inventory;
 
 /// Meilisearch database handle type alias.
@@ -28,11 +28,24 @@ fn new_client() -> Client {
 
 /// Wait for a Meilisearch task to complete and return the result.
 pub async fn meilisearch_wait_for_task(task: TaskInfo) -> anyhow::Result<()> {
+    _wait_for_task(task, None).await
+}
+
+/// Wait for a Meilisearch task to complete, for at most the given time.
+/// Use for tasks that may wait behind many others, like document batches.
+pub async fn meilisearch_wait_for_task_timeout(
+    task: TaskInfo,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    _wait_for_task(task, Some(timeout)).await
+}
+
+async fn _wait_for_task(task: TaskInfo, timeout: Option<Duration>) -> anyhow::Result<()> {
     let res = task
         .wait_for_completion(
             &MeilisearchDatabaseHandle::global_session().await?.clone(),
-            None,
-            None,
+            timeout.map(|_| Duration::from_millis(500)),
+            timeout,
         )
         .await?;
     if let meilisearch_sdk::tasks::Task::Succeeded { .. } = res {
@@ -206,6 +219,136 @@ async fn _get_meilisearch_schema(c: CollectionId) -> anyhow::Result<MeilisearchD
     })
 }
 
+/// Get the search index settings of a collection, as JSON.
+pub async fn get_search_settings_json(c: &CollectionId) -> anyhow::Result<serde_json::Value> {
+    let index = MeilisearchDatabaseHandle::collection_session(c).await?;
+    Ok(serde_json::to_value(index.get_settings().await?)?)
+}
+
+/// Replace the search index settings of a collection with the given JSON settings,
+/// and wait for the index to be updated.
+pub async fn set_search_settings_json(
+    c: &CollectionId,
+    settings: serde_json::Value,
+) -> anyhow::Result<()> {
+    let settings: meilisearch_sdk::settings::Settings = serde_json::from_value(settings)?;
+    let index = MeilisearchDatabaseHandle::collection_session(c).await?;
+    let task = index.set_settings(&settings).await?;
+    meilisearch_wait_for_task(task).await?;
+    drop_redis_cache("query_meilisearch_schema", c).await?;
+    Ok(())
+}
+
+/// Name of the temporary index a collection is reindexed into, before it is swapped in.
+/// Each reindex request has its own, so concurrent fresh reindexes don't drop each other's index.
+fn fresh_search_index_uid(c: &CollectionId, reindex_id: i64) -> anyhow::Result<String> {
+    Ok(format!("{}_reindex_{}", c.database_name()?, reindex_id))
+}
+
+/// Get the index to reindex a collection into: the collection index itself, or
+/// the fresh index of the given reindex, created by [create_fresh_search_index].
+pub async fn get_reindex_target_search_index(
+    c: &CollectionId,
+    fresh_reindex_id: Option<i64>,
+) -> anyhow::Result<Arc<meilisearch_sdk::indexes::Index>> {
+    let Some(reindex_id) = fresh_reindex_id else {
+        return MeilisearchDatabaseHandle::collection_session(c).await;
+    };
+    let client = MeilisearchDatabaseHandle::global_session().await?;
+    Ok(Arc::new(
+        client.index(fresh_search_index_uid(c, reindex_id)?),
+    ))
+}
+
+/// Create an empty index with the current settings of the collection index, to reindex the
+/// collection into. An index left over from an earlier attempt of the same reindex is dropped first.
+pub async fn create_fresh_search_index(c: &CollectionId, reindex_id: i64) -> anyhow::Result<()> {
+    let client = MeilisearchDatabaseHandle::global_session().await?;
+    let settings = MeilisearchDatabaseHandle::collection_session(c)
+        .await?
+        .get_settings()
+        .await?;
+    let uid = fresh_search_index_uid(c, reindex_id)?;
+    drop_search_index_uid(&client, &uid).await?;
+    meilisearch_wait_for_task(client.create_index(&uid, Some("id")).await?).await?;
+    let task = client.index(uid).set_settings(&settings).await?;
+    meilisearch_wait_for_task(task).await?;
+    Ok(())
+}
+
+/// Send the task that swaps the fresh index created by [create_fresh_search_index] with the
+/// collection index, in a single step. Returns the task uid, for [finish_fresh_search_index_swap].
+pub async fn swap_fresh_search_index(c: &CollectionId, reindex_id: i64) -> anyhow::Result<i64> {
+    let client = MeilisearchDatabaseHandle::global_session().await?;
+    let uid = fresh_search_index_uid(c, reindex_id)?;
+    let task = client
+        .swap_indexes([&SwapIndexes {
+            indexes: (c.database_name()?.to_string(), uid),
+        }])
+        .await?;
+    Ok(task.task_uid as i64)
+}
+
+/// Wait for the swap task sent by [swap_fresh_search_index], then drop the fresh index,
+/// which holds the old documents after the swap. Can be called again after a failure.
+pub async fn finish_fresh_search_index_swap(
+    c: &CollectionId,
+    reindex_id: i64,
+    swap_task_uid: i64,
+) -> anyhow::Result<()> {
+    let client = MeilisearchDatabaseHandle::global_session().await?;
+    let task = client
+        .wait_for_task(
+            MeilisearchTaskUid(swap_task_uid as u32),
+            Some(Duration::from_millis(500)),
+            Some(SEARCH_INDEX_SWAP_TIMEOUT),
+        )
+        .await?;
+    if !matches!(task, meilisearch_sdk::tasks::Task::Succeeded { .. }) {
+        anyhow::bail!("search index swap task failed: {:?}", task);
+    }
+    drop_search_index_uid(&client, &fresh_search_index_uid(c, reindex_id)?).await?;
+    drop_redis_cache("query_meilisearch_schema", c).await?;
+    Ok(())
+}
+
+/// Drop the fresh index of a reindex that is not swapped in.
+pub async fn drop_fresh_search_index(c: &CollectionId, reindex_id: i64) -> anyhow::Result<()> {
+    let client = MeilisearchDatabaseHandle::global_session().await?;
+    drop_search_index_uid(&client, &fresh_search_index_uid(c, reindex_id)?).await
+}
+
+/// Drop an index, if it exists. The fresh index uids are longer than a [DatabaseIdentifier],
+/// so they are not managed with [DatabaseSpaceManager::drop_space].
+async fn drop_search_index_uid(
+    client: &MeilisearchDatabaseHandle,
+    uid: &str,
+) -> anyhow::Result<()> {
+    if client.get_index(uid).await.is_err() {
+        return Ok(());
+    }
+    meilisearch_wait_for_task(client.delete_index(uid).await?).await
+}
+
+/// Longest wait for the index swap task, which runs after the tasks queued before it.
+const SEARCH_INDEX_SWAP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Task uid saved in the database, for the Meilisearch client methods that take a task.
+pub(crate) struct MeilisearchTaskUid(pub u32);
+
+impl AsRef<u32> for MeilisearchTaskUid {
+    fn as_ref(&self) -> &u32 {
+        &self.0
+    }
+}
+
+/// Count the documents in a search index. Pending indexing tasks are not counted.
+pub async fn search_index_document_count(
+    index: &meilisearch_sdk::indexes::Index,
+) -> anyhow::Result<u64> {
+    Ok(index.get_stats().await?.number_of_documents as u64)
+}
+
 pub struct SearchFieldConfigurations {
     pub filterable_attributes: Vec<String>,
     pub sortable_attributes: Vec<String>,
@@ -8,10 +8,20 @@ mod clickhouse;
 pub use clickhouse::ClickhouseDatabaseHandle;
 
 mod meilisearch;
+pub use meilisearch::create_fresh_search_index;
+pub use meilisearch::drop_fresh_search_index;
+pub use meilisearch::finish_fresh_search_index_swap;
+pub use meilisearch::get_reindex_target_search_index;
+pub use meilisearch::get_search_settings_json;
 pub use meilisearch::meilisearch_wait_for_task;
+pub use meilisearch::meilisearch_wait_for_task_timeout;
 pub use meilisearch::query_meilisearch_schema;
+pub use meilisearch::search_index_document_count;
 pub use meilisearch::search_index_include_table;
+pub use meilisearch::set_search_settings_json;
+pub use meilisearch::swap_fresh_search_index;
 pub use meilisearch::MeilisearchDatabaseHandle;
+pub(crate) use meilisearch::MeilisearchTaskUid;
 
 mod scylla;
 mod scylla_migrate;
@@ -19,6 +29,7 @@ pub use scylla::ScyllaDatabaseHandle;
 pub use scylla_migrate::query_scylla_schema;
 
 mod seaweed;
+pub use seaweed::blob_object_key;
 pub use seaweed::S3DatabaseHandle;
 
 mod seekstorm;
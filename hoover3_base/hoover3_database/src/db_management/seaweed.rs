@@ -1,4 +1,5 @@
 use std::env;
+use std::path::Path;
 use std::sync::Arc;
 
 use s3::creds::Credentials;
@@ -20,6 +21,155 @@ impl S3Configs {
             .expect("cannot create bucket")
             .with_path_style()
     }
+
+    /// Get the object store bucket for a collection.
+    pub fn collection_bucket(&self, c: &CollectionId) -> anyhow::Result<Box<Bucket>> {
+        Ok(self._get_bucket(&c.database_name()?))
+    }
+
+    /// Upload a local file into the collection bucket, stored under the blob key.
+    pub async fn put_blob_file(
+        &self,
+        c: &CollectionId,
+        blob_sha3_256: &str,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        let bucket = self.collection_bucket(c)?;
+        put_object_file(&bucket, &blob_object_key(blob_sha3_256)?, path).await
+    }
+
+    /// Download a blob from the collection bucket into a local file.
+    /// Returns the number of bytes written.
+    pub async fn get_blob_file(
+        &self,
+        c: &CollectionId,
+        blob_sha3_256: &str,
+        path: &Path,
+    ) -> anyhow::Result<u64> {
+        let bucket = self.collection_bucket(c)?;
+        get_object_file(&bucket, &blob_object_key(blob_sha3_256)?, path).await
+    }
+
+    /// Delete a blob from the collection bucket.
+    pub async fn delete_blob(&self, c: &CollectionId, blob_sha3_256: &str) -> anyhow::Result<()> {
+        let bucket = self.collection_bucket(c)?;
+        bucket
+            .delete_object(blob_object_key(blob_sha3_256)?)
+            .await?;
+        Ok(())
+    }
+
+    /// Get the bucket that holds the collection backup archives, creating it if missing.
+    /// It is shared by all collections, so backups survive dropping their collection.
+    pub async fn backup_bucket(&self) -> anyhow::Result<Box<Bucket>> {
+        let name = DatabaseIdentifier::new(BACKUP_BUCKET_NAME)?;
+        self.create_space(&name).await?;
+        Ok(self._get_bucket(&name))
+    }
+
+    /// Upload a local file into the backup bucket.
+    pub async fn put_backup_file(&self, key: &str, path: &Path) -> anyhow::Result<()> {
+        put_object_file(&*self.backup_bucket().await?, key, path).await
+    }
+
+    /// Download a file from the backup bucket. Returns the number of bytes written.
+    pub async fn get_backup_file(&self, key: &str, path: &Path) -> anyhow::Result<u64> {
+        get_object_file(&*self.backup_bucket().await?, key, path).await
+    }
+
+    /// List the files in the backup bucket under a key prefix,
+    /// as (key, size in bytes, last modified) tuples.
+    pub async fn list_backup_files(
+        &self,
+        prefix: &str,
+    ) -> anyhow::Result<Vec<(String, u64, String)>> {
+        list_object_files(&*self.backup_bucket().await?, prefix).await
+    }
+
+    /// Get the bucket that holds the graph export files, creating it if missing.
+    /// It is shared by all collections, like the backup bucket.
+    pub async fn export_bucket(&self) -> anyhow::Result<Box<Bucket>> {
+        let name = DatabaseIdentifier::new(EXPORT_BUCKET_NAME)?;
+        self.create_space(&name).await?;
+        Ok(self._get_bucket(&name))
+    }
+
+    /// Upload a local file into the export bucket.
+    pub async fn put_export_file(&self, key: &str, path: &Path) -> anyhow::Result<()> {
+        put_object_file(&*self.export_bucket().await?, key, path).await
+    }
+
+    /// List the files in the export bucket under a key prefix,
+    /// as (key, size in bytes, last modified) tuples.
+    pub async fn list_export_files(
+        &self,
+        prefix: &str,
+    ) -> anyhow::Result<Vec<(String, u64, String)>> {
+        list_object_files(&*self.export_bucket().await?, prefix).await
+    }
+}
+
+/// Name of the bucket that holds the collection backup archives.
+const BACKUP_BUCKET_NAME: &str = "hoover3_backups";
+
+/// Name of the bucket that holds the graph export files.
+const EXPORT_BUCKET_NAME: &str = "hoover3_exports";
+
+async fn list_object_files(
+    bucket: &Bucket,
+    prefix: &str,
+) -> anyhow::Result<Vec<(String, u64, String)>> {
+    Ok(bucket
+        .list(prefix.to_string(), None)
+        .await?
+        .into_iter()
+        .flat_map(|page| page.contents)
+        .map(|object| (object.key, object.size, object.last_modified))
+        .collect())
+}
+
+async fn put_object_file(bucket: &Bucket, key: &str, path: &Path) -> anyhow::Result<()> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let response = bucket.put_object_stream(&mut file, key).await?;
+    if response.status_code() != 200 {
+        anyhow::bail!(
+            "got status code {} from upload of {}, wanted 200",
+            response.status_code(),
+            key
+        );
+    }
+    Ok(())
+}
+
+async fn get_object_file(bucket: &Bucket, key: &str, path: &Path) -> anyhow::Result<u64> {
+    use tokio::io::AsyncWriteExt;
+    let mut file = tokio::fs::File::create(path).await?;
+    let status_code = bucket.get_object_to_writer(key, &mut file).await?;
+    if status_code != 200 {
+        anyhow::bail!(
+            "got status code {} from download of {}, wanted 200",
+            status_code,
+            key
+        );
+    }
+    file.flush().await?;
+    file.sync_all().await?;
+    drop(file);
+    Ok(tokio::fs::metadata(path).await?.len())
+}
+
+/// Object key used to store a blob in the collection bucket.
+/// Blobs are split into sub-folders by their hash prefix, same as the worker tempdirs.
+pub fn blob_object_key(blob_sha3_256: &str) -> anyhow::Result<String> {
+    if blob_sha3_256.len() < 6 || !blob_sha3_256.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("invalid blob hash: {:?}", blob_sha3_256);
+    }
+    Ok(format!(
+        "blobs/{}/{}/{}",
+        &blob_sha3_256[0..3],
+        &blob_sha3_256[3..6],
+        blob_sha3_256
+    ))
 }
 
 /// Seaweed database handle type alias.
@@ -0,0 +1,243 @@
+//! Export and import of the Scylla tables of a collection, one JSON object per row.
+//! Used by the collection backup and restore tasks. The tables are the ones from the
+//! model inventory, plus the graph tables, which are not declared with the `#[model]` macro.
+
+use std::path::Path;
+
+use anyhow::Result;
+use charybdis::model::BaseModel;
+use futures::TryStreamExt;
+use hoover3_types::identifier::{CollectionId, DatabaseIdentifier};
+use scylla::batch::{Batch, BatchType};
+use scylla::prepared_statement::PreparedStatement;
+use scylla::serialize::row::SerializeRow;
+use scylla::transport::{PagingState, PagingStateResponse};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+use crate::db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle};
+use crate::models::collection::{
+    find_graph_edge_source_counter, get_scylla_schema_from_inventory, GraphEdgePageAssignment,
+    GraphEdgePageContent, GraphEdgePageList, GraphEdgeSourceCounter, GraphNodePkMap,
+};
+
+/// Number of rows read from Scylla in one page.
+const EXPORT_PAGE_SIZE: i32 = 1000;
+
+/// Number of rows written to Scylla in one batch.
+const IMPORT_BATCH_SIZE: usize = 100;
+
+/// List the names of all the collection tables that are backed up, sorted.
+pub fn list_backup_table_names() -> Vec<String> {
+    let mut tables = get_scylla_schema_from_inventory()
+        .tables
+        .keys()
+        .map(|t| t.to_string())
+        .collect::<Vec<_>>();
+    tables.extend(
+        [
+            GraphEdgeSourceCounter::DB_MODEL_NAME,
+            GraphEdgePageContent::DB_MODEL_NAME,
+            GraphEdgePageList::DB_MODEL_NAME,
+            GraphEdgePageAssignment::DB_MODEL_NAME,
+            GraphNodePkMap::DB_MODEL_NAME,
+        ]
+        .map(String::from),
+    );
+    tables.sort();
+    tables
+}
+
+/// Write all the rows of a collection table into a file, one JSON object per line.
+/// Returns the number of rows written.
+pub async fn export_table_rows(c: &CollectionId, table_name: &str, path: &Path) -> Result<u64> {
+    let table = DatabaseIdentifier::new(table_name)?;
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let query = scylla::query::Query::new(format!(
+        "SELECT JSON * FROM {}.{}",
+        c.database_name()?,
+        table
+    ))
+    .with_page_size(EXPORT_PAGE_SIZE);
+
+    let mut file = BufWriter::new(tokio::fs::File::create(path).await?);
+    let mut paging_state = PagingState::default();
+    let mut count = 0;
+    loop {
+        let (result, paging_response) = session
+            .execute_single_page(query.clone(), (), paging_state)
+            .await?;
+        for row in result.into_rows_result()?.rows::<(String,)>()? {
+            let (row_json,) = row?;
+            file.write_all(row_json.as_bytes()).await?;
+            file.write_all(b"\n").await?;
+            count += 1;
+        }
+        match paging_response {
+            PagingStateResponse::HasMorePages { state } => paging_state = state,
+            PagingStateResponse::NoMorePages => break,
+        }
+    }
+    file.flush().await?;
+    Ok(count)
+}
+
+/// Insert the rows of a file written by [export_table_rows] into a collection table.
+/// Rows with the same primary key are overwritten; the edge counters are set to the
+/// exported values. Returns the number of rows read from the file.
+pub async fn import_table_rows(c: &CollectionId, table_name: &str, path: &Path) -> Result<u64> {
+    if table_name == GraphEdgeSourceCounter::DB_MODEL_NAME {
+        return import_edge_counter_rows(c, path).await;
+    }
+    let table = DatabaseIdentifier::new(table_name)?;
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let statement = session
+        .get_session()
+        .prepare(format!(
+            "INSERT INTO {}.{} JSON ?",
+            c.database_name()?,
+            table
+        ))
+        .await?;
+
+    let mut lines = BufReader::new(tokio::fs::File::open(path).await?).lines();
+    let mut values = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut count = 0;
+    while let Some(line) = lines.next_line().await? {
+        if line.is_empty() {
+            continue;
+        }
+        values.push((line,));
+        count += 1;
+        if values.len() >= IMPORT_BATCH_SIZE {
+            execute_batch(&session, &statement, BatchType::Unlogged, &mut values).await?;
+        }
+    }
+    execute_batch(&session, &statement, BatchType::Unlogged, &mut values).await?;
+    Ok(count)
+}
+
+/// Counter columns can't be inserted, only incremented: add the difference
+/// between the exported value and the current one.
+async fn import_edge_counter_rows(c: &CollectionId, path: &Path) -> Result<u64> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let statement = session
+        .get_session()
+        .prepare(format!(
+            "UPDATE {}.{}
+              SET item_count = item_count + ?,
+              removed_count = removed_count + ?
+              WHERE pk_source = ?
+              AND edge_type = ?
+              AND direction_out = ?;",
+            c.database_name()?,
+            GraphEdgeSourceCounter::DB_MODEL_NAME
+        ))
+        .await?;
+
+    let mut lines = BufReader::new(tokio::fs::File::open(path).await?).lines();
+    let mut values = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut count = 0;
+    while let Some(line) = lines.next_line().await? {
+        if line.is_empty() {
+            continue;
+        }
+        let row: GraphEdgeSourceCounter = serde_json::from_str(&line)?;
+        count += 1;
+        let current = find_graph_edge_source_counter!(
+            "pk_source = ? AND edge_type = ? AND direction_out = ?",
+            (
+                row.pk_source.clone(),
+                row.edge_type.clone(),
+                row.direction_out
+            )
+        )
+        .execute(&session)
+        .await?
+        .try_next()
+        .await?
+        .map(|current| {
+            (
+                current.item_count.0,
+                current.removed_count.map_or(0, |r| r.0),
+            )
+        })
+        .unwrap_or((0, 0));
+        let increment = row.item_count.0 - current.0;
+        let removed_increment = row.removed_count.map_or(0, |r| r.0) - current.1;
+        if increment == 0 && removed_increment == 0 {
+            continue;
+        }
+        values.push((
+            scylla::frame::value::Counter(increment),
+            scylla::frame::value::Counter(removed_increment),
+            row.pk_source,
+            row.edge_type,
+            row.direction_out,
+        ));
+        if values.len() >= IMPORT_BATCH_SIZE {
+            execute_batch(&session, &statement, BatchType::Counter, &mut values).await?;
+        }
+    }
+    execute_batch(&session, &statement, BatchType::Counter, &mut values).await?;
+    Ok(count)
+}
+
+/// Run the statement once for each of the values, in a single batch, then clear the values.
+async fn execute_batch<V: SerializeRow>(
+    session: &ScyllaDatabaseHandle,
+    statement: &PreparedStatement,
+    batch_type: BatchType,
+    values: &mut Vec<V>,
+) -> Result<()> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let mut batch = Batch::new(batch_type);
+    for _ in 0..values.len() {
+        batch.append_statement(statement.clone());
+    }
+    session.batch(&batch, &*values).await?;
+    values.clear();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_export_import_table_rows() -> Result<()> {
+    use crate::client_query::collections::{create_new_collection, drop_collection};
+    use crate::migrate::migrate_common;
+    use crate::models::collection::graph_add_nodes;
+    use crate::models::common::collection::CollectionDbRow;
+
+    migrate_common().await?;
+    let source = CollectionId::new("test_backup_export_source")?;
+    let target = CollectionId::new("test_backup_export_target")?;
+    for c in [&source, &target] {
+        drop_collection(c.clone()).await?;
+        create_new_collection(c.clone()).await?;
+    }
+    let now = chrono::Utc::now();
+    let nodes = ["a", "b", "c"].map(|id| CollectionDbRow {
+        collection_id: id.to_string(),
+        collection_title: id.to_string(),
+        collection_description: "".to_string(),
+        time_created: now,
+        time_modified: now,
+    });
+    graph_add_nodes(&source, &nodes).await?;
+
+    let tables = list_backup_table_names();
+    assert!(tables.contains(&GraphNodePkMap::DB_MODEL_NAME.to_string()));
+    let path = std::env::temp_dir().join("test_export_import_table_rows.jsonl");
+    let exported = export_table_rows(&source, GraphNodePkMap::DB_MODEL_NAME, &path).await?;
+    assert_eq!(exported, 3);
+    let imported = import_table_rows(&target, GraphNodePkMap::DB_MODEL_NAME, &path).await?;
+    assert_eq!(imported, 3);
+    let exported_again = export_table_rows(&target, GraphNodePkMap::DB_MODEL_NAME, &path).await?;
+    assert_eq!(exported_again, 3);
+    tokio::fs::remove_file(&path).await?;
+
+    for c in [source, target] {
+        drop_collection(c).await?;
+    }
+    Ok(())
+}
@@ -20,6 +20,8 @@ use hoover3_types::identifier::DEFAULT_KEYSPACE_NAME;
 
 use super::db_management::redis::with_redis_lock;
 
+pub mod backup;
+
 /// Load and check all database schemas. This call will panic if the schema is not valid.
 pub fn check_code_schema() {
     let scylla_schema = get_scylla_schema_from_inventory();
@@ -258,5 +260,8 @@ pub async fn query_collection_schema(c: CollectionId) -> Result<CollectionSchema
 /// Useful for adding tables that we don't implemented with the `#[model]` macro,
 /// since they are part of that macro's implementation -- things like the graph tables.
 pub fn get_extra_charybdis_codes() -> Vec<String> {
-    vec![include_str!("../../src/models/collection/graph_models.rs").to_string()]
+    vec![
+        include_str!("../../src/models/collection/graph_models.rs").to_string(),
+        include_str!("../../src/models/collection/search_outbox_models.rs").to_string(),
+    ]
 }
@@ -1,11 +1,16 @@
 //! This module implements the `impl_model_callbacks` macro, which is used to add Charybdis callbacks to model structs.
-//! These callbacks are used to insert/update/delete rows in the secondary databases, Nebula and Meilisearch.
+//! These callbacks are used to insert/update/delete rows in the secondary tables:
+//! the graph node map and the search indexing outbox.
 
 use crate::db_management::search_index_include_table;
 use crate::db_management::DatabaseSpaceManager;
 use crate::db_management::MeilisearchDatabaseHandle;
 use crate::models::collection::graph::graph_add_nodes;
-use charybdis::model::BaseModel;
+use crate::models::collection::search_outbox::{
+    search_outbox_add_documents, search_outbox_delete_documents, search_outbox_insert_rows,
+};
+use charybdis::model::{BaseModel, Model};
+use meilisearch_sdk::task_info::TaskInfo;
 
 use hoover3_types::identifier::CollectionId;
 use hoover3_types::identifier::DatabaseIdentifier;
@@ -187,7 +192,26 @@ impl DatabaseExtraCallbacks {
         })
     }
 
-    /// Insert a batch of rows into the secondary databases, Nebula and Meilisearch.
+    /// Insert a batch of rows into their table after their search indexing outbox entries,
+    /// in Scylla batches of up to `chunk_size` rows, then add them to the graph node map.
+    pub async fn insert_rows<T>(&self, data: &[T], chunk_size: usize) -> anyhow::Result<()>
+    where
+        T: Model + serde::Serialize + Send + Sync + 'static,
+        <T as BaseModel>::PrimaryKey: serde::Serialize,
+        <T as BaseModel>::PrimaryKey: for<'a> serde::Deserialize<'a>,
+        <T as BaseModel>::PrimaryKey: 'static + Send + Sync,
+    {
+        if data.is_empty() {
+            return Ok(());
+        }
+        search_outbox_insert_rows(&self.collection_id, data, chunk_size).await?;
+        graph_add_nodes(&self.collection_id, data).await?;
+        Ok(())
+    }
+
+    /// Insert a batch of rows already written to their table into the secondary databases:
+    /// the graph node map, and the search indexing outbox.
+    /// Used by the model callbacks; batch writes should use [Self::insert_rows].
     pub async fn insert<T>(&self, data: &[T]) -> anyhow::Result<()>
     where
         T: BaseModel + serde::Serialize + Send + Sync + 'static,
@@ -200,28 +224,43 @@ impl DatabaseExtraCallbacks {
         }
         let _table_id = DatabaseIdentifier::new(T::DB_MODEL_NAME)?;
 
-        use tokio::time::Duration;
-
-        if search_index_include_table(T::DB_MODEL_NAME)? {
-            let mut search_data = vec![];
-            for d in data.iter() {
-                search_data.push(get_search_index_json(d)?);
-            }
-            let _search_result = tokio::time::timeout(
-                Duration::from_secs(30),
-                self.search_index.add_documents(&search_data, Some("id")),
-            )
-            .await??;
-            // takes too much time
-            // meilisearch_wait_for_task(_search_result).await?;
-        }
+        // the search indexer sends the documents to Meilisearch later, see `search_outbox`
+        search_outbox_add_documents(&self.collection_id, data).await?;
 
         graph_add_nodes(&self.collection_id, data).await?;
 
         Ok(())
     }
 
-    /// Delete a batch of rows from the secondary databases, Nebula and Meilisearch.
+    /// Add a batch of rows to the search index directly, if their table is indexed,
+    /// bypassing the outbox - used when rebuilding the index.
+    /// Returns the Meilisearch task, without waiting for it.
+    pub async fn add_search_documents<T>(&self, data: &[T]) -> anyhow::Result<Option<TaskInfo>>
+    where
+        T: BaseModel + serde::Serialize + Send + Sync + 'static,
+        <T as BaseModel>::PrimaryKey: serde::Serialize,
+        <T as BaseModel>::PrimaryKey: for<'a> serde::Deserialize<'a>,
+        <T as BaseModel>::PrimaryKey: 'static + Send + Sync,
+    {
+        if data.is_empty() || !search_index_include_table(T::DB_MODEL_NAME)? {
+            return Ok(None);
+        }
+        use tokio::time::Duration;
+
+        let mut search_data = vec![];
+        for d in data.iter() {
+            search_data.push(get_search_index_json(d)?);
+        }
+        let task = tokio::time::timeout(
+            Duration::from_secs(30),
+            self.search_index.add_documents(&search_data, Some("id")),
+        )
+        .await??;
+        Ok(Some(task))
+    }
+
+    /// Delete a batch of rows from the secondary databases, by queueing their removal
+    /// in the search indexing outbox.
     pub async fn delete<T>(&self, data: &[T]) -> anyhow::Result<()>
     where
         T: BaseModel + Send,
@@ -237,17 +276,14 @@ impl DatabaseExtraCallbacks {
             .map(|d| row_pk_hash::<T>(&d.primary_key_values()))
             .collect::<Vec<String>>();
 
-        if search_index_include_table(T::DB_MODEL_NAME)? {
-            let _search_result = self.search_index.delete_documents(&pks).await?;
-            // maybe takes too much time?
-            // meilisearch_wait_for_task(_search_result).await?;
-        }
+        search_outbox_delete_documents(&self.collection_id, T::DB_MODEL_NAME, pks).await?;
 
         Ok(())
     }
 }
 
-/// Macro to implement Charybdis callbacks for a model struct.
+/// Macro to implement Charybdis callbacks for a model struct,
+/// and to register the model table for rebuilding the search index and for graph repair.
 #[macro_export]
 macro_rules! impl_model_callbacks {
     ($name:ident) => {
@@ -276,6 +312,20 @@ macro_rules! impl_model_callbacks {
             }
         }
 
+        $crate::inventory::submit! {
+            $crate::models::collection::ModelSearchIndexStatic {
+                table_name: <$name as ::charybdis::model::BaseModel>::DB_MODEL_NAME,
+                index_all_rows: $crate::models::collection::search_index_all_rows::<$name>,
+            }
+        }
+
+        $crate::inventory::submit! {
+            $crate::models::collection::GraphNodeTableStatic {
+                table_name: <$name as ::charybdis::model::BaseModel>::DB_MODEL_NAME,
+                find_row: $crate::models::collection::graph_node_find_row::<$name>,
+            }
+        }
+
         impl $name {
             /// Compute a stable hash of a row's primary key, and concatenate it with table name.
             pub fn row_pk_hash(&self) -> String {
@@ -0,0 +1,380 @@
+//! Multi-hop graph traversal.
+//!
+//! The traversal works on row primary key hashes (see [row_pk_hash]), which are also the
+//! search document ids, so a search result can be used as the start node.
+//! Each edge type registers a function listing the neighbors of a node (see the
+//! `declare_stored_graph_edge` and `declare_implicit_graph_edge` macros), so paths can be
+//! followed without knowing the model types - this is the dynamic variant, [graph_traverse].
+//! The typed variant, [GraphPathQuery], checks at compile time that the edges of a route
+//! connect, then runs the same traversal.
+
+use std::collections::{BTreeMap, HashSet};
+use std::marker::PhantomData;
+
+use async_stream::try_stream;
+use charybdis::model::BaseModel;
+use charybdis::operations::Find;
+use futures::future::BoxFuture;
+use futures::{pin_mut, StreamExt, TryStreamExt};
+use hoover3_types::db_schema::{
+    GraphEdgeId, GraphEdgeSchemaDynamic, GraphTraversalDirection, GraphTraversalHop,
+    GraphTraversalLimits, GraphTraversalPath, GraphTraversalPathEdge, GraphTraversalQuery,
+};
+use hoover3_types::identifier::{CollectionId, DatabaseIdentifier};
+
+use super::declare_edge::{BaseModel2, GraphEdge, ParentChildRelationship};
+use super::query_edge::{list_edge_targets, ResultStream};
+use crate::db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle};
+use crate::models::collection::{
+    find_graph_node_pk_map, get_graph_edges_types_from_inventory, row_pk_hash, GraphEdgeTypeStatic,
+};
+
+/// Function listing the neighbors of a node over one edge type, as row primary key hashes.
+/// The arguments are the collection, the node primary key hash,
+/// and the direction (true = from source to target).
+pub type GraphEdgeNeighborsFn =
+    fn(CollectionId, String, bool) -> BoxFuture<'static, anyhow::Result<ResultStream<String>>>;
+
+/// List the neighbors of a node over a stored edge type.
+pub fn stored_edge_neighbors<E: GraphEdge>(
+    collection_id: CollectionId,
+    pk_hash: String,
+    direction_out: bool,
+) -> BoxFuture<'static, anyhow::Result<ResultStream<String>>> {
+    Box::pin(list_edge_targets(
+        collection_id,
+        E::edge_type(),
+        direction_out,
+        pk_hash,
+    ))
+}
+
+/// List the neighbors of a node over an implicit edge type:
+/// the children in the partition of a parent, or the parent of a child.
+pub fn implicit_edge_neighbors<E: ParentChildRelationship>(
+    collection_id: CollectionId,
+    pk_hash: String,
+    direction_out: bool,
+) -> BoxFuture<'static, anyhow::Result<ResultStream<String>>> {
+    Box::pin(async move {
+        let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+        let Some(pk_json) = node_pk_json(&session, &pk_hash).await? else {
+            return anyhow::Ok(futures::stream::empty().boxed());
+        };
+        if direction_out {
+            let parent: <E::SourceType as BaseModel>::PrimaryKey = serde_json::from_str(&pk_json)?;
+            let partition = E::parent_primary_to_child_partition(&parent);
+            let children = <E::DestType as Find>::find_by_partition_key_value(partition)
+                .execute(&session)
+                .await?;
+            let children = children
+                .map(|child| anyhow::Ok(row_pk_hash::<E::DestType>(&child?.primary_key_values())));
+            Ok(children.boxed())
+        } else {
+            let child: <E::DestType as BaseModel>::PrimaryKey = serde_json::from_str(&pk_json)?;
+            let partition = <E::DestType as BaseModel2>::primary_to_partition(&child);
+            let parent = E::child_partition_to_parent_primary(&partition);
+            let parent_hash = row_pk_hash::<E::SourceType>(&parent);
+            // the parent row may be missing; only existing nodes are in the node map
+            let parent_exists = node_pk_json(&session, &parent_hash).await?.is_some();
+            Ok(futures::stream::iter(parent_exists.then_some(Ok(parent_hash))).boxed())
+        }
+    })
+}
+
+/// Get the primary key of a node as JSON, from the node map.
+/// Returns `None` if the node is not in the map.
+async fn node_pk_json(
+    session: &ScyllaDatabaseHandle,
+    pk_hash: &str,
+) -> anyhow::Result<Option<String>> {
+    let row = find_graph_node_pk_map!("pk = ?", (pk_hash.to_string(),))
+        .execute(session)
+        .await?
+        .try_next()
+        .await?;
+    Ok(row.map(|row| row.value))
+}
+
+/// Get the table name of a node from its primary key hash.
+pub(crate) fn node_table(pk_hash: &str) -> Option<DatabaseIdentifier> {
+    let (table, _hash) = pk_hash.rsplit_once('_')?;
+    DatabaseIdentifier::new(table).ok()
+}
+
+/// List the edge types and directions that a hop follows from a node.
+fn hop_edges(
+    schema: &GraphEdgeSchemaDynamic,
+    hop: &GraphTraversalHop,
+    pk_hash: &str,
+) -> Vec<(GraphEdgeId, bool)> {
+    let Some(table) = node_table(pk_hash) else {
+        return vec![];
+    };
+    let mut edges = vec![];
+    for (direction_out, edges_by_table) in [
+        (true, &schema.edges_by_source),
+        (false, &schema.edges_by_target),
+    ] {
+        let follow = match hop.direction {
+            GraphTraversalDirection::Out => direction_out,
+            GraphTraversalDirection::In => !direction_out,
+            GraphTraversalDirection::Both => true,
+        };
+        if !follow {
+            continue;
+        }
+        for edge in edges_by_table.get(&table).into_iter().flatten() {
+            let edge_type = GraphEdgeId(edge.edge_type.clone());
+            if hop.edge_types.is_empty() || hop.edge_types.contains(&edge_type) {
+                edges.push((edge_type, direction_out));
+            }
+        }
+    }
+    edges
+}
+
+/// Largest depth followed by [graph_traverse]; deeper queries stop at it.
+/// Typed routes can have at most this many steps.
+pub const GRAPH_TRAVERSAL_MAX_DEPTH: u32 = 8;
+/// Largest `max_fan_out` accepted by [graph_traverse]; larger limits are capped.
+pub const GRAPH_TRAVERSAL_MAX_FAN_OUT: u32 = 1000;
+/// Largest `max_paths` accepted by [graph_traverse]; larger limits are capped.
+pub const GRAPH_TRAVERSAL_MAX_PATHS: u32 = 10000;
+/// Largest number of paths kept for the next depth of a traversal;
+/// the paths found after it is reached are still returned, but not followed further.
+pub const GRAPH_TRAVERSAL_MAX_FRONTIER: usize = 10000;
+
+/// Run a multi-hop traversal from a node, and return a stream of the paths found,
+/// shortest paths first. A path never goes through the same node twice.
+/// The depth and the limits of the query are capped to the `GRAPH_TRAVERSAL_MAX_*` constants;
+/// a `min_depth` over [GRAPH_TRAVERSAL_MAX_DEPTH] is an error, since no path could be returned.
+pub async fn graph_traverse(
+    collection_id: &CollectionId,
+    query: GraphTraversalQuery,
+) -> anyhow::Result<ResultStream<GraphTraversalPath>> {
+    let schema = get_graph_edges_types_from_inventory();
+    if query.hops.is_empty() {
+        anyhow::bail!("graph traversal: no hops given");
+    }
+    if query.max_depth == 0 || query.min_depth > query.max_depth {
+        anyhow::bail!(
+            "graph traversal: invalid depth range {}..={}",
+            query.min_depth,
+            query.max_depth
+        );
+    }
+    if query.min_depth > GRAPH_TRAVERSAL_MAX_DEPTH {
+        anyhow::bail!(
+            "graph traversal: min depth {} is over the limit of {}",
+            query.min_depth,
+            GRAPH_TRAVERSAL_MAX_DEPTH
+        );
+    }
+    for edge_type in query.hops.iter().flat_map(|hop| hop.edge_types.iter()) {
+        if !schema.edges_by_types.contains_key(edge_type) {
+            anyhow::bail!("graph traversal: unknown edge type {}", edge_type);
+        }
+    }
+    let neighbors_fns = inventory::iter::<GraphEdgeTypeStatic>
+        .into_iter()
+        .map(|edge| (edge.edge_type, edge.list_neighbors))
+        .collect::<BTreeMap<_, _>>();
+
+    let collection_id = collection_id.clone();
+    let GraphTraversalQuery {
+        start_pk_hash,
+        hops,
+        max_depth,
+        min_depth,
+        mut limits,
+    } = query;
+    let max_depth = max_depth.min(GRAPH_TRAVERSAL_MAX_DEPTH);
+    limits.max_fan_out = limits.max_fan_out.min(GRAPH_TRAVERSAL_MAX_FAN_OUT);
+    limits.max_paths = limits.max_paths.min(GRAPH_TRAVERSAL_MAX_PATHS);
+    let stream = try_stream! {
+        let mut visited = HashSet::from([start_pk_hash.clone()]);
+        let mut frontier = vec![GraphTraversalPath {
+            nodes: vec![start_pk_hash],
+            edges: vec![],
+        }];
+        let mut path_count = 0;
+
+        'traversal: for depth in 1..=max_depth {
+            let hop = &hops[(depth as usize - 1).min(hops.len() - 1)];
+            let mut next_frontier = vec![];
+            'frontier: for path in frontier {
+                let node = path.nodes.last().cloned().unwrap_or_default();
+                let mut fan_out = 0;
+                for (edge_type, direction_out) in hop_edges(&schema, hop, &node) {
+                    let Some(list_neighbors) = neighbors_fns.get(edge_type.to_string().as_str())
+                    else {
+                        continue;
+                    };
+                    let neighbors =
+                        list_neighbors(collection_id.clone(), node.clone(), direction_out).await?;
+                    pin_mut!(neighbors);
+                    while fan_out < limits.max_fan_out {
+                        let Some(neighbor) = neighbors.next().await else {
+                            break;
+                        };
+                        let neighbor = neighbor?;
+                        if path.nodes.contains(&neighbor)
+                            || (limits.unique_nodes && visited.contains(&neighbor))
+                        {
+                            continue;
+                        }
+                        fan_out += 1;
+                        if limits.unique_nodes {
+                            visited.insert(neighbor.clone());
+                        }
+                        let mut new_path = path.clone();
+                        new_path.nodes.push(neighbor);
+                        new_path.edges.push(GraphTraversalPathEdge {
+                            edge_type: edge_type.clone(),
+                            direction_out,
+                        });
+                        if depth >= min_depth {
+                            yield new_path.clone();
+                            path_count += 1;
+                            if path_count >= limits.max_paths {
+                                break 'traversal;
+                            }
+                        }
+                        if depth < max_depth && next_frontier.len() < GRAPH_TRAVERSAL_MAX_FRONTIER {
+                            next_frontier.push(new_path);
+                        } else if depth < min_depth {
+                            // nothing more to return or to follow at this depth
+                            break 'frontier;
+                        }
+                    }
+                }
+            }
+            frontier = next_frontier;
+            if frontier.is_empty() {
+                break;
+            }
+        }
+    };
+    Ok(Box::pin(stream))
+}
+
+/// Typed multi-hop route through the graph, from a `Start` model to a `Current` model.
+/// Each step is checked at compile time to start from the model the previous step ended on:
+///
+/// ```ignore
+/// let route = GraphPathQuery::<FsBlobHashesDbRow>::new()
+///     .backward(EmailToAttachment)
+///     .forward(BlobToEmailHeaders);
+/// let emails = route.end_nodes(&c, &attachment_pk, GraphTraversalLimits::default()).await?;
+/// ```
+pub struct GraphPathQuery<Start, Current = Start> {
+    hops: Vec<GraphTraversalHop>,
+    _ph: PhantomData<fn() -> (Start, Current)>,
+}
+
+impl<Start: BaseModel> GraphPathQuery<Start, Start> {
+    /// Create an empty route, starting from a `Start` node.
+    pub fn new() -> Self {
+        Self {
+            hops: vec![],
+            _ph: PhantomData,
+        }
+    }
+}
+
+impl<Start: BaseModel> Default for GraphPathQuery<Start, Start> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Start, Current> GraphPathQuery<Start, Current>
+where
+    Start: BaseModel,
+    <Start as BaseModel>::PrimaryKey: serde::Serialize,
+    Current: BaseModel + Send + Sync + 'static,
+    <Current as BaseModel>::PrimaryKey: for<'a> serde::Deserialize<'a>,
+{
+    /// Follow the edge from its source to its target.
+    pub fn forward<E>(mut self, _edge: E) -> GraphPathQuery<Start, E::DestType>
+    where
+        E: GraphEdge<SourceType = Current>,
+    {
+        self.hops.push(GraphTraversalHop {
+            edge_types: vec![E::edge_type()],
+            direction: GraphTraversalDirection::Out,
+        });
+        GraphPathQuery {
+            hops: self.hops,
+            _ph: PhantomData,
+        }
+    }
+
+    /// Follow the edge from its target back to its source.
+    pub fn backward<E>(mut self, _edge: E) -> GraphPathQuery<Start, E::SourceType>
+    where
+        E: GraphEdge<DestType = Current>,
+    {
+        self.hops.push(GraphTraversalHop {
+            edge_types: vec![E::edge_type()],
+            direction: GraphTraversalDirection::In,
+        });
+        GraphPathQuery {
+            hops: self.hops,
+            _ph: PhantomData,
+        }
+    }
+
+    /// Get the dynamic query for this route, returning only the paths that follow all its steps.
+    pub fn to_query(
+        &self,
+        start: &<Start as BaseModel>::PrimaryKey,
+        limits: GraphTraversalLimits,
+    ) -> GraphTraversalQuery {
+        GraphTraversalQuery {
+            start_pk_hash: row_pk_hash::<Start>(start),
+            hops: self.hops.clone(),
+            max_depth: self.hops.len() as u32,
+            min_depth: self.hops.len() as u32,
+            limits,
+        }
+    }
+
+    /// Run the route from a start node, and return a stream of the paths found.
+    pub async fn paths(
+        &self,
+        collection_id: &CollectionId,
+        start: &<Start as BaseModel>::PrimaryKey,
+        limits: GraphTraversalLimits,
+    ) -> anyhow::Result<ResultStream<GraphTraversalPath>> {
+        graph_traverse(collection_id, self.to_query(start, limits)).await
+    }
+
+    /// Run the route from a start node, and return a stream of the paths found,
+    /// together with the rows of the nodes they end on.
+    pub async fn end_nodes(
+        &self,
+        collection_id: &CollectionId,
+        start: &<Start as BaseModel>::PrimaryKey,
+        limits: GraphTraversalLimits,
+    ) -> anyhow::Result<ResultStream<(GraphTraversalPath, Current)>> {
+        let paths = self.paths(collection_id, start, limits).await?;
+        let session = ScyllaDatabaseHandle::collection_session(collection_id).await?;
+        let stream = paths.and_then(move |path| {
+            let session = session.clone();
+            async move {
+                let end = path.nodes.last().cloned().unwrap_or_default();
+                let Some(pk_json) = node_pk_json(&session, &end).await? else {
+                    anyhow::bail!("graph node {} is missing from the node map", end);
+                };
+                let pk: <Current as BaseModel>::PrimaryKey = serde_json::from_str(&pk_json)?;
+                let row = <Current as Find>::find_by_primary_key_value(pk)
+                    .execute(&session)
+                    .await?;
+                Ok((path, row))
+            }
+        });
+        Ok(Box::pin(stream))
+    }
+}
@@ -221,6 +221,7 @@ macro_rules! declare_stored_graph_edge {
                 source_type: <$source as  $crate::charybdis::model::BaseModel>::DB_MODEL_NAME,
                 target_type:  <$dest as  $crate::charybdis::model::BaseModel>::DB_MODEL_NAME,
                 edge_store_type: ::hoover3_types::db_schema::EdgeStoreImplementation::Stored,
+                list_neighbors: $crate::models::collection::stored_edge_neighbors::<$struct_name>,
             });
             impl $crate::models::collection::GraphEdge for $struct_name {
                 type SourceType = $source;
@@ -312,6 +313,7 @@ macro_rules! declare_implicit_graph_edge {
                 source_type: <$source as  $crate::charybdis::model::BaseModel>::DB_MODEL_NAME,
                 target_type:  <$dest as  $crate::charybdis::model::BaseModel>::DB_MODEL_NAME,
                 edge_store_type: ::hoover3_types::db_schema::EdgeStoreImplementation::Implicit,
+                list_neighbors: $crate::models::collection::implicit_edge_neighbors::<$struct_name>,
             });
             impl $crate::models::collection::GraphEdge for $struct_name {
                 type SourceType = $source;
@@ -0,0 +1,284 @@
+//! Node degree statistics, read from the edge counters ([GraphEdgeSourceCounter]).
+//!
+//! Each stored edge increments a counter for its source and for its target, by edge type
+//! and direction, so node degrees are known without reading the edge pages.
+//! Implicit edges have no counters, and are not part of these statistics.
+//! The degree distributions of a collection need a full read of the counter table, so they
+//! are computed by a workflow and saved into [GraphDegreeStatisticsCache].
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+
+use charybdis::batch::ModelBatch;
+use charybdis::operations::Find;
+use futures::TryStreamExt;
+use hoover3_types::db_schema::{
+    GraphDegreeBucket, GraphDegreeStatistics, GraphEdgeId, GraphNodeEdgeCount, GraphTopNode,
+};
+use hoover3_types::identifier::{CollectionId, DatabaseIdentifier};
+
+use super::export::graph_node_labels;
+use crate::constants::CQL_SELECT_BATCH_SIZE;
+use crate::db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle};
+use crate::models::collection::{
+    find_graph_edge_source_counter, get_graph_edges_types_from_inventory,
+    GraphDegreeStatisticsCache, GraphEdgeSourceCounter,
+};
+
+/// Get the number of stored edges of a node, for each edge type, in both directions.
+/// The result is sorted by edge type; edge types without edges at the node are left out.
+pub async fn graph_node_edge_counts(
+    collection_id: &CollectionId,
+    pk_hash: &str,
+) -> anyhow::Result<Vec<GraphNodeEdgeCount>> {
+    let session = ScyllaDatabaseHandle::collection_session(collection_id).await?;
+    let counters = find_graph_edge_source_counter!("pk_source = ?", (pk_hash.to_string(),))
+        .execute(&session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let mut counts: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for counter in counters {
+        let edge_count = counter.edge_count().max(0) as u64;
+        let count = counts.entry(counter.edge_type.clone()).or_default();
+        match counter.direction_out {
+            true => count.0 += edge_count,
+            false => count.1 += edge_count,
+        }
+    }
+    let mut result = vec![];
+    for (edge_type, (out_count, in_count)) in counts {
+        if out_count == 0 && in_count == 0 {
+            continue;
+        }
+        result.push(GraphNodeEdgeCount {
+            edge_type: GraphEdgeId(DatabaseIdentifier::new(&edge_type)?),
+            out_count,
+            in_count,
+        });
+    }
+    Ok(result)
+}
+
+/// Largest number of most connected nodes kept in the degree statistics.
+pub const GRAPH_DEGREE_TOP_N_MAX: usize = 100;
+
+/// Number of counter rows read between two calls of the progress callback.
+const DEGREE_PROGRESS_ROWS: u64 = 10000;
+
+/// Compute the degree distributions and the most connected nodes of all the stored edge types,
+/// in both directions, from one read of the edge counter table, and save them for
+/// [graph_degree_statistics]. Only the nodes with at least one edge are counted.
+/// `progress` is called with the number of counter rows read.
+pub async fn compute_graph_degree_statistics(
+    collection_id: &CollectionId,
+    progress: impl Fn(u64),
+) -> anyhow::Result<Vec<GraphDegreeStatistics>> {
+    let session = ScyllaDatabaseHandle::collection_session(collection_id).await?;
+    let mut counters = GraphEdgeSourceCounter::find_all().execute(&session).await?;
+    let mut accumulators: BTreeMap<(String, bool), DegreeAccumulator> = BTreeMap::new();
+    let mut row_count = 0_u64;
+    while let Some(counter) = counters.try_next().await? {
+        row_count += 1;
+        if row_count % DEGREE_PROGRESS_ROWS == 0 {
+            progress(row_count);
+        }
+        let degree = counter.edge_count();
+        if degree <= 0 {
+            continue;
+        }
+        accumulators
+            .entry((counter.edge_type.clone(), counter.direction_out))
+            .or_insert_with(|| DegreeAccumulator::new(GRAPH_DEGREE_TOP_N_MAX))
+            .add(counter.pk_source, degree as u64);
+    }
+    progress(row_count);
+
+    let computed_at = chrono::Utc::now();
+    let mut result = vec![];
+    for ((edge_type, direction_out), accumulator) in accumulators {
+        let top_nodes = accumulator.top_nodes();
+        let pk_hashes = top_nodes
+            .iter()
+            .map(|(pk_hash, _)| pk_hash.clone())
+            .collect::<Vec<_>>();
+        let mut labels = vec![];
+        for pk_chunk in pk_hashes.chunks(CQL_SELECT_BATCH_SIZE) {
+            labels.extend(graph_node_labels(collection_id, pk_chunk).await?);
+        }
+        result.push(GraphDegreeStatistics {
+            edge_type: GraphEdgeId(DatabaseIdentifier::new(&edge_type)?),
+            direction_out,
+            node_count: accumulator.node_count,
+            edge_count: accumulator.edge_count,
+            max_degree: accumulator.max_degree,
+            distribution: accumulator.distribution(),
+            top_nodes: top_nodes
+                .into_iter()
+                .zip(labels)
+                .map(|((pk_hash, degree), label)| GraphTopNode {
+                    pk_hash,
+                    label,
+                    degree,
+                })
+                .collect(),
+            computed_at,
+        });
+    }
+
+    // replace the saved statistics, and remove those of the edge types without edges anymore
+    let rows = result
+        .iter()
+        .map(|statistics| {
+            Ok(GraphDegreeStatisticsCache {
+                edge_type: statistics.edge_type.to_string(),
+                direction_out: statistics.direction_out,
+                statistics_json: serde_json::to_string(statistics)?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    GraphDegreeStatisticsCache::batch()
+        .chunked_insert(&session, &rows, 1024)
+        .await?;
+    let old_rows = GraphDegreeStatisticsCache::find_all()
+        .execute(&session)
+        .await?
+        .try_filter(|old| {
+            std::future::ready(!rows.iter().any(|row| {
+                row.edge_type == old.edge_type && row.direction_out == old.direction_out
+            }))
+        })
+        .try_collect::<Vec<_>>()
+        .await?;
+    GraphDegreeStatisticsCache::batch()
+        .chunked_delete(&session, &old_rows, 1024)
+        .await?;
+    Ok(result)
+}
+
+/// Get the degree distributions and the `top_n` most connected nodes of the stored
+/// edge types, in both directions, as saved by [compute_graph_degree_statistics];
+/// `top_n` is capped to [GRAPH_DEGREE_TOP_N_MAX].
+/// `edge_types` selects the edge types; all of them if empty.
+/// Empty if the statistics were never computed.
+pub async fn graph_degree_statistics(
+    collection_id: &CollectionId,
+    edge_types: &[GraphEdgeId],
+    top_n: usize,
+) -> anyhow::Result<Vec<GraphDegreeStatistics>> {
+    let schema = get_graph_edges_types_from_inventory();
+    for edge_type in edge_types.iter() {
+        if !schema.edges_by_types.contains_key(edge_type) {
+            anyhow::bail!("graph degree statistics: unknown edge type {}", edge_type);
+        }
+    }
+    let edge_types = edge_types
+        .iter()
+        .map(|edge_type| edge_type.to_string())
+        .collect::<Vec<_>>();
+
+    let session = ScyllaDatabaseHandle::collection_session(collection_id).await?;
+    let rows = GraphDegreeStatisticsCache::find_all()
+        .execute(&session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let mut result = vec![];
+    for row in rows {
+        if !edge_types.is_empty() && !edge_types.contains(&row.edge_type) {
+            continue;
+        }
+        let mut statistics: GraphDegreeStatistics = serde_json::from_str(&row.statistics_json)?;
+        statistics
+            .top_nodes
+            .truncate(top_n.min(GRAPH_DEGREE_TOP_N_MAX));
+        result.push(statistics);
+    }
+    result.sort_by(|a, b| (&a.edge_type, a.direction_out).cmp(&(&b.edge_type, b.direction_out)));
+    Ok(result)
+}
+
+/// Degree statistics of one edge type and direction, built one node at a time.
+struct DegreeAccumulator {
+    node_count: u64,
+    edge_count: u64,
+    max_degree: u64,
+    /// Node count by bucket, keyed by the base 2 logarithm of the degree.
+    buckets: BTreeMap<u32, u64>,
+    /// The `top_n` largest degrees seen so far, smallest first.
+    top: BinaryHeap<Reverse<(u64, String)>>,
+    top_n: usize,
+}
+
+impl DegreeAccumulator {
+    fn new(top_n: usize) -> Self {
+        Self {
+            node_count: 0,
+            edge_count: 0,
+            max_degree: 0,
+            buckets: BTreeMap::new(),
+            top: BinaryHeap::new(),
+            top_n,
+        }
+    }
+
+    /// Add a node with a non-zero degree.
+    fn add(&mut self, pk_hash: String, degree: u64) {
+        self.node_count += 1;
+        self.edge_count += degree;
+        self.max_degree = self.max_degree.max(degree);
+        *self.buckets.entry(degree.ilog2()).or_default() += 1;
+        if self.top_n == 0 {
+            return;
+        }
+        if self.top.len() < self.top_n {
+            self.top.push(Reverse((degree, pk_hash)));
+        } else if self.top.peek().is_some_and(|Reverse(min)| degree > min.0) {
+            self.top.pop();
+            self.top.push(Reverse((degree, pk_hash)));
+        }
+    }
+
+    fn distribution(&self) -> Vec<GraphDegreeBucket> {
+        self.buckets
+            .iter()
+            .map(|(log2, node_count)| GraphDegreeBucket {
+                min_degree: 1 << log2,
+                max_degree: u64::MAX >> (63 - log2),
+                node_count: *node_count,
+            })
+            .collect()
+    }
+
+    /// The most connected nodes, largest degree first, then by primary key hash.
+    fn top_nodes(&self) -> Vec<(String, u64)> {
+        let mut top = self
+            .top
+            .iter()
+            .map(|Reverse((degree, pk_hash))| (pk_hash.clone(), *degree))
+            .collect::<Vec<_>>();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top
+    }
+}
+
+#[test]
+fn test_degree_accumulator() {
+    let mut accumulator = DegreeAccumulator::new(2);
+    for (pk_hash, degree) in [("a", 1), ("b", 5), ("c", 2), ("d", 3), ("e", 9)] {
+        accumulator.add(pk_hash.to_string(), degree);
+    }
+    assert_eq!(accumulator.node_count, 5);
+    assert_eq!(accumulator.edge_count, 20);
+    assert_eq!(accumulator.max_degree, 9);
+    let buckets = accumulator
+        .distribution()
+        .into_iter()
+        .map(|b| (b.min_degree, b.max_degree, b.node_count))
+        .collect::<Vec<_>>();
+    assert_eq!(buckets, vec![(1, 1, 1), (2, 3, 2), (4, 7, 1), (8, 15, 1)]);
+    assert_eq!(
+        accumulator.top_nodes(),
+        vec![("e".to_string(), 9), ("b".to_string(), 5)]
+    );
+}
@@ -0,0 +1,480 @@
+//! Export of the graph of a collection into GraphML, GEXF or JSON Graph files.
+//!
+//! The export walks the declared edge types from the outgoing side of each node,
+//! using the same neighbor functions as the traversal (see [GraphEdgeNeighborsFn]),
+//! so stored and implicit edges are exported alike. Edges and nodes are spooled into
+//! temporary files while the graph is walked; the nodes are spread over buckets by hash,
+//! so each bucket can be deduplicated in memory on its own. Then the nodes are labeled
+//! from their model rows and both are written out.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use charybdis::operations::Find;
+use futures::{StreamExt, TryStreamExt};
+use hoover3_types::db_schema::{GraphEdgeId, GraphExportFormat, GraphExportRequest};
+use hoover3_types::identifier::CollectionId;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+use super::chain::{node_table, GraphEdgeNeighborsFn};
+use super::repair::graph_node_find_row_fns;
+use crate::constants::CQL_SELECT_BATCH_SIZE;
+use crate::db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle};
+use crate::models::collection::{
+    find_graph_node_pk_map, get_graph_edges_types_from_inventory, GraphEdgeTypeStatic,
+    GraphNodePkMap,
+};
+
+/// Number of temporary files the exported nodes are spread over, to deduplicate them
+/// one file at a time.
+const GRAPH_EXPORT_NODE_BUCKETS: usize = 64;
+
+/// Number of model row lookups run in parallel when labeling nodes.
+const GRAPH_EXPORT_ROW_LOOKUPS: usize = 16;
+
+/// Row fields used as the node label, in order of preference.
+const GRAPH_EXPORT_LABEL_FIELDS: &[&str] = &[
+    "subject",
+    "display_name",
+    "address",
+    "file_name",
+    "path",
+    "title",
+    "name",
+];
+
+/// Export the graph of a collection into a local file, in the requested format.
+/// Returns the number of nodes and edges written.
+/// `progress` is called with the number of edges read so far.
+pub async fn graph_export_to_file(
+    c: &CollectionId,
+    request: &GraphExportRequest,
+    path: &Path,
+    progress: impl Fn(u64),
+) -> anyhow::Result<(u64, u64)> {
+    let schema = get_graph_edges_types_from_inventory();
+    for edge_type in request.edge_types.iter() {
+        if !schema.edges_by_types.contains_key(edge_type) {
+            anyhow::bail!("graph export: unknown edge type {}", edge_type);
+        }
+    }
+    let edge_types = schema
+        .edges_by_types
+        .keys()
+        .filter(|edge_type| request.edge_types.is_empty() || request.edge_types.contains(edge_type))
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    let neighbors_fns = inventory::iter::<GraphEdgeTypeStatic>
+        .into_iter()
+        .map(|edge| (edge.edge_type, edge.list_neighbors))
+        .collect::<BTreeMap<_, _>>();
+    // the edge types to list from the source side and from the target side, by node table
+    let mut edges_by_table: BTreeMap<String, Vec<(GraphEdgeId, bool, GraphEdgeNeighborsFn)>> =
+        BTreeMap::new();
+    for edge_type in edge_types.iter() {
+        let edge = &schema.edges_by_types[edge_type];
+        let Some(list_neighbors) = neighbors_fns.get(edge_type.to_string().as_str()) else {
+            continue;
+        };
+        edges_by_table
+            .entry(edge.source_type.to_string())
+            .or_default()
+            .push((edge_type.clone(), true, *list_neighbors));
+        if !request.nodes.is_empty() && request.include_neighbors {
+            edges_by_table
+                .entry(edge.target_type.to_string())
+                .or_default()
+                .push((edge_type.clone(), false, *list_neighbors));
+        }
+    }
+
+    let subset = request.nodes.iter().cloned().collect::<BTreeSet<_>>();
+    let edges_path = path.with_extension("edges.tmp");
+    let mut edges_file = BufWriter::new(tokio::fs::File::create(&edges_path).await?);
+    let mut nodes = GraphExportNodeSpool::create(path).await?;
+    for node in subset.iter() {
+        nodes.insert(node).await?;
+    }
+    let mut edge_count = 0;
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let mut start_nodes = if subset.is_empty() {
+        GraphNodePkMap::find_all()
+            .execute(&session)
+            .await?
+            .map_ok(|row| row.pk)
+            .map_err(anyhow::Error::from)
+            .boxed()
+    } else {
+        futures::stream::iter(subset.clone().into_iter().map(anyhow::Ok)).boxed()
+    };
+    while let Some(node) = start_nodes.try_next().await? {
+        let Some(table) = node_table(&node) else {
+            continue;
+        };
+        let mut node_written = !subset.is_empty();
+        for (edge_type, direction_out, list_neighbors) in
+            edges_by_table.get(&table.to_string()).into_iter().flatten()
+        {
+            let mut neighbors = list_neighbors(c.clone(), node.clone(), *direction_out).await?;
+            while let Some(neighbor) = neighbors.try_next().await? {
+                let in_subset = subset.contains(&neighbor);
+                // without the neighbors, keep the edges inside the subset;
+                // with them, the edges inside the subset are listed from their source only
+                let keep = match (subset.is_empty(), request.include_neighbors) {
+                    (true, _) => true,
+                    (false, false) => in_subset,
+                    (false, true) => *direction_out || !in_subset,
+                };
+                if !keep {
+                    continue;
+                }
+                let (source, target) = match direction_out {
+                    true => (node.as_str(), neighbor.as_str()),
+                    false => (neighbor.as_str(), node.as_str()),
+                };
+                let line = format!("{}\t{}\t{}\n", edge_type, source, target);
+                edges_file.write_all(line.as_bytes()).await?;
+                nodes.insert(&neighbor).await?;
+                if !node_written {
+                    nodes.insert(&node).await?;
+                    node_written = true;
+                }
+                edge_count += 1;
+                if edge_count % 1000 == 0 {
+                    progress(edge_count);
+                }
+            }
+        }
+    }
+    edges_file.flush().await?;
+    drop(edges_file);
+    progress(edge_count);
+
+    let mut out = BufWriter::new(tokio::fs::File::create(path).await?);
+    let writer = GraphExportWriter::new(request.format);
+    out.write_all(writer.header().as_bytes()).await?;
+    let mut node_count = 0_u64;
+    for bucket in nodes.finish().await? {
+        let bucket_nodes = read_node_bucket(&bucket).await?;
+        for node_chunk in bucket_nodes.chunks(CQL_SELECT_BATCH_SIZE) {
+            let labels = graph_node_labels(c, node_chunk).await?;
+            for (node, label) in node_chunk.iter().zip(labels) {
+                let table = node_table(node).map(|t| t.to_string()).unwrap_or_default();
+                out.write_all(
+                    writer
+                        .node(node_count == 0, node, &label, &table)
+                        .as_bytes(),
+                )
+                .await?;
+                node_count += 1;
+            }
+        }
+        tokio::fs::remove_file(&bucket).await?;
+    }
+    out.write_all(writer.edges_start().as_bytes()).await?;
+    let mut lines = BufReader::new(tokio::fs::File::open(&edges_path).await?).lines();
+    let mut index = 0;
+    while let Some(line) = lines.next_line().await? {
+        let mut parts = line.splitn(3, '\t');
+        let (Some(edge_type), Some(source), Some(target)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("graph export: invalid edge line {:?}", line);
+        };
+        out.write_all(writer.edge(index, edge_type, source, target).as_bytes())
+            .await?;
+        index += 1;
+    }
+    out.write_all(writer.footer().as_bytes()).await?;
+    out.flush().await?;
+    tokio::fs::remove_file(&edges_path).await?;
+    Ok((node_count, edge_count))
+}
+
+/// Temporary files holding the exported node ids, one per line, with duplicates;
+/// a node always goes into the same bucket.
+struct GraphExportNodeSpool {
+    paths: Vec<PathBuf>,
+    files: Vec<BufWriter<tokio::fs::File>>,
+}
+
+impl GraphExportNodeSpool {
+    async fn create(path: &Path) -> anyhow::Result<Self> {
+        let mut paths = vec![];
+        let mut files = vec![];
+        for bucket in 0..GRAPH_EXPORT_NODE_BUCKETS {
+            let bucket_path = path.with_extension(format!("nodes{}.tmp", bucket));
+            files.push(BufWriter::new(tokio::fs::File::create(&bucket_path).await?));
+            paths.push(bucket_path);
+        }
+        Ok(Self { paths, files })
+    }
+
+    async fn insert(&mut self, node: &str) -> anyhow::Result<()> {
+        let mut hasher = DefaultHasher::new();
+        node.hash(&mut hasher);
+        let bucket = (hasher.finish() % GRAPH_EXPORT_NODE_BUCKETS as u64) as usize;
+        let file = &mut self.files[bucket];
+        file.write_all(node.as_bytes()).await?;
+        file.write_all(b"\n").await?;
+        Ok(())
+    }
+
+    /// Flush and close the buckets, and return their paths.
+    async fn finish(self) -> anyhow::Result<Vec<PathBuf>> {
+        for mut file in self.files {
+            file.flush().await?;
+        }
+        Ok(self.paths)
+    }
+}
+
+/// Read the distinct nodes of a bucket written by [GraphExportNodeSpool], sorted.
+async fn read_node_bucket(path: &Path) -> anyhow::Result<Vec<String>> {
+    let mut nodes = BTreeSet::new();
+    let mut lines = BufReader::new(tokio::fs::File::open(path).await?).lines();
+    while let Some(line) = lines.next_line().await? {
+        nodes.insert(line);
+    }
+    Ok(nodes.into_iter().collect())
+}
+
+/// Get the labels of a chunk of nodes, in the same order.
+/// Nodes missing from the node map are labeled with their id,
+/// and nodes without a row or a label field with their primary key.
+pub(crate) async fn graph_node_labels(
+    c: &CollectionId,
+    pk_hashes: &[String],
+) -> anyhow::Result<Vec<String>> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let mut pk_values = find_graph_node_pk_map!("pk IN ?", (pk_hashes.to_vec(),))
+        .execute(&session)
+        .await?
+        .map_ok(|pk_map| (pk_map.pk, pk_map.value))
+        .try_collect::<HashMap<_, _>>()
+        .await?;
+    let find_row_fns = graph_node_find_row_fns();
+    let lookups = pk_hashes.iter().map(|pk_hash| {
+        let pk_json = pk_values.remove(pk_hash);
+        let find_row = node_table(pk_hash)
+            .and_then(|table| find_row_fns.get(table.to_string().as_str()).copied());
+        let c = c.clone();
+        async move {
+            let Some(pk_json) = pk_json else {
+                return anyhow::Ok(pk_hash.clone());
+            };
+            let row = match find_row {
+                Some(find_row) => find_row(c, pk_json.clone()).await?,
+                None => None,
+            };
+            Ok(row.as_ref().and_then(graph_node_label).unwrap_or(pk_json))
+        }
+    });
+    futures::stream::iter(lookups)
+        .buffered(GRAPH_EXPORT_ROW_LOOKUPS)
+        .try_collect()
+        .await
+}
+
+/// Pick a label for a node from its row: the first non-empty text field among
+/// [GRAPH_EXPORT_LABEL_FIELDS].
+fn graph_node_label(row: &serde_json::Value) -> Option<String> {
+    GRAPH_EXPORT_LABEL_FIELDS.iter().find_map(|field| {
+        let value = row.get(*field)?.as_str()?;
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// Escape text for XML content and attribute values.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // control characters are not allowed in XML 1.0
+            ch if (ch as u32) < 0x20 && !matches!(ch, '\t' | '\n' | '\r') => {}
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Formats the pieces of a graph export file: header, nodes, edges and footer.
+/// The nodes must all be written before the edges.
+struct GraphExportWriter {
+    format: GraphExportFormat,
+}
+
+impl GraphExportWriter {
+    fn new(format: GraphExportFormat) -> Self {
+        Self { format }
+    }
+
+    fn header(&self) -> String {
+        match self.format {
+            GraphExportFormat::GraphMl => concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+                "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+                "  <key id=\"table\" for=\"node\" attr.name=\"table\" attr.type=\"string\"/>\n",
+                "  <key id=\"edge_type\" for=\"edge\" attr.name=\"edge_type\" attr.type=\"string\"/>\n",
+                "  <graph id=\"G\" edgedefault=\"directed\">\n",
+            )
+            .to_string(),
+            GraphExportFormat::Gexf => concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<gexf xmlns=\"http://gexf.net/1.2\" version=\"1.2\">\n",
+                "  <graph defaultedgetype=\"directed\">\n",
+                "    <attributes class=\"node\">\n",
+                "      <attribute id=\"table\" title=\"table\" type=\"string\"/>\n",
+                "    </attributes>\n",
+                "    <nodes>\n",
+            )
+            .to_string(),
+            GraphExportFormat::JsonGraph => {
+                "{\"graph\":{\"directed\":true,\"nodes\":[".to_string()
+            }
+        }
+    }
+
+    fn node(&self, first: bool, id: &str, label: &str, table: &str) -> String {
+        match self.format {
+            GraphExportFormat::GraphMl => format!(
+                "    <node id=\"{}\"><data key=\"label\">{}</data><data key=\"table\">{}</data></node>\n",
+                xml_escape(id),
+                xml_escape(label),
+                xml_escape(table)
+            ),
+            GraphExportFormat::Gexf => format!(
+                "      <node id=\"{}\" label=\"{}\"><attvalues><attvalue for=\"table\" value=\"{}\"/></attvalues></node>\n",
+                xml_escape(id),
+                xml_escape(label),
+                xml_escape(table)
+            ),
+            GraphExportFormat::JsonGraph => format!(
+                "{}\n{}",
+                if first { "" } else { "," },
+                serde_json::json!({"id": id, "label": label, "metadata": {"table": table}})
+            ),
+        }
+    }
+
+    fn edges_start(&self) -> String {
+        match self.format {
+            GraphExportFormat::GraphMl => "".to_string(),
+            GraphExportFormat::Gexf => "    </nodes>\n    <edges>\n".to_string(),
+            GraphExportFormat::JsonGraph => "],\"edges\":[".to_string(),
+        }
+    }
+
+    fn edge(&self, index: u64, edge_type: &str, source: &str, target: &str) -> String {
+        match self.format {
+            GraphExportFormat::GraphMl => format!(
+                "    <edge id=\"e{}\" source=\"{}\" target=\"{}\"><data key=\"edge_type\">{}</data></edge>\n",
+                index,
+                xml_escape(source),
+                xml_escape(target),
+                xml_escape(edge_type)
+            ),
+            GraphExportFormat::Gexf => format!(
+                "      <edge id=\"{}\" source=\"{}\" target=\"{}\" label=\"{}\"/>\n",
+                index,
+                xml_escape(source),
+                xml_escape(target),
+                xml_escape(edge_type)
+            ),
+            GraphExportFormat::JsonGraph => format!(
+                "{}\n{}",
+                if index == 0 { "" } else { "," },
+                serde_json::json!({
+                    "source": source,
+                    "target": target,
+                    "relation": edge_type,
+                    "directed": true
+                })
+            ),
+        }
+    }
+
+    fn footer(&self) -> String {
+        match self.format {
+            GraphExportFormat::GraphMl => "  </graph>\n</graphml>\n".to_string(),
+            GraphExportFormat::Gexf => "    </edges>\n  </graph>\n</gexf>\n".to_string(),
+            GraphExportFormat::JsonGraph => "\n]}}\n".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+fn write_test_graph(format: GraphExportFormat) -> String {
+    let writer = GraphExportWriter::new(format);
+    let mut out = writer.header();
+    out += &writer.node(true, "a_1", "<A & \"B\">", "a");
+    out += &writer.node(false, "b_2", "b\u{1}", "b");
+    out += &writer.edges_start();
+    out += &writer.edge(0, "a_to_b", "a_1", "b_2");
+    out += &writer.edge(1, "a_to_b", "a_1", "a_1");
+    out += &writer.footer();
+    out
+}
+
+#[test]
+fn test_xml_escape() {
+    assert_eq!(xml_escape("a<b>&\"c'"), "a&lt;b&gt;&amp;&quot;c&apos;");
+    assert_eq!(xml_escape("x\u{0}\ty"), "x\ty");
+}
+
+#[test]
+fn test_graph_node_label() {
+    let row = serde_json::json!({"path": "", "file_name": "f.txt", "name": "n"});
+    assert_eq!(graph_node_label(&row), Some("f.txt".to_string()));
+    assert_eq!(graph_node_label(&serde_json::json!({"size": 3})), None);
+}
+
+#[test]
+fn test_graph_export_json_graph() {
+    let out = write_test_graph(GraphExportFormat::JsonGraph);
+    let graph: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(graph["graph"]["nodes"].as_array().unwrap().len(), 2);
+    assert_eq!(graph["graph"]["nodes"][0]["label"], "<A & \"B\">");
+    assert_eq!(graph["graph"]["nodes"][1]["metadata"]["table"], "b");
+    assert_eq!(graph["graph"]["edges"].as_array().unwrap().len(), 2);
+    assert_eq!(graph["graph"]["edges"][0]["relation"], "a_to_b");
+}
+
+#[test]
+fn test_graph_export_xml_formats() {
+    for format in [GraphExportFormat::GraphMl, GraphExportFormat::Gexf] {
+        let out = write_test_graph(format);
+        assert!(out.contains("&lt;A &amp; &quot;B&quot;&gt;"));
+        assert!(!out.contains('\u{1}'));
+        assert_eq!(out.matches("<node ").count(), 2);
+        assert_eq!(out.matches("<edge ").count(), 2);
+        assert!(out.ends_with(match format {
+            GraphExportFormat::GraphMl => "</graphml>\n",
+            _ => "</gexf>\n",
+        }));
+    }
+}
+
+#[tokio::test]
+async fn test_graph_export_node_spool() -> anyhow::Result<()> {
+    let path = std::env::temp_dir().join("test_graph_export_node_spool.json");
+    let mut spool = GraphExportNodeSpool::create(&path).await?;
+    for node in ["a_1", "b_2", "a_1", "c_3", "b_2"] {
+        spool.insert(node).await?;
+    }
+    let mut nodes = vec![];
+    for bucket in spool.finish().await? {
+        nodes.extend(read_node_bucket(&bucket).await?);
+        tokio::fs::remove_file(&bucket).await?;
+    }
+    nodes.sort();
+    assert_eq!(nodes, vec!["a_1", "b_2", "c_3"]);
+    Ok(())
+}
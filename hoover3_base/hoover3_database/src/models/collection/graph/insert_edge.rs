@@ -1,13 +1,15 @@
 use super::super::graph_models::*;
-use super::super::row_pk_hash;
+use super::super::{get_graph_edges_types_from_inventory, row_pk_hash};
+use super::chain::node_table;
 use super::declare_edge::GraphEdge;
+use super::query_edge::list_edge_targets;
 use crate::constants::CQL_SELECT_BATCH_SIZE;
 use crate::db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle};
 use charybdis::{batch::ModelBatch, model::BaseModel};
 use futures::pin_mut;
 use futures::FutureExt;
-use futures::{stream::FuturesUnordered, StreamExt};
-use hoover3_types::db_schema::GraphEdgeId;
+use futures::{stream::FuturesUnordered, StreamExt, TryStreamExt};
+use hoover3_types::db_schema::{EdgeStoreImplementation, GraphEdgeId};
 use hoover3_types::identifier::CollectionId;
 
 use scylla::batch::{Batch, BatchType};
@@ -52,10 +54,11 @@ where
     Ok(nodes.len())
 }
 
-/// A batch for inserting edges into the graph.
+/// A batch for inserting or removing edges in the graph.
 pub struct EdgeBatchOperation<E: GraphEdge> {
     collection_id: CollectionId,
     edges: Vec<(String, String)>,
+    removed_edges: Vec<(String, String)>,
     _ph: std::marker::PhantomData<E>,
 }
 
@@ -65,18 +68,28 @@ impl<E: GraphEdge> EdgeBatchOperation<E> {
         Self {
             collection_id,
             edges: Vec::new(),
+            removed_edges: Vec::new(),
             _ph: std::marker::PhantomData,
         }
     }
 
     /// Execute the batch operation.
+    /// Edges are removed first, then added.
+    /// Returns the number of edges removed and added.
     pub async fn execute(&self) -> anyhow::Result<usize> {
-        graph_add_edges(
+        let removed = graph_remove_edges(
+            self.collection_id.clone(),
+            E::edge_type(),
+            self.removed_edges.clone(),
+        )
+        .await?;
+        let added = graph_add_edges(
             self.collection_id.clone(),
             E::edge_type(),
             self.edges.clone(),
         )
-        .await
+        .await?;
+        Ok(removed + added)
     }
 }
 impl<E: GraphEdge> EdgeBatchOperation<E>
@@ -95,6 +108,14 @@ where
         self.edges.push((s, d));
     }
 
+    /// Remove edge in batch, using references to models.
+    /// Edges that do not exist are ignored.
+    pub fn remove_edge(&mut self, source: &E::SourceType, dest: &E::DestType) {
+        let s = row_pk_hash::<E::SourceType>(&source.primary_key_values());
+        let d = row_pk_hash::<E::DestType>(&dest.primary_key_values());
+        self.removed_edges.push((s, d));
+    }
+
     /// Add edge to batch, using references to primary keys.
     pub fn add_edge_from_pk(
         &mut self,
@@ -105,6 +126,18 @@ where
         let d = row_pk_hash::<E::DestType>(dest);
         self.edges.push((s, d));
     }
+
+    /// Remove edge in batch, using references to primary keys.
+    /// Edges that do not exist are ignored.
+    pub fn remove_edge_from_pk(
+        &mut self,
+        source: &<E::SourceType as BaseModel>::PrimaryKey,
+        dest: &<E::DestType as BaseModel>::PrimaryKey,
+    ) {
+        let s = row_pk_hash::<E::SourceType>(source);
+        let d = row_pk_hash::<E::DestType>(dest);
+        self.removed_edges.push((s, d));
+    }
 }
 
 /// Add many edges to graph for a specific edge type.
@@ -217,7 +250,14 @@ async fn add_edges_single_batch(
         .await?;
 
     let edge_count = edges.len();
-    add_edges_batch_increment_counters(&collection_id, &edge_type, &edges, direction_out).await?;
+    edges_batch_increment_counters(
+        &collection_id,
+        &edge_type,
+        &edges,
+        direction_out,
+        "item_count",
+    )
+    .await?;
 
     Ok(edge_count)
 }
@@ -291,12 +331,14 @@ async fn add_edges_get_page_assignments(
     Ok(page_assignments)
 }
 
-/// Increments the counters for the edges being added.
-async fn add_edges_batch_increment_counters(
+/// Increments the source counters for the edges being added or removed:
+/// `counter_column` is `item_count` for added edges, and `removed_count` for removed edges.
+async fn edges_batch_increment_counters(
     collection_id: &CollectionId,
     edge_type: &str,
     edges: &[(String, String)],
     direction_out: bool,
+    counter_column: &str,
 ) -> Result<(), anyhow::Error> {
     let session = ScyllaDatabaseHandle::collection_session(collection_id).await?;
 
@@ -311,12 +353,14 @@ async fn add_edges_batch_increment_counters(
     let q = format!(
         "
     UPDATE {}.graph_edge_pages_counter
-      SET item_count = item_count + ?
+      SET {} = {} + ?
       WHERE pk_source = ?
       AND edge_type = ?
       AND direction_out = ?;
       ",
-        collection_id.database_name()?.to_string()
+        collection_id.database_name()?.to_string(),
+        counter_column,
+        counter_column
     );
     // tracing::info!("add_edges: q: {}", q);
     let q = session.get_session().prepare(q).await?;
@@ -335,6 +379,258 @@ async fn add_edges_batch_increment_counters(
     Ok(())
 }
 
+/// Remove many edges from graph for a specific edge type, in both directions.
+/// Returns the number of edges removed or an error.
+pub(crate) async fn graph_remove_edges(
+    collection_id: CollectionId,
+    edge_type: GraphEdgeId,
+    edges: Vec<(String, String)>,
+) -> Result<usize, anyhow::Error> {
+    let edge_type = edge_type.0.to_string();
+    let mut futures = FuturesUnordered::new();
+    let mut count = 0;
+    for edge_chunk in edges.chunks(CQL_SELECT_BATCH_SIZE) {
+        futures.push(
+            remove_edges_single_batch(
+                collection_id.clone(),
+                edge_type.clone(),
+                edge_chunk.to_vec(),
+                true,
+            )
+            .boxed(),
+        );
+        let edge_chunk_rev = edge_chunk
+            .iter()
+            .map(|(a, b)| (b.clone(), a.clone()))
+            .collect();
+        // only count the OUT direction, so each edge is counted once
+        let remove_rev = remove_edges_single_batch(
+            collection_id.clone(),
+            edge_type.clone(),
+            edge_chunk_rev,
+            false,
+        )
+        .map(|r| r.map(|_| 0));
+        futures.push(remove_rev.boxed());
+        while futures.len() > CQL_PARALLEL_BATCHES {
+            let result = futures.next().await.unwrap();
+            count += result?;
+        }
+    }
+    while let Some(result) = futures.next().await {
+        count += result?;
+    }
+    Ok(count)
+}
+
+/// Removes multiple edges of the same type from the graph database.
+/// Edges that don't exist are skipped.
+/// The removed edges are counted in the `removed_count` counters of their sources;
+/// the `item_count` counters are not decremented, since they are used to assign pages for new edges.
+/// Returns the number of edges removed or an error.
+async fn remove_edges_single_batch(
+    collection_id: CollectionId,
+    edge_type: String,
+    edges: Vec<(String, String)>,
+    direction_out: bool,
+) -> Result<usize, anyhow::Error> {
+    if edges.is_empty() {
+        return Ok(0);
+    }
+
+    let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+    let mut existing_edges = find_graph_edge_page_assignment!(
+        "edge_pks IN ? AND edge_type = ? AND direction_out = ?",
+        (edges, edge_type.to_string(), direction_out)
+    )
+    .execute(&session)
+    .await?;
+
+    let mut edge_pages_rows = Vec::new();
+    let mut edge_page_assign_rows = Vec::new();
+    while let Some(edge) = existing_edges.next().await {
+        let edge = edge?;
+        edge_pages_rows.push(GraphEdgePageContent {
+            pk_source: edge.edge_pks.0.clone(),
+            edge_type: edge.edge_type.clone(),
+            direction_out,
+            page_id: edge.page_id,
+            pk_target: edge.edge_pks.1.clone(),
+        });
+        edge_page_assign_rows.push(edge);
+    }
+
+    GraphEdgePageContent::batch()
+        .chunked_delete(&session, &edge_pages_rows, 1024)
+        .await?;
+    GraphEdgePageAssignment::batch()
+        .chunked_delete(&session, &edge_page_assign_rows, 1024)
+        .await?;
+
+    if edge_page_assign_rows.is_empty() {
+        return Ok(0);
+    }
+    let removed_edges = edge_page_assign_rows
+        .iter()
+        .map(|edge| edge.edge_pks.clone())
+        .collect::<Vec<_>>();
+    edges_batch_increment_counters(
+        &collection_id,
+        &edge_type,
+        &removed_edges,
+        direction_out,
+        "removed_count",
+    )
+    .await?;
+    let pages = edge_page_assign_rows
+        .iter()
+        .map(|edge| (edge.edge_pks.0.clone(), edge.page_id))
+        .collect::<HashSet<_>>();
+    remove_empty_edge_pages(&collection_id, &edge_type, direction_out, pages).await?;
+
+    Ok(edge_page_assign_rows.len())
+}
+
+/// Removes the given pages from the page list, if they no longer contain any edges.
+/// The page that new edges are added to is always kept, so that an edge added
+/// at the same time is not left in a page missing from the list.
+/// Returns the number of pages removed.
+async fn remove_empty_edge_pages(
+    collection_id: &CollectionId,
+    edge_type: &str,
+    direction_out: bool,
+    pages: HashSet<(String, i32)>,
+) -> Result<usize, anyhow::Error> {
+    let session = ScyllaDatabaseHandle::collection_session(collection_id).await?;
+    let mut empty_pages = Vec::new();
+    for (source, page_id) in pages {
+        let Some(counter) = find_graph_edge_source_counter!(
+            "pk_source = ? AND edge_type = ? AND direction_out = ?",
+            (source.clone(), edge_type.to_string(), direction_out)
+        )
+        .execute(&session)
+        .await?
+        .try_next()
+        .await?
+        else {
+            continue;
+        };
+        let current_page_id = counter.item_count.0 / CQL_TARGET_PARTITION_SIZE as i64;
+        if page_id as i64 >= current_page_id {
+            continue;
+        }
+        let page_has_edges = find_graph_edge_page_content!(
+            "pk_source = ? AND edge_type = ? AND direction_out = ? AND page_id = ? LIMIT 1",
+            (
+                source.clone(),
+                edge_type.to_string(),
+                direction_out,
+                page_id
+            )
+        )
+        .execute(&session)
+        .await?
+        .try_next()
+        .await?
+        .is_some();
+        if !page_has_edges {
+            empty_pages.push(GraphEdgePageList {
+                pk_source: source,
+                edge_type: edge_type.to_string(),
+                direction_out,
+                page_id,
+            });
+        }
+    }
+    GraphEdgePageList::batch()
+        .chunked_delete(&session, &empty_pages, 1024)
+        .await?;
+    Ok(empty_pages.len())
+}
+
+impl GraphEdgeSourceCounter {
+    /// Number of edges currently stored for this source, edge type and direction.
+    pub fn edge_count(&self) -> i64 {
+        self.item_count.0 - self.removed_count.map_or(0, |removed| removed.0)
+    }
+}
+
+/// Remove all the edges of a type that start from a source node, given its primary key hash.
+/// Returns the number of edges removed.
+pub async fn graph_remove_source_edges(
+    collection_id: &CollectionId,
+    edge_type: GraphEdgeId,
+    source_pk_hash: &str,
+) -> Result<usize, anyhow::Error> {
+    let targets = list_edge_targets(
+        collection_id.clone(),
+        edge_type.clone(),
+        true,
+        source_pk_hash.to_string(),
+    )
+    .await?
+    .try_collect::<Vec<_>>()
+    .await?;
+    let edges = targets
+        .into_iter()
+        .map(|target| (source_pk_hash.to_string(), target))
+        .collect();
+    graph_remove_edges(collection_id.clone(), edge_type, edges).await
+}
+
+/// Remove all the stored edges of a node, of all edge types and in both directions,
+/// given its primary key hash. Implicit edges are not stored, so they are not affected.
+/// Returns the number of edges removed.
+pub async fn graph_remove_node_edges(
+    collection_id: &CollectionId,
+    pk_hash: &str,
+) -> Result<usize, anyhow::Error> {
+    let Some(table) = node_table(pk_hash) else {
+        anyhow::bail!("invalid graph node: {}", pk_hash);
+    };
+    let schema = get_graph_edges_types_from_inventory();
+    let mut count = 0;
+    for (direction_out, edges_by_table) in [
+        (true, &schema.edges_by_source),
+        (false, &schema.edges_by_target),
+    ] {
+        for edge in edges_by_table.get(&table).into_iter().flatten() {
+            if edge.edge_store_type != EdgeStoreImplementation::Stored {
+                continue;
+            }
+            let edge_type = GraphEdgeId(edge.edge_type.clone());
+            let neighbors = list_edge_targets(
+                collection_id.clone(),
+                edge_type.clone(),
+                direction_out,
+                pk_hash.to_string(),
+            )
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+            let edges = neighbors
+                .into_iter()
+                .map(|neighbor| match direction_out {
+                    true => (pk_hash.to_string(), neighbor),
+                    false => (neighbor, pk_hash.to_string()),
+                })
+                .collect();
+            count += graph_remove_edges(collection_id.clone(), edge_type, edges).await?;
+        }
+    }
+    Ok(count)
+}
+
+/// Remove all the edges of type `E` that start from a source node.
+/// Returns the number of edges removed.
+pub async fn edge_remove_from_source_pk<E: GraphEdge>(
+    collection_id: &CollectionId,
+    source: &<E::SourceType as BaseModel>::PrimaryKey,
+) -> Result<usize, anyhow::Error> {
+    let source_pk_hash = row_pk_hash::<E::SourceType>(source);
+    graph_remove_source_edges(collection_id, E::edge_type(), &source_pk_hash).await
+}
+
 /// Filters out edges that already exist in the database.
 /// Returns vector of edges that don't already exist in the database
 async fn skip_existing_edges(
@@ -382,7 +678,7 @@ mod tests {
     };
     use futures::{FutureExt, TryStreamExt};
     use hoover3_tracing::init_tracing;
-    use hoover3_types::identifier::CollectionId;
+    use hoover3_types::identifier::{CollectionId, DatabaseIdentifier};
 
     async fn create_test_collection(name: &str) -> Result<CollectionId, anyhow::Error> {
         init_tracing();
@@ -477,4 +773,79 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_remove_edges_basic() -> Result<(), anyhow::Error> {
+        let collection_id =
+            tokio::spawn(async move { create_test_collection("test_remove_edges_basic").await })
+                .await??;
+        let edge_type = GraphEdgeId(DatabaseIdentifier::new("links_to")?);
+        let edges = vec![
+            ("doc1".to_string(), "doc2".to_string()),
+            ("doc1".to_string(), "doc3".to_string()),
+        ];
+        let added =
+            graph_add_edges(collection_id.clone(), edge_type.clone(), edges.clone()).await?;
+        assert_eq!(added, 4);
+
+        // removing an edge that does not exist is a no-op
+        let removed = graph_remove_edges(
+            collection_id.clone(),
+            edge_type.clone(),
+            vec![edges[0].clone(), ("doc2".to_string(), "doc3".to_string())],
+        )
+        .await?;
+        assert_eq!(removed, 1);
+
+        let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+        for (edge, direction_out, exists) in [
+            (edges[0].clone(), true, false),
+            ((edges[0].1.clone(), edges[0].0.clone()), false, false),
+            (edges[1].clone(), true, true),
+            ((edges[1].1.clone(), edges[1].0.clone()), false, true),
+        ] {
+            let found =
+                skip_existing_edges(&collection_id, &edge_type.0, &[edge.clone()], direction_out)
+                    .await?;
+            assert_eq!(found.is_empty(), exists, "edge {:?}", edge);
+            let content = find_graph_edge_page_content!(
+                "pk_source = ? AND edge_type = ? AND direction_out = ? AND page_id = ?",
+                (edge.0.clone(), edge_type.0.to_string(), direction_out, 0)
+            )
+            .execute(&session)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+            assert_eq!(content.iter().any(|c| c.pk_target == edge.1), exists);
+        }
+
+        // the removed edge is counted on the source counter, which still assigns pages
+        let counter = find_graph_edge_source_counter!(
+            "pk_source = ? AND edge_type = ? AND direction_out = ?",
+            ("doc1", edge_type.0.to_string(), true)
+        )
+        .execute(&session)
+        .await?
+        .try_next()
+        .await?
+        .expect("Counter should exist");
+        assert_eq!(counter.item_count.0, 2);
+        assert_eq!(counter.edge_count(), 1);
+
+        // remove the remaining edges of the source
+        let removed = graph_remove_source_edges(&collection_id, edge_type.clone(), "doc1").await?;
+        assert_eq!(removed, 1);
+        for direction_out in [true, false] {
+            let edge = match direction_out {
+                true => edges[1].clone(),
+                false => (edges[1].1.clone(), edges[1].0.clone()),
+            };
+            let found =
+                skip_existing_edges(&collection_id, &edge_type.0, &[edge], direction_out).await?;
+            assert_eq!(found.len(), 1);
+        }
+
+        drop_collection(collection_id).await?;
+        Ok(())
+    }
 }
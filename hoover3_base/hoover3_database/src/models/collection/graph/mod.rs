@@ -6,3 +6,15 @@ pub use declare_edge::*;
 
 mod query_edge;
 pub use query_edge::*;
+
+mod chain;
+pub use chain::*;
+
+mod repair;
+pub use repair::*;
+
+mod export;
+pub use export::*;
+
+mod degree;
+pub use degree::*;
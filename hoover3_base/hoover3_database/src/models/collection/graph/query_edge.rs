@@ -116,7 +116,7 @@ fn chunk_stream<T: Send + 'static>(
 /// A stream of chunks containing target node primary key hashes as strings.
 /// These can be looked up in the database model [GraphNodePkMap] to get the primary fields
 /// of the target node.
-async fn list_edge_targets(
+pub(crate) async fn list_edge_targets(
     collection_id: CollectionId,
     edge_type: GraphEdgeId,
     direction_out: bool,
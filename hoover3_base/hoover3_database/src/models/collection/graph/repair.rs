@@ -0,0 +1,222 @@
+//! Graph consistency repair - removes the stored edges that point to rows that no longer exist.
+//!
+//! Rows can be deleted without removing their edges, so stored edges may be left dangling.
+//! The repair scans the edge page table one token range at a time, and removes the edges
+//! whose endpoints are missing from [GraphNodePkMap] or from their model table.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use charybdis::model::BaseModel;
+use charybdis::operations::Find;
+use futures::future::BoxFuture;
+use futures::{StreamExt, TryStreamExt};
+use hoover3_types::db_schema::{GraphEdgeId, GraphEdgeRepairResult};
+use hoover3_types::identifier::{CollectionId, DatabaseIdentifier};
+
+use super::chain::node_table;
+use super::insert_edge::graph_remove_edges;
+use crate::constants::CQL_SELECT_BATCH_SIZE;
+use crate::db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle};
+use crate::models::collection::{
+    find_graph_edge_page_content, find_graph_node_pk_map, GraphEdgePageContent,
+};
+
+/// Number of token ranges the edge table is split into for the repair.
+pub const GRAPH_REPAIR_SEGMENT_COUNT: u32 = 64;
+
+/// Number of edge rows checked together.
+const GRAPH_REPAIR_CHUNK_SIZE: usize = 1000;
+
+/// Number of model row lookups run in parallel.
+const GRAPH_REPAIR_ROW_LOOKUPS: usize = 16;
+
+/// Function finding a row in a collection, given its primary key as JSON.
+pub type GraphNodeFindRowFn =
+    fn(CollectionId, String) -> BoxFuture<'static, anyhow::Result<Option<serde_json::Value>>>;
+
+/// Static registration of a model table as a table of graph nodes - used for compile-time inventory.
+pub struct GraphNodeTableStatic {
+    /// The table name of the model.
+    pub table_name: &'static str,
+    /// Finds a row in a collection, given its primary key as JSON, and returns it as JSON.
+    pub find_row: GraphNodeFindRowFn,
+}
+
+inventory::collect!(GraphNodeTableStatic);
+
+/// Find a row of model `T`, given its primary key as JSON, and return it as JSON.
+pub fn graph_node_find_row<T>(
+    c: CollectionId,
+    pk_json: String,
+) -> BoxFuture<'static, anyhow::Result<Option<serde_json::Value>>>
+where
+    T: BaseModel + serde::Serialize + Send + Sync + 'static,
+    <T as BaseModel>::PrimaryKey: for<'a> serde::Deserialize<'a> + Send,
+{
+    Box::pin(async move {
+        let pk: <T as BaseModel>::PrimaryKey = serde_json::from_str(&pk_json)?;
+        let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+        let row = <T as Find>::maybe_find_first(T::FIND_BY_PRIMARY_KEY_QUERY, pk)
+            .execute(&session)
+            .await?;
+        Ok(row.map(|row| serde_json::to_value(row)).transpose()?)
+    })
+}
+
+/// Get the row lookup functions of the graph node tables, by table name.
+pub(crate) fn graph_node_find_row_fns() -> HashMap<&'static str, GraphNodeFindRowFn> {
+    inventory::iter::<GraphNodeTableStatic>
+        .into_iter()
+        .map(|table| (table.table_name, table.find_row))
+        .collect()
+}
+
+/// Get the inclusive range of partition tokens scanned by a repair segment.
+fn segment_token_range(segment: u32) -> (i64, i64) {
+    let width = (1_i128 << 64) / GRAPH_REPAIR_SEGMENT_COUNT as i128;
+    let start = i64::MIN as i128 + width * segment as i128;
+    let end = if segment + 1 >= GRAPH_REPAIR_SEGMENT_COUNT {
+        i64::MAX as i128
+    } else {
+        start + width - 1
+    };
+    (start as i64, end as i64)
+}
+
+/// Repair the stored edges in one token range of the edge table:
+/// remove the edges that have an endpoint missing from the node map or from its model table.
+/// Both directions of a dangling edge are removed, together with their page entries,
+/// and the source counters are updated. `progress` is called with the number of rows read.
+pub async fn graph_repair_edges_segment(
+    c: &CollectionId,
+    segment: u32,
+    progress: impl Fn(u64),
+) -> anyhow::Result<GraphEdgeRepairResult> {
+    if segment >= GRAPH_REPAIR_SEGMENT_COUNT {
+        anyhow::bail!("graph repair: invalid segment {}", segment);
+    }
+    let (token_start, token_end) = segment_token_range(segment);
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let mut rows = find_graph_edge_page_content!(
+        "token(pk_source, edge_type, direction_out, page_id) >= ?
+        AND token(pk_source, edge_type, direction_out, page_id) <= ?",
+        (token_start, token_end)
+    )
+    .execute(&session)
+    .await?;
+
+    let mut result = GraphEdgeRepairResult::default();
+    let mut node_exists = HashMap::new();
+    let mut chunk = Vec::with_capacity(GRAPH_REPAIR_CHUNK_SIZE);
+    loop {
+        let row = rows.try_next().await?;
+        let is_last = row.is_none();
+        chunk.extend(row);
+        if chunk.len() >= GRAPH_REPAIR_CHUNK_SIZE || (is_last && !chunk.is_empty()) {
+            result += repair_edges_chunk(c, &session, &mut node_exists, &chunk).await?;
+            chunk.clear();
+            progress(result.scanned_count);
+        }
+        if is_last {
+            break;
+        }
+    }
+    Ok(result)
+}
+
+/// Check a chunk of edge rows, and remove the dangling edges.
+/// `node_exists` caches the node checks between chunks.
+async fn repair_edges_chunk(
+    c: &CollectionId,
+    session: &ScyllaDatabaseHandle,
+    node_exists: &mut HashMap<String, bool>,
+    rows: &[GraphEdgePageContent],
+) -> anyhow::Result<GraphEdgeRepairResult> {
+    let unknown_nodes = rows
+        .iter()
+        .flat_map(|row| [&row.pk_source, &row.pk_target])
+        .filter(|pk_hash| !node_exists.contains_key(*pk_hash))
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    node_exists.extend(graph_nodes_exist(c, session, unknown_nodes).await?);
+
+    // both directions of an edge are removed together, so use the source-to-target order
+    let mut dangling_edges: BTreeMap<String, BTreeSet<(String, String)>> = BTreeMap::new();
+    for row in rows {
+        if node_exists[&row.pk_source] && node_exists[&row.pk_target] {
+            continue;
+        }
+        let edge = match row.direction_out {
+            true => (row.pk_source.clone(), row.pk_target.clone()),
+            false => (row.pk_target.clone(), row.pk_source.clone()),
+        };
+        dangling_edges
+            .entry(row.edge_type.clone())
+            .or_default()
+            .insert(edge);
+    }
+
+    let mut result = GraphEdgeRepairResult {
+        scanned_count: rows.len() as u64,
+        ..Default::default()
+    };
+    for (edge_type, edges) in dangling_edges {
+        result.dangling_count += edges.len() as u64;
+        let edge_type = GraphEdgeId(DatabaseIdentifier::new(&edge_type)?);
+        let removed = graph_remove_edges(c.clone(), edge_type, edges.into_iter().collect()).await?;
+        result.removed_count += removed as u64;
+    }
+    Ok(result)
+}
+
+/// Check if graph nodes exist, both in the node map and in their model table.
+/// Nodes of tables without a registered model are only checked in the node map.
+async fn graph_nodes_exist(
+    c: &CollectionId,
+    session: &ScyllaDatabaseHandle,
+    pk_hashes: BTreeSet<String>,
+) -> anyhow::Result<HashMap<String, bool>> {
+    let pk_hashes = pk_hashes.into_iter().collect::<Vec<_>>();
+    let mut pk_values = HashMap::new();
+    for pk_chunk in pk_hashes.chunks(CQL_SELECT_BATCH_SIZE) {
+        let pk_maps = find_graph_node_pk_map!("pk IN ?", (pk_chunk.to_vec(),))
+            .execute(session)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        pk_values.extend(pk_maps.into_iter().map(|pk_map| (pk_map.pk, pk_map.value)));
+    }
+
+    let find_row_fns = graph_node_find_row_fns();
+    let checks = pk_hashes.into_iter().map(|pk_hash| {
+        let pk_json = pk_values.remove(&pk_hash);
+        let find_row = node_table(&pk_hash)
+            .and_then(|table| find_row_fns.get(table.to_string().as_str()).copied());
+        let c = c.clone();
+        async move {
+            let exists = match (pk_json, find_row) {
+                (None, _) => false,
+                (Some(_), None) => true,
+                (Some(pk_json), Some(find_row)) => find_row(c, pk_json).await?.is_some(),
+            };
+            anyhow::Ok((pk_hash, exists))
+        }
+    });
+    futures::stream::iter(checks)
+        .buffer_unordered(GRAPH_REPAIR_ROW_LOOKUPS)
+        .try_collect()
+        .await
+}
+
+#[test]
+fn test_segment_token_ranges_cover_all_tokens() {
+    let ranges = (0..GRAPH_REPAIR_SEGMENT_COUNT)
+        .map(segment_token_range)
+        .collect::<Vec<_>>();
+    assert_eq!(ranges[0].0, i64::MIN);
+    assert_eq!(ranges[ranges.len() - 1].1, i64::MAX);
+    for pair in ranges.windows(2) {
+        assert!(pair[0].0 < pair[0].1);
+        assert_eq!(pair[0].1 + 1, pair[1].0);
+    }
+}
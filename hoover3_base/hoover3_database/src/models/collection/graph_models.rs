@@ -5,6 +5,7 @@ use charybdis::types::{Boolean, Counter, Frozen, Int, Text, Tuple};
 
 /// Tracks the number of edges across all pages for a primary key, edge type, and direction.
 /// Useful to know the total page count for this parameter combination.
+/// The number of edges currently stored is `item_count - removed_count`.
 #[charybdis_model(
     table_name = graph_edge_pages_counter,
     partition_keys = [pk_source],
@@ -18,8 +19,10 @@ pub struct GraphEdgeSourceCounter {
     pub edge_type: Text,
     /// Edge Direction - true = OUT, false = IN
     pub direction_out: Boolean,
-    /// Counter for the number of edges
+    /// Counter for the number of edges ever added - used to assign pages to new edges
     pub item_count: Counter,
+    /// Counter for the number of edges removed; null if none were removed
+    pub removed_count: Option<Counter>,
 }
 
 /// Edge data keyed by page - each partition contains a page of edges sorted by target_pk.
@@ -93,3 +96,20 @@ pub struct GraphNodePkMap {
     /// Value of the model primary key and clustering keys, as json
     pub value: Text,
 }
+
+/// Degree statistics of a stored edge type and direction, saved by the workflow that
+/// computes them, for reading without scanning the edge counters.
+#[charybdis_model(
+    table_name = graph_degree_statistics_cache,
+    partition_keys = [edge_type],
+    clustering_keys = [direction_out],
+)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphDegreeStatisticsCache {
+    /// The type of edge
+    pub edge_type: Text,
+    /// Edge Direction - true = OUT, false = IN
+    pub direction_out: Boolean,
+    /// The statistics, as JSON
+    pub statistics_json: Text,
+}
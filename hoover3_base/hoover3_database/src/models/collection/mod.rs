@@ -11,3 +11,12 @@ pub use graph::*;
 
 mod model_inventory;
 pub use model_inventory::*;
+
+mod search_index;
+pub use search_index::*;
+
+mod search_outbox_models;
+pub use search_outbox_models::*;
+
+mod search_outbox;
+pub use search_outbox::*;
@@ -202,6 +202,8 @@ pub struct GraphEdgeTypeStatic {
     pub target_type: &'static str,
     /// Implementation of the edge store
     pub edge_store_type: EdgeStoreImplementation,
+    /// Lists the neighbors of a node over this edge type - used for multi-hop traversal.
+    pub list_neighbors: super::GraphEdgeNeighborsFn,
 }
 
 impl From<&GraphEdgeTypeStatic> for GraphEdgeTypeDynamic {
@@ -0,0 +1,170 @@
+//! This module rebuilds the search index of a collection from the rows stored in Scylla.
+//! Each model registers a function that feeds all its rows to the index -
+//! see the `impl_model_callbacks` macro - so the index can be rebuilt without knowing the model types.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use charybdis::model::Model;
+use charybdis::operations::Find;
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+use hoover3_types::identifier::CollectionId;
+use meilisearch_sdk::indexes::Index;
+use tracing::info;
+
+use crate::db_management::{
+    meilisearch_wait_for_task, meilisearch_wait_for_task_timeout, search_index_include_table,
+    DatabaseSpaceManager, MeilisearchDatabaseHandle, ScyllaDatabaseHandle,
+};
+use crate::models::collection::DatabaseExtraCallbacks;
+
+/// Number of rows sent to the search index in one request.
+const SEARCH_INDEX_BATCH_SIZE: usize = 1000;
+
+/// Number of requests sent to the search index before waiting for the oldest one to finish,
+/// so a large table does not fill the Meilisearch task queue.
+const SEARCH_INDEX_MAX_PENDING_TASKS: usize = 16;
+
+/// Time to wait for one request to be indexed, including the time spent in the task queue.
+const SEARCH_INDEX_TASK_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Callback receiving the number of rows sent to the search index so far, after each request.
+pub type SearchIndexProgress = Arc<dyn Fn(u64) + Send + Sync>;
+
+/// Static registration of a model table in the search index - used for compile-time inventory.
+pub struct ModelSearchIndexStatic {
+    /// The table name of the model.
+    pub table_name: &'static str,
+    /// Sends all the rows of the table in a collection to the given search index,
+    /// waits for them to be indexed and returns their count.
+    pub index_all_rows: fn(
+        CollectionId,
+        Arc<Index>,
+        SearchIndexProgress,
+    ) -> BoxFuture<'static, anyhow::Result<u64>>,
+}
+
+inventory::collect!(ModelSearchIndexStatic);
+
+/// Send all the rows of a model table in a collection to the search index,
+/// and wait for them to be indexed. Returns the number of rows indexed.
+/// Does nothing for tables without stored search fields.
+pub fn search_index_all_rows<T>(
+    c: CollectionId,
+    index: Arc<Index>,
+    progress: SearchIndexProgress,
+) -> BoxFuture<'static, anyhow::Result<u64>>
+where
+    T: Model + serde::Serialize + Send + Sync + 'static,
+    <T as charybdis::model::BaseModel>::PrimaryKey: serde::Serialize,
+    <T as charybdis::model::BaseModel>::PrimaryKey: for<'a> serde::Deserialize<'a>,
+    <T as charybdis::model::BaseModel>::PrimaryKey: 'static + Send + Sync,
+{
+    Box::pin(async move {
+        if !search_index_include_table(T::DB_MODEL_NAME)? {
+            return Ok(0);
+        }
+        let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+        let db_extra = DatabaseExtraCallbacks {
+            collection_id: c.clone(),
+            search_index: index,
+        };
+        let mut rows = T::find_all().execute(&session).await?;
+
+        let mut count = 0_u64;
+        let mut tasks = VecDeque::new();
+        let mut batch = Vec::with_capacity(SEARCH_INDEX_BATCH_SIZE);
+        loop {
+            let row = rows.try_next().await?;
+            let is_last = row.is_none();
+            batch.extend(row);
+            if batch.len() >= SEARCH_INDEX_BATCH_SIZE || (is_last && !batch.is_empty()) {
+                tasks.extend(db_extra.add_search_documents(&batch).await?);
+                count += batch.len() as u64;
+                batch.clear();
+                progress(count);
+            }
+            if tasks.len() > SEARCH_INDEX_MAX_PENDING_TASKS {
+                if let Some(task) = tasks.pop_front() {
+                    meilisearch_wait_for_task_timeout(task, SEARCH_INDEX_TASK_TIMEOUT).await?;
+                }
+            }
+            if is_last {
+                break;
+            }
+        }
+        for task in tasks {
+            meilisearch_wait_for_task_timeout(task, SEARCH_INDEX_TASK_TIMEOUT).await?;
+        }
+        Ok(count)
+    })
+}
+
+/// List the names of the model tables that have rows in the search index, sorted.
+pub fn search_index_table_names() -> anyhow::Result<Vec<String>> {
+    let mut tables = vec![];
+    for table in inventory::iter::<ModelSearchIndexStatic> {
+        if search_index_include_table(table.table_name)? {
+            tables.push(table.table_name.to_string());
+        }
+    }
+    tables.sort();
+    Ok(tables)
+}
+
+/// Send all the rows of one model table in a collection to the given search index,
+/// and wait for them to be indexed. Returns the number of rows indexed.
+pub async fn index_table_rows(
+    c: &CollectionId,
+    table_name: &str,
+    index: Arc<Index>,
+    progress: SearchIndexProgress,
+) -> anyhow::Result<u64> {
+    let Some(table) = inventory::iter::<ModelSearchIndexStatic>
+        .into_iter()
+        .find(|t| t.table_name == table_name)
+    else {
+        anyhow::bail!("table {} has no search index registration", table_name);
+    };
+    (table.index_all_rows)(c.clone(), index, progress).await
+}
+
+/// Rebuild the search index of a collection: remove all the documents,
+/// then index again all the rows of all the model tables.
+/// Returns the number of rows indexed.
+pub async fn rebuild_search_index(c: &CollectionId) -> anyhow::Result<u64> {
+    info!("rebuild search index for collection {}", c);
+    let index = MeilisearchDatabaseHandle::collection_session(c).await?;
+    meilisearch_wait_for_task(index.delete_all_documents().await?).await?;
+
+    let mut count = 0;
+    for table in search_index_table_names()? {
+        let table_count = index_table_rows(c, &table, index.clone(), Arc::new(|_| {})).await?;
+        info!("search index {}: {} rows", table, table_count);
+        count += table_count;
+    }
+    Ok(count)
+}
+
+#[tokio::test]
+async fn test_search_index_table_names() -> anyhow::Result<()> {
+    let tables = search_index_table_names()?;
+    let mut sorted = tables.clone();
+    sorted.sort();
+    assert_eq!(tables, sorted);
+    for table in tables.iter() {
+        assert!(search_index_include_table(table)?);
+    }
+
+    let c = CollectionId::new("test_search_index_table_names")?;
+    let index = Arc::new(
+        MeilisearchDatabaseHandle::global_session()
+            .await?
+            .index("unused"),
+    );
+    let res = index_table_rows(&c, "no_such_table", index, Arc::new(|_| {})).await;
+    assert!(res.is_err());
+    Ok(())
+}
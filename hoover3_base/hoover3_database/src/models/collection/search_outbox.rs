@@ -0,0 +1,782 @@
+//! This module implements the search indexing outbox of a collection.
+//! Instead of sending documents to the search index in the write path, the changes are
+//! written to the [SearchIndexOutboxRow] table before the rows (see [search_outbox_insert_rows]),
+//! and the search indexer
+//! tasks send them to the search index later, in large batches. An entry is only removed
+//! once the search index task that applied it has succeeded, so a failed task is sent again
+//! instead of leaving a gap in the index.
+//!
+//! Processes that run the search indexer register a [SearchOutboxNotifyStatic], which is called
+//! after entries are queued, at most once every few seconds for each collection.
+//!
+//! While a fresh search reindex runs, the entries applied to the collection index are also kept
+//! in [SearchReindexReplayRow], and sent to the fresh index before it replaces the old one.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use charybdis::batch::ModelBatch;
+use charybdis::model::{BaseModel, Model};
+use charybdis::operations::{Find, Insert};
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+use hoover3_types::collection::{SearchIndexingLag, SearchOutboxDrainResult};
+use hoover3_types::identifier::CollectionId;
+use meilisearch_sdk::tasks::Task;
+use scylla::batch::Batch;
+use scylla::serialize::row::SerializeRow;
+use scylla::transport::{PagingState, PagingStateResponse};
+use tracing::{info, warn};
+
+use crate::db_management::redis::with_redis_lock;
+use crate::db_management::{
+    drop_fresh_search_index, finish_fresh_search_index_swap, get_reindex_target_search_index,
+    meilisearch_wait_for_task_timeout, search_index_document_count, search_index_include_table,
+    swap_fresh_search_index, DatabaseSpaceManager, MeilisearchDatabaseHandle, MeilisearchTaskUid,
+    ScyllaDatabaseHandle,
+};
+use crate::models::collection::{
+    get_search_index_json, row_pk_hash, SearchIndexOutboxRow, SearchReindexReplayRow,
+    SearchReindexState,
+};
+
+/// Number of partitions of the outbox table.
+const SEARCH_OUTBOX_SHARD_COUNT: i32 = 16;
+
+/// Maximum number of outbox entries handled in one drain round.
+const SEARCH_OUTBOX_ROUND_SIZE: usize = 10_000;
+
+/// Largest size of the batches written by [search_outbox_insert_rows], estimated from the JSON
+/// size of the rows; well under the Scylla batch size failure threshold, 1 MB by default.
+const SEARCH_OUTBOX_BATCH_BYTES: usize = 256 * 1024;
+
+/// Number of documents sent to the search index in one request.
+const SEARCH_OUTBOX_REQUEST_SIZE: usize = 2_000;
+
+/// Number of times an entry is sent to the search index before it is dropped,
+/// if its last request held only this entry.
+const SEARCH_OUTBOX_MAX_ATTEMPTS: i32 = 5;
+
+/// Time to wait for a search index task in a drain round; unfinished tasks are checked again
+/// in the next round.
+const SEARCH_OUTBOX_TASK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Minimum time between two notifications of the search indexer, for one collection.
+const SEARCH_OUTBOX_NOTIFY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time to wait for each search index task sending the replayed changes to a fresh index.
+const SEARCH_REINDEX_REPLAY_TASK_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Static registration of a search indexer - used for compile-time inventory.
+pub struct SearchOutboxNotifyStatic {
+    /// Called after entries are queued in the outbox of a collection,
+    /// to make sure the search indexer for that collection is running.
+    pub notify: fn(CollectionId) -> BoxFuture<'static, Result<()>>,
+}
+
+inventory::collect!(SearchOutboxNotifyStatic);
+
+/// Insert rows into their table, and queue their search index documents in the outbox
+/// of the collection. The outbox entries are written first, so a row is never saved without
+/// its entry; if the row write fails, the caller writes the rows and their entries again.
+/// Both writes use batches of at most `chunk_size` rows and about [SEARCH_OUTBOX_BATCH_BYTES].
+pub async fn search_outbox_insert_rows<T>(
+    c: &CollectionId,
+    data: &[T],
+    chunk_size: usize,
+) -> Result<()>
+where
+    T: Model + serde::Serialize + Send + Sync + 'static,
+    <T as BaseModel>::PrimaryKey: serde::Serialize,
+    <T as BaseModel>::PrimaryKey: for<'a> serde::Deserialize<'a>,
+    <T as BaseModel>::PrimaryKey: 'static + Send + Sync,
+{
+    if data.is_empty() {
+        return Ok(());
+    }
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let outbox_rows = outbox_document_rows(data)?;
+    insert_sized_batches(
+        &session,
+        SearchIndexOutboxRow::INSERT_QUERY,
+        &outbox_rows,
+        chunk_size,
+    )
+    .await?;
+    insert_sized_batches(&session, T::INSERT_QUERY, data, chunk_size).await?;
+    if !outbox_rows.is_empty() {
+        notify_search_indexer(c).await;
+    }
+    Ok(())
+}
+
+/// Insert rows with the given query, in batches of at most `chunk_size` rows, and of at most
+/// [SEARCH_OUTBOX_BATCH_BYTES] by their JSON size. A larger row is written in a batch of its own.
+async fn insert_sized_batches<T>(
+    session: &ScyllaDatabaseHandle,
+    query: &str,
+    rows: &[T],
+    chunk_size: usize,
+) -> Result<()>
+where
+    T: SerializeRow + serde::Serialize,
+{
+    let chunk_size = chunk_size.max(1);
+    let mut batch = Batch::default();
+    let mut values: Vec<&dyn SerializeRow> = vec![];
+    let mut batch_bytes = 0;
+    for row in rows {
+        let row_bytes = serde_json::to_vec(row)?.len();
+        if !values.is_empty()
+            && (values.len() >= chunk_size || batch_bytes + row_bytes > SEARCH_OUTBOX_BATCH_BYTES)
+        {
+            session.batch(&batch, &values).await?;
+            batch = Batch::default();
+            values.clear();
+            batch_bytes = 0;
+        }
+        batch.append_statement(query);
+        values.push(row);
+        batch_bytes += row_bytes;
+    }
+    if !values.is_empty() {
+        session.batch(&batch, &values).await?;
+    }
+    Ok(())
+}
+
+/// Queue the search index documents of a batch of rows in the outbox of the collection.
+/// Does nothing for tables without stored search fields.
+/// Prefer [search_outbox_insert_rows], which writes the rows and their entries together.
+pub async fn search_outbox_add_documents<T>(c: &CollectionId, data: &[T]) -> Result<()>
+where
+    T: BaseModel + serde::Serialize + Send + Sync + 'static,
+    <T as BaseModel>::PrimaryKey: serde::Serialize,
+    <T as BaseModel>::PrimaryKey: for<'a> serde::Deserialize<'a>,
+    <T as BaseModel>::PrimaryKey: 'static + Send + Sync,
+{
+    let rows = outbox_document_rows(data)?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+    queue_outbox_rows(c, &rows).await
+}
+
+/// Build the outbox entries adding the search index documents of the rows, one per row.
+/// Empty for tables without stored search fields.
+fn outbox_document_rows<T>(data: &[T]) -> Result<Vec<SearchIndexOutboxRow>>
+where
+    T: BaseModel + serde::Serialize + Send + Sync + 'static,
+    <T as BaseModel>::PrimaryKey: serde::Serialize,
+    <T as BaseModel>::PrimaryKey: for<'a> serde::Deserialize<'a>,
+    <T as BaseModel>::PrimaryKey: 'static + Send + Sync,
+{
+    if data.is_empty() || !search_index_include_table(T::DB_MODEL_NAME)? {
+        return Ok(vec![]);
+    }
+    let queued_at = chrono::Utc::now();
+    data.iter()
+        .map(|d| {
+            let doc_id = row_pk_hash::<T>(&d.primary_key_values());
+            anyhow::Ok(SearchIndexOutboxRow {
+                shard: outbox_shard(&doc_id),
+                doc_id,
+                queued_at,
+                table_name: T::DB_MODEL_NAME.to_string(),
+                document: Some(get_search_index_json(d)?.to_string()),
+                task_uid: None,
+                attempts: 0,
+            })
+        })
+        .collect()
+}
+
+/// Queue the removal of search index documents in the outbox of the collection.
+/// The document ids are the row primary key hashes - see [row_pk_hash].
+/// Does nothing for tables without stored search fields.
+pub async fn search_outbox_delete_documents(
+    c: &CollectionId,
+    table_name: &str,
+    doc_ids: Vec<String>,
+) -> Result<()> {
+    if doc_ids.is_empty() || !search_index_include_table(table_name)? {
+        return Ok(());
+    }
+    let queued_at = chrono::Utc::now();
+    let rows = doc_ids
+        .into_iter()
+        .map(|doc_id| SearchIndexOutboxRow {
+            shard: outbox_shard(&doc_id),
+            doc_id,
+            queued_at,
+            table_name: table_name.to_string(),
+            document: None,
+            task_uid: None,
+            attempts: 0,
+        })
+        .collect::<Vec<_>>();
+    queue_outbox_rows(c, &rows).await
+}
+
+async fn queue_outbox_rows(c: &CollectionId, rows: &[SearchIndexOutboxRow]) -> Result<()> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    SearchIndexOutboxRow::batch()
+        .chunked_insert(&session, rows, 1024)
+        .await?;
+    notify_search_indexer(c).await;
+    Ok(())
+}
+
+/// Call the registered search indexers, unless they were called recently for this collection.
+/// Errors are only logged: the entries are already saved, and are sent with the next notification.
+async fn notify_search_indexer(c: &CollectionId) {
+    static LAST_NOTIFIED: Mutex<BTreeMap<CollectionId, Instant>> = Mutex::new(BTreeMap::new());
+    {
+        let mut last_notified = LAST_NOTIFIED.lock().unwrap();
+        let now = Instant::now();
+        if last_notified
+            .get(c)
+            .is_some_and(|t| now.duration_since(*t) < SEARCH_OUTBOX_NOTIFY_INTERVAL)
+        {
+            return;
+        }
+        last_notified.insert(c.clone(), now);
+    }
+    for indexer in inventory::iter::<SearchOutboxNotifyStatic> {
+        if let Err(e) = (indexer.notify)(c.clone()).await {
+            warn!("cannot notify search indexer for collection {}: {:#}", c, e);
+        }
+    }
+}
+
+/// Pick the outbox partition of a document. All the changes of a document use the same one,
+/// so they are read together, in the order they were queued.
+fn outbox_shard(doc_id: &str) -> i32 {
+    let hash = doc_id
+        .bytes()
+        .fold(0_u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32));
+    (hash % SEARCH_OUTBOX_SHARD_COUNT as u32) as i32
+}
+
+/// Run one drain round on the outbox of a collection: send the new entries to the search index,
+/// check the tasks of the entries sent earlier, remove the entries whose task succeeded,
+/// and mark the ones whose task failed to be sent again. Rounds of a collection run one at a time.
+pub async fn drain_search_outbox_round(c: &CollectionId) -> Result<SearchOutboxDrainResult> {
+    let c = c.clone();
+    with_redis_lock(&search_outbox_lock_id(&c), async move {
+        _drain_search_outbox_round(&c).await
+    })
+    .await?
+}
+
+/// Name of the lock held by the drain rounds of a collection.
+fn search_outbox_lock_id(c: &CollectionId) -> String {
+    format!("search_outbox_{}", c)
+}
+
+async fn _drain_search_outbox_round(c: &CollectionId) -> Result<SearchOutboxDrainResult> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let rows = read_outbox_rows(&session).await?;
+    let mut result = SearchOutboxDrainResult {
+        read_count: rows.len() as u64,
+        ..Default::default()
+    };
+    if rows.is_empty() {
+        return Ok(result);
+    }
+
+    // rows are sorted by shard, document and time: the last entry of a document is its latest
+    // change. Older entries can be removed right away, since the latest change is sent after them.
+    let mut done = vec![];
+    let mut unsent = vec![];
+    let mut sent = BTreeMap::<i64, Vec<SearchIndexOutboxRow>>::new();
+    let mut rows = rows.into_iter().peekable();
+    while let Some(row) = rows.next() {
+        if rows
+            .peek()
+            .is_some_and(|next| next.shard == row.shard && next.doc_id == row.doc_id)
+        {
+            result.superseded_count += 1;
+            done.push(row);
+            continue;
+        }
+        match row.task_uid {
+            Some(task_uid) => sent.entry(task_uid).or_default().push(row),
+            None => unsent.push(row),
+        }
+    }
+    for (task_uid, rows) in send_outbox_rows(c, &session, unsent).await? {
+        sent.entry(task_uid).or_default().extend(rows);
+    }
+
+    // tasks finish in order, so once one is still running, the next ones are too
+    let client = MeilisearchDatabaseHandle::global_session().await?;
+    let mut retry = vec![];
+    let mut applied = vec![];
+    let mut timed_out = false;
+    for (task_uid, rows) in sent {
+        let status = if timed_out {
+            None
+        } else {
+            wait_for_outbox_task(&client, task_uid).await
+        };
+        match status {
+            Some(Ok(())) => {
+                result.indexed_count += rows.len() as u64;
+                applied.extend(rows.iter().cloned());
+                done.extend(rows);
+            }
+            Some(Err(error)) => {
+                warn!("search outbox {}: task {} failed: {}", c, task_uid, error);
+                // a failed request is split for the next attempts, see [outbox_request_size],
+                // so only the documents that also fail on their own are dropped
+                let sent_alone = rows.len() == 1;
+                for mut row in rows {
+                    if sent_alone && row.attempts >= SEARCH_OUTBOX_MAX_ATTEMPTS {
+                        warn!(
+                            "search outbox {}: dropping document {} after {} attempts",
+                            c, row.doc_id, row.attempts
+                        );
+                        result.dropped_count += 1;
+                        done.push(row);
+                    } else {
+                        result.retry_count += 1;
+                        row.task_uid = None;
+                        retry.push(row);
+                    }
+                }
+            }
+            None => {
+                timed_out = true;
+                result.pending_count += rows.len() as u64;
+            }
+        }
+    }
+
+    SearchIndexOutboxRow::batch()
+        .chunked_update(&session, &retry, 1024)
+        .await?;
+    copy_to_reindex_replay(&session, &applied).await?;
+    SearchIndexOutboxRow::batch()
+        .chunked_delete(&session, &done, 1024)
+        .await?;
+    info!("search outbox {}: {:?}", c, result);
+    Ok(result)
+}
+
+/// Copy the entries applied to the collection index into the replay table of each fresh reindex
+/// not swapped in yet. The reindexes are read after the entries: if a reindex started later,
+/// the entry was queued before it, and its row is read by the reindex itself.
+async fn copy_to_reindex_replay(
+    session: &ScyllaDatabaseHandle,
+    applied: &[SearchIndexOutboxRow],
+) -> Result<()> {
+    if applied.is_empty() {
+        return Ok(());
+    }
+    let reindexes = SearchReindexState::find_all()
+        .execute(session)
+        .await?
+        .try_filter(|state| std::future::ready(state.swap_task_uid.is_none()))
+        .try_collect::<Vec<_>>()
+        .await?;
+    for state in reindexes {
+        let rows = applied
+            .iter()
+            .map(|row| SearchReindexReplayRow {
+                reindex_id: state.reindex_id,
+                shard: row.shard,
+                doc_id: row.doc_id.clone(),
+                queued_at: row.queued_at,
+                table_name: row.table_name.clone(),
+                document: row.document.clone(),
+            })
+            .collect::<Vec<_>>();
+        SearchReindexReplayRow::batch()
+            .chunked_insert(session, &rows, 1024)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Start keeping the changes applied to the collection index for a fresh reindex,
+/// to replay them into its index before the swap - see [finish_fresh_search_reindex].
+/// Call before reading the rows to reindex.
+pub async fn begin_fresh_search_reindex(c: &CollectionId, reindex_id: i64) -> Result<()> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    SearchReindexState {
+        reindex_id,
+        document_count: None,
+        swap_task_uid: None,
+    }
+    .insert()
+    .execute(&session)
+    .await?;
+    Ok(())
+}
+
+/// Finish a fresh reindex: check the document count of its index against the indexed rows,
+/// send it the changes applied to the collection index since [begin_fresh_search_reindex],
+/// then swap it in. The steps done are saved, so this can be called again after a failure,
+/// also after the swap. Returns the document count of the collection index.
+pub async fn finish_fresh_search_reindex(
+    c: &CollectionId,
+    reindex_id: i64,
+    row_count: u64,
+) -> Result<u64> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let state = SearchReindexState::maybe_find_first_by_reindex_id(reindex_id)
+        .execute(&session)
+        .await?;
+    if let Some(mut state) = state {
+        if state.document_count.is_none() {
+            let index = get_reindex_target_search_index(c, Some(reindex_id)).await?;
+            let document_count = search_index_document_count(&index).await?;
+            if document_count != row_count {
+                anyhow::bail!(
+                    "reindex {}: fresh index has {} documents, {} rows were indexed; not swapping",
+                    c,
+                    document_count,
+                    row_count
+                );
+            }
+            state.document_count = Some(document_count as i64);
+            state.insert().execute(&session).await?;
+        }
+        let swap_task_uid = match state.swap_task_uid {
+            Some(swap_task_uid) => swap_task_uid,
+            None => {
+                // hold the drain lock, so no entry is applied to the old index between
+                // the replay and the swap; entries sent after the swap go to the new index
+                let c = c.clone();
+                with_redis_lock(&search_outbox_lock_id(&c), async move {
+                    replay_and_swap_fresh_index(&c, state).await
+                })
+                .await??
+            }
+        };
+        finish_fresh_search_index_swap(c, reindex_id, swap_task_uid).await?;
+        delete_fresh_search_reindex_state(&session, reindex_id).await?;
+    }
+    let index = MeilisearchDatabaseHandle::collection_session(c).await?;
+    search_index_document_count(&index).await
+}
+
+/// Give up a fresh reindex that failed before its index was swapped in: drop its index and
+/// the changes kept for it. Does nothing once the swap task was sent.
+pub async fn abandon_fresh_search_reindex(c: &CollectionId, reindex_id: i64) -> Result<()> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let state = SearchReindexState::maybe_find_first_by_reindex_id(reindex_id)
+        .execute(&session)
+        .await?;
+    if state.is_some_and(|state| state.swap_task_uid.is_some()) {
+        warn!(
+            "reindex {}: fresh index already swapped in, not dropping it",
+            c
+        );
+        return Ok(());
+    }
+    drop_fresh_search_index(c, reindex_id).await?;
+    delete_fresh_search_reindex_state(&session, reindex_id).await
+}
+
+/// Remove the changes kept for a fresh reindex, then its state.
+async fn delete_fresh_search_reindex_state(
+    session: &ScyllaDatabaseHandle,
+    reindex_id: i64,
+) -> Result<()> {
+    for shard in 0..SEARCH_OUTBOX_SHARD_COUNT {
+        SearchReindexReplayRow::delete_by_reindex_id_and_shard(reindex_id, shard)
+            .execute(session)
+            .await?;
+    }
+    SearchReindexState::delete_by_reindex_id(reindex_id)
+        .execute(session)
+        .await?;
+    Ok(())
+}
+
+/// Replay the changes kept for a fresh reindex into its index, then send the swap task
+/// and save its uid. Returns the swap task uid.
+async fn replay_and_swap_fresh_index(c: &CollectionId, state: SearchReindexState) -> Result<i64> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let replay_count = replay_fresh_search_reindex(c, &session, state.reindex_id).await?;
+    info!(
+        "reindex {}: {} changes replayed into the fresh index",
+        c, replay_count
+    );
+    let swap_task_uid = swap_fresh_search_index(c, state.reindex_id).await?;
+    SearchReindexState {
+        swap_task_uid: Some(swap_task_uid),
+        ..state
+    }
+    .insert()
+    .execute(&session)
+    .await?;
+    Ok(swap_task_uid)
+}
+
+/// Send the latest change of each document to the index of a fresh reindex: from the entries
+/// applied to the collection index since the reindex started, and from the entries still in
+/// the outbox, which may have been sent to the collection index already.
+/// Returns the number of documents sent.
+async fn replay_fresh_search_reindex(
+    c: &CollectionId,
+    session: &ScyllaDatabaseHandle,
+    reindex_id: i64,
+) -> Result<u64> {
+    type QueuedAt = chrono::DateTime<chrono::Utc>;
+    let mut latest = BTreeMap::<String, (QueuedAt, Option<String>)>::new();
+    let mut keep_latest = |doc_id: String, queued_at: QueuedAt, document: Option<String>| {
+        if latest
+            .get(&doc_id)
+            .is_none_or(|(latest_at, _)| *latest_at <= queued_at)
+        {
+            latest.insert(doc_id, (queued_at, document));
+        }
+    };
+    for shard in 0..SEARCH_OUTBOX_SHARD_COUNT {
+        let mut rows = SearchReindexReplayRow::find_by_reindex_id_and_shard(reindex_id, shard)
+            .execute(session)
+            .await?;
+        while let Some(row) = rows.try_next().await? {
+            keep_latest(row.doc_id, row.queued_at, row.document);
+        }
+        let mut rows = SearchIndexOutboxRow::find_by_shard(shard)
+            .execute(session)
+            .await?;
+        while let Some(row) = rows.try_next().await? {
+            keep_latest(row.doc_id, row.queued_at, row.document);
+        }
+    }
+
+    let index = get_reindex_target_search_index(c, Some(reindex_id)).await?;
+    let replay_count = latest.len() as u64;
+    let (additions, deletions): (Vec<_>, Vec<_>) = latest
+        .into_iter()
+        .partition(|(_, (_, document))| document.is_some());
+    let mut tasks = vec![];
+    for chunk in additions.chunks(SEARCH_OUTBOX_REQUEST_SIZE) {
+        let documents = chunk
+            .iter()
+            .filter_map(|(_, (_, document))| document.as_deref())
+            .map(serde_json::from_str::<serde_json::Value>)
+            .collect::<Result<Vec<_>, _>>()?;
+        tasks.push(index.add_documents(&documents, Some("id")).await?);
+    }
+    for chunk in deletions.chunks(SEARCH_OUTBOX_REQUEST_SIZE) {
+        let doc_ids = chunk.iter().map(|(doc_id, _)| doc_id).collect::<Vec<_>>();
+        tasks.push(index.delete_documents(&doc_ids).await?);
+    }
+    for task in tasks {
+        meilisearch_wait_for_task_timeout(task, SEARCH_REINDEX_REPLAY_TASK_TIMEOUT).await?;
+    }
+    Ok(replay_count)
+}
+
+/// Read the first entries of the outbox, up to the round size, sorted by shard, document and time.
+async fn read_outbox_rows(session: &ScyllaDatabaseHandle) -> Result<Vec<SearchIndexOutboxRow>> {
+    let mut rows = vec![];
+    for shard in 0..SEARCH_OUTBOX_SHARD_COUNT {
+        let mut stream = SearchIndexOutboxRow::find_by_shard(shard)
+            .execute(session)
+            .await?;
+        while rows.len() < SEARCH_OUTBOX_ROUND_SIZE {
+            let Some(row) = stream.try_next().await? else {
+                break;
+            };
+            rows.push(row);
+        }
+        if rows.len() >= SEARCH_OUTBOX_ROUND_SIZE {
+            break;
+        }
+    }
+    Ok(rows)
+}
+
+/// Send the entries to the search index, in batches, and save the uid of the task of each entry.
+/// Returns the entries grouped by task uid.
+async fn send_outbox_rows(
+    c: &CollectionId,
+    session: &ScyllaDatabaseHandle,
+    rows: Vec<SearchIndexOutboxRow>,
+) -> Result<BTreeMap<i64, Vec<SearchIndexOutboxRow>>> {
+    let index = MeilisearchDatabaseHandle::collection_session(c).await?;
+    let (additions, deletions): (Vec<_>, Vec<_>) =
+        rows.into_iter().partition(|row| row.document.is_some());
+
+    let mut sent = BTreeMap::new();
+    for (attempts, rows) in group_by_attempts(additions) {
+        for chunk in rows.chunks(outbox_request_size(attempts)) {
+            let documents = chunk
+                .iter()
+                .filter_map(|row| row.document.as_deref())
+                .map(serde_json::from_str::<serde_json::Value>)
+                .collect::<Result<Vec<_>, _>>()?;
+            let task = index.add_documents(&documents, Some("id")).await?;
+            sent.insert(task.task_uid as i64, chunk.to_vec());
+        }
+    }
+    for (attempts, rows) in group_by_attempts(deletions) {
+        for chunk in rows.chunks(outbox_request_size(attempts)) {
+            let doc_ids = chunk.iter().map(|row| &row.doc_id).collect::<Vec<_>>();
+            let task = index.delete_documents(&doc_ids).await?;
+            sent.insert(task.task_uid as i64, chunk.to_vec());
+        }
+    }
+    for (task_uid, rows) in sent.iter_mut() {
+        for row in rows.iter_mut() {
+            row.task_uid = Some(*task_uid);
+            row.attempts += 1;
+        }
+        SearchIndexOutboxRow::batch()
+            .chunked_update(session, rows.as_slice(), 1024)
+            .await?;
+    }
+    Ok(sent)
+}
+
+/// Group the entries by the number of times they were sent.
+fn group_by_attempts(rows: Vec<SearchIndexOutboxRow>) -> BTreeMap<i32, Vec<SearchIndexOutboxRow>> {
+    let mut groups = BTreeMap::<i32, Vec<_>>::new();
+    for row in rows {
+        groups.entry(row.attempts).or_default().push(row);
+    }
+    groups
+}
+
+/// Number of entries sent in one request, for entries already sent `attempts` times.
+/// Each failure splits the request 16 ways, so a document that can't be indexed is soon sent
+/// on its own, and the other documents of its first request are indexed without it.
+fn outbox_request_size(attempts: i32) -> usize {
+    (SEARCH_OUTBOX_REQUEST_SIZE >> (4 * attempts.clamp(0, 15) as usize)).max(1)
+}
+
+/// Wait for a search index task. Returns `None` if it is still running after the timeout,
+/// and an error message if it failed or can't be found.
+async fn wait_for_outbox_task(
+    client: &MeilisearchDatabaseHandle,
+    task_uid: i64,
+) -> Option<Result<(), String>> {
+    let res = client
+        .wait_for_task(
+            MeilisearchTaskUid(task_uid as u32),
+            Some(Duration::from_millis(500)),
+            Some(SEARCH_OUTBOX_TASK_TIMEOUT),
+        )
+        .await;
+    match res {
+        Ok(Task::Succeeded { .. }) => Some(Ok(())),
+        Ok(task) => Some(Err(format!("{:?}", task))),
+        Err(meilisearch_sdk::errors::Error::Timeout) => None,
+        Err(e) => Some(Err(e.to_string())),
+    }
+}
+
+/// Check if the outbox of a collection has entries, reading at most one entry of each shard.
+pub async fn search_outbox_has_entries(c: &CollectionId) -> Result<bool> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let query = scylla::query::Query::new(format!(
+        "SELECT doc_id FROM {}.{} WHERE shard = ? LIMIT 1",
+        c.database_name()?,
+        SearchIndexOutboxRow::DB_MODEL_NAME
+    ));
+    for shard in 0..SEARCH_OUTBOX_SHARD_COUNT {
+        let result = session.execute_unpaged(query.clone(), (shard,)).await?;
+        if result.into_rows_result()?.rows_num() > 0 {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// API Client method to get the search indexing lag of a collection,
+/// from the entries in its outbox.
+pub async fn query_search_indexing_lag(c: CollectionId) -> Result<SearchIndexingLag> {
+    let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+    let query = scylla::query::Query::new(format!(
+        "SELECT queued_at, task_uid FROM {}.{} WHERE shard = ?",
+        c.database_name()?,
+        SearchIndexOutboxRow::DB_MODEL_NAME
+    ))
+    .with_page_size(5000);
+
+    let mut queued_count = 0;
+    let mut submitted_count = 0;
+    let mut oldest_queued_at: Option<chrono::DateTime<chrono::Utc>> = None;
+    for shard in 0..SEARCH_OUTBOX_SHARD_COUNT {
+        let mut paging_state = PagingState::default();
+        loop {
+            let (result, paging_response) = session
+                .execute_single_page(query.clone(), (shard,), paging_state)
+                .await?;
+            for row in result
+                .into_rows_result()?
+                .rows::<(chrono::DateTime<chrono::Utc>, Option<i64>)>()?
+            {
+                let (queued_at, task_uid) = row?;
+                queued_count += 1;
+                if task_uid.is_some() {
+                    submitted_count += 1;
+                }
+                oldest_queued_at =
+                    Some(oldest_queued_at.map_or(queued_at, |oldest| oldest.min(queued_at)));
+            }
+            match paging_response {
+                PagingStateResponse::HasMorePages { state } => paging_state = state,
+                PagingStateResponse::NoMorePages => break,
+            }
+        }
+    }
+    let lag_seconds = oldest_queued_at
+        .map(|oldest| (chrono::Utc::now() - oldest).num_seconds().max(0) as u64)
+        .unwrap_or(0);
+    Ok(SearchIndexingLag {
+        collection_id: c,
+        queued_count,
+        submitted_count,
+        oldest_queued_at,
+        lag_seconds,
+    })
+}
+
+#[test]
+fn test_outbox_shard() {
+    for doc_id in ["a", "table_0123456789ABCDEF", ""] {
+        let shard = outbox_shard(doc_id);
+        assert!((0..SEARCH_OUTBOX_SHARD_COUNT).contains(&shard));
+        assert_eq!(shard, outbox_shard(doc_id));
+    }
+}
+
+#[test]
+fn test_outbox_request_size() {
+    assert_eq!(outbox_request_size(0), SEARCH_OUTBOX_REQUEST_SIZE);
+    for attempts in 1..=SEARCH_OUTBOX_MAX_ATTEMPTS {
+        assert!(outbox_request_size(attempts) < outbox_request_size(attempts - 1).max(2));
+    }
+    assert_eq!(outbox_request_size(SEARCH_OUTBOX_MAX_ATTEMPTS - 1), 1);
+}
+
+#[tokio::test]
+async fn test_search_indexing_lag_empty_outbox() -> Result<()> {
+    use crate::client_query::collections::{create_new_collection, drop_collection};
+    use crate::migrate::migrate_common;
+
+    migrate_common().await?;
+    let c = CollectionId::new("test_search_indexing_lag_empty_outbox")?;
+    drop_collection(c.clone()).await?;
+    create_new_collection(c.clone()).await?;
+
+    let lag = query_search_indexing_lag(c.clone()).await?;
+    assert_eq!(lag.queued_count, 0);
+    assert_eq!(lag.oldest_queued_at, None);
+    assert_eq!(lag.lag_seconds, 0);
+    let round = drain_search_outbox_round(&c).await?;
+    assert_eq!(round, SearchOutboxDrainResult::default());
+
+    drop_collection(c).await?;
+    Ok(())
+}
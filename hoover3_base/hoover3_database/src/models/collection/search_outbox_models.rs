@@ -0,0 +1,72 @@
+//! This module contains the table definition for the search indexing outbox.
+
+use charybdis::macros::charybdis_model;
+use charybdis::types::{BigInt, Int, Text, Timestamp};
+
+/// A change to a search index document, waiting to be sent to the search index.
+/// Written together with the Scylla rows, and removed by the search indexer
+/// once the search index task that applied it has succeeded.
+/// All the changes of a document are in the same shard, sorted by the time they were queued.
+#[charybdis_model(
+    table_name = search_index_outbox,
+    partition_keys = [shard],
+    clustering_keys = [doc_id, queued_at],
+)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SearchIndexOutboxRow {
+    /// Shard number, computed from the document id
+    pub shard: Int,
+    /// Search index document id - see `row_pk_hash`
+    pub doc_id: Text,
+    /// Time the change was queued
+    pub queued_at: Timestamp,
+    /// Table of the row the document was made from
+    pub table_name: Text,
+    /// The document, as JSON, or `None` if the document is deleted
+    pub document: Option<Text>,
+    /// Uid of the search index task applying this change, once sent
+    pub task_uid: Option<BigInt>,
+    /// Number of times the change was sent to the search index
+    pub attempts: Int,
+}
+
+/// A fresh search reindex of the collection that is not finished yet.
+/// While it exists, the search indexer copies the entries it applies to the collection index
+/// into [SearchReindexReplayRow], so they are sent to the fresh index before the swap.
+#[charybdis_model(
+    table_name = search_reindex_state,
+    partition_keys = [reindex_id],
+    clustering_keys = [],
+)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SearchReindexState {
+    /// Id of the reindex request - its request time, in milliseconds
+    pub reindex_id: BigInt,
+    /// Document count of the fresh index, once checked against the indexed rows
+    pub document_count: Option<BigInt>,
+    /// Uid of the search index task swapping the fresh index in, once sent
+    pub swap_task_uid: Option<BigInt>,
+}
+
+/// An outbox entry applied to the collection index while a fresh reindex was running,
+/// kept to be sent to the fresh index before it is swapped in.
+#[charybdis_model(
+    table_name = search_reindex_replay,
+    partition_keys = [reindex_id, shard],
+    clustering_keys = [doc_id, queued_at],
+)]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SearchReindexReplayRow {
+    /// Id of the reindex request - see [SearchReindexState]
+    pub reindex_id: BigInt,
+    /// Shard number of the outbox entry
+    pub shard: Int,
+    /// Search index document id
+    pub doc_id: Text,
+    /// Time the change was queued in the outbox
+    pub queued_at: Timestamp,
+    /// Table of the row the document was made from
+    pub table_name: Text,
+    /// The document, as JSON, or `None` if the document is deleted
+    pub document: Option<Text>,
+}
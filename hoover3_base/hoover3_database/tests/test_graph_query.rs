@@ -187,3 +187,363 @@ async fn test_graph_query() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_graph_traversal() -> Result<(), anyhow::Error> {
+    use futures::TryStreamExt;
+    use hoover3_types::db_schema::{
+        GraphTraversalDirection, GraphTraversalHop, GraphTraversalLimits, GraphTraversalQuery,
+    };
+
+    let c = create_test_collection("test_graph_traversal").await?;
+
+    let mut test_model_a = TestModelA {
+        id_a: "test_a".to_string(),
+    };
+    let mut test_model_b = TestModelB {
+        id_b: "test_b".to_string(),
+    };
+    let mut test_model_child_b = TestModelChildB {
+        id_b: "test_b".to_string(),
+        id_cluster: "test_cluster".to_string(),
+    };
+    let mut test_model_grandchild_b = TestModelGrandchildB {
+        id_b: "test_b".to_string(),
+        id_cluster: "test_cluster".to_string(),
+        id_cluster2: "test_cluster2".to_string(),
+        normal: "test_normal".to_string(),
+    };
+
+    let cb = DatabaseExtraCallbacks::new(&c).await?;
+    let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+    TestModelA::insert_cb(&mut test_model_a, &cb)
+        .execute(&session)
+        .await?;
+    TestModelB::insert_cb(&mut test_model_b, &cb)
+        .execute(&session)
+        .await?;
+    TestModelChildB::insert_cb(&mut test_model_child_b, &cb)
+        .execute(&session)
+        .await?;
+    TestModelGrandchildB::insert_cb(&mut test_model_grandchild_b, &cb)
+        .execute(&session)
+        .await?;
+
+    let mut edges = TestModelEdge::edge_batch(&c);
+    edges.add_edge(&test_model_a, &test_model_b);
+    edges.execute().await?;
+    // makes a cycle: B -> ChildB -> B
+    let mut edges = TestModelEdge2::edge_batch(&c);
+    edges.add_edge(&test_model_child_b, &test_model_b);
+    edges.execute().await?;
+
+    // typed route: A -> B -> ChildB -> GrandchildB, mixing stored and implicit edges
+    let route = GraphPathQuery::<TestModelA>::new()
+        .forward(TestModelEdge)
+        .forward(TestImplicitEdge)
+        .forward(TestImplicitEdge2);
+    let found = route
+        .end_nodes(
+            &c,
+            &test_model_a.primary_key_values(),
+            GraphTraversalLimits::default(),
+        )
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(found.len(), 1);
+    let (path, end) = &found[0];
+    assert_eq!(end, &test_model_grandchild_b);
+    assert_eq!(
+        path.nodes,
+        vec![
+            test_model_a.row_pk_hash(),
+            test_model_b.row_pk_hash(),
+            test_model_child_b.row_pk_hash(),
+            test_model_grandchild_b.row_pk_hash(),
+        ]
+    );
+
+    // typed route going back over an implicit edge, then a stored edge
+    let route = GraphPathQuery::<TestModelGrandchildB>::new()
+        .backward(TestImplicitEdge2)
+        .forward(TestModelEdge2)
+        .backward(TestModelEdge);
+    let found = route
+        .end_nodes(
+            &c,
+            &test_model_grandchild_b.primary_key_values(),
+            GraphTraversalLimits::default(),
+        )
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].1, test_model_a);
+
+    // dynamic query over all outgoing edges: the cycle back to B is not followed
+    let query = GraphTraversalQuery {
+        start_pk_hash: test_model_b.row_pk_hash(),
+        hops: vec![GraphTraversalHop {
+            edge_types: vec![],
+            direction: GraphTraversalDirection::Out,
+        }],
+        max_depth: 5,
+        min_depth: 1,
+        limits: GraphTraversalLimits::default(),
+    };
+    let paths = graph_traverse(&c, query.clone())
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(paths.len(), 2);
+    assert_eq!(paths[0].nodes.len(), 2);
+    assert_eq!(paths[1].nodes.len(), 3);
+    assert_eq!(
+        paths[1].nodes.last(),
+        Some(&test_model_grandchild_b.row_pk_hash())
+    );
+
+    // the depth and the limits are capped on the server
+    let capped_query = GraphTraversalQuery {
+        max_depth: u32::MAX,
+        limits: GraphTraversalLimits {
+            max_fan_out: u32::MAX,
+            max_paths: u32::MAX,
+            ..GraphTraversalLimits::default()
+        },
+        ..query.clone()
+    };
+    let capped_paths = graph_traverse(&c, capped_query)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(capped_paths, paths);
+
+    // a min depth over the cap is an error, not an empty result
+    let too_deep_query = GraphTraversalQuery {
+        min_depth: GRAPH_TRAVERSAL_MAX_DEPTH + 1,
+        max_depth: GRAPH_TRAVERSAL_MAX_DEPTH + 4,
+        ..query.clone()
+    };
+    assert!(graph_traverse(&c, too_deep_query).await.is_err());
+
+    // both directions, limited to one path
+    let query = GraphTraversalQuery {
+        hops: vec![GraphTraversalHop {
+            edge_types: vec![],
+            direction: GraphTraversalDirection::Both,
+        }],
+        limits: GraphTraversalLimits {
+            max_paths: 1,
+            ..GraphTraversalLimits::default()
+        },
+        ..query
+    };
+    let paths = graph_traverse(&c, query)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(paths.len(), 1);
+
+    drop_collection(c).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_graph_repair() -> Result<(), anyhow::Error> {
+    use charybdis::operations::Delete;
+    use futures::TryStreamExt;
+    use hoover3_types::db_schema::GraphEdgeRepairResult;
+
+    let c = create_test_collection("test_graph_repair").await?;
+
+    let mut test_model_a = TestModelA {
+        id_a: "test_a".to_string(),
+    };
+    let mut test_model_b = TestModelB {
+        id_b: "test_b".to_string(),
+    };
+    let mut deleted_model_b = TestModelB {
+        id_b: "test_b_deleted".to_string(),
+    };
+
+    let cb = DatabaseExtraCallbacks::new(&c).await?;
+    let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+    TestModelA::insert_cb(&mut test_model_a, &cb)
+        .execute(&session)
+        .await?;
+    TestModelB::insert_cb(&mut test_model_b, &cb)
+        .execute(&session)
+        .await?;
+    TestModelB::insert_cb(&mut deleted_model_b, &cb)
+        .execute(&session)
+        .await?;
+    // the row is gone, but it is still in the node map
+    deleted_model_b.delete().execute(&session).await?;
+
+    let mut edges = TestModelEdge::edge_batch(&c);
+    edges.add_edge(&test_model_a, &test_model_b);
+    edges.add_edge(&test_model_a, &deleted_model_b);
+    // never inserted, so not in the node map
+    edges.add_edge_from_pk(
+        &test_model_a.primary_key_values(),
+        &("test_b_missing".to_string(),),
+    );
+    edges.execute().await?;
+
+    let mut result = GraphEdgeRepairResult::default();
+    for segment in 0..GRAPH_REPAIR_SEGMENT_COUNT {
+        result += graph_repair_edges_segment(&c, segment, |_| {}).await?;
+    }
+    assert_eq!(result.removed_count, 2);
+    assert!(result.dangling_count >= 2);
+
+    let targets = edge_list_targets_pk::<TestModelEdge>(&c, &test_model_a.primary_key_values())
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(targets, vec![test_model_b.primary_key_values()]);
+
+    // a second repair finds nothing to remove
+    let mut result = GraphEdgeRepairResult::default();
+    for segment in 0..GRAPH_REPAIR_SEGMENT_COUNT {
+        result += graph_repair_edges_segment(&c, segment, |_| {}).await?;
+    }
+    assert_eq!(result.dangling_count, 0);
+    assert_eq!(result.scanned_count, 2);
+
+    // remove the edges of a node, in both directions
+    let removed = graph_remove_node_edges(&c, &test_model_b.row_pk_hash()).await?;
+    assert_eq!(removed, 1);
+    let sources = edge_list_source_pk::<TestModelEdge>(&c, &test_model_b.primary_key_values())
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert!(sources.is_empty());
+
+    drop_collection(c).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_graph_export() -> Result<(), anyhow::Error> {
+    use hoover3_types::db_schema::{GraphExportFormat, GraphExportRequest};
+
+    let c = create_test_collection("test_graph_export").await?;
+
+    let mut test_model_a = TestModelA {
+        id_a: "test_a".to_string(),
+    };
+    let mut test_model_b = TestModelB {
+        id_b: "test_b".to_string(),
+    };
+    let cb = DatabaseExtraCallbacks::new(&c).await?;
+    let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+    TestModelA::insert_cb(&mut test_model_a, &cb)
+        .execute(&session)
+        .await?;
+    TestModelB::insert_cb(&mut test_model_b, &cb)
+        .execute(&session)
+        .await?;
+    let mut edges = TestModelEdge::edge_batch(&c);
+    edges.add_edge(&test_model_a, &test_model_b);
+    edges.execute().await?;
+
+    let path = std::env::temp_dir().join("test_graph_export.json");
+    let mut request = GraphExportRequest {
+        format: GraphExportFormat::JsonGraph,
+        edge_types: vec![TestModelEdge::edge_type()],
+        nodes: vec![],
+        include_neighbors: false,
+    };
+    let (node_count, edge_count) = graph_export_to_file(&c, &request, &path, |_| {}).await?;
+    assert_eq!((node_count, edge_count), (2, 1));
+    let graph: serde_json::Value = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+    let edge = &graph["graph"]["edges"][0];
+    assert_eq!(edge["source"], test_model_a.row_pk_hash());
+    assert_eq!(edge["target"], test_model_b.row_pk_hash());
+    assert_eq!(edge["relation"], "test_model_edge");
+
+    // a node subset keeps only the edges inside it, unless the neighbors are included
+    request.nodes = vec![test_model_a.row_pk_hash()];
+    let counts = graph_export_to_file(&c, &request, &path, |_| {}).await?;
+    assert_eq!(counts, (1, 0));
+    request.include_neighbors = true;
+    let counts = graph_export_to_file(&c, &request, &path, |_| {}).await?;
+    assert_eq!(counts, (2, 1));
+
+    request.format = GraphExportFormat::GraphMl;
+    graph_export_to_file(&c, &request, &path, |_| {}).await?;
+    let graphml = tokio::fs::read_to_string(&path).await?;
+    assert_eq!(graphml.matches("<edge ").count(), 1);
+
+    tokio::fs::remove_file(&path).await?;
+    drop_collection(c).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_graph_degree_statistics() -> Result<(), anyhow::Error> {
+    use hoover3_types::db_schema::GraphNodeEdgeCount;
+
+    let c = create_test_collection("test_graph_degree_statistics").await?;
+
+    let mut models_a = ["test_a1", "test_a2"].map(|id| TestModelA {
+        id_a: id.to_string(),
+    });
+    let mut models_b = ["test_b1", "test_b2"].map(|id| TestModelB {
+        id_b: id.to_string(),
+    });
+    let cb = DatabaseExtraCallbacks::new(&c).await?;
+    let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+    for model in models_a.iter_mut() {
+        TestModelA::insert_cb(model, &cb).execute(&session).await?;
+    }
+    for model in models_b.iter_mut() {
+        TestModelB::insert_cb(model, &cb).execute(&session).await?;
+    }
+    let mut edges = TestModelEdge::edge_batch(&c);
+    edges.add_edge(&models_a[0], &models_b[0]);
+    edges.add_edge(&models_a[0], &models_b[1]);
+    edges.add_edge(&models_a[1], &models_b[0]);
+    edges.execute().await?;
+
+    let counts = graph_node_edge_counts(&c, &models_a[0].row_pk_hash()).await?;
+    assert_eq!(
+        counts,
+        vec![GraphNodeEdgeCount {
+            edge_type: TestModelEdge::edge_type(),
+            out_count: 2,
+            in_count: 0,
+        }]
+    );
+    let counts = graph_node_edge_counts(&c, &models_b[0].row_pk_hash()).await?;
+    assert_eq!((counts[0].out_count, counts[0].in_count), (0, 2));
+
+    // nothing is saved until the statistics are computed
+    assert!(graph_degree_statistics(&c, &[], 1).await?.is_empty());
+    let computed = compute_graph_degree_statistics(&c, |_| {}).await?;
+    assert_eq!(computed.len(), 2);
+    let statistics = graph_degree_statistics(&c, &[TestModelEdge::edge_type()], 1).await?;
+    assert_eq!(statistics.len(), 2);
+    for stats in statistics.iter() {
+        assert_eq!(stats.node_count, 2);
+        assert_eq!(stats.edge_count, 3);
+        assert_eq!(stats.max_degree, 2);
+        assert_eq!(stats.top_nodes.len(), 1);
+        assert_eq!(stats.top_nodes[0].degree, 2);
+        let top_node = match stats.direction_out {
+            true => models_a[0].row_pk_hash(),
+            false => models_b[0].row_pk_hash(),
+        };
+        assert_eq!(stats.top_nodes[0].pk_hash, top_node);
+    }
+
+    drop_collection(c).await?;
+
+    Ok(())
+}
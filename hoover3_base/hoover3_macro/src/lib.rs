@@ -16,6 +16,16 @@
 //! }
 //! ```
 //!
+//! Retry, timeout and heartbeat settings can follow the queue name;
+//! the defaults are one retry, a 10 minute timeout and no heartbeat:
+//!
+//! ```rust
+//! #[activity("my_queue", retries = 5, backoff = 2.0, timeout = 3600, heartbeat = 60)]
+//! async fn my_activity(input: MyInput) -> anyhow::Result<MyOutput> {
+//!     // Errors wrapped with `hoover3_taskdef::non_retryable` are not retried.
+//! }
+//! ```
+//!
 //! ### Workflows
 //!
 //! Workflows orchestrate activities and must be asynchronous:
@@ -40,7 +50,9 @@
 
 use proc_macro::TokenStream;
 
-/// Attribute macro for defining activities. Its only argument is the queue name.
+/// Attribute macro for defining activities. The first argument is the queue name,
+/// followed by optional `name = value` settings: `retries`, `retry_interval`, `backoff`,
+/// `max_retry_interval`, `timeout` and `heartbeat` (durations in seconds).
 #[proc_macro_attribute]
 pub fn activity(_attr: TokenStream, item: TokenStream) -> TokenStream {
     hoover3_macro2::activity(_attr.into(), item.into()).into()
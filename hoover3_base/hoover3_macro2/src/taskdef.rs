@@ -60,7 +60,7 @@ fn extract_activity_return_type(f: &ItemFn) -> Box<syn::Type> {
 /// it generates this:
 /// `::hoover3_taskdef::make_activity!(foo, T, V); async fn foo(x: T) -> Result<V> { ... }`
 pub fn activity(attrs: TokenStream, item: TokenStream) -> TokenStream {
-    let queue_name = syn::parse2::<syn::Expr>(attrs).expect("parse activity queue name");
+    let (queue_name, options) = parse_activity_attrs(attrs);
     let f = syn::parse2::<syn::ItemFn>(item).expect("parse activity function");
 
     let macro_arg_type = extract_activity_arg_type(&f);
@@ -92,12 +92,52 @@ pub fn activity(attrs: TokenStream, item: TokenStream) -> TokenStream {
 
     let result = quote! {
         #f_doc #f_vis #f_async fn #f_name(#f_args) #f_out #f_body
-        #macro_name!(#queue_name, #f_name, #macro_arg_type, #macro_ret_type);
+        #macro_name!(#queue_name, #f_name, #macro_arg_type, #macro_ret_type #(, #options)*);
     };
 
     result
 }
 
+/// Parse the `#[activity]` arguments: the queue, then optional settings like
+/// `retries = 5, timeout = 3600, heartbeat = 60`, passed on to `ActivityRunOptions`.
+fn parse_activity_attrs(attrs: TokenStream) -> (syn::Expr, Vec<syn::ExprAssign>) {
+    use syn::parse::Parser;
+    let args = syn::punctuated::Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated
+        .parse2(attrs)
+        .expect("parse activity arguments");
+    let mut args = args.into_iter();
+    let queue_name = args.next().expect("missing activity queue name");
+    let options = args
+        .map(|arg| {
+            let syn::Expr::Assign(option) = arg else {
+                panic!("activity options must look like `name = value`");
+            };
+            let syn::Expr::Path(name) = option.left.as_ref() else {
+                panic!("activity option name must be an identifier");
+            };
+            let name = name.path.get_ident().expect("activity option name");
+            assert!(
+                ACTIVITY_OPTIONS.contains(&name.to_string().as_str()),
+                "unknown activity option `{}`, expected one of {:?}",
+                name,
+                ACTIVITY_OPTIONS
+            );
+            option
+        })
+        .collect();
+    (queue_name, options)
+}
+
+/// Settings accepted by `#[activity]`, same as the `ActivityRunOptions` builder methods.
+const ACTIVITY_OPTIONS: &[&str] = &[
+    "retries",
+    "retry_interval",
+    "backoff",
+    "max_retry_interval",
+    "timeout",
+    "heartbeat",
+];
+
 /// Get workflow function argument type `T`
 /// from a function declared like this:
 /// `#[workflow] async fn foo(ctx: WfContext, x: T) -> anyhow::Result<V> { ... }`
@@ -229,6 +269,31 @@ fn test_activity_async_with_tuple() {
     assert_eq!(format!("{}", act), "# [doc = r\" Doc\"] pub async fn foo ((x , y) : (u64 , u64)) -> Result < u64 > { x + 1 } :: hoover3_taskdef :: make_activity ! (\"task_queue\" , foo , (u64 , u64) , u64) ;");
 }
 
+#[test]
+fn test_activity_with_options() {
+    let item = quote! {
+        /// Doc
+        async fn foo(x: u64) -> Result<u64> {
+            x + 1
+        }
+    };
+    let args = quote! { "task_queue", retries = 5, timeout = 2 * 3600 };
+    let act = activity(args, item);
+    assert_eq!(format!("{}", act), "# [doc = r\" Doc\"] async fn foo (x : u64) -> Result < u64 > { x + 1 } :: hoover3_taskdef :: make_activity ! (\"task_queue\" , foo , u64 , u64 , retries = 5 , timeout = 2 * 3600) ;");
+}
+
+#[test]
+#[should_panic(expected = "unknown activity option")]
+fn test_activity_with_unknown_option() {
+    let item = quote! {
+        /// Doc
+        async fn foo(x: u64) -> Result<u64> {
+            x + 1
+        }
+    };
+    activity(quote! { "task_queue", retry = 5 }, item);
+}
+
 #[test]
 fn test_workflow() {
     let item = quote! {
@@ -0,0 +1,166 @@
+//! Workflow control - cancel, terminate, pause and resume a workflow together with its child workflow tree.
+//!
+//! Cancel and pause are sent to every running workflow in the tree, not just the root,
+//! so the children stop at the same time as the parent instead of running to completion.
+
+use anyhow::Context;
+use hoover3_types::tasks::UiWorkflowStatusCode;
+use temporal_client::{WorkflowClientTrait, WorkflowService};
+use temporal_sdk_core_protos::coresdk::AsJsonPayloadExt;
+use temporal_sdk_core_protos::temporal::api::common::v1::Payloads;
+use temporal_sdk_core_protos::temporal::api::workflowservice::v1::ListWorkflowExecutionsRequest;
+use tracing::{info, warn};
+
+use super::status::convert_status;
+use super::status::query_workflow_execution_status;
+use crate::get_client;
+use crate::tasks::local_backend;
+use crate::TEMPORALIO_NAMESPACE;
+
+/// Name of the signal that pauses (payload `true`) or resumes (payload `false`) a workflow.
+pub const PAUSE_SIGNAL_NAME: &str = "hoover3_pause";
+
+/// Maximum number of workflows visited in a tree, to keep a broken tree from looping forever.
+const CONTROL_TREE_LIMIT: usize = 100_000;
+
+/// List the running workflows in the tree under a workflow, including itself, parents before children.
+pub async fn list_running_workflow_tree(workflow_id: &str) -> anyhow::Result<Vec<String>> {
+    let root_status = convert_status(query_workflow_execution_status(workflow_id).await?);
+    if root_status != UiWorkflowStatusCode::Running {
+        return Ok(vec![]);
+    }
+    let mut tree = vec![workflow_id.to_string()];
+    let mut next = 0;
+    while next < tree.len() && tree.len() < CONTROL_TREE_LIMIT {
+        let children = list_running_children(&tree[next]).await?;
+        tree.extend(children);
+        next += 1;
+    }
+    Ok(tree)
+}
+
+/// List the running child workflows of a workflow, going through all the result pages.
+async fn list_running_children(workflow_id: &str) -> anyhow::Result<Vec<String>> {
+    let client = get_client().await?;
+    let mut client = (*client).clone();
+    let query = format!(
+        "ParentWorkflowId=\"{}\" AND ExecutionStatus=\"Running\"",
+        workflow_id
+    );
+    let mut children = vec![];
+    let mut next_page_token = vec![];
+    loop {
+        let page = client
+            .list_workflow_executions(ListWorkflowExecutionsRequest {
+                namespace: TEMPORALIO_NAMESPACE.to_string(),
+                page_size: 1000,
+                next_page_token,
+                query: query.clone(),
+            })
+            .await?
+            .into_inner();
+        for execution in page.executions {
+            children.push(execution.execution.context("no execution")?.workflow_id);
+        }
+        if page.next_page_token.is_empty() {
+            break;
+        }
+        next_page_token = page.next_page_token;
+    }
+    Ok(children)
+}
+
+/// Request cancellation of a workflow and all its running children.
+/// Each workflow stops at its next step and ends with the Canceled status;
+/// running activities see the cancellation on their next heartbeat.
+/// Returns the number of workflows cancelled.
+pub async fn cancel_workflow_tree(workflow_id: String) -> anyhow::Result<u32> {
+    if let Some(local) = local_backend()? {
+        return local.stop_tree(&workflow_id, UiWorkflowStatusCode::Canceled);
+    }
+    let client = get_client().await?;
+    let tree = list_running_workflow_tree(&workflow_id).await?;
+    let mut count = 0;
+    // children first, so the parents don't start new children in the meantime
+    for wf_id in tree.iter().rev() {
+        match client
+            .cancel_workflow_execution(
+                wf_id.clone(),
+                None,
+                format!("cancel requested for {workflow_id}"),
+                None,
+            )
+            .await
+        {
+            Ok(_) => count += 1,
+            Err(e) => warn!("cannot cancel workflow {wf_id}: {e:?}"),
+        }
+    }
+    info!("cancel workflow tree {workflow_id}: {count} workflows");
+    Ok(count)
+}
+
+/// Terminate a workflow and all its running children right away, without letting them clean up.
+/// Use this for workflows that don't react to [cancel_workflow_tree].
+/// Returns the number of workflows terminated.
+pub async fn terminate_workflow_tree(workflow_id: String) -> anyhow::Result<u32> {
+    if let Some(local) = local_backend()? {
+        return local.stop_tree(&workflow_id, UiWorkflowStatusCode::Terminated);
+    }
+    let client = get_client().await?;
+    let tree = list_running_workflow_tree(&workflow_id).await?;
+    let mut count = 0;
+    for wf_id in tree.iter().rev() {
+        match client
+            .terminate_workflow_execution(wf_id.clone(), None)
+            .await
+        {
+            Ok(_) => count += 1,
+            Err(e) => warn!("cannot terminate workflow {wf_id}: {e:?}"),
+        }
+    }
+    info!("terminate workflow tree {workflow_id}: {count} workflows");
+    Ok(count)
+}
+
+/// Pause a workflow and all its running children. Paused workflows don't start
+/// new activities or child workflows; the ones already started run to completion.
+/// Returns the number of workflows paused.
+pub async fn pause_workflow_tree(workflow_id: String) -> anyhow::Result<u32> {
+    signal_pause_workflow_tree(workflow_id, true).await
+}
+
+/// Resume a workflow paused with [pause_workflow_tree], together with its running children.
+/// Returns the number of workflows resumed.
+pub async fn resume_workflow_tree(workflow_id: String) -> anyhow::Result<u32> {
+    signal_pause_workflow_tree(workflow_id, false).await
+}
+
+async fn signal_pause_workflow_tree(workflow_id: String, paused: bool) -> anyhow::Result<u32> {
+    if let Some(local) = local_backend()? {
+        return Ok(local.pause_tree(&workflow_id, paused));
+    }
+    let client = get_client().await?;
+    let tree = list_running_workflow_tree(&workflow_id).await?;
+    let mut count = 0;
+    for wf_id in tree.iter() {
+        let payloads = Payloads {
+            payloads: vec![paused.as_json_payload()?],
+        };
+        match client
+            .signal_workflow_execution(
+                wf_id.clone(),
+                "".to_string(),
+                PAUSE_SIGNAL_NAME.to_string(),
+                Some(payloads),
+                None,
+            )
+            .await
+        {
+            Ok(_) => count += 1,
+            Err(e) => warn!("cannot signal workflow {wf_id}: {e:?}"),
+        }
+    }
+    info!("pause={paused} workflow tree {workflow_id}: {count} workflows");
+    Ok(count)
+}
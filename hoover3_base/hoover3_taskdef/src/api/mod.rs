@@ -1,4 +1,5 @@
 //! API functions for taskdef crate - check workflow status, result, execution tree, etc.
 
+pub mod control;
 pub mod status;
 pub mod status_tree;
@@ -7,6 +7,7 @@ use anyhow;
 use temporal_client::WorkflowClientTrait;
 
 use crate::get_client;
+use crate::tasks::local_backend;
 
 /// Converts a Temporal workflow execution status to a UI-friendly status code
 pub fn convert_status(status: WorkflowExecutionStatus) -> UiWorkflowStatusCode {
@@ -22,10 +23,27 @@ pub fn convert_status(status: WorkflowExecutionStatus) -> UiWorkflowStatusCode {
     }
 }
 
+/// Converts a UI status code back to the Temporal workflow execution status
+pub fn convert_status_back(status: UiWorkflowStatusCode) -> WorkflowExecutionStatus {
+    match status {
+        UiWorkflowStatusCode::Unspecified => WorkflowExecutionStatus::Unspecified,
+        UiWorkflowStatusCode::Running => WorkflowExecutionStatus::Running,
+        UiWorkflowStatusCode::Completed => WorkflowExecutionStatus::Completed,
+        UiWorkflowStatusCode::Failed => WorkflowExecutionStatus::Failed,
+        UiWorkflowStatusCode::Canceled => WorkflowExecutionStatus::Canceled,
+        UiWorkflowStatusCode::Terminated => WorkflowExecutionStatus::Terminated,
+        UiWorkflowStatusCode::ContinuedAsNew => WorkflowExecutionStatus::ContinuedAsNew,
+        UiWorkflowStatusCode::TimedOut => WorkflowExecutionStatus::TimedOut,
+    }
+}
+
 /// Retrieves the current status of a workflow execution
 pub async fn query_workflow_execution_status(
     workflow_id: &str,
 ) -> anyhow::Result<WorkflowExecutionStatus> {
+    if let Some(local) = local_backend()? {
+        return Ok(convert_status_back(local.status(workflow_id)?));
+    }
     let client = get_client().await?;
     let describe = client
         .describe_workflow_execution(workflow_id.to_string(), None)
@@ -39,6 +57,9 @@ pub async fn query_workflow_execution_status(
 
 /// Retrieves the final result payload from a completed workflow execution
 pub async fn query_workflow_execution_result(workflow_id: &str) -> anyhow::Result<Vec<u8>> {
+    if let Some(local) = local_backend()? {
+        return local.result(workflow_id);
+    }
     let client = get_client().await?;
     use temporal_sdk_core_protos::temporal::api::enums::v1::EventType::WorkflowExecutionCompleted;
     use temporal_sdk_core_protos::temporal::api::history::v1::history_event::Attributes::WorkflowExecutionCompletedEventAttributes;
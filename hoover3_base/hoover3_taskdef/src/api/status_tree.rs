@@ -15,6 +15,7 @@ use temporal_sdk_core_protos::temporal::api::workflowservice::v1::ListWorkflowEx
 
 use super::status::convert_status;
 use super::status::query_workflow_execution_status;
+use crate::tasks::local_backend;
 
 const TREE_NODE_LIMIT: usize = 24;
 
@@ -22,6 +23,9 @@ const TREE_NODE_LIMIT: usize = 24;
 pub async fn get_workflow_status_tree(
     workflow_id: String,
 ) -> anyhow::Result<TemporalioWorkflowStatusTree> {
+    if let Some(local) = local_backend()? {
+        return local.status_tree(&workflow_id);
+    }
     hoover3_database::db_management::redis::with_redis_cache(
         "temporalio_get_workflow_status_tree",
         5,
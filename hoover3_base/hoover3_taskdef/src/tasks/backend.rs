@@ -0,0 +1,70 @@
+//! Task backend selection - run workflows on a Temporal server, or in-process with [super::local].
+//!
+//! The backend is chosen once per process: by calling [set_task_backend] before starting
+//! any workflow, or else from the `HOOVER3_TASK_BACKEND` environment variable.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use hoover3_database::system_paths::get_data_root;
+use tracing::{info, warn};
+
+use super::local::LocalWfContext;
+
+/// Environment variable for selecting the task backend: `temporal` (default) or `local`.
+pub const TASK_BACKEND_ENV_VAR: &str = "HOOVER3_TASK_BACKEND";
+/// Environment variable for the local backend state directory. Defaults to `data/local_tasks`.
+pub const TASK_STATE_DIR_ENV_VAR: &str = "HOOVER3_TASK_STATE_DIR";
+
+/// Where workflows and activities run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskBackend {
+    /// On a Temporal server, with activities and workflows executed by the worker processes.
+    Temporal,
+    /// In the current process, on the tokio runtime, with the workflow state kept in the given directory.
+    /// No workers are needed.
+    Local(PathBuf),
+}
+
+static TASK_BACKEND: OnceLock<TaskBackend> = OnceLock::new();
+
+/// Select the task backend for this process. Fails if a different backend was already selected,
+/// either by an earlier call or by the first workflow started.
+pub fn set_task_backend(backend: TaskBackend) -> anyhow::Result<()> {
+    let current = TASK_BACKEND.get_or_init(|| backend.clone());
+    if *current != backend {
+        anyhow::bail!("task backend is already set to {current:?}");
+    }
+    Ok(())
+}
+
+/// Get the task backend for this process.
+pub fn task_backend() -> &'static TaskBackend {
+    TASK_BACKEND.get_or_init(task_backend_from_env)
+}
+
+fn task_backend_from_env() -> TaskBackend {
+    let backend = match std::env::var(TASK_BACKEND_ENV_VAR).as_deref() {
+        Ok("local") => TaskBackend::Local(
+            std::env::var(TASK_STATE_DIR_ENV_VAR)
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| get_data_root().join("local_tasks")),
+        ),
+        Ok("temporal") | Err(_) => TaskBackend::Temporal,
+        Ok(other) => {
+            warn!("unknown task backend {other:?}, using temporal");
+            TaskBackend::Temporal
+        }
+    };
+    info!("task backend: {backend:?}");
+    backend
+}
+
+/// Context passed to workflow functions, for running activities and child workflows.
+#[derive(Clone)]
+pub enum WfContext {
+    /// Workflow running on a Temporal worker.
+    Temporal(temporal_sdk::WfContext),
+    /// Workflow running in-process on the local backend.
+    Local(LocalWfContext),
+}
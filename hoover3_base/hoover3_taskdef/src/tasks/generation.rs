@@ -0,0 +1,41 @@
+//! Workflow generations - run a workflow tree again, even though its workflow ids come from its arguments.
+//!
+//! Workflow ids are made from the argument hash, so starting the same workflow again
+//! returns the old result. Bumping the generation gives the whole tree new workflow ids:
+//! the root workflow is started in a generation, and its children inherit it.
+//! Generation 0 keeps the original workflow ids.
+
+use std::future::Future;
+
+tokio::task_local! {
+    static WORKFLOW_GENERATION: u32;
+}
+
+/// Run a future with the given workflow generation. Inside it, the client methods of
+/// [super::TemporalioWorkflowDescriptor] start and query the workflows of that generation.
+pub async fn with_workflow_generation<F: Future>(generation: u32, f: F) -> F::Output {
+    WORKFLOW_GENERATION.scope(generation, f).await
+}
+
+/// Get the workflow generation for the current workflow or client call; 0 if none was set.
+pub fn current_workflow_generation() -> u32 {
+    WORKFLOW_GENERATION.try_with(|g| *g).unwrap_or(0)
+}
+
+/// Make a workflow id from the workflow name, argument hash and generation.
+pub(crate) fn generation_workflow_id(name: &str, arg_hash: &str, generation: u32) -> String {
+    if generation == 0 {
+        format!("{}_{}", name, arg_hash)
+    } else {
+        format!("{}_g{}_{}", name, generation, arg_hash)
+    }
+}
+
+#[tokio::test]
+async fn test_workflow_generation() {
+    assert_eq!(current_workflow_generation(), 0);
+    let g = with_workflow_generation(3, async { current_workflow_generation() }).await;
+    assert_eq!(g, 3);
+    assert_eq!(generation_workflow_id("wf", "abc", 0), "wf_abc");
+    assert_eq!(generation_workflow_id("wf", "abc", 3), "wf_g3_abc");
+}
@@ -0,0 +1,101 @@
+//! Activity heartbeats - tell Temporal that a long-running activity is still alive,
+//! and record progress details that the next attempt can resume from after a crash.
+//!
+//! The activity context is kept in a task-local, set by the activity registration,
+//! so activity functions don't need an extra argument.
+//! Outside of an activity (e.g. in tests), heartbeats are ignored.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use serde::{de::DeserializeOwned, Serialize};
+use temporal_sdk::ActContext;
+use temporal_sdk_core_protos::coresdk::AsJsonPayloadExt;
+use tracing::warn;
+
+tokio::task_local! {
+    static ACTIVITY_CONTEXT: Arc<ActContext>;
+}
+
+/// Run the activity future with its context available to [activity_heartbeat].
+pub(crate) async fn with_activity_context<F: Future>(ctx: ActContext, f: F) -> F::Output {
+    ACTIVITY_CONTEXT.scope(Arc::new(ctx), f).await
+}
+
+/// Handle to the running activity, used for sending heartbeats.
+/// Clone it into blocking threads, where the task-local context is not available.
+#[derive(Clone, Default)]
+pub struct ActivityHeartbeat(Option<Arc<ActContext>>);
+
+impl ActivityHeartbeat {
+    /// Get the handle for the activity running on the current task.
+    pub fn current() -> Self {
+        Self(ACTIVITY_CONTEXT.try_with(|ctx| ctx.clone()).ok())
+    }
+
+    /// Send a heartbeat with progress details. Heartbeats are throttled by the Temporal SDK,
+    /// so this can be called for every item processed.
+    pub fn record<T: Serialize>(&self, details: &T) {
+        let Some(ctx) = &self.0 else {
+            return;
+        };
+        match details.as_json_payload() {
+            Ok(payload) => ctx.record_heartbeat(vec![payload]),
+            Err(e) => warn!("failed to serialize heartbeat details: {e:?}"),
+        }
+    }
+
+    /// Get the progress details from the last heartbeat of the previous attempt, if any.
+    pub fn last_details<T: DeserializeOwned>(&self) -> Option<T> {
+        let payload = self.0.as_ref()?.get_heartbeat_details().first()?;
+        match serde_json::from_slice(&payload.data) {
+            Ok(details) => Some(details),
+            Err(e) => {
+                warn!("failed to parse heartbeat details, starting over: {e:?}");
+                None
+            }
+        }
+    }
+
+    /// Check if cancellation was requested for the activity, e.g. because its workflow was cancelled.
+    /// The cancellation is only seen after a heartbeat, so activities that check it should heartbeat too.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.as_ref().is_some_and(|ctx| ctx.is_cancelled())
+    }
+
+    /// Run a blocking function with this activity as the current one.
+    pub fn scope_sync<R>(self, f: impl FnOnce() -> R) -> R {
+        match self.0 {
+            Some(ctx) => ACTIVITY_CONTEXT.sync_scope(ctx, f),
+            None => f(),
+        }
+    }
+}
+
+/// Send a heartbeat for the current activity, with progress details.
+/// Does nothing when not called from an activity.
+pub fn activity_heartbeat<T: Serialize>(details: &T) {
+    ActivityHeartbeat::current().record(details)
+}
+
+/// Get the progress details from the last heartbeat of the previous attempt
+/// of the current activity. Returns `None` on the first attempt,
+/// or when not called from an activity.
+pub fn activity_heartbeat_details<T: DeserializeOwned>() -> Option<T> {
+    ActivityHeartbeat::current().last_details()
+}
+
+/// Check if cancellation was requested for the current activity.
+/// Returns `false` when not called from an activity.
+pub fn activity_is_cancelled() -> bool {
+    ActivityHeartbeat::current().is_cancelled()
+}
+
+#[tokio::test]
+async fn test_heartbeat_outside_activity() {
+    activity_heartbeat(&5_u32);
+    assert_eq!(activity_heartbeat_details::<u32>(), None);
+    assert!(!activity_is_cancelled());
+    let handle = ActivityHeartbeat::current();
+    assert_eq!(handle.scope_sync(activity_heartbeat_details::<u32>), None);
+}
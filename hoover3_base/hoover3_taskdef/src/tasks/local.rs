@@ -0,0 +1,424 @@
+//! In-process task backend - runs workflows and activities on the local tokio runtime,
+//! without a Temporal server or worker processes.
+//!
+//! Workflows run as tokio tasks; activities run inside their workflow task, with the
+//! retry, timeout and task queue concurrency settings used on Temporal.
+//! Heartbeat timeouts are not checked, and heartbeat details are not kept between attempts.
+//! The workflow status and results are kept in a state directory, one JSON file per workflow,
+//! so they can still be read after a restart.
+//! Workflows still running when the process stopped are marked as failed on the next start.
+//!
+//! Same as on Temporal, starting a workflow (or child workflow) that is still running
+//! waits for the running one, and starting one that has closed runs it again.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use futures::future::{BoxFuture, Shared};
+use futures::FutureExt;
+use hoover3_types::tasks::{TemporalioWorkflowStatusTree, UiWorkflowStatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, Semaphore};
+use tracing::{info, warn};
+
+use super::backend::{task_backend, TaskBackend};
+use super::{is_non_retryable, ActivityRunOptions};
+use crate::task_inventory::list_task_queues;
+
+/// Context of a workflow running on the local backend.
+#[derive(Clone, Debug)]
+pub struct LocalWfContext {
+    workflow_id: String,
+}
+
+impl LocalWfContext {
+    /// The id of the running workflow.
+    pub fn workflow_id(&self) -> &str {
+        &self.workflow_id
+    }
+}
+
+/// Workflow state, saved as JSON in the state directory.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct LocalWorkflowRecord {
+    workflow_id: String,
+    workflow_type: String,
+    queue_name: String,
+    parent_workflow_id: Option<String>,
+    status: UiWorkflowStatusCode,
+    result: Option<serde_json::Value>,
+    error: Option<String>,
+}
+
+/// Future that resolves when a workflow run is done; the outcome is in its record.
+pub(crate) type LocalRunDone = Shared<BoxFuture<'static, ()>>;
+
+/// Workflow run function: returns the result, or `None` if the workflow was cancelled.
+pub(crate) type LocalRunFn = Box<
+    dyn FnOnce(LocalWfContext) -> BoxFuture<'static, anyhow::Result<Option<serde_json::Value>>>,
+>;
+
+struct LocalRun {
+    done: LocalRunDone,
+    abort: tokio::task::AbortHandle,
+}
+
+#[derive(Default)]
+struct LocalBackendState {
+    records: BTreeMap<String, LocalWorkflowRecord>,
+    running: HashMap<String, LocalRun>,
+    paused: HashSet<String>,
+}
+
+/// The in-process backend: workflow records, running workflows, and task queue limits.
+pub(crate) struct LocalBackend {
+    state_dir: PathBuf,
+    state: Mutex<LocalBackendState>,
+    resumed: Notify,
+    queues: HashMap<&'static str, Arc<Semaphore>>,
+}
+
+static LOCAL_BACKEND: Mutex<Option<Arc<LocalBackend>>> = Mutex::new(None);
+
+/// Get the local backend, if it is the selected task backend.
+pub(crate) fn local_backend() -> anyhow::Result<Option<Arc<LocalBackend>>> {
+    let TaskBackend::Local(state_dir) = task_backend() else {
+        return Ok(None);
+    };
+    let mut backend = LOCAL_BACKEND.lock().unwrap();
+    if backend.is_none() {
+        *backend = Some(Arc::new(LocalBackend::open(state_dir.clone())?));
+    }
+    Ok(backend.clone())
+}
+
+/// Get the local backend for a workflow context that was created by it.
+pub(crate) fn local_backend_for(ctx: &LocalWfContext) -> anyhow::Result<Arc<LocalBackend>> {
+    local_backend()?.with_context(|| {
+        format!(
+            "workflow {} runs locally, but the local backend is not selected",
+            ctx.workflow_id
+        )
+    })
+}
+
+impl LocalBackend {
+    /// Load the workflow records from the state directory.
+    fn open(state_dir: PathBuf) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&state_dir)
+            .with_context(|| format!("create task state dir {state_dir:?}"))?;
+        let backend = Self {
+            state_dir: state_dir.clone(),
+            state: Mutex::new(LocalBackendState::default()),
+            resumed: Notify::new(),
+            queues: list_task_queues()
+                .map(|q| {
+                    (
+                        q.queue_name,
+                        Arc::new(Semaphore::new(q.max_concurrency.max(1) as usize)),
+                    )
+                })
+                .collect(),
+        };
+        let mut records = BTreeMap::new();
+        for entry in std::fs::read_dir(&state_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let mut record: LocalWorkflowRecord = serde_json::from_slice(&std::fs::read(&path)?)
+                .with_context(|| format!("read task state {path:?}"))?;
+            if record.status == UiWorkflowStatusCode::Running {
+                record.status = UiWorkflowStatusCode::Failed;
+                record.error = Some("the process stopped while the workflow was running".into());
+                backend.save(&record)?;
+            }
+            records.insert(record.workflow_id.clone(), record);
+        }
+        info!(
+            "local task backend: {} workflows in {:?}",
+            records.len(),
+            state_dir
+        );
+        backend.state.lock().unwrap().records = records;
+        Ok(backend)
+    }
+
+    fn save(&self, record: &LocalWorkflowRecord) -> anyhow::Result<()> {
+        let path = self.state_dir.join(format!("{}.json", record.workflow_id));
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(record)?)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    /// Start a workflow, unless it is already running. Returns a future for its completion.
+    pub(crate) fn start(
+        self: &Arc<Self>,
+        workflow_id: &str,
+        workflow_type: &str,
+        queue_name: &str,
+        parent_workflow_id: Option<String>,
+        run: LocalRunFn,
+    ) -> anyhow::Result<LocalRunDone> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(running) = state.running.get(workflow_id) {
+            return Ok(running.done.clone());
+        }
+        let record = LocalWorkflowRecord {
+            workflow_id: workflow_id.to_string(),
+            workflow_type: workflow_type.to_string(),
+            queue_name: queue_name.to_string(),
+            parent_workflow_id,
+            status: UiWorkflowStatusCode::Running,
+            result: None,
+            error: None,
+        };
+        self.save(&record)?;
+        state.records.insert(workflow_id.to_string(), record);
+
+        let handle = tokio::spawn(run(LocalWfContext {
+            workflow_id: workflow_id.to_string(),
+        }));
+        let abort = handle.abort_handle();
+        let backend = self.clone();
+        let id = workflow_id.to_string();
+        let done = async move {
+            let (status, result, error) = match handle.await {
+                Ok(Ok(Some(result))) => (UiWorkflowStatusCode::Completed, Some(result), None),
+                Ok(Ok(None)) => (UiWorkflowStatusCode::Canceled, None, None),
+                Ok(Err(e)) => (UiWorkflowStatusCode::Failed, None, Some(format!("{e:#}"))),
+                // stopped by cancel or terminate, which already saved the status
+                Err(e) if e.is_cancelled() => (UiWorkflowStatusCode::Canceled, None, None),
+                Err(e) => (UiWorkflowStatusCode::Failed, None, Some(e.to_string())),
+            };
+            if let Err(e) = backend.finish(&id, status, result, error) {
+                warn!("cannot save local workflow {id} result: {e:#}");
+            }
+        }
+        .boxed()
+        .shared();
+        // drive the run to completion, even if nobody waits for it
+        tokio::spawn(done.clone());
+        state.running.insert(
+            workflow_id.to_string(),
+            LocalRun {
+                done: done.clone(),
+                abort,
+            },
+        );
+        Ok(done)
+    }
+
+    fn finish(
+        &self,
+        workflow_id: &str,
+        status: UiWorkflowStatusCode,
+        result: Option<serde_json::Value>,
+        error: Option<String>,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.running.remove(workflow_id);
+        state.paused.remove(workflow_id);
+        let Some(record) = state.records.get_mut(workflow_id) else {
+            return Ok(());
+        };
+        if record.status != UiWorkflowStatusCode::Running {
+            return Ok(());
+        }
+        record.status = status;
+        record.result = result;
+        record.error = error;
+        let record = record.clone();
+        self.save(&record)
+    }
+
+    /// Get the status of a workflow.
+    pub(crate) fn status(&self, workflow_id: &str) -> anyhow::Result<UiWorkflowStatusCode> {
+        let state = self.state.lock().unwrap();
+        let record = state
+            .records
+            .get(workflow_id)
+            .with_context(|| format!("workflow {workflow_id} not found"))?;
+        Ok(record.status.clone())
+    }
+
+    /// Get the serialized result of a completed workflow; fails for workflows that did not complete.
+    pub(crate) fn result(&self, workflow_id: &str) -> anyhow::Result<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        let record = state
+            .records
+            .get(workflow_id)
+            .with_context(|| format!("workflow {workflow_id} not found"))?;
+        match (&record.status, &record.result) {
+            (UiWorkflowStatusCode::Completed, Some(result)) => Ok(serde_json::to_vec(result)?),
+            (status, _) => anyhow::bail!(
+                "workflow {workflow_id} is {status}: {}",
+                record.error.as_deref().unwrap_or("no result")
+            ),
+        }
+    }
+
+    /// Run an activity inside a workflow, retrying it according to its options.
+    pub(crate) async fn run_activity<T, F, Fut>(
+        &self,
+        ctx: &LocalWfContext,
+        name: &str,
+        queue_name: &str,
+        options: ActivityRunOptions,
+        func: F,
+    ) -> anyhow::Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        self.wait_if_paused(&ctx.workflow_id).await;
+        let _permit = match self.queues.get(queue_name) {
+            Some(queue) => Some(queue.clone().acquire_owned().await?),
+            None => None,
+        };
+        let mut retry_interval = options.initial_interval;
+        let mut attempt = 1;
+        loop {
+            let error = match tokio::time::timeout(options.start_to_close_timeout, func()).await {
+                Ok(Ok(result)) => return Ok(result),
+                Ok(Err(e)) => e,
+                Err(_) => anyhow::anyhow!(
+                    "activity {name} timed out after {:?}",
+                    options.start_to_close_timeout
+                ),
+            };
+            if is_non_retryable(&error) || attempt >= options.maximum_attempts {
+                return Err(error.context(format!("activity {name} failed, attempt {attempt}")));
+            }
+            warn!("activity {name} attempt {attempt} failed, retrying: {error:#}");
+            tokio::time::sleep(retry_interval).await;
+            retry_interval = retry_interval.mul_f64(options.backoff_coefficient);
+            if let Some(maximum_interval) = options.maximum_interval {
+                retry_interval = retry_interval.min(maximum_interval);
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Wait until the workflow is not paused.
+    pub(crate) async fn wait_if_paused(&self, workflow_id: &str) {
+        loop {
+            let resumed = self.resumed.notified();
+            if !self.state.lock().unwrap().paused.contains(workflow_id) {
+                return;
+            }
+            resumed.await;
+        }
+    }
+
+    /// List the running workflows in the tree under a workflow, parents before children.
+    fn running_tree(state: &LocalBackendState, workflow_id: &str) -> Vec<String> {
+        if !state.running.contains_key(workflow_id) {
+            return vec![];
+        }
+        let mut tree = vec![workflow_id.to_string()];
+        let mut next = 0;
+        while next < tree.len() {
+            let parent = tree[next].clone();
+            tree.extend(
+                state
+                    .records
+                    .values()
+                    .filter(|r| r.parent_workflow_id.as_deref() == Some(parent.as_str()))
+                    .filter(|r| state.running.contains_key(&r.workflow_id))
+                    .map(|r| r.workflow_id.clone()),
+            );
+            next += 1;
+        }
+        tree
+    }
+
+    /// Stop a workflow and its running children, saving the given status (Canceled or Terminated).
+    /// Returns the number of workflows stopped.
+    pub(crate) fn stop_tree(
+        &self,
+        workflow_id: &str,
+        status: UiWorkflowStatusCode,
+    ) -> anyhow::Result<u32> {
+        let mut state = self.state.lock().unwrap();
+        let tree = Self::running_tree(&state, workflow_id);
+        for id in tree.iter().rev() {
+            if let Some(running) = state.running.remove(id) {
+                running.abort.abort();
+            }
+            state.paused.remove(id);
+            if let Some(record) = state.records.get_mut(id) {
+                record.status = status.clone();
+                record.error = Some(format!("{status} by request"));
+                let record = record.clone();
+                self.save(&record)?;
+            }
+        }
+        Ok(tree.len() as u32)
+    }
+
+    /// Pause or resume a workflow and its running children. Returns the number of workflows changed.
+    pub(crate) fn pause_tree(&self, workflow_id: &str, paused: bool) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        let tree = Self::running_tree(&state, workflow_id);
+        for id in tree.iter() {
+            if paused {
+                state.paused.insert(id.clone());
+            } else {
+                state.paused.remove(id);
+            }
+        }
+        drop(state);
+        if !paused {
+            self.resumed.notify_waiters();
+        }
+        tree.len() as u32
+    }
+
+    /// Build the status tree of a workflow and all its children, running or not.
+    pub(crate) fn status_tree(
+        &self,
+        workflow_id: &str,
+    ) -> anyhow::Result<TemporalioWorkflowStatusTree> {
+        let state = self.state.lock().unwrap();
+        let root = state
+            .records
+            .get(workflow_id)
+            .with_context(|| format!("workflow {workflow_id} not found"))?;
+        let mut tree = TemporalioWorkflowStatusTree {
+            root_workflow_id: workflow_id.to_string(),
+            nodes: BTreeMap::from([(workflow_id.to_string(), root.status.clone())]),
+            parent: BTreeMap::new(),
+            children: BTreeMap::new(),
+            counts: BTreeMap::new(),
+            total_counts: BTreeMap::from([(root.status.clone(), 1)]),
+            root_status: root.status.clone(),
+        };
+        let mut open = vec![workflow_id.to_string()];
+        while let Some(parent_id) = open.pop() {
+            let mut children = vec![];
+            let mut counts = BTreeMap::new();
+            for child in state
+                .records
+                .values()
+                .filter(|r| r.parent_workflow_id.as_deref() == Some(parent_id.as_str()))
+            {
+                tree.nodes
+                    .insert(child.workflow_id.clone(), child.status.clone());
+                tree.parent
+                    .insert(child.workflow_id.clone(), parent_id.clone());
+                *counts.entry(child.status.clone()).or_insert(0) += 1;
+                *tree.total_counts.entry(child.status.clone()).or_insert(0) += 1;
+                children.push(child.workflow_id.clone());
+                open.push(child.workflow_id.clone());
+            }
+            tree.children.insert(parent_id.clone(), children);
+            tree.counts.insert(parent_id, counts);
+        }
+        Ok(tree)
+    }
+}
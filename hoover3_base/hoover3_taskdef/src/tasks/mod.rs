@@ -1,5 +1,8 @@
 //! Task definition macros, clients, workers - wrappers over Temporal SDK.
 
+use crate::api::control::{
+    cancel_workflow_tree, pause_workflow_tree, resume_workflow_tree, terminate_workflow_tree,
+};
 use crate::api::status::convert_status;
 use crate::api::status::query_workflow_execution_result;
 use crate::api::status::query_workflow_execution_status;
@@ -23,7 +26,7 @@ pub use temporal_sdk::ActContext;
 pub use temporal_sdk::ActivityError;
 pub use temporal_sdk::StartedChildWorkflow;
 pub use temporal_sdk::Worker;
-pub use temporal_sdk::{ActivityOptions, WfContext, WfExitValue, WorkflowResult};
+pub use temporal_sdk::{ActivityOptions, WfExitValue, WorkflowResult};
 pub use temporal_sdk_core::protos::coresdk::activity_result::activity_resolution::Status;
 pub use temporal_sdk_core::protos::coresdk::activity_result::ActivityResolution;
 pub use temporal_sdk_core::protos::temporal::api::common::v1::RetryPolicy;
@@ -33,6 +36,26 @@ pub use temporal_sdk_core_protos::temporal::api::enums::v1::WorkflowExecutionSta
 pub use temporal_sdk_core_protos::temporal::api::workflowservice::v1::StartWorkflowExecutionResponse;
 use tracing::{info, warn};
 
+mod heartbeat;
+use heartbeat::with_activity_context;
+pub use heartbeat::{
+    activity_heartbeat, activity_heartbeat_details, activity_is_cancelled, ActivityHeartbeat,
+};
+mod pause;
+use pause::{wait_if_paused, with_pause_gate};
+mod generation;
+use generation::generation_workflow_id;
+pub use generation::{current_workflow_generation, with_workflow_generation};
+mod backend;
+pub use backend::{
+    set_task_backend, task_backend, TaskBackend, WfContext, TASK_BACKEND_ENV_VAR,
+    TASK_STATE_DIR_ENV_VAR,
+};
+mod local;
+pub(crate) use local::local_backend;
+pub use local::LocalWfContext;
+use local::{local_backend_for, LocalRunDone};
+
 /// The default namespace for Temporalio tasks
 pub const TEMPORALIO_NAMESPACE: &str = "default";
 /// Global name for this Temporalio thing (activity, workflow)
@@ -57,6 +80,124 @@ pub trait TemporalioDescriptorValueTypes {
     type Ret: Send + Sync + 'static + for<'de> Deserialize<'de> + Serialize;
 }
 
+/// Retry, backoff, timeout and heartbeat settings for an activity.
+/// Set through the `#[activity]` macro arguments, e.g.
+/// `#[activity(MyQueue, retries = 5, timeout = 3600, heartbeat = 60)]`;
+/// each argument calls the builder method with the same name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActivityRunOptions {
+    /// Total number of attempts, including the first one.
+    pub maximum_attempts: i32,
+    /// Time to wait before the first retry.
+    pub initial_interval: Duration,
+    /// Multiplier for the time to wait between consecutive retries.
+    pub backoff_coefficient: f64,
+    /// Upper bound for the time to wait between retries; `None` means no limit.
+    pub maximum_interval: Option<Duration>,
+    /// Time limit for a single attempt.
+    pub start_to_close_timeout: Duration,
+    /// Time limit between heartbeats; `None` means the activity does not heartbeat.
+    /// Only checked by Temporal; the local backend ignores it.
+    pub heartbeat_timeout: Option<Duration>,
+}
+
+impl ActivityRunOptions {
+    /// Settings for activities that don't set any: one retry, 10 minute timeout, no heartbeat.
+    pub const DEFAULT: Self = Self {
+        maximum_attempts: 2,
+        initial_interval: Duration::from_millis(1050),
+        backoff_coefficient: 2.0,
+        maximum_interval: None,
+        start_to_close_timeout: Duration::from_secs(600),
+        heartbeat_timeout: None,
+    };
+
+    /// Number of retries after the first failed attempt.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.maximum_attempts = retries as i32 + 1;
+        self
+    }
+
+    /// Time to wait before the first retry, in seconds.
+    pub fn retry_interval(mut self, seconds: u64) -> Self {
+        self.initial_interval = Duration::from_secs(seconds);
+        self
+    }
+
+    /// Multiplier for the time to wait between consecutive retries.
+    pub fn backoff(mut self, coefficient: f64) -> Self {
+        self.backoff_coefficient = coefficient;
+        self
+    }
+
+    /// Upper bound for the time to wait between retries, in seconds.
+    pub fn max_retry_interval(mut self, seconds: u64) -> Self {
+        self.maximum_interval = Some(Duration::from_secs(seconds));
+        self
+    }
+
+    /// Time limit for a single attempt, in seconds.
+    pub fn timeout(mut self, seconds: u64) -> Self {
+        self.start_to_close_timeout = Duration::from_secs(seconds);
+        self
+    }
+
+    /// Time limit between heartbeats, in seconds.
+    pub fn heartbeat(mut self, seconds: u64) -> Self {
+        self.heartbeat_timeout = Some(Duration::from_secs(seconds));
+        self
+    }
+
+    /// Convert to the Temporal retry policy.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            initial_interval: Some(duration_to_prost(self.initial_interval)),
+            backoff_coefficient: self.backoff_coefficient,
+            maximum_interval: self.maximum_interval.map(duration_to_prost),
+            maximum_attempts: self.maximum_attempts,
+            ..Default::default()
+        }
+    }
+}
+
+fn duration_to_prost(d: Duration) -> ProstDuration {
+    ProstDuration {
+        seconds: d.as_secs() as i64,
+        nanos: d.subsec_nanos() as i32,
+    }
+}
+
+/// Marker for activity errors that should not be retried, e.g. bad input or a corrupted file.
+/// Attach it using [non_retryable]; the activity then fails on the first attempt,
+/// regardless of its retry settings.
+#[derive(Debug, Clone, Copy)]
+pub struct NonRetryableError;
+
+impl std::fmt::Display for NonRetryableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "non-retryable error")
+    }
+}
+
+/// Mark an activity error as non-retryable.
+pub fn non_retryable(error: impl Into<anyhow::Error>) -> anyhow::Error {
+    error.into().context(NonRetryableError)
+}
+
+/// Check if an activity error was marked as non-retryable.
+pub fn is_non_retryable(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<NonRetryableError>().is_some()
+}
+
+/// Convert an activity error for Temporal, keeping the non-retryable classification.
+fn classify_activity_error(error: anyhow::Error) -> ActivityError {
+    if is_non_retryable(&error) {
+        ActivityError::NonRetryable(error)
+    } else {
+        ActivityError::from(error)
+    }
+}
+
 /// Trait implemented by the `make_activity` macro.
 pub trait TemporalioActivityDescriptor:
     TemporalioDescriptorName + TemporalioDescriptorRegister + TemporalioDescriptorValueTypes
@@ -64,11 +205,18 @@ pub trait TemporalioActivityDescriptor:
     /// The function that implements the activity.
     fn func(arg: Self::Arg) -> impl Future<Output = Result<Self::Ret, anyhow::Error>> + Send;
 
+    /// Retry, timeout and heartbeat settings, from the `#[activity]` macro arguments.
+    fn options() -> ActivityRunOptions {
+        ActivityRunOptions::DEFAULT
+    }
+
     /// Register the activity into a given worker
     fn register(worker: &mut Worker) -> anyhow::Result<()> {
         let n = Self::name();
-        let act_fn = move |_ctx: ActContext, arg: Self::Arg| async move {
-            Self::func(arg).await.map_err(ActivityError::from)
+        let act_fn = move |ctx: ActContext, arg: Self::Arg| async move {
+            with_activity_context(ctx, Self::func(arg))
+                .await
+                .map_err(classify_activity_error)
         };
         worker.register_activity(n, act_fn);
         Ok(())
@@ -81,22 +229,32 @@ pub trait TemporalioActivityDescriptor:
     ) -> impl Future<Output = Result<Self::Ret, anyhow::Error>> {
         let wf_ctx = wf_ctx.clone();
         async move {
+            let wf_ctx = match wf_ctx {
+                WfContext::Temporal(wf_ctx) => wf_ctx,
+                WfContext::Local(ctx) => {
+                    return local_backend_for(&ctx)?
+                        .run_activity(
+                            &ctx,
+                            Self::name(),
+                            Self::queue_name(),
+                            Self::options(),
+                            || Self::func(arg.clone()),
+                        )
+                        .await;
+                }
+            };
             let Ok(input) = arg.as_json_payload() else {
                 anyhow::bail!("Error serializing argument for activity {}", Self::name());
             };
+            wait_if_paused().await;
+            let options = Self::options();
             let opt = ActivityOptions {
                 activity_type: Self::name().to_string(),
                 input,
                 task_queue: Some(Self::queue_name().to_string()),
-                retry_policy: Some(RetryPolicy {
-                    initial_interval: Some(ProstDuration {
-                        seconds: 1,
-                        nanos: 50_000_000, // 50ms
-                    }),
-                    maximum_attempts: 2,
-                    ..Default::default()
-                }),
-                start_to_close_timeout: Some(Duration::from_secs(600)),
+                retry_policy: Some(options.retry_policy()),
+                start_to_close_timeout: Some(options.start_to_close_timeout),
+                heartbeat_timeout: options.heartbeat_timeout,
                 ..Default::default()
             };
 
@@ -119,7 +277,7 @@ pub trait TemporalioActivityDescriptor:
 /// Create an activity descriptor struct called $id_activity
 #[macro_export]
 macro_rules! make_activity {
-    ($queue_obj:expr,$id:ident,$arg:ty,$ret:ty) => {
+    ($queue_obj:expr,$id:ident,$arg:ty,$ret:ty $(, $opt:ident = $val:expr)* $(,)?) => {
         $crate::paste! {
             #[doc = "Macro-generated unit struct that holds our "]
             #[doc = stringify!($id)]
@@ -145,6 +303,9 @@ macro_rules! make_activity {
                     // Ok(tokio::task::spawn($id(arg).boxed()).await??)
                     $id(arg).boxed().await
                 }
+                fn options() -> $crate::ActivityRunOptions {
+                    $crate::ActivityRunOptions::DEFAULT $(.$opt($val))*
+                }
             }
             $crate::inventory::submit!($crate::task_inventory::ActivityDefinitionStatic {
                 name: stringify!($id),
@@ -159,7 +320,7 @@ pub use make_activity;
 /// Create an activity descriptor struct called $id_activity
 #[macro_export]
 macro_rules! make_activity_sync {
-    ($queue_obj:expr,$id:ident,$arg:ty,$ret:ty) => {
+    ($queue_obj:expr,$id:ident,$arg:ty,$ret:ty $(, $opt:ident = $val:expr)* $(,)?) => {
         $crate::paste! {
             #[doc = "Macro-generated unit struct that holds our "]
             #[doc = stringify!($id)]
@@ -181,7 +342,11 @@ macro_rules! make_activity_sync {
             }
             impl $crate::TemporalioActivityDescriptor for [<$id _activity>] {
                 async fn func(arg: Self::Arg) -> Result<Self::Ret, anyhow::Error> {
-                    tokio::task::spawn_blocking(move || $id(arg)).await?
+                    let heartbeat = $crate::ActivityHeartbeat::current();
+                    tokio::task::spawn_blocking(move || heartbeat.scope_sync(|| $id(arg))).await?
+                }
+                fn options() -> $crate::ActivityRunOptions {
+                    $crate::ActivityRunOptions::DEFAULT $(.$opt($val))*
                 }
             }
             $crate::inventory::submit!($crate::task_inventory::ActivityDefinitionStatic {
@@ -200,6 +365,8 @@ pub enum ChildWorkflowFuture<T: Sized + TemporalioWorkflowDescriptor> {
     Running(StartedChildWorkflow),
     /// Represents a workflow that was already completed
     AlreadyCompleted(T::Arg, PhantomData<T>),
+    /// Represents a child workflow running on the local backend, with its workflow id
+    Local(String, LocalRunDone, PhantomData<T>),
 }
 
 impl<T: Sized + TemporalioWorkflowDescriptor> ChildWorkflowFuture<T> {
@@ -231,6 +398,12 @@ impl<T: Sized + TemporalioWorkflowDescriptor> ChildWorkflowFuture<T> {
                     let result: T::Ret = serde_json::from_slice(&payload)?;
                     Ok(result)
                 }
+                ChildWorkflowFuture::Local(wf_id, done, _) => {
+                    done.await;
+                    let payload = query_workflow_execution_result(&wf_id).await?;
+                    let result: T::Ret = serde_json::from_slice(&payload)?;
+                    Ok(result)
+                }
             }
         }
     }
@@ -246,31 +419,71 @@ pub trait TemporalioWorkflowDescriptor:
         arg: Self::Arg,
     ) -> impl Future<Output = WorkflowResult<Self::Ret>> + Send;
 
-    /// Register the workflow into a given worker
+    /// Register the workflow into a given worker.
+    /// The workflow stops with the Canceled status as soon as a cancellation is requested,
+    /// and waits before starting activities or child workflows while it is paused.
     fn register(worker: &mut Worker) -> anyhow::Result<()> {
         let n = Self::name();
-        let wf_fn = move |ctx: WfContext| async move {
+        let wf_fn = move |ctx: temporal_sdk::WfContext| async move {
             let arg: Self::Arg = serde_json::from_slice(&ctx.get_args()[0].data)?;
-            Self::wf_func(ctx, arg).await
+            // the generation is sent as the second input, and inherited by the children
+            let generation: u32 = match ctx.get_args().get(1) {
+                Some(payload) => serde_json::from_slice(&payload.data)?,
+                None => 0,
+            };
+            let cancel_ctx = ctx.clone();
+            let cancelled = cancel_ctx.cancelled();
+            let run = with_pause_gate(&ctx, Self::wf_func(WfContext::Temporal(ctx.clone()), arg));
+            let run = with_workflow_generation(generation, run);
+            futures::pin_mut!(run, cancelled);
+            match futures::future::select(run, cancelled).await {
+                futures::future::Either::Left((result, _)) => result,
+                futures::future::Either::Right(_) => {
+                    info!("workflow {} cancelled", Self::name());
+                    Ok(WfExitValue::Cancelled)
+                }
+            }
         };
         worker.register_wf(n, wf_fn);
         Ok(())
     }
 
-    /// Generate a workflow id from an argument, using the stable hash of the argument.
+    /// Generate a workflow id from an argument, using the stable hash of the argument
+    /// and the current workflow generation (see [with_workflow_generation]).
     fn workflow_id(arg: &Self::Arg) -> String {
-        format!(
-            "{}_{}",
+        Self::workflow_id_for_generation(arg, current_workflow_generation())
+    }
+
+    /// Generate a workflow id from an argument for the given workflow generation.
+    fn workflow_id_for_generation(arg: &Self::Arg, generation: u32) -> String {
+        generation_workflow_id(
             Self::name(),
-            hoover3_types::stable_hash::stable_hash(arg).unwrap()
+            &hoover3_types::stable_hash::stable_hash(arg).unwrap(),
+            generation,
         )
     }
 
     /// Start a workflow using a HTTP client. If the workflow already exists, the function returns Ok without restarting it. Returns the workflow id.
-    fn client_start(arg: &Self::Arg) -> impl Future<Output = Result<String, anyhow::Error>> {
+    fn client_start(arg: &Self::Arg) -> impl Future<Output = Result<String, anyhow::Error>>
+    where
+        Self: Sized + 'static,
+    {
         async move {
             let workflow_id = Self::workflow_id(arg);
-            let input = vec![arg.as_json_payload()?];
+            if let Some(local) = local_backend()? {
+                local.start(
+                    &workflow_id,
+                    Self::name(),
+                    Self::queue_name(),
+                    None,
+                    local_run_fn::<Self>(current_workflow_generation(), arg.clone()),
+                )?;
+                return Ok(workflow_id);
+            }
+            let input = vec![
+                arg.as_json_payload()?,
+                current_workflow_generation().as_json_payload()?,
+            ];
             let client = get_client().await?;
 
             use temporal_sdk_core_protos::temporal::api::enums::v1::WorkflowIdConflictPolicy;
@@ -309,7 +522,7 @@ pub trait TemporalioWorkflowDescriptor:
         arg: Self::Arg,
     ) -> impl Future<Output = Result<Self::Ret, anyhow::Error>>
     where
-        Self: Sized,
+        Self: Sized + 'static,
     {
         async move { Self::start_as_child(wf_ctx, arg).await?.result().await }
     }
@@ -321,7 +534,7 @@ pub trait TemporalioWorkflowDescriptor:
         args: Vec<Self::Arg>,
     ) -> impl Future<Output = anyhow::Result<Vec<(Self::Arg, anyhow::Result<Self::Ret>)>>>
     where
-        Self: Sized,
+        Self: Sized + 'static,
     {
         use futures::StreamExt;
         async move {
@@ -357,16 +570,33 @@ pub trait TemporalioWorkflowDescriptor:
         arg: Self::Arg,
     ) -> impl Future<Output = Result<ChildWorkflowFuture<Self>, anyhow::Error>>
     where
-        Self: Sized,
+        Self: Sized + 'static,
     {
         let arg = arg.clone();
         let wf_ctx = wf_ctx.clone();
         async move {
             let arg = arg.clone();
-            let wf_ctx = wf_ctx.clone();
-
             let workflow_id = Self::workflow_id(&arg);
-            let input = vec![arg.as_json_payload()?];
+            let wf_ctx = match wf_ctx {
+                WfContext::Temporal(wf_ctx) => wf_ctx,
+                WfContext::Local(ctx) => {
+                    let local = local_backend_for(&ctx)?;
+                    local.wait_if_paused(ctx.workflow_id()).await;
+                    let done = local.start(
+                        &workflow_id,
+                        Self::name(),
+                        Self::queue_name(),
+                        Some(ctx.workflow_id().to_string()),
+                        local_run_fn::<Self>(current_workflow_generation(), arg),
+                    )?;
+                    return Ok(ChildWorkflowFuture::Local(workflow_id, done, PhantomData));
+                }
+            };
+            let input = vec![
+                arg.as_json_payload()?,
+                current_workflow_generation().as_json_payload()?,
+            ];
+            wait_if_paused().await;
 
             use temporal_sdk_core_protos::temporal::api::enums::v1::WorkflowIdConflictPolicy;
             use temporal_sdk_core_protos::temporal::api::enums::v1::WorkflowIdReusePolicy;
@@ -436,6 +666,26 @@ pub trait TemporalioWorkflowDescriptor:
         }
     }
 
+    /// Cancel the workflow and its running children using a HTTP client. Returns the number of workflows cancelled.
+    fn client_cancel(arg: &Self::Arg) -> impl Future<Output = Result<u32, anyhow::Error>> {
+        cancel_workflow_tree(Self::workflow_id(arg))
+    }
+
+    /// Terminate the workflow and its running children using a HTTP client. Returns the number of workflows terminated.
+    fn client_terminate(arg: &Self::Arg) -> impl Future<Output = Result<u32, anyhow::Error>> {
+        terminate_workflow_tree(Self::workflow_id(arg))
+    }
+
+    /// Pause the workflow and its running children using a HTTP client. Returns the number of workflows paused.
+    fn client_pause(arg: &Self::Arg) -> impl Future<Output = Result<u32, anyhow::Error>> {
+        pause_workflow_tree(Self::workflow_id(arg))
+    }
+
+    /// Resume the paused workflow and its running children using a HTTP client. Returns the number of workflows resumed.
+    fn client_resume(arg: &Self::Arg) -> impl Future<Output = Result<u32, anyhow::Error>> {
+        resume_workflow_tree(Self::workflow_id(arg))
+    }
+
     /// Wait for a workflow to complete using HTTP client, sleeping until it finishes, returning the result of the workflow.
     fn client_wait_for_completion(
         arg: &Self::Arg,
@@ -457,6 +707,24 @@ pub trait TemporalioWorkflowDescriptor:
     }
 }
 
+/// Make the function that runs a workflow on the local backend, in the given generation.
+fn local_run_fn<T: TemporalioWorkflowDescriptor + 'static>(
+    generation: u32,
+    arg: T::Arg,
+) -> local::LocalRunFn {
+    use futures::FutureExt;
+    Box::new(move |ctx| {
+        with_workflow_generation(generation, async move {
+            match T::wf_func(WfContext::Local(ctx), arg).await? {
+                WfExitValue::Normal(result) => Ok(Some(serde_json::to_value(result)?)),
+                WfExitValue::Cancelled => Ok(None),
+                _ => anyhow::bail!("workflow {} exit value not supported locally", T::name()),
+            }
+        })
+        .boxed()
+    })
+}
+
 /// Create a workflow descriptor struct called $id_workflow
 #[macro_export]
 macro_rules! make_workflow {
@@ -555,7 +823,15 @@ pub fn spawn_worker_on_thread<T: TaskQueue>(t: T) -> std::thread::JoinHandle<()>
 }
 
 /// Create tokio runtime and run a worker on it on the current thread.
+/// With the local task backend, tasks run in the process that starts them, so this returns right away.
 pub fn run_worker<T: TaskQueue>(t: T) -> anyhow::Result<()> {
+    if let TaskBackend::Local(_) = task_backend() {
+        info!(
+            "local task backend: no worker needed for queue {:?}",
+            t.queue_name()
+        );
+        return Ok(());
+    }
     use tokio::runtime::Builder;
     hoover3_tracing::set_process_memory_limit(t.max_memory_mb())?;
     let rt = Builder::new_multi_thread()
@@ -581,6 +857,7 @@ async fn run_worker_async<T: TaskQueue>(t: T) -> anyhow::Result<()> {
 #[cfg(test)]
 pub mod test {
     use crate::declare_task_queue;
+    use hoover3_types::tasks::UiWorkflowStatusCode;
 
     use super::*;
 
@@ -598,6 +875,45 @@ pub mod test {
         Ok(_payload)
     }
 
+    make_activity!(
+        TestQueue,
+        test_function_options,
+        u32,
+        u32,
+        retries = 4,
+        timeout = 30,
+        heartbeat = 5,
+    );
+    async fn test_function_options(_payload: u32) -> Result<u32, anyhow::Error> {
+        Err(non_retryable(anyhow::anyhow!("bad input: {_payload}")))
+    }
+
+    #[test]
+    fn test_activity_options() {
+        assert_eq!(
+            test_function_async_activity::options(),
+            ActivityRunOptions::DEFAULT
+        );
+        let options = test_function_options_activity::options();
+        assert_eq!(options.maximum_attempts, 5);
+        assert_eq!(options.start_to_close_timeout, Duration::from_secs(30));
+        assert_eq!(options.heartbeat_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(options.retry_policy().maximum_attempts, 5);
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_error() {
+        let err = test_function_options_activity::func(3).await.unwrap_err();
+        assert!(is_non_retryable(&err));
+        assert!(format!("{err:#}").contains("bad input: 3"));
+        assert!(matches!(
+            classify_activity_error(err),
+            ActivityError::NonRetryable(_)
+        ));
+        let err = anyhow::anyhow!("db timeout").context("saving rows");
+        assert!(!is_non_retryable(&err));
+    }
+
     make_workflow!(TestQueue, sample_workflow2, u32, u32);
     async fn sample_workflow2(ctx: WfContext, arg: u32) -> WorkflowResult<u32> {
         println!("sample_workflow 1 T={:?}", std::thread::current().id());
@@ -608,6 +924,93 @@ pub mod test {
         Ok(WfExitValue::Normal(act1 + act2))
     }
 
+    make_activity!(TestQueue, test_function_slow, u32, u32);
+    async fn test_function_slow(_payload: u32) -> Result<u32, anyhow::Error> {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        Ok(_payload)
+    }
+
+    make_workflow!(TestQueue, sample_slow_workflow, u32, u32);
+    async fn sample_slow_workflow(ctx: WfContext, arg: u32) -> WorkflowResult<u32> {
+        let mut total = 0;
+        for _ in 0..arg {
+            total += test_function_slow_activity::run(&ctx, 1).await?;
+        }
+        Ok(WfExitValue::Normal(total))
+    }
+
+    /// Poll a condition until it holds, failing after a minute.
+    async fn wait_until<F, Fut>(what: &str, mut check: F) -> anyhow::Result<()>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<bool>>,
+    {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(60);
+        while !check().await? {
+            if tokio::time::Instant::now() > deadline {
+                anyhow::bail!("timed out waiting for {what}");
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_task_cancel_pause_resume() -> anyhow::Result<()> {
+        spawn_worker_on_thread(TestQueue);
+        let x = 30_u32;
+        sample_slow_workflow_workflow::client_start(&x).await?;
+        // the running tree is listed from the Temporal visibility store, which lags behind the start
+        wait_until("the workflow to be paused", || async move {
+            Ok(sample_slow_workflow_workflow::client_pause(&x).await? == 1)
+        })
+        .await?;
+        let status = sample_slow_workflow_workflow::client_get_status(&x).await?;
+        assert_eq!(status.task_status, UiWorkflowStatusCode::Running);
+        assert_eq!(sample_slow_workflow_workflow::client_resume(&x).await?, 1);
+        assert_eq!(sample_slow_workflow_workflow::client_cancel(&x).await?, 1);
+
+        wait_until("the workflow to be canceled", || async move {
+            let status = sample_slow_workflow_workflow::client_get_status(&x).await?;
+            Ok(status.task_status != UiWorkflowStatusCode::Running)
+        })
+        .await?;
+        let status = sample_slow_workflow_workflow::client_get_status(&x).await?;
+        assert_eq!(status.task_status, UiWorkflowStatusCode::Canceled);
+        // nothing left to cancel, once the visibility store sees it closed
+        wait_until("no running workflows left", || async move {
+            Ok(sample_slow_workflow_workflow::client_cancel(&x).await? == 0)
+        })
+        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_task_generation_rerun() -> anyhow::Result<()> {
+        spawn_worker_on_thread(TestQueue);
+        let x = 5_u32;
+        let id0 = sample_workflow2_workflow::client_start(&x).await?;
+        assert_eq!(
+            sample_workflow2_workflow::client_wait_for_completion(&x).await?,
+            x + x
+        );
+        let id1 = with_workflow_generation(1, async {
+            let id1 = sample_workflow2_workflow::client_start(&x).await?;
+            assert_eq!(
+                sample_workflow2_workflow::client_wait_for_completion(&x).await?,
+                x + x
+            );
+            anyhow::Ok(id1)
+        })
+        .await?;
+        assert_ne!(id0, id1);
+        assert_eq!(
+            id1,
+            sample_workflow2_workflow::workflow_id_for_generation(&x, 1)
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_task_client_works() {
         let _client = get_client().await.unwrap();
@@ -0,0 +1,90 @@
+//! Workflow pause gate - keeps a paused workflow from starting new activities and child workflows.
+//!
+//! The gate listens to the pause signal sent by [crate::api::control::pause_workflow_tree]
+//! and is kept in a task-local set by the workflow registration, so that
+//! [super::TemporalioActivityDescriptor::run] and [super::TemporalioWorkflowDescriptor::start_as_child]
+//! can wait on it without an extra argument.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::lock::Mutex;
+use futures::{FutureExt, StreamExt};
+use temporal_sdk::{DrainableSignalStream, SignalData, WfContext};
+use tracing::{info, warn};
+
+use crate::api::control::PAUSE_SIGNAL_NAME;
+
+tokio::task_local! {
+    static WORKFLOW_PAUSE_GATE: Arc<Mutex<PauseGate>>;
+}
+
+struct PauseGate {
+    paused: bool,
+    signals: DrainableSignalStream,
+}
+
+impl PauseGate {
+    fn apply(&mut self, signal: SignalData) {
+        match parse_pause_signal(&signal) {
+            Some(paused) => self.paused = paused,
+            None => warn!("ignoring bad pause signal: {:?}", signal.input),
+        }
+    }
+}
+
+/// Read the paused flag from a pause signal.
+fn parse_pause_signal(signal: &SignalData) -> Option<bool> {
+    let payload = signal.input.first()?;
+    serde_json::from_slice(&payload.data).ok()
+}
+
+/// Run the workflow future with a pause gate listening on its signals.
+pub(crate) async fn with_pause_gate<F: Future>(ctx: &WfContext, f: F) -> F::Output {
+    let gate = PauseGate {
+        paused: false,
+        signals: ctx.make_signal_channel(PAUSE_SIGNAL_NAME),
+    };
+    WORKFLOW_PAUSE_GATE
+        .scope(Arc::new(Mutex::new(gate)), f)
+        .await
+}
+
+/// Wait until the current workflow is not paused.
+/// Does nothing when not called from a workflow.
+pub(crate) async fn wait_if_paused() {
+    let Ok(gate) = WORKFLOW_PAUSE_GATE.try_with(|gate| gate.clone()) else {
+        return;
+    };
+    let mut gate = gate.lock().await;
+    while let Some(Some(signal)) = gate.signals.next().now_or_never() {
+        gate.apply(signal);
+    }
+    if gate.paused {
+        info!("workflow paused, waiting for resume signal");
+    }
+    while gate.paused {
+        match gate.signals.next().await {
+            Some(signal) => gate.apply(signal),
+            None => break,
+        }
+    }
+}
+
+#[test]
+fn test_parse_pause_signal() {
+    use temporal_sdk_core_protos::coresdk::AsJsonPayloadExt;
+    let signal = |input| SignalData {
+        input,
+        headers: Default::default(),
+    };
+    assert_eq!(
+        parse_pause_signal(&signal(vec![true.as_json_payload().unwrap()])),
+        Some(true)
+    );
+    assert_eq!(
+        parse_pause_signal(&signal(vec![false.as_json_payload().unwrap()])),
+        Some(false)
+    );
+    assert_eq!(parse_pause_signal(&signal(vec![])), None);
+}
@@ -0,0 +1,190 @@
+//! Integration tests for the in-process task backend - no Temporal server or workers.
+
+#![allow(unused_crate_dependencies)]
+#![allow(unused_extern_crates)]
+
+use std::time::Duration;
+
+use hoover3_taskdef::*;
+use hoover3_types::tasks::UiWorkflowStatusCode;
+
+declare_task_queue!(LocalTestQueue, "taskdef_test_local_task_queue", 2, 4, 256);
+
+/// Test activity
+#[activity(LocalTestQueue)]
+async fn local_test_double(payload: u32) -> anyhow::Result<u32> {
+    Ok(payload * 2)
+}
+
+/// Test activity
+#[activity(LocalTestQueue, retries = 3, retry_interval = 0)]
+async fn local_test_fails_once(payload: u32) -> anyhow::Result<u32> {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    static ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+    if ATTEMPTS.fetch_add(1, Ordering::SeqCst) == 0 {
+        anyhow::bail!("first attempt fails");
+    }
+    Ok(payload)
+}
+
+/// Signal sent by [local_test_blocked] when it starts.
+fn blocked_started() -> &'static tokio::sync::Notify {
+    static STARTED: std::sync::OnceLock<tokio::sync::Notify> = std::sync::OnceLock::new();
+    STARTED.get_or_init(tokio::sync::Notify::new)
+}
+
+/// Test activity
+#[activity(LocalTestQueue)]
+async fn local_test_blocked(_payload: u32) -> anyhow::Result<u32> {
+    blocked_started().notify_one();
+    std::future::pending().await
+}
+
+/// Number of runs of [local_test_counted_child].
+static COUNTED_CHILD_RUNS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+/// Test activity
+#[activity(LocalTestQueue)]
+async fn local_test_count_run(payload: u32) -> anyhow::Result<u32> {
+    COUNTED_CHILD_RUNS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    Ok(payload)
+}
+
+/// Test workflow
+#[workflow(LocalTestQueue)]
+async fn local_test_child(ctx: WfContext, arg: u32) -> WorkflowResult<u32> {
+    let doubled = local_test_double_activity::run(&ctx, arg).await?;
+    let same = local_test_fails_once_activity::run(&ctx, arg).await?;
+    Ok(WfExitValue::Normal(doubled + same))
+}
+
+/// Test workflow
+#[workflow(LocalTestQueue)]
+async fn local_test_parent(ctx: WfContext, arg: u32) -> WorkflowResult<u32> {
+    let mut total = local_test_child_workflow::run_as_child(&ctx, arg).await?;
+    for (_arg, result) in local_test_child_workflow::run_parallel(&ctx, (0..arg).collect()).await? {
+        total += result?;
+    }
+    Ok(WfExitValue::Normal(total))
+}
+
+/// Test workflow
+#[workflow(LocalTestQueue)]
+async fn local_test_blocked_parent(ctx: WfContext, arg: u32) -> WorkflowResult<u32> {
+    Ok(WfExitValue::Normal(
+        local_test_blocked_activity::run(&ctx, arg).await?,
+    ))
+}
+
+/// Test workflow
+#[workflow(LocalTestQueue)]
+async fn local_test_counted_child(ctx: WfContext, arg: u32) -> WorkflowResult<u32> {
+    Ok(WfExitValue::Normal(
+        local_test_count_run_activity::run(&ctx, arg).await?,
+    ))
+}
+
+/// Test workflow
+#[workflow(LocalTestQueue)]
+async fn local_test_counted_parent(ctx: WfContext, arg: u32) -> WorkflowResult<u32> {
+    Ok(WfExitValue::Normal(
+        local_test_counted_child_workflow::run_as_child(&ctx, arg).await?,
+    ))
+}
+
+/// Select the local backend, with one state dir shared by all the tests of this binary:
+/// the backend can only be set once per process.
+fn use_local_backend() -> anyhow::Result<()> {
+    static STATE_DIR: std::sync::OnceLock<std::path::PathBuf> = std::sync::OnceLock::new();
+    let state_dir = STATE_DIR.get_or_init(|| {
+        let state_dir =
+            std::env::temp_dir().join(format!("hoover3_local_tasks_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&state_dir);
+        state_dir
+    });
+    set_task_backend(TaskBackend::Local(state_dir.clone()))
+}
+
+#[tokio::test]
+async fn test_local_backend_workflow_tree() -> anyhow::Result<()> {
+    use_local_backend()?;
+    let x = 4_u32;
+    local_test_parent_workflow::client_start(&x).await?;
+    let result = local_test_parent_workflow::client_wait_for_completion(&x).await?;
+    // child(4) = 12; children 0..4 = 3 * (0 + 1 + 2 + 3); child(4) is not run again
+    assert_eq!(result, 12 + 18);
+
+    let status = local_test_parent_workflow::client_get_status(&x).await?;
+    assert_eq!(status.task_status, UiWorkflowStatusCode::Completed);
+    let tree = api::status_tree::get_workflow_status_tree(status.workflow_id).await?;
+    assert_eq!(tree.root_status, UiWorkflowStatusCode::Completed);
+    assert_eq!(tree.total_counts[&UiWorkflowStatusCode::Completed], 6);
+
+    // closed workflows run again when started again, same as on Temporal
+    local_test_parent_workflow::client_start(&x).await?;
+    assert_eq!(
+        local_test_parent_workflow::client_wait_for_completion(&x).await?,
+        30
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_local_backend_rerun_completed_child() -> anyhow::Result<()> {
+    use std::sync::atomic::Ordering;
+    use_local_backend()?;
+    let x = 7_u32;
+    local_test_counted_parent_workflow::client_start(&x).await?;
+    assert_eq!(
+        local_test_counted_parent_workflow::client_wait_for_completion(&x).await?,
+        7
+    );
+    assert_eq!(COUNTED_CHILD_RUNS.load(Ordering::SeqCst), 1);
+
+    // a second scan runs its child workflow again, even though the first run completed it
+    local_test_counted_parent_workflow::client_start(&x).await?;
+    assert_eq!(
+        local_test_counted_parent_workflow::client_wait_for_completion(&x).await?,
+        7
+    );
+    assert_eq!(COUNTED_CHILD_RUNS.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+/// Same steps as `test_task_cancel_pause_resume` on Temporal, without waiting on
+/// the visibility store: the local backend sees the workflow as running once it starts.
+#[tokio::test]
+async fn test_local_backend_cancel_pause_resume() -> anyhow::Result<()> {
+    use_local_backend()?;
+    let x = 50_u32;
+    local_test_blocked_parent_workflow::client_start(&x).await?;
+    // the activity never returns, so the workflow is running until it is cancelled
+    tokio::time::timeout(Duration::from_secs(30), blocked_started().notified()).await?;
+
+    assert_eq!(
+        local_test_blocked_parent_workflow::client_pause(&x).await?,
+        1
+    );
+    let status = local_test_blocked_parent_workflow::client_get_status(&x).await?;
+    assert_eq!(status.task_status, UiWorkflowStatusCode::Running);
+    assert_eq!(
+        local_test_blocked_parent_workflow::client_resume(&x).await?,
+        1
+    );
+    assert_eq!(
+        local_test_blocked_parent_workflow::client_cancel(&x).await?,
+        1
+    );
+
+    let status = local_test_blocked_parent_workflow::client_get_status(&x).await?;
+    assert_eq!(status.task_status, UiWorkflowStatusCode::Canceled);
+    assert!(local_test_blocked_parent_workflow::client_get_result(&x)
+        .await
+        .is_err());
+    // nothing left to cancel
+    assert_eq!(
+        local_test_blocked_parent_workflow::client_cancel(&x).await?,
+        0
+    );
+    Ok(())
+}
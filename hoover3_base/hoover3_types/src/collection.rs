@@ -1,6 +1,6 @@
 //! Types and structures related to collections.
 
-use crate::identifier::CollectionId;
+use crate::identifier::{CollectionId, DatabaseIdentifier};
 
 /// UI representation of a collection with metadata
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
@@ -16,3 +16,133 @@ pub struct CollectionUiRow {
     /// Timestamp of the most recent modification to the collection
     pub time_modified: chrono::DateTime<chrono::Utc>,
 }
+
+/// A collection backup archive, stored in the object store.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct CollectionBackupInfo {
+    /// The collection that was backed up
+    pub collection_id: CollectionId,
+    /// Name of the backup, unique for the collection
+    pub backup_name: DatabaseIdentifier,
+    /// Key of the archive in the backup bucket
+    pub archive_key: String,
+    /// Size of the archive, in bytes
+    pub size_bytes: u64,
+    /// Time the archive was last modified, as reported by the object store
+    pub last_modified: String,
+}
+
+/// Result of backing up a collection into an archive.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct CollectionBackupResult {
+    /// The collection that was backed up
+    pub collection_id: CollectionId,
+    /// Name of the backup
+    pub backup_name: DatabaseIdentifier,
+    /// Key of the archive in the backup bucket
+    pub archive_key: String,
+    /// Number of tables saved
+    pub table_count: u64,
+    /// Number of rows saved, across all tables
+    pub row_count: u64,
+    /// Size of the archive, in bytes
+    pub size_bytes: u64,
+}
+
+/// Result of restoring a backup archive into a collection.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct CollectionRestoreResult {
+    /// The collection that was backed up
+    pub source_collection_id: CollectionId,
+    /// Name of the backup
+    pub backup_name: DatabaseIdentifier,
+    /// The collection the backup was restored into
+    pub collection_id: CollectionId,
+    /// Number of tables restored
+    pub table_count: u64,
+    /// Number of rows restored, across all tables
+    pub row_count: u64,
+    /// Number of rows added to the rebuilt search index
+    pub search_row_count: u64,
+}
+
+/// Result of rebuilding the search index of a collection from its Scylla rows.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct SearchReindexResult {
+    /// The reindexed collection
+    pub collection_id: CollectionId,
+    /// If the rows were indexed into a fresh index, swapped with the old one at the end
+    pub fresh_index: bool,
+    /// Number of rows indexed for each table, sorted by table name
+    pub tables: Vec<(String, u64)>,
+    /// Number of rows indexed, across all tables
+    pub row_count: u64,
+    /// Number of documents in the search index after the reindex
+    pub document_count: u64,
+}
+
+/// Result of draining the search indexing outbox of a collection, in one or more rounds.
+#[derive(
+    Default,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct SearchOutboxDrainResult {
+    /// Number of outbox entries read
+    pub read_count: u64,
+    /// Number of entries removed from the outbox after their search index task succeeded
+    pub indexed_count: u64,
+    /// Number of entries replaced by a newer entry for the same document
+    pub superseded_count: u64,
+    /// Number of entries sent again after their search index task failed
+    pub retry_count: u64,
+    /// Number of entries dropped after failing too many times
+    pub dropped_count: u64,
+    /// Number of entries still waiting for their search index task, after the last round
+    pub pending_count: u64,
+}
+
+impl std::ops::Add<SearchOutboxDrainResult> for SearchOutboxDrainResult {
+    type Output = SearchOutboxDrainResult;
+    /// Adds the counts of a later round to this one; the pending count is the later one
+    fn add(self, rhs: SearchOutboxDrainResult) -> Self::Output {
+        SearchOutboxDrainResult {
+            read_count: self.read_count + rhs.read_count,
+            indexed_count: self.indexed_count + rhs.indexed_count,
+            superseded_count: self.superseded_count + rhs.superseded_count,
+            retry_count: self.retry_count + rhs.retry_count,
+            dropped_count: self.dropped_count + rhs.dropped_count,
+            pending_count: rhs.pending_count,
+        }
+    }
+}
+
+impl std::ops::AddAssign<SearchOutboxDrainResult> for SearchOutboxDrainResult {
+    /// Adds the counts of a later round to this one in place
+    fn add_assign(&mut self, rhs: SearchOutboxDrainResult) {
+        *self = *self + rhs;
+    }
+}
+
+/// Search indexing lag of a collection: the entries of its indexing outbox
+/// that are not yet confirmed by the search index.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct SearchIndexingLag {
+    /// The collection
+    pub collection_id: CollectionId,
+    /// Number of entries in the outbox
+    pub queued_count: u64,
+    /// Number of those entries already sent to the search index, waiting for their task
+    pub submitted_count: u64,
+    /// Time the oldest entry was queued, if the outbox is not empty
+    pub oldest_queued_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Seconds since the oldest entry was queued, or zero if the outbox is empty
+    pub lag_seconds: u64,
+}
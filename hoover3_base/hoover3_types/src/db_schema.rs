@@ -429,3 +429,257 @@ pub struct GraphEdgeSchemaDynamic {
     /// Map of target node types to their edge types
     pub edges_by_target: BTreeMap<DatabaseIdentifier, Vec<GraphEdgeTypeDynamic>>,
 }
+
+/// Direction in which a graph traversal follows edges.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum GraphTraversalDirection {
+    /// From the source node of the edge to its target node
+    Out,
+    /// From the target node of the edge to its source node
+    In,
+    /// Both directions
+    Both,
+}
+
+/// One hop of a graph traversal: the edges that can be followed from the current node.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphTraversalHop {
+    /// Edge types to follow; if empty, all the edge types of the current node are followed
+    pub edge_types: Vec<GraphEdgeId>,
+    /// Direction to follow the edges in
+    pub direction: GraphTraversalDirection,
+}
+
+/// Limits for a graph traversal, to keep its cost bounded on densely connected nodes.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphTraversalLimits {
+    /// Maximum number of neighbors followed from a single node; the others are skipped
+    pub max_fan_out: u32,
+    /// Maximum number of paths returned
+    pub max_paths: u32,
+    /// If set, every node is reached only once, by the first path found to it;
+    /// otherwise a node can be reached by multiple paths, but never twice in the same path
+    pub unique_nodes: bool,
+}
+
+impl Default for GraphTraversalLimits {
+    fn default() -> Self {
+        Self {
+            max_fan_out: 100,
+            max_paths: 1000,
+            unique_nodes: true,
+        }
+    }
+}
+
+/// Query for a multi-hop graph traversal, starting from a single node.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphTraversalQuery {
+    /// The start node, as a row primary key hash - the same as the search document id
+    pub start_pk_hash: String,
+    /// The hops to make, in order; when `max_depth` is larger than the hop count,
+    /// the last hop is repeated
+    pub hops: Vec<GraphTraversalHop>,
+    /// Maximum number of edges in a path
+    pub max_depth: u32,
+    /// Minimum number of edges in a path; shorter paths are followed, but not returned.
+    /// Must not be over the server depth limit, which caps `max_depth`
+    pub min_depth: u32,
+    /// Fan-out and result limits
+    pub limits: GraphTraversalLimits,
+}
+
+/// An edge of a graph traversal path.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphTraversalPathEdge {
+    /// Type of the edge
+    pub edge_type: GraphEdgeId,
+    /// If the edge was followed from its source to its target
+    pub direction_out: bool,
+}
+
+/// A path found by a graph traversal, from the start node.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphTraversalPath {
+    /// Row primary key hashes of the nodes, starting with the start node
+    pub nodes: Vec<String>,
+    /// The edges between the nodes; `edges[i]` links `nodes[i]` and `nodes[i + 1]`
+    pub edges: Vec<GraphTraversalPathEdge>,
+}
+
+/// Result of repairing the stored graph edges of a collection, for one or more token ranges.
+#[derive(
+    Default,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct GraphEdgeRepairResult {
+    /// Number of edge rows read, counting each direction of an edge separately
+    pub scanned_count: u64,
+    /// Number of edges with an endpoint missing from the node map or from its model table
+    pub dangling_count: u64,
+    /// Number of dangling edges removed
+    pub removed_count: u64,
+}
+
+impl std::ops::Add<GraphEdgeRepairResult> for GraphEdgeRepairResult {
+    type Output = GraphEdgeRepairResult;
+    fn add(self, rhs: GraphEdgeRepairResult) -> Self::Output {
+        GraphEdgeRepairResult {
+            scanned_count: self.scanned_count + rhs.scanned_count,
+            dangling_count: self.dangling_count + rhs.dangling_count,
+            removed_count: self.removed_count + rhs.removed_count,
+        }
+    }
+}
+
+impl std::ops::AddAssign<GraphEdgeRepairResult> for GraphEdgeRepairResult {
+    fn add_assign(&mut self, rhs: GraphEdgeRepairResult) {
+        *self = *self + rhs;
+    }
+}
+
+/// File format of a graph export.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum GraphExportFormat {
+    /// GraphML XML format
+    GraphMl,
+    /// GEXF XML format, used by Gephi
+    Gexf,
+    /// JSON Graph Format
+    JsonGraph,
+}
+
+impl GraphExportFormat {
+    /// File name extension of the format.
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            GraphExportFormat::GraphMl => "graphml",
+            GraphExportFormat::Gexf => "gexf",
+            GraphExportFormat::JsonGraph => "json",
+        }
+    }
+
+    /// Get the format from a file name extension.
+    pub fn from_file_extension(extension: &str) -> Option<Self> {
+        [
+            GraphExportFormat::GraphMl,
+            GraphExportFormat::Gexf,
+            GraphExportFormat::JsonGraph,
+        ]
+        .into_iter()
+        .find(|format| format.file_extension() == extension)
+    }
+}
+
+/// What to export from the graph of a collection.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphExportRequest {
+    /// File format of the export
+    pub format: GraphExportFormat,
+    /// Edge types to export; all the declared edge types if empty
+    pub edge_types: Vec<GraphEdgeId>,
+    /// Row primary key hashes of the nodes to export; all the nodes if empty
+    pub nodes: Vec<String>,
+    /// With a node subset, also export the edges from those nodes to nodes outside the subset
+    pub include_neighbors: bool,
+}
+
+/// Result of exporting the graph of a collection into a file in the object store.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphExportResult {
+    /// The exported collection
+    pub collection_id: CollectionId,
+    /// Name of the export, unique for the collection
+    pub export_name: DatabaseIdentifier,
+    /// Key of the file in the export bucket
+    pub file_key: String,
+    /// Number of nodes in the file
+    pub node_count: u64,
+    /// Number of edges in the file
+    pub edge_count: u64,
+    /// Size of the file, in bytes
+    pub size_bytes: u64,
+}
+
+/// A graph export file, stored in the object store.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphExportInfo {
+    /// The exported collection
+    pub collection_id: CollectionId,
+    /// Name of the export, unique for the collection
+    pub export_name: DatabaseIdentifier,
+    /// File format of the export
+    pub format: GraphExportFormat,
+    /// Key of the file in the export bucket
+    pub file_key: String,
+    /// Size of the file, in bytes
+    pub size_bytes: u64,
+    /// Time the file was last modified, as reported by the object store
+    pub last_modified: String,
+}
+
+/// Number of stored edges of one type at a graph node, read from the edge counters.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphNodeEdgeCount {
+    /// Type of the edges
+    pub edge_type: GraphEdgeId,
+    /// Number of edges from this node, as the source
+    pub out_count: u64,
+    /// Number of edges to this node, as the target
+    pub in_count: u64,
+}
+
+/// Number of nodes with a degree in a range; the ranges of a distribution are powers of two.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphDegreeBucket {
+    /// Smallest degree in the bucket
+    pub min_degree: u64,
+    /// Largest degree in the bucket
+    pub max_degree: u64,
+    /// Number of nodes with a degree in the bucket
+    pub node_count: u64,
+}
+
+/// A node among the most connected nodes of an edge type.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphTopNode {
+    /// Row primary key hash of the node
+    pub pk_hash: String,
+    /// Label of the node, taken from its row
+    pub label: String,
+    /// Number of edges of the node
+    pub degree: u64,
+}
+
+/// Degree statistics of one stored edge type and direction, over the nodes with edges.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GraphDegreeStatistics {
+    /// Type of the edges
+    pub edge_type: GraphEdgeId,
+    /// If the degrees count the edges from the nodes (out) or to the nodes (in)
+    pub direction_out: bool,
+    /// Number of nodes with at least one edge
+    pub node_count: u64,
+    /// Total number of edges
+    pub edge_count: u64,
+    /// Largest degree of a node
+    pub max_degree: u64,
+    /// Number of nodes by degree range, in increasing order of degree
+    pub distribution: Vec<GraphDegreeBucket>,
+    /// The most connected nodes, largest degree first
+    pub top_nodes: Vec<GraphTopNode>,
+    /// When the statistics were computed
+    pub computed_at: Timestamp,
+}
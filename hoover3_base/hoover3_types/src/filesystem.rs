@@ -117,6 +117,36 @@ impl std::ops::AddAssign<FsScanHashesResult> for FsScanHashesResult {
     }
 }
 
+/// Results from removing the rows of a datasource
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct FsRemoveDatasourceResult {
+    /// Number of directories removed
+    pub dir_count: u64,
+    /// Number of files removed
+    pub file_count: u64,
+    /// Number of blobs left to be checked by the garbage collector
+    pub blob_candidate_count: u64,
+}
+
+impl std::ops::Add<FsRemoveDatasourceResult> for FsRemoveDatasourceResult {
+    type Output = FsRemoveDatasourceResult;
+    /// Adds two removal results together
+    fn add(self, rhs: FsRemoveDatasourceResult) -> Self::Output {
+        FsRemoveDatasourceResult {
+            dir_count: self.dir_count + rhs.dir_count,
+            file_count: self.file_count + rhs.file_count,
+            blob_candidate_count: self.blob_candidate_count + rhs.blob_candidate_count,
+        }
+    }
+}
+
+impl std::ops::AddAssign<FsRemoveDatasourceResult> for FsRemoveDatasourceResult {
+    /// Adds another removal result to this one in place
+    fn add_assign(&mut self, rhs: FsRemoveDatasourceResult) {
+        *self = *self + rhs;
+    }
+}
+
 /// Results from all scanning tasks
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub struct FsScanResult {
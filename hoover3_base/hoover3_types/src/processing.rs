@@ -2,7 +2,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    filesystem::FsScanResult,
+    filesystem::{FsRemoveDatasourceResult, FsScanResult},
     identifier::{CollectionId, DatabaseIdentifier},
 };
 
@@ -19,6 +19,46 @@ pub struct ProcessDatasourceTaskResult {
     pub process: CollectionProcessingResult,
 }
 
+/// Result of removing a datasource and the data derived from it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RemoveDatasourceTaskResult {
+    /// The collection id.
+    pub collection_id: CollectionId,
+    /// The datasource id.
+    pub datasource_id: DatabaseIdentifier,
+    /// The rows removed from the filesystem scanner tables.
+    pub remove: FsRemoveDatasourceResult,
+    /// The blob garbage collection result.
+    pub blob_gc: BlobGcResult,
+}
+
+/// Result of checking blobs for references, and removing the unreferenced ones.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BlobGcResult {
+    /// The number of blobs checked.
+    pub checked_count: u64,
+    /// The number of blobs removed, because nothing referenced them anymore.
+    pub removed_count: u64,
+}
+
+impl std::ops::Add<BlobGcResult> for BlobGcResult {
+    type Output = BlobGcResult;
+    /// Adds two garbage collection results together
+    fn add(self, rhs: BlobGcResult) -> Self::Output {
+        BlobGcResult {
+            checked_count: self.checked_count + rhs.checked_count,
+            removed_count: self.removed_count + rhs.removed_count,
+        }
+    }
+}
+
+impl std::ops::AddAssign<BlobGcResult> for BlobGcResult {
+    /// Adds another garbage collection result to this one in place
+    fn add_assign(&mut self, rhs: BlobGcResult) {
+        *self = *self + rhs;
+    }
+}
+
 /// Result of processing a number of de-duplicated blobs.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct CollectionProcessingResult {
@@ -36,6 +76,38 @@ pub struct CollectionProcessingResult {
 
     /// Results for large pages
     pub large_page_results: ProcessPageResult,
+
+    /// Results for the OCR pass, if OCR is enabled for the collection.
+    #[serde(default)]
+    pub ocr_results: ProcessPageResult,
+}
+
+/// Per-collection OCR settings, for scanned PDFs and images.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct OcrSettings {
+    /// Whether OCR runs for the collection.
+    pub enabled: bool,
+    /// Tesseract language codes, e.g. "eng", "deu", "ron".
+    pub languages: Vec<String>,
+}
+
+impl Default for OcrSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            languages: vec!["eng".to_string()],
+        }
+    }
+}
+
+impl OcrSettings {
+    /// Language string in the format Tesseract expects, e.g. "eng+deu".
+    pub fn tesseract_languages(&self) -> String {
+        if self.languages.is_empty() {
+            return "eng".to_string();
+        }
+        self.languages.join("+")
+    }
 }
 
 /// The result of processing a page.
@@ -48,3 +120,83 @@ pub struct ProcessPageResult {
     /// The number of items that were not processed correctly.
     pub item_errors: i32,
 }
+
+impl std::ops::Add<ProcessPageResult> for ProcessPageResult {
+    type Output = ProcessPageResult;
+    /// Adds two page results together
+    fn add(self, rhs: ProcessPageResult) -> Self::Output {
+        ProcessPageResult {
+            item_count: self.item_count + rhs.item_count,
+            item_success: self.item_success + rhs.item_success,
+            item_errors: self.item_errors + rhs.item_errors,
+        }
+    }
+}
+
+impl std::ops::AddAssign<ProcessPageResult> for ProcessPageResult {
+    /// Adds another page result to this one in place
+    fn add_assign(&mut self, rhs: ProcessPageResult) {
+        *self = *self + rhs;
+    }
+}
+
+/// A blob that failed a processing stage, as listed in the UI.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProcessingFailureUiRow {
+    /// The sha3-256 hash of the blob.
+    pub blob_sha3_256: String,
+    /// The processing stage that failed, e.g. "download", "tika".
+    pub stage: String,
+    /// The mime type of the blob, if it was detected before the failure.
+    pub mime_type: Option<String>,
+    /// The error, with its chain of causes.
+    pub error: String,
+    /// How many times the stage failed for this blob.
+    pub attempt_count: i32,
+    /// The plan page the blob was processed in.
+    pub plan_page_id: i32,
+    /// When the stage failed the last time.
+    pub failed_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A page of the blobs that failed processing.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProcessingFailurePage {
+    /// The failed blobs and stages of the page.
+    pub rows: Vec<ProcessingFailureUiRow>,
+    /// The blob to continue the listing after, if there are more pages.
+    pub next_page: Option<String>,
+}
+
+/// Number of failed blobs for a mime type and processing stage.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ProcessingFailureCount {
+    /// The mime type of the blobs; "unknown" if it was not detected.
+    pub mime_type: String,
+    /// The processing stage that failed.
+    pub stage: String,
+    /// The number of failed blobs.
+    pub count: i64,
+}
+
+/// Failed blob counts of a page of the failure records.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessingFailureCountPage {
+    /// The counts of the page, by mime type and stage.
+    pub counts: Vec<ProcessingFailureCount>,
+    /// The blob to continue counting after, if there are more pages.
+    pub next_page: Option<String>,
+}
+
+/// Result of processing the failed blobs of a collection again.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryFailedBlobsResult {
+    /// The number of failed blobs found.
+    pub failed_blob_count: i32,
+    /// Results for processing the failed blobs again.
+    pub retry_results: ProcessPageResult,
+    /// Results for the new blobs found in the retried containers.
+    pub new_page_results: ProcessPageResult,
+    /// Results for running OCR again on the blobs that failed it.
+    pub ocr_results: ProcessPageResult,
+}
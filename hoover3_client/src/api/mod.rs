@@ -14,7 +14,9 @@ use hoover3_types::db_schema::DynamicQueryResponse;
 use hoover3_types::docker_health::*;
 use hoover3_types::filesystem::FsMetadataBasic;
 use hoover3_types::identifier::*;
-use hoover3_types::processing::ProcessDatasourceTaskResult;
+use hoover3_types::processing::{
+    OcrSettings, ProcessDatasourceTaskResult, ProcessingFailureCountPage, ProcessingFailurePage,
+};
 use hoover3_types::tasks::*;
 
 /// Struct records previous server calls, their timing and results.
@@ -245,6 +247,217 @@ server_wrapper!(
     UiWorkflowStatus
 );
 
+server_wrapper!(
+    hoover3_server::api::processing_tasks,
+    start_reprocessing,
+    (CollectionId, DatabaseIdentifier),
+    String
+);
+
+server_wrapper!(
+    hoover3_server::api::processing_tasks,
+    get_reprocessing_status,
+    (CollectionId, DatabaseIdentifier),
+    UiWorkflowStatus
+);
+
+server_wrapper!(
+    hoover3_server::api::processing_tasks,
+    start_datasource_removal,
+    (CollectionId, DatabaseIdentifier),
+    String
+);
+
+server_wrapper!(
+    hoover3_server::api::processing_tasks,
+    get_datasource_removal_status,
+    (CollectionId, DatabaseIdentifier),
+    UiWorkflowStatus
+);
+
+server_wrapper!(
+    hoover3_server::api::processing_tasks,
+    restart_processing,
+    (CollectionId, DatabaseIdentifier),
+    String
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_processing::api,
+    get_processing_generation,
+    CollectionId,
+    u32
+);
+
+server_wrapper!(
+    hoover3_server::api::processing_tasks,
+    cancel_processing,
+    (CollectionId, DatabaseIdentifier),
+    u32
+);
+
+server_wrapper!(
+    hoover3_server::api::processing_tasks,
+    terminate_processing,
+    (CollectionId, DatabaseIdentifier),
+    u32
+);
+
+server_wrapper!(
+    hoover3_server::api::processing_tasks,
+    pause_processing,
+    (CollectionId, DatabaseIdentifier),
+    u32
+);
+
+server_wrapper!(
+    hoover3_server::api::processing_tasks,
+    resume_processing,
+    (CollectionId, DatabaseIdentifier),
+    u32
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_processing::api,
+    get_ocr_settings,
+    CollectionId,
+    OcrSettings
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_processing::api,
+    set_ocr_settings,
+    (CollectionId, OcrSettings),
+    OcrSettings
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_processing::api,
+    list_processing_failures,
+    (CollectionId, Option<String>, Option<String>),
+    ProcessingFailurePage
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_processing::api,
+    count_processing_failures,
+    (CollectionId, Option<String>),
+    ProcessingFailureCountPage
+);
+
+server_wrapper!(
+    hoover3_server::api::processing_tasks,
+    start_retry_failed,
+    CollectionId,
+    String
+);
+
+server_wrapper!(
+    hoover3_server::api::processing_tasks,
+    get_retry_failed_status,
+    CollectionId,
+    UiWorkflowStatus
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    list_collection_backups,
+    CollectionId,
+    Vec<CollectionBackupInfo>
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    start_collection_backup,
+    (CollectionId, DatabaseIdentifier),
+    String
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    get_collection_backup_status,
+    (CollectionId, DatabaseIdentifier),
+    UiWorkflowStatus
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    start_collection_restore,
+    (CollectionId, DatabaseIdentifier, CollectionId),
+    String
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    get_collection_restore_status,
+    (CollectionId, DatabaseIdentifier, CollectionId),
+    UiWorkflowStatus
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    start_collection_reindex,
+    (CollectionId, bool),
+    String
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    get_search_indexing_lag,
+    CollectionId,
+    SearchIndexingLag
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    start_collection_search_indexer,
+    CollectionId,
+    String
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    start_collection_graph_repair,
+    CollectionId,
+    String
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    start_collection_graph_degrees,
+    CollectionId,
+    String
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    start_collection_graph_export,
+    (
+        CollectionId,
+        DatabaseIdentifier,
+        hoover3_types::db_schema::GraphExportRequest
+    ),
+    String
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    get_collection_graph_export_status,
+    (
+        CollectionId,
+        DatabaseIdentifier,
+        hoover3_types::db_schema::GraphExportRequest
+    ),
+    UiWorkflowStatus
+);
+
+server_wrapper!(
+    hoover3_server::hoover3_database_operations::api,
+    list_collection_graph_exports,
+    CollectionId,
+    Vec<hoover3_types::db_schema::GraphExportInfo>
+);
+
 server_wrapper!(
     hoover3_taskdef::api::status_tree,
     get_workflow_status_tree,
@@ -307,3 +520,28 @@ server_wrapper!(
     (),
     hoover3_types::db_schema::GraphEdgeSchemaDynamic
 );
+
+server_wrapper!(
+    hoover3_database::client_query::search_api,
+    graph_traversal_paths,
+    (CollectionId, hoover3_types::db_schema::GraphTraversalQuery),
+    Vec<hoover3_types::db_schema::GraphTraversalPath>
+);
+
+server_wrapper!(
+    hoover3_database::client_query::search_api,
+    graph_node_degrees,
+    (CollectionId, String),
+    Vec<hoover3_types::db_schema::GraphNodeEdgeCount>
+);
+
+server_wrapper!(
+    hoover3_database::client_query::search_api,
+    graph_degree_distributions,
+    (
+        CollectionId,
+        Vec<hoover3_types::db_schema::GraphEdgeId>,
+        u64
+    ),
+    Vec<hoover3_types::db_schema::GraphDegreeStatistics>
+);
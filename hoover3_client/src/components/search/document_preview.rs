@@ -3,7 +3,7 @@ use hoover3_types::db_schema::GraphEdgeSchemaDynamic;
 use hoover3_types::identifier::CollectionId;
 
 use crate::components::search::context::SearchParams;
-use crate::api::get_graph_schema;
+use crate::api::{get_graph_schema, graph_node_degrees};
 use dioxus::logger::tracing;
 
 #[component]
@@ -34,6 +34,11 @@ pub fn DocumentPreviewDisplay() -> Element {
                 selected_table_type: selected_table_type
             }
 
+            NodeDegreesDisplay {
+                selected_id: selected_id,
+                selected_collection_id: selected_collection_id
+            }
+
             if let Some(Some(schema)) = graph_schema.read().as_ref() {
                 GraphSchemaDisplay {
                     schema: schema.clone()
@@ -107,6 +112,61 @@ fn DocumentPreviewHeader(
     }
 }
 
+/// Component for displaying the edge counts of the selected document, by edge type
+#[component]
+fn NodeDegreesDisplay(
+    selected_id: ReadOnlySignal<Option<String>>,
+    selected_collection_id: ReadOnlySignal<Option<CollectionId>>
+) -> Element {
+    let degrees = use_resource(move || async move {
+        let (Some(pk_hash), Some(collection_id)) =
+            (selected_id.read().clone(), selected_collection_id.read().clone())
+        else {
+            return None;
+        };
+        match graph_node_degrees((collection_id, pk_hash)).await {
+            Ok(degrees) => Some(degrees),
+            Err(e) => {
+                tracing::error!("Failed to fetch node degrees: {:?}", e);
+                None
+            }
+        }
+    });
+
+    rsx! {
+        if let Some(Some(degrees)) = degrees.read().as_ref() {
+            if !degrees.is_empty() {
+                div {
+                    class: "node-degrees",
+                    style: "background-color: #f8fafc; padding: 1rem; border-radius: 0.375rem;",
+
+                    h3 {
+                        style: "font-size: 1.25rem; font-weight: 600; margin-bottom: 0.5rem;",
+                        "Graph Edges"
+                    }
+
+                    table {
+                        style: "width: 100%; border-collapse: collapse;",
+                        tr {
+                            th { style: "text-align: left; padding: 0.5rem; border-bottom: 1px solid #e2e8f0;", "Edge Type" }
+                            th { style: "text-align: right; padding: 0.5rem; border-bottom: 1px solid #e2e8f0;", "Out" }
+                            th { style: "text-align: right; padding: 0.5rem; border-bottom: 1px solid #e2e8f0;", "In" }
+                        }
+                        for degree in degrees.iter() {
+                            tr {
+                                key: "{degree.edge_type}",
+                                td { style: "padding: 0.5rem; border-bottom: 1px solid #e2e8f0;", "{degree.edge_type}" }
+                                td { style: "text-align: right; padding: 0.5rem; border-bottom: 1px solid #e2e8f0;", "{degree.out_count}" }
+                                td { style: "text-align: right; padding: 0.5rem; border-bottom: 1px solid #e2e8f0;", "{degree.in_count}" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 /// Component for displaying the graph schema information
 #[component]
 fn GraphSchemaDisplay(schema: GraphEdgeSchemaDynamic) -> Element {
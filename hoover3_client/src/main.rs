@@ -2,6 +2,13 @@
 
 /// Main dioxus Entrypoint. Sets up launch configurations for Dioxus, as well as server backend main function.
 pub fn main() {
+    // with the local task backend, extraction jobs run in this same executable,
+    // started again with a subcommand
+    #[cfg(feature = "server")]
+    if let Some(result) = hoover3_server::run_sandbox_subcommand() {
+        result.expect("extraction job failed");
+        return;
+    }
     hoover3_tracing::init_tracing();
     hoover3_tracing::set_process_memory_limit(4096).unwrap();
 
@@ -29,6 +36,12 @@ pub fn main() {
                 hoover3_server::init_server_plugins().unwrap();
                 // hoover3_server::migrate_all().await.unwrap();
 
+                // with the local task backend there is no worker process to run the sweep
+                if let hoover3_taskdef::TaskBackend::Local(_) = hoover3_taskdef::task_backend() {
+                    hoover3_server::hoover3_database_operations::indexer::spawn_search_outbox_sweeper()
+                        .unwrap();
+                }
+
                 // Start workers. Dioxus doesn't reap threads, so if we use `spawn_worker_on_thread` here,
                 //
 
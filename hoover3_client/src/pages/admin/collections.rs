@@ -3,6 +3,8 @@ use std::collections::BTreeMap;
 use dioxus::prelude::*;
 use dioxus_logger::tracing::{error, info};
 use hoover3_types::datasource::DatasourceUiRow;
+use hoover3_types::processing::{ProcessingFailureCount, ProcessingFailureUiRow};
+use hoover3_types::tasks::UiWorkflowStatusCode;
 use hoover3_types::{collection::CollectionUiRow, identifier::CollectionId};
 
 use crate::api::*;
@@ -179,6 +181,7 @@ pub fn CollectionAdminDetailsPage(collection_id: CollectionId) -> Element {
     rsx! {
         CollectionInfoCard {c: collection_id.clone()}
         CollectionDatasourceListCard { c:  collection_id.clone() }
+        CollectionProcessingFailuresCard { c: collection_id.clone() }
     }
 }
 
@@ -287,3 +290,192 @@ fn CollectionDatasourceListCard(c: CollectionId) -> Element {
         }
     }
 }
+
+impl DataRowDisplay for ProcessingFailureCount {}
+
+impl DataRowDisplay for ProcessingFailureUiRow {
+    fn get_headers() -> Vec<&'static str> {
+        vec![
+            "Blob",
+            "Stage",
+            "Mime Type",
+            "Error",
+            "Attempts",
+            "Failed At",
+        ]
+    }
+
+    fn render_cell(&self, header_name: &str) -> Element {
+        match header_name {
+            "Blob" => rsx! { code { "{self.blob_sha3_256}" } },
+            "Stage" => rsx! { "{self.stage}" },
+            "Mime Type" => rsx! { "{self.mime_type.as_deref().unwrap_or(\"unknown\")}" },
+            "Error" => rsx! { "{self.error}" },
+            "Attempts" => rsx! { "{self.attempt_count}" },
+            "Failed At" => rsx! { "{self.failed_at}" },
+            _ => panic!("unknown {header_name}"),
+        }
+    }
+}
+
+/// Component that lists the blobs that failed processing, counted by mime type and stage,
+/// with a button to process the failed blobs again.
+#[component]
+fn CollectionProcessingFailuresCard(c: CollectionId) -> Element {
+    let mut mime_filter = use_signal(|| None::<String>);
+    // the blob the failure listing starts after; None for the first page
+    let mut failures_after = use_signal(|| None::<String>);
+    let c2 = c.clone();
+    let mut counts_res = use_resource(move || {
+        let c2 = c2.clone();
+        async move {
+            // the counts come in pages of the failure table; add them up
+            let mut counts = BTreeMap::<(String, String), i64>::new();
+            let mut after = None;
+            loop {
+                let page = crate::api::count_processing_failures((c2.clone(), after)).await?;
+                for count in page.counts {
+                    *counts.entry((count.mime_type, count.stage)).or_default() += count.count;
+                }
+                after = page.next_page;
+                if after.is_none() {
+                    break;
+                }
+            }
+            Ok::<_, ServerFnError>(
+                counts
+                    .into_iter()
+                    .map(|((mime_type, stage), count)| ProcessingFailureCount {
+                        mime_type,
+                        stage,
+                        count,
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        }
+    });
+    let counts = use_memo(move || {
+        if let Some(Ok(r)) = counts_res.read().as_ref() {
+            r.clone()
+        } else {
+            vec![]
+        }
+    });
+    let c2 = c.clone();
+    let mut failures_res = use_resource(move || {
+        let c2 = c2.clone();
+        let mime = mime_filter.read().clone();
+        let after = failures_after.read().clone();
+        async move { crate::api::list_processing_failures((c2, mime, after)).await }
+    });
+    let failures = use_memo(move || {
+        if let Some(Ok(r)) = failures_res.read().as_ref() {
+            r.rows.clone()
+        } else {
+            vec![]
+        }
+    });
+    let failures_next_page = use_memo(move || {
+        if let Some(Ok(r)) = failures_res.read().as_ref() {
+            r.next_page.clone()
+        } else {
+            None
+        }
+    });
+    let c2 = c.clone();
+    let mut retry_status_res =
+        use_resource(move || crate::api::get_retry_failed_status(c2.clone()));
+    let retry_running = use_memo(move || {
+        retry_status_res.read().as_ref().is_some_and(|s| {
+            s.as_ref()
+                .is_ok_and(|s| s.task_status == UiWorkflowStatusCode::Running)
+        })
+    });
+
+    spawn(async move {
+        loop {
+            crate::time::sleep(std::time::Duration::from_secs(3)).await;
+            if !*retry_running.peek() {
+                break;
+            }
+            retry_status_res.restart();
+            if !*retry_running.peek() {
+                counts_res.restart();
+                failures_after.set(None);
+                failures_res.restart();
+            }
+        }
+    });
+
+    let c2 = c.clone();
+    let filter_title = match mime_filter.read().as_ref() {
+        Some(mime) => format!("Failed Blobs - {mime}"),
+        None => "Failed Blobs".to_string(),
+    };
+    rsx! {
+        HtmlTable {
+            title: "Processing Failures",
+            data: counts,
+            extra_buttons: Some(Callback::new(move |_| {
+                let c2 = c2.clone();
+                rsx!{
+                button {
+                    disabled: *retry_running.read() || counts.read().is_empty(),
+                    onclick: move |_| {
+                        let c2 = c2.clone();
+                        async move {
+                            match crate::api::start_retry_failed(c2).await {
+                                Ok(_) => retry_status_res.restart(),
+                                Err(e) => error!("failed to retry failed blobs: {e:#?}"),
+                            }
+                        }
+                    },
+                    if *retry_running.read() { "RETRYING..." } else { "RETRY FAILED" }
+                }
+            }})),
+            extra: Some(("Actions", Callback::new(move |row: ProcessingFailureCount| {
+                rsx!{
+                button {
+                    class: "secondary",
+                    onclick: move |_| {
+                        failures_after.set(None);
+                        mime_filter.set(Some(row.mime_type.clone()));
+                    },
+                    "Show"
+                }
+                }
+            })))
+        }
+        HtmlTable {
+            title: filter_title,
+            data: failures,
+            extra_buttons: Some(Callback::new(move |_| {
+                rsx!{
+                if mime_filter.read().is_some() {
+                    button {
+                        class: "secondary",
+                        onclick: move |_| {
+                            failures_after.set(None);
+                            mime_filter.set(None);
+                        },
+                        "Show All"
+                    }
+                }
+                if failures_after.read().is_some() {
+                    button {
+                        class: "secondary",
+                        onclick: move |_| failures_after.set(None),
+                        "First Page"
+                    }
+                }
+                if let Some(next_page) = failures_next_page.read().clone() {
+                    button {
+                        class: "secondary",
+                        onclick: move |_| failures_after.set(Some(next_page.clone())),
+                        "Next Page"
+                    }
+                }
+            }})),
+        }
+    }
+}
@@ -69,6 +69,8 @@ pub fn DatasourceAdminDetailsPage(
         tracing::info!("done scanning for results");
     });
 
+    let restart_c = collection_id.clone();
+    let restart_d = datasource_id.clone();
     rsx! {
         div {
             class: "container-fluid",
@@ -76,6 +78,28 @@ pub fn DatasourceAdminDetailsPage(
                 "Collection {collection_id}"
             }
             DatasourceInfoCard {c: c.clone(), ds: d.clone()}
+            if scan_status.read().as_ref().is_some_and(|s| s.as_ref().is_ok_and(|s| s.task_status == UiWorkflowStatusCode::Running)) {
+                ProcessingControlButtons {
+                    c: c.clone(),
+                    ds: d.clone(),
+                    on_change: move |_| scan_status_res.restart(),
+                }
+            } else if scan_status.read().is_some() {
+                button {
+                    class: "secondary",
+                    onclick: move |_| {
+                        let c = restart_c.clone();
+                        let d = restart_d.clone();
+                        async move {
+                            if crate::api::restart_processing((c, d)).await.is_ok() {
+                                scan_result.set(None);
+                                scan_status_res.restart();
+                            }
+                        }
+                    },
+                    "RERUN FROM SCRATCH"
+                }
+            }
             WorkflowStatusDisplay {
                 title: "Scan".to_string(),
                 scan_status,
@@ -117,6 +141,62 @@ fn DatasourceInfoCard(c: CollectionId, ds: DatabaseIdentifier) -> Element {
     }
 }
 
+/// Buttons for pausing, resuming, cancelling and terminating the processing of a data source.
+#[component]
+fn ProcessingControlButtons(
+    c: CollectionId,
+    ds: DatabaseIdentifier,
+    on_change: Callback,
+) -> Element {
+    let mut message = use_signal(|| "".to_string());
+    let run = use_callback(move |action: &'static str| {
+        let c = c.clone();
+        let ds = ds.clone();
+        spawn(async move {
+            let arg = (c, ds);
+            let result = match action {
+                "pause" => crate::api::pause_processing(arg).await,
+                "resume" => crate::api::resume_processing(arg).await,
+                "cancel" => crate::api::cancel_processing(arg).await,
+                _ => crate::api::terminate_processing(arg).await,
+            };
+            match result {
+                Ok(count) => message.set(format!("{action}: {count} workflows")),
+                Err(e) => message.set(format!("{action} failed: {e}")),
+            }
+            on_change.call(());
+        });
+    });
+    rsx! {
+        div {
+            role: "group",
+            button {
+                class: "secondary",
+                onclick: move |_| run.call("pause"),
+                "PAUSE"
+            }
+            button {
+                class: "secondary",
+                onclick: move |_| run.call("resume"),
+                "RESUME"
+            }
+            button {
+                class: "contrast",
+                onclick: move |_| run.call("cancel"),
+                "CANCEL"
+            }
+            button {
+                class: "contrast outline",
+                onclick: move |_| run.call("terminate"),
+                "TERMINATE"
+            }
+        }
+        if !message.read().is_empty() {
+            p { "{message}" }
+        }
+    }
+}
+
 /// Component that displays the status of a workflow, including self-refreshing progress bar.
 #[component]
 pub fn WorkflowStatusDisplay(
@@ -1,6 +1,7 @@
 //! Datasource management module that provides functionality to create, update, and delete datasources.
 
 use charybdis::operations::InsertWithCallbacks;
+use charybdis::operations::UpdateWithCallbacks;
 use hoover3_database::db_management::redis::drop_redis_cache;
 use hoover3_database::db_management::redis::with_redis_cache;
 use hoover3_database::db_management::redis::with_redis_lock;
@@ -29,7 +30,9 @@ pub async fn get_all_datasources(c: CollectionId) -> Result<Vec<DatasourceUiRow>
     let mut v = vec![];
     use futures::StreamExt;
     while let Some(Ok(x)) = rows.next().await {
-        v.push(x.to_ui_row(&c));
+        if x.time_deleted.is_none() {
+            v.push(x.to_ui_row(&c));
+        }
     }
     Ok(v)
 }
@@ -48,6 +51,9 @@ pub async fn create_datasource(
                 .execute(&session)
                 .await
             {
+                if ds.time_deleted.is_some() {
+                    anyhow::bail!("datasource {name} is being deleted");
+                }
                 return Ok(ds.to_ui_row(&c));
             }
             let now = chrono::offset::Utc::now();
@@ -57,6 +63,7 @@ pub async fn create_datasource(
                 datasource_settings: settings_serialized,
                 time_created: now,
                 time_modified: now,
+                time_deleted: None,
             };
             let cb_info = DatabaseExtraCallbacks::new(&c).await?;
             DatasourceDbRow::insert_cb(&mut row, &cb_info)
@@ -69,13 +76,51 @@ pub async fn create_datasource(
     .await?
 }
 
-/// Drop a datasource from the given collection. Dropping a non-existent datasource is a no-op.
+/// Mark a datasource as deleted, hiding it from the datasource list. Dropping a non-existent datasource is a no-op.
+/// The data derived from the datasource is removed by the datasource removal workflow,
+/// which calls [purge_datasource] at the end.
 pub async fn drop_datasource((c, name): (CollectionId, DatabaseIdentifier)) -> anyhow::Result<()> {
     tokio::spawn(async move {
         let session = ScyllaDatabaseHandle::collection_session(&c).await?;
-        DatasourceDbRow::delete_by_datasource_id(name.to_string())
+        let Some(mut row) = DatasourceDbRow::maybe_find_first_by_datasource_id(name.to_string())
             .execute(&session)
-            .await?;
+            .await?
+        else {
+            return Ok(());
+        };
+        if row.time_deleted.is_none() {
+            let now = chrono::offset::Utc::now();
+            row.time_deleted = Some(now);
+            row.time_modified = now;
+            let cb_info = DatabaseExtraCallbacks::new(&c).await?;
+            DatasourceDbRow::update_cb(&mut row, &cb_info)
+                .execute(&session)
+                .await?;
+        }
+
+        drop_redis_cache("get_datasource", &(c, name)).await?;
+        Ok(())
+    })
+    .await?
+}
+
+/// Delete the datasource row from the given collection, after its derived data was removed.
+/// Purging a non-existent datasource is a no-op.
+pub async fn purge_datasource((c, name): (CollectionId, DatabaseIdentifier)) -> anyhow::Result<()> {
+    tokio::spawn(async move {
+        let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+        if let Some(row) = DatasourceDbRow::maybe_find_first_by_datasource_id(name.to_string())
+            .execute(&session)
+            .await?
+        {
+            DatasourceDbRow::delete_by_datasource_id(name.to_string())
+                .execute(&session)
+                .await?;
+            DatabaseExtraCallbacks::new(&c)
+                .await?
+                .delete(&[row])
+                .await?;
+        }
 
         drop_redis_cache("get_datasource", &(c, name)).await?;
         Ok(())
@@ -143,6 +188,16 @@ async fn test_datasource_query() -> Result<()> {
         .map(|x| x.datasource_id)
         .collect::<Vec<_>>();
     assert!(!list.contains(&name));
+    // the row is kept until the derived data is removed
+    assert!(get_datasource((cid.clone(), name.clone())).await.is_ok());
+    assert!(
+        create_datasource((cid.clone(), name.clone(), settings.clone()))
+            .await
+            .is_err()
+    );
+
+    purge_datasource((cid.clone(), name.clone())).await?;
+    assert!(get_datasource((cid.clone(), name.clone())).await.is_err());
 
     drop_collection(cid.clone()).await?;
 
@@ -0,0 +1,82 @@
+//! Datasource trait and inventory - each datasource kind (local disk, S3, WebDAV, ...)
+//! implements [Datasource] and registers itself with [declare_datasource_kind].
+//! The scanner and readers then only use [open_datasource] to get the right implementation
+//! for some `DatasourceSettings`.
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::stream::Stream;
+use hoover3_types::datasource::DatasourceSettings;
+use hoover3_types::filesystem::FsMetadataBasic;
+use std::path::Path;
+use std::pin::Pin;
+
+/// Stream of file chunks, as returned by [Datasource::read_range].
+pub type ByteChunkStream = Pin<Box<dyn Stream<Item = Result<Vec<u8>>> + Send>>;
+
+/// Operations that every datasource kind must implement.
+/// All paths are relative to the datasource root, and so are the returned paths.
+pub trait Datasource: Send + Sync {
+    /// Get metadata for a single file or directory.
+    fn stat<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<FsMetadataBasic>>;
+
+    /// List all files and directories directly inside the given directory.
+    fn list<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<FsMetadataBasic>>>;
+
+    /// Read `length` bytes of a file starting from `offset`, or until the end
+    /// of the file if `length` is `None`, as a stream of byte chunks.
+    fn read_range<'a>(
+        &'a self,
+        path: &'a Path,
+        offset: u64,
+        length: Option<u64>,
+    ) -> BoxFuture<'a, Result<ByteChunkStream>>;
+}
+
+/// Inventory datasource kind definition.
+pub struct DatasourceKindStatic {
+    /// Datasource type, as returned by `DatasourceSettings::type_str`.
+    pub type_str: &'static str,
+    /// Build the datasource implementation from its settings.
+    pub open_fn: fn(&DatasourceSettings) -> Result<Box<dyn Datasource>>,
+}
+
+inventory::collect!(DatasourceKindStatic);
+
+/// Declare a datasource kind in the inventory.
+/// Arguments:
+/// - datasource type (string literal) - same as `DatasourceSettings::type_str`
+/// - function that opens the datasource: `fn(&DatasourceSettings) -> Result<Box<dyn Datasource>>`
+#[macro_export]
+macro_rules! declare_datasource_kind {
+    ($type_str:expr, $open_fn:expr) => {
+        $crate::inventory::submit!($crate::datasource::DatasourceKindStatic {
+            type_str: $type_str,
+            open_fn: $open_fn,
+        });
+    };
+}
+pub use declare_datasource_kind;
+
+/// List all datasource kinds compiled into this binary.
+pub fn list_datasource_kinds() -> impl Iterator<Item = &'static DatasourceKindStatic> {
+    inventory::iter::<DatasourceKindStatic>()
+}
+
+/// Get the datasource implementation for the given settings.
+pub fn open_datasource(settings: &DatasourceSettings) -> Result<Box<dyn Datasource>> {
+    let type_str = settings.type_str();
+    let Some(kind) = list_datasource_kinds().find(|k| k.type_str == type_str) else {
+        anyhow::bail!("Datasource type not supported: {}", type_str);
+    };
+    (kind.open_fn)(settings)
+}
+
+#[test]
+fn test_datasource_kinds_registered() {
+    let mut kinds = list_datasource_kinds()
+        .map(|k| k.type_str)
+        .collect::<Vec<_>>();
+    kinds.sort();
+    assert_eq!(kinds, vec!["LocalDisk", "S3", "WebDav"]);
+}
@@ -1,6 +1,11 @@
 //! Data access module - logic to connect to "data sources" (filesystems, databases, etc)
 //! that are scanned by the system.
 
+pub use inventory;
+
 pub mod api;
+pub mod datasource;
 pub mod list_disk;
 pub mod models;
+pub mod s3;
+pub mod webdav;
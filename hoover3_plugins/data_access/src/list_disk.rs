@@ -1,16 +1,41 @@
-//! List files and directories on disk.
+//! List files and directories in a datasource, and read them.
+//! Also contains the local disk datasource implementation.
 use anyhow::{Context, Result};
+use futures::future::BoxFuture;
 use futures::stream::{self, Stream};
+use futures::FutureExt;
 use hoover3_types::datasource::DatasourceSettings;
+use hoover3_types::filesystem::FsMetadataBasic;
 use hoover3_types::identifier::{CollectionId, DatabaseIdentifier};
 use std::io::SeekFrom;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
 
+use hoover3_database::client_query::list_disk::{get_path_metadata, list_directory};
 use hoover3_database::system_paths::get_data_root;
 
-/// Read a file from disk and return it as an async stream of 4MB byte chunks.
+use crate::datasource::{declare_datasource_kind, open_datasource, ByteChunkStream, Datasource};
+
+/// Get metadata for a single file or directory in the datasource.
+/// The path is relative to the datasource root, and so is the returned path.
+pub async fn get_datasource_path_metadata(
+    settings: &DatasourceSettings,
+    relative_path: &Path,
+) -> Result<FsMetadataBasic> {
+    open_datasource(settings)?.stat(relative_path).await
+}
+
+/// List all files and directories in a directory of the datasource.
+/// The path is relative to the datasource root, and so are the returned paths.
+pub async fn list_datasource_directory(
+    settings: &DatasourceSettings,
+    relative_path: &Path,
+) -> Result<Vec<FsMetadataBasic>> {
+    open_datasource(settings)?.list(relative_path).await
+}
+
+/// Read a file from the datasource and return it as an async stream of byte chunks.
 ///
 /// # Returns
 /// * The size of the file in bytes
@@ -21,67 +46,118 @@ pub async fn read_file_to_stream(
     parent_dir_path: String,
     file_name: String,
 ) -> Result<(usize, Pin<Box<dyn Stream<Item = Result<Vec<u8>>> + Send>>)> {
-    // get datasource dir
     let ds_row = crate::api::get_datasource((collection_id.clone(), datasource_id.clone())).await?;
+    let datasource = open_datasource(&ds_row.datasource_settings)?;
 
-    let DatasourceSettings::LocalDisk { path: root_path } = &ds_row.datasource_settings else {
-        anyhow::bail!("Datasource is not a local disk");
-    };
-    let root_path = root_path.to_path_buf();
+    let relative_path = PathBuf::from(&parent_dir_path).join(&file_name);
+    let file_size = datasource.stat(&relative_path).await?.size_bytes;
+    let stream = datasource.read_range(&relative_path, 0, None).await?;
+    Ok((file_size as usize, stream))
+}
+
+/// Datasource for a directory on the local disk, relative to the data root.
+pub struct LocalDiskDatasource {
+    root_path: PathBuf,
+}
 
-    let file_path = root_path.join(&parent_dir_path).join(&file_name);
+impl LocalDiskDatasource {
+    /// Build the datasource from `DatasourceSettings::LocalDisk`.
+    pub fn new(settings: &DatasourceSettings) -> Result<Self> {
+        let DatasourceSettings::LocalDisk { path: root_path } = settings else {
+            anyhow::bail!("Datasource is not a local disk");
+        };
+        Ok(Self {
+            root_path: root_path.to_path_buf(),
+        })
+    }
 
-    fs_read_file_to_stream(file_path).await
+    fn open(settings: &DatasourceSettings) -> Result<Box<dyn Datasource>> {
+        Ok(Box::new(Self::new(settings)?))
+    }
+}
+
+declare_datasource_kind!("LocalDisk", LocalDiskDatasource::open);
+
+impl Datasource for LocalDiskDatasource {
+    fn stat<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<FsMetadataBasic>> {
+        async move {
+            let mut meta = get_path_metadata(self.root_path.join(path)).await?;
+            meta.path = path.to_path_buf();
+            Ok(meta)
+        }
+        .boxed()
+    }
+
+    fn list<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<FsMetadataBasic>>> {
+        async move {
+            let mut children = list_directory(self.root_path.join(path)).await?;
+            for c in children.iter_mut() {
+                c.path = c
+                    .path
+                    .strip_prefix(&self.root_path)
+                    .context("path is not relative to datasource root")?
+                    .to_path_buf();
+            }
+            Ok(children)
+        }
+        .boxed()
+    }
+
+    fn read_range<'a>(
+        &'a self,
+        path: &'a Path,
+        offset: u64,
+        length: Option<u64>,
+    ) -> BoxFuture<'a, Result<ByteChunkStream>> {
+        fs_read_file_to_stream(self.root_path.join(path), offset, length).boxed()
+    }
 }
 
 async fn fs_read_file_to_stream(
     relative_path: PathBuf,
-) -> Result<(usize, Pin<Box<dyn Stream<Item = Result<Vec<u8>>> + Send>>)> {
-    const CHUNK_SIZE: usize = 4 * 1024 * 1024; // 4MB chunks
+    offset: u64,
+    length: Option<u64>,
+) -> Result<ByteChunkStream> {
+    const CHUNK_SIZE: u64 = 4 * 1024 * 1024; // 4MB chunks
     let path = get_data_root().join(relative_path);
 
     let file = tokio::fs::File::open(&path)
         .await
         .context(format!("Failed to open file: {:?}", path))?;
 
-    let file_size = file.metadata().await?.len() as usize;
+    let file_size = file.metadata().await?.len();
+    let end = match length {
+        Some(length) => std::cmp::min(file_size, offset.saturating_add(length)),
+        None => file_size,
+    };
 
     // Create a stream that reads chunks on demand
-    let stream = stream::unfold(
-        (file, 0usize, file_size),
-        |(mut file, chunk_index, file_size)| async move {
-            if chunk_index * CHUNK_SIZE >= file_size {
-                return None; // We've read the entire file
-            }
+    let stream = stream::unfold((file, offset), move |(mut file, position)| async move {
+        if position >= end {
+            return None; // We've read the entire range
+        }
 
-            // Calculate the size of this chunk (might be smaller for the last chunk)
-            let remaining = file_size - (chunk_index * CHUNK_SIZE);
-            let this_chunk_size = std::cmp::min(CHUNK_SIZE, remaining);
-
-            // Seek to the correct position and read the chunk
-            let mut buffer = vec![0u8; this_chunk_size];
-            match file
-                .seek(SeekFrom::Start((chunk_index * CHUNK_SIZE) as u64))
-                .await
-            {
-                Ok(_) => {}
-                Err(e) => {
-                    return Some((
-                        Err(anyhow::Error::new(e).context("Failed to seek in file")),
-                        (file, chunk_index + 1, file_size),
-                    ))
-                }
-            }
+        // Calculate the size of this chunk (might be smaller for the last chunk)
+        let this_chunk_size = std::cmp::min(CHUNK_SIZE, end - position);
 
-            match file.read_exact(&mut buffer).await {
-                Ok(_) => Some((Ok(buffer), (file, chunk_index + 1, file_size))),
-                Err(e) => Some((
-                    Err(anyhow::Error::new(e).context("Failed to read chunk from file")),
-                    (file, chunk_index + 1, file_size),
-                )),
-            }
-        },
-    );
+        // Seek to the correct position and read the chunk
+        let mut buffer = vec![0u8; this_chunk_size as usize];
+        let next_position = position + this_chunk_size;
+        if let Err(e) = file.seek(SeekFrom::Start(position)).await {
+            return Some((
+                Err(anyhow::Error::new(e).context("Failed to seek in file")),
+                (file, next_position),
+            ));
+        }
+
+        match file.read_exact(&mut buffer).await {
+            Ok(_) => Some((Ok(buffer), (file, next_position))),
+            Err(e) => Some((
+                Err(anyhow::Error::new(e).context("Failed to read chunk from file")),
+                (file, next_position),
+            )),
+        }
+    });
 
-    Ok((file_size, Box::pin(stream)))
+    Ok(Box::pin(stream))
 }
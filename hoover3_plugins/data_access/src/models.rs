@@ -23,6 +23,8 @@ pub struct DatasourceDbRow {
     pub time_created: Timestamp,
     /// Timestamp of the most recent modification to the datasource
     pub time_modified: Timestamp,
+    /// Timestamp when the datasource was marked as deleted; its data is being removed
+    pub time_deleted: Option<Timestamp>,
 }
 
 impl DatasourceDbRow {
@@ -0,0 +1,348 @@
+//! S3 datasource - list "directories" and read objects from an S3-compatible bucket.
+//! Directories are emulated using the "/" delimiter, same as the AWS console.
+//! All paths returned are relative to the datasource prefix.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::stream;
+use futures::FutureExt;
+use hoover3_types::datasource::DatasourceSettings;
+use hoover3_types::filesystem::FsMetadataBasic;
+use s3::creds::Credentials;
+use s3::error::S3Error;
+use s3::{Bucket, Region};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::datasource::{declare_datasource_kind, ByteChunkStream, Datasource};
+
+/// Size of a ranged read request.
+const CHUNK_SIZE: u64 = 4 * 1024 * 1024; // 4MB chunks
+
+/// Connection settings for a S3 datasource, taken from `DatasourceSettings::S3`.
+#[derive(Debug, Clone)]
+pub struct S3Datasource {
+    bucket: Box<Bucket>,
+    prefix: String,
+}
+
+impl S3Datasource {
+    /// Build the bucket client from the datasource settings.
+    pub fn new(settings: &DatasourceSettings) -> Result<Self> {
+        let DatasourceSettings::S3 {
+            url,
+            bucket,
+            access_key,
+            secret_key,
+            path,
+        } = settings
+        else {
+            anyhow::bail!("Datasource is not S3");
+        };
+        let region = Region::Custom {
+            region: "eu-central-1".to_owned(),
+            endpoint: url.clone(),
+        };
+        let creds = Credentials::new(Some(access_key), Some(secret_key), None, None, None)?;
+        let bucket = Bucket::new(bucket, region, creds)?.with_path_style();
+        let prefix = path
+            .to_str()
+            .context("non-utf8 datasource path")?
+            .trim_matches('/')
+            .to_string();
+        Ok(Self { bucket, prefix })
+    }
+
+    /// Object key for a path relative to the datasource prefix.
+    fn object_key(&self, relative_path: &Path) -> Result<String> {
+        let relative_path = relative_path
+            .to_str()
+            .context("non-utf8 path")?
+            .trim_matches('/');
+        Ok(match (self.prefix.is_empty(), relative_path.is_empty()) {
+            (_, true) => self.prefix.clone(),
+            (true, false) => relative_path.to_string(),
+            (false, false) => format!("{}/{}", self.prefix, relative_path),
+        })
+    }
+
+    /// Path relative to the datasource prefix, for an object key.
+    fn relative_path(&self, key: &str) -> PathBuf {
+        let key = key.trim_end_matches('/');
+        let relative = if self.prefix.is_empty() {
+            key
+        } else {
+            key.strip_prefix(&self.prefix)
+                .unwrap_or(key)
+                .trim_start_matches('/')
+        };
+        PathBuf::from(relative)
+    }
+
+    /// Get metadata for a single object or "directory" prefix.
+    /// A missing object is a directory only if some objects are stored under it;
+    /// otherwise, and for any other S3 error, this fails.
+    pub async fn get_path_metadata(&self, relative_path: &Path) -> Result<FsMetadataBasic> {
+        let key = self.object_key(relative_path)?;
+        if key.is_empty() {
+            return Ok(dir_metadata(relative_path.to_path_buf()));
+        }
+        let status_code = match self.bucket.head_object(&key).await {
+            Ok((head, 200)) => {
+                return Ok(FsMetadataBasic {
+                    is_dir: false,
+                    is_file: true,
+                    size_bytes: head.content_length.unwrap_or_default().max(0) as u64,
+                    modified: head
+                        .last_modified
+                        .as_deref()
+                        .and_then(|d| DateTime::parse_from_rfc2822(d).ok())
+                        .map(|d| d.with_timezone(&Utc)),
+                    created: None,
+                    path: relative_path.to_path_buf(),
+                });
+            }
+            Ok((_head, status_code)) => status_code,
+            Err(S3Error::HttpFailWithBody(status_code, _)) => status_code,
+            Err(e) => {
+                return Err(anyhow::Error::new(e).context(format!("s3 head failed: {:?}", key)))
+            }
+        };
+        if status_code != 404 {
+            anyhow::bail!("got status code {} from s3 head for {:?}", status_code, key);
+        }
+        if !self.has_objects_under(&key).await? {
+            anyhow::bail!("s3 path not found: {:?}", key);
+        }
+        Ok(dir_metadata(relative_path.to_path_buf()))
+    }
+
+    /// Check if some object key starts with the "directory" prefix `key/`.
+    async fn has_objects_under(&self, key: &str) -> Result<bool> {
+        let prefix = format!("{}/", key);
+        let (page, _status_code) = self
+            .bucket
+            .list_page(prefix.clone(), Some("/".to_string()), None, None, Some(1))
+            .await
+            .context(format!("s3 list failed: {:?}", prefix))?;
+        Ok(!page.contents.is_empty() || !page.common_prefixes.unwrap_or_default().is_empty())
+    }
+
+    /// List the objects and common prefixes directly under the given "directory".
+    pub async fn list_directory(&self, relative_path: &Path) -> Result<Vec<FsMetadataBasic>> {
+        let key = self.object_key(relative_path)?;
+        let prefix = if key.is_empty() {
+            key
+        } else {
+            format!("{}/", key)
+        };
+        info!("s3 list_directory: {:?}", prefix);
+        let pages = self
+            .bucket
+            .list(prefix.clone(), Some("/".to_string()))
+            .await
+            .context(format!("s3 list failed: {:?}", prefix))?;
+
+        let mut entries = vec![];
+        for page in pages {
+            for common_prefix in page.common_prefixes.unwrap_or_default() {
+                entries.push(dir_metadata(self.relative_path(&common_prefix.prefix)));
+            }
+            for object in page.contents {
+                // skip the empty "directory marker" objects some tools create
+                if object.key == prefix || object.key.ends_with('/') {
+                    continue;
+                }
+                entries.push(FsMetadataBasic {
+                    is_dir: false,
+                    is_file: true,
+                    size_bytes: object.size,
+                    modified: DateTime::parse_from_rfc3339(&object.last_modified)
+                        .ok()
+                        .map(|d| d.with_timezone(&Utc)),
+                    created: None,
+                    path: self.relative_path(&object.key),
+                });
+            }
+        }
+        entries.sort_by(|a, b| (!a.is_dir, &a.path).cmp(&(!b.is_dir, &b.path)));
+        Ok(entries)
+    }
+
+    /// Read a range of an object as an async stream of 4MB chunks, using ranged GET requests.
+    /// If `length` is `None`, read until the end of the object.
+    pub async fn read_object_range(
+        &self,
+        relative_path: &Path,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<ByteChunkStream> {
+        let key = self.object_key(relative_path)?;
+        let (head, status_code) = self
+            .bucket
+            .head_object(&key)
+            .await
+            .context(format!("s3 head failed: {:?}", key))?;
+        if status_code != 200 {
+            anyhow::bail!("got status code {} from s3 head for {:?}", status_code, key);
+        }
+        let file_size = head.content_length.unwrap_or_default().max(0) as u64;
+        let range_end = match length {
+            Some(length) => std::cmp::min(file_size, offset.saturating_add(length)),
+            None => file_size,
+        };
+        let bucket = self.bucket.clone();
+
+        let stream = stream::unfold(offset, move |offset| {
+            let bucket = bucket.clone();
+            let key = key.clone();
+            async move {
+                if offset >= range_end {
+                    return None;
+                }
+                let end = std::cmp::min(offset + CHUNK_SIZE, range_end) - 1;
+                let chunk = match bucket.get_object_range(&key, offset, Some(end)).await {
+                    Ok(response) if matches!(response.status_code(), 200 | 206) => {
+                        let data = response.bytes().to_vec();
+                        if data.len() as u64 == end - offset + 1 {
+                            Ok(data)
+                        } else {
+                            Err(anyhow::anyhow!(
+                                "s3 ranged read for {:?} returned {} bytes, wanted {}",
+                                key,
+                                data.len(),
+                                end - offset + 1
+                            ))
+                        }
+                    }
+                    Ok(response) => Err(anyhow::anyhow!(
+                        "got status code {} from s3 ranged read for {:?}",
+                        response.status_code(),
+                        key
+                    )),
+                    Err(e) => Err(anyhow::Error::new(e).context("Failed to read chunk from s3")),
+                };
+                Some((chunk, end + 1))
+            }
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    fn open(settings: &DatasourceSettings) -> Result<Box<dyn Datasource>> {
+        Ok(Box::new(Self::new(settings)?))
+    }
+}
+
+declare_datasource_kind!("S3", S3Datasource::open);
+
+impl Datasource for S3Datasource {
+    fn stat<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<FsMetadataBasic>> {
+        self.get_path_metadata(path).boxed()
+    }
+
+    fn list<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<FsMetadataBasic>>> {
+        self.list_directory(path).boxed()
+    }
+
+    fn read_range<'a>(
+        &'a self,
+        path: &'a Path,
+        offset: u64,
+        length: Option<u64>,
+    ) -> BoxFuture<'a, Result<ByteChunkStream>> {
+        self.read_object_range(path, offset, length).boxed()
+    }
+}
+
+fn dir_metadata(path: PathBuf) -> FsMetadataBasic {
+    FsMetadataBasic {
+        is_dir: true,
+        is_file: false,
+        size_bytes: 0,
+        modified: None,
+        created: None,
+        path,
+    }
+}
+
+#[tokio::test]
+async fn test_s3_datasource_minio() -> Result<()> {
+    use futures::StreamExt;
+    use s3::BucketConfiguration;
+
+    // MinIO from the docker-compose stack, with the default credentials.
+    let url = std::env::var("MINIO_URL").unwrap_or_else(|_| "http://localhost:8090".to_owned());
+    let settings = DatasourceSettings::S3 {
+        url: url.clone(),
+        bucket: "test-s3-datasource".to_string(),
+        access_key: "minioadmin".to_string(),
+        secret_key: "minioadmin".to_string(),
+        path: PathBuf::from("/data/"),
+    };
+    let ds = S3Datasource::new(&settings)?;
+    if !ds.bucket.exists().await? {
+        Bucket::create_with_path_style(
+            "test-s3-datasource",
+            Region::Custom {
+                region: "eu-central-1".to_owned(),
+                endpoint: url,
+            },
+            Credentials::new(Some("minioadmin"), Some("minioadmin"), None, None, None)?,
+            BucketConfiguration::default(),
+        )
+        .await?;
+    }
+    let big_file = (0..(CHUNK_SIZE + 1000))
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    ds.bucket.put_object("data/a.txt", b"hello").await?;
+    ds.bucket.put_object("data/dir/b.bin", &big_file).await?;
+    ds.bucket.put_object("other/c.txt", b"outside").await?;
+
+    let root = ds.list_directory(Path::new("")).await?;
+    let root = root
+        .iter()
+        .map(|e| (e.is_dir, e.path.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        root,
+        vec![
+            (true, PathBuf::from("dir")),
+            (false, PathBuf::from("a.txt"))
+        ]
+    );
+
+    let dir = ds.list_directory(Path::new("dir")).await?;
+    assert_eq!(dir.len(), 1);
+    assert_eq!(dir[0].path, PathBuf::from("dir/b.bin"));
+    assert_eq!(dir[0].size_bytes, big_file.len() as u64);
+
+    let meta = ds.get_path_metadata(Path::new("dir/b.bin")).await?;
+    assert!(meta.is_file);
+    assert!(ds.get_path_metadata(Path::new("dir")).await?.is_dir);
+    assert!(ds.get_path_metadata(Path::new("missing")).await.is_err());
+
+    let mut stream = ds.read_range(Path::new("dir/b.bin"), 0, None).await?;
+    let mut data = vec![];
+    while let Some(chunk) = stream.next().await {
+        data.extend(chunk?);
+    }
+    assert_eq!(data, big_file);
+
+    let offset = CHUNK_SIZE - 10;
+    let mut stream = ds
+        .read_range(Path::new("dir/b.bin"), offset, Some(20))
+        .await?;
+    let mut data = vec![];
+    while let Some(chunk) = stream.next().await {
+        data.extend(chunk?);
+    }
+    assert_eq!(data, big_file[offset as usize..offset as usize + 20]);
+
+    for key in ["data/a.txt", "data/dir/b.bin", "other/c.txt"] {
+        ds.bucket.delete_object(key).await?;
+    }
+    Ok(())
+}
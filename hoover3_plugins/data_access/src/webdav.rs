@@ -0,0 +1,458 @@
+//! WebDAV datasource - list directories with PROPFIND and read files with streaming GET.
+//! All paths returned are relative to the datasource path on the server.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
+use hoover3_types::datasource::DatasourceSettings;
+use hoover3_types::filesystem::FsMetadataBasic;
+use percent_encoding::percent_decode_str;
+use quick_xml::events::Event;
+use reqwest::{Method, StatusCode, Url};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::datasource::{declare_datasource_kind, ByteChunkStream, Datasource};
+
+/// Properties requested for every listed entry.
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop>
+    <d:resourcetype/>
+    <d:getcontentlength/>
+    <d:getlastmodified/>
+    <d:creationdate/>
+  </d:prop>
+</d:propfind>"#;
+
+/// Connection settings for a WebDAV datasource, taken from `DatasourceSettings::WebDav`.
+#[derive(Debug, Clone)]
+pub struct WebDavDatasource {
+    client: reqwest::Client,
+    root_url: Url,
+    username: String,
+    password: String,
+}
+
+impl WebDavDatasource {
+    /// Build the HTTP client from the datasource settings.
+    pub fn new(settings: &DatasourceSettings) -> Result<Self> {
+        let DatasourceSettings::WebDav {
+            url,
+            username,
+            password,
+            path,
+        } = settings
+        else {
+            anyhow::bail!("Datasource is not WebDAV");
+        };
+        let mut root_url = Url::parse(url).context("invalid WebDAV url")?;
+        append_path_segments(&mut root_url, path)?;
+        Ok(Self {
+            client: reqwest::Client::new(),
+            root_url,
+            username: username.clone(),
+            password: password.clone(),
+        })
+    }
+
+    /// Full URL for a path relative to the datasource root.
+    fn url_for(&self, relative_path: &Path) -> Result<Url> {
+        let mut url = self.root_url.clone();
+        append_path_segments(&mut url, relative_path)?;
+        Ok(url)
+    }
+
+    fn request(&self, method: Method, url: Url) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, url);
+        if self.username.is_empty() {
+            request
+        } else {
+            request.basic_auth(&self.username, Some(&self.password))
+        }
+    }
+
+    /// Run a PROPFIND request on the path, with the given depth (0 or 1).
+    async fn propfind(&self, relative_path: &Path, depth: u8) -> Result<Vec<PropfindEntry>> {
+        let url = self.url_for(relative_path)?;
+        let response = self
+            .request(Method::from_bytes(b"PROPFIND")?, url.clone())
+            .header("Depth", depth.to_string())
+            .header("Content-Type", "application/xml; charset=utf-8")
+            .body(PROPFIND_BODY)
+            .send()
+            .await
+            .context(format!("webdav PROPFIND failed: {}", url))?;
+        if response.status() != StatusCode::MULTI_STATUS {
+            anyhow::bail!(
+                "got status code {} from webdav PROPFIND {}, wanted 207",
+                response.status(),
+                url
+            );
+        }
+        parse_propfind_response(&response.text().await?)
+    }
+
+    /// Get metadata for a single file or directory.
+    pub async fn get_path_metadata(&self, relative_path: &Path) -> Result<FsMetadataBasic> {
+        let entry = self
+            .propfind(relative_path, 0)
+            .await?
+            .into_iter()
+            .next()
+            .context("empty webdav PROPFIND response")?;
+        Ok(entry.into_metadata(relative_path.to_path_buf()))
+    }
+
+    /// List all files and directories directly inside the given directory.
+    pub async fn list_directory(&self, relative_path: &Path) -> Result<Vec<FsMetadataBasic>> {
+        info!("webdav list_directory: {:?}", relative_path);
+        let dir_url_path = decoded_url_path(self.url_for(relative_path)?.path());
+        let mut entries = vec![];
+        for entry in self.propfind(relative_path, 1).await? {
+            let href_path = decoded_url_path(&entry.href);
+            // the response also contains the listed directory itself
+            if href_path == dir_url_path {
+                continue;
+            }
+            let Some(name) = href_path.rsplit('/').next().filter(|n| !n.is_empty()) else {
+                continue;
+            };
+            let path = relative_path.join(name);
+            entries.push(entry.into_metadata(path));
+        }
+        entries.sort_by(|a, b| (!a.is_dir, &a.path).cmp(&(!b.is_dir, &b.path)));
+        Ok(entries)
+    }
+
+    /// Read a range of a file as an async stream of byte chunks, using a single streaming GET request.
+    /// If `length` is `None`, read until the end of the file.
+    pub async fn read_file_range(
+        &self,
+        relative_path: &Path,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<ByteChunkStream> {
+        if length == Some(0) {
+            return Ok(Box::pin(futures::stream::empty()));
+        }
+        let url = self.url_for(relative_path)?;
+        let mut request = self.request(Method::GET, url.clone());
+        let is_range = offset > 0 || length.is_some();
+        if is_range {
+            let range = match length {
+                Some(length) => format!("bytes={}-{}", offset, offset + length - 1),
+                None => format!("bytes={}-", offset),
+            };
+            request = request.header("Range", range);
+        }
+        let response = request
+            .send()
+            .await
+            .context(format!("webdav GET failed: {}", url))?;
+        let wanted_status = if is_range {
+            StatusCode::PARTIAL_CONTENT
+        } else {
+            StatusCode::OK
+        };
+        if response.status() != wanted_status {
+            anyhow::bail!(
+                "got status code {} from webdav GET {}, wanted {}",
+                response.status(),
+                url,
+                wanted_status
+            );
+        }
+        let stream = response.bytes_stream().map(|chunk| {
+            chunk
+                .map(|c| c.to_vec())
+                .map_err(|e| anyhow::Error::new(e).context("Failed to read chunk from webdav"))
+        });
+        Ok(Box::pin(stream))
+    }
+
+    fn open(settings: &DatasourceSettings) -> Result<Box<dyn Datasource>> {
+        Ok(Box::new(Self::new(settings)?))
+    }
+}
+
+declare_datasource_kind!("WebDav", WebDavDatasource::open);
+
+impl Datasource for WebDavDatasource {
+    fn stat<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<FsMetadataBasic>> {
+        self.get_path_metadata(path).boxed()
+    }
+
+    fn list<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Result<Vec<FsMetadataBasic>>> {
+        self.list_directory(path).boxed()
+    }
+
+    fn read_range<'a>(
+        &'a self,
+        path: &'a Path,
+        offset: u64,
+        length: Option<u64>,
+    ) -> BoxFuture<'a, Result<ByteChunkStream>> {
+        self.read_file_range(path, offset, length).boxed()
+    }
+}
+
+/// Append the components of a relative path to the URL, percent-encoding them.
+fn append_path_segments(url: &mut Url, path: &Path) -> Result<()> {
+    let path = path.to_str().context("non-utf8 path")?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("WebDAV url cannot be a base: {}", url))?
+        .pop_if_empty()
+        .extend(path.split('/').filter(|s| !s.is_empty()));
+    Ok(())
+}
+
+/// Decode a href (absolute URL or absolute path) into a path without the trailing slash.
+fn decoded_url_path(href: &str) -> String {
+    let path = match Url::parse(href) {
+        Ok(url) => url.path().to_string(),
+        Err(_) => href.to_string(),
+    };
+    percent_decode_str(&path)
+        .decode_utf8_lossy()
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// One `<response>` element from a PROPFIND multi-status reply.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct PropfindEntry {
+    href: String,
+    is_dir: bool,
+    size_bytes: u64,
+    modified: Option<DateTime<Utc>>,
+    created: Option<DateTime<Utc>>,
+}
+
+impl PropfindEntry {
+    fn into_metadata(self, path: PathBuf) -> FsMetadataBasic {
+        FsMetadataBasic {
+            is_dir: self.is_dir,
+            is_file: !self.is_dir,
+            size_bytes: if self.is_dir { 0 } else { self.size_bytes },
+            modified: self.modified,
+            created: self.created,
+            path,
+        }
+    }
+}
+
+/// Parse the PROPFIND XML reply. Namespace prefixes differ between servers,
+/// so elements are matched on their local name only.
+fn parse_propfind_response(xml: &str) -> Result<Vec<PropfindEntry>> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut entries = vec![];
+    let mut current: Option<PropfindEntry> = None;
+    let mut element_stack: Vec<Vec<u8>> = vec![];
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = e.local_name().as_ref().to_vec();
+                if name == b"response" {
+                    current = Some(PropfindEntry::default());
+                }
+                if name == b"collection" {
+                    if let Some(entry) = current.as_mut() {
+                        entry.is_dir = true;
+                    }
+                }
+                element_stack.push(name);
+            }
+            Event::Empty(e) => {
+                if e.local_name().as_ref() == b"collection" {
+                    if let Some(entry) = current.as_mut() {
+                        entry.is_dir = true;
+                    }
+                }
+            }
+            Event::Text(t) => {
+                let (Some(entry), Some(element)) = (current.as_mut(), element_stack.last()) else {
+                    continue;
+                };
+                let text = t.unescape()?;
+                let text = text.trim();
+                match element.as_slice() {
+                    b"href" => entry.href = text.to_string(),
+                    b"getcontentlength" => entry.size_bytes = text.parse().unwrap_or_default(),
+                    b"getlastmodified" => {
+                        entry.modified = DateTime::parse_from_rfc2822(text)
+                            .ok()
+                            .map(|d| d.with_timezone(&Utc))
+                    }
+                    b"creationdate" => {
+                        entry.created = DateTime::parse_from_rfc3339(text)
+                            .ok()
+                            .map(|d| d.with_timezone(&Utc))
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(e) => {
+                element_stack.pop();
+                if e.local_name().as_ref() == b"response" {
+                    if let Some(entry) = current.take() {
+                        entries.push(entry);
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEXTCLOUD_PROPFIND: &str = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:s="http://sabredav.org/ns">
+ <d:response>
+  <d:href>/remote.php/dav/files/alice/Shared%20Folder/</d:href>
+  <d:propstat>
+   <d:prop>
+    <d:resourcetype><d:collection/></d:resourcetype>
+    <d:getlastmodified>Tue, 01 Oct 2024 10:00:00 GMT</d:getlastmodified>
+   </d:prop>
+   <d:status>HTTP/1.1 200 OK</d:status>
+  </d:propstat>
+ </d:response>
+ <d:response>
+  <d:href>/remote.php/dav/files/alice/Shared%20Folder/report%20%26%20notes.pdf</d:href>
+  <d:propstat>
+   <d:prop>
+    <d:resourcetype/>
+    <d:getcontentlength>1234</d:getcontentlength>
+    <d:getlastmodified>Tue, 01 Oct 2024 11:00:00 GMT</d:getlastmodified>
+   </d:prop>
+   <d:status>HTTP/1.1 200 OK</d:status>
+  </d:propstat>
+ </d:response>
+ <D:response xmlns:D="DAV:">
+  <D:href>http://localhost/remote.php/dav/files/alice/Shared%20Folder/sub/</D:href>
+  <D:propstat>
+   <D:prop>
+    <D:resourcetype><D:collection></D:collection></D:resourcetype>
+   </D:prop>
+  </D:propstat>
+ </D:response>
+</d:multistatus>"#;
+
+    #[test]
+    fn test_parse_propfind_response() -> Result<()> {
+        let entries = parse_propfind_response(NEXTCLOUD_PROPFIND)?;
+        assert_eq!(entries.len(), 3);
+        assert!(entries[0].is_dir);
+        assert_eq!(
+            decoded_url_path(&entries[0].href),
+            "/remote.php/dav/files/alice/Shared Folder"
+        );
+
+        assert!(!entries[1].is_dir);
+        assert_eq!(entries[1].size_bytes, 1234);
+        assert!(entries[1].modified.is_some());
+        assert_eq!(
+            decoded_url_path(&entries[1].href),
+            "/remote.php/dav/files/alice/Shared Folder/report & notes.pdf"
+        );
+
+        assert!(entries[2].is_dir);
+        assert_eq!(
+            decoded_url_path(&entries[2].href),
+            "/remote.php/dav/files/alice/Shared Folder/sub"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_webdav_url_for() -> Result<()> {
+        let ds = WebDavDatasource::new(&DatasourceSettings::WebDav {
+            url: "https://cloud.example.com/remote.php/dav/files/alice/".to_string(),
+            username: "alice".to_string(),
+            password: "secret".to_string(),
+            path: PathBuf::from("/Shared Folder"),
+        })?;
+        assert_eq!(
+            ds.url_for(Path::new("sub/a#b.txt"))?.as_str(),
+            "https://cloud.example.com/remote.php/dav/files/alice/Shared%20Folder/sub/a%23b.txt"
+        );
+        Ok(())
+    }
+
+    /// Needs the WebDAV server from the docker-compose stack.
+    #[tokio::test]
+    async fn test_webdav_datasource_server() -> Result<()> {
+        let url =
+            std::env::var("WEBDAV_URL").unwrap_or_else(|_| "http://localhost:8091".to_owned());
+        let settings = DatasourceSettings::WebDav {
+            url,
+            username: "hoover3".to_string(),
+            password: "hoover3".to_string(),
+            path: PathBuf::from("test_webdav_datasource"),
+        };
+        let ds = WebDavDatasource::new(&settings)?;
+        let root_url = ds.url_for(Path::new(""))?;
+        let _ = ds.request(Method::DELETE, root_url.clone()).send().await?;
+        for dir in ["", "dir"] {
+            ds.request(Method::from_bytes(b"MKCOL")?, ds.url_for(Path::new(dir))?)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        let big_file = (0..5_000_000_u32)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        for (path, data) in [
+            ("a b.txt", b"hello".to_vec()),
+            ("dir/c.bin", big_file.clone()),
+        ] {
+            ds.request(Method::PUT, ds.url_for(Path::new(path))?)
+                .body(data)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+
+        let root = ds.list_directory(Path::new("")).await?;
+        let root = root
+            .iter()
+            .map(|e| (e.is_dir, e.path.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            root,
+            vec![
+                (true, PathBuf::from("dir")),
+                (false, PathBuf::from("a b.txt"))
+            ]
+        );
+        let meta = ds.get_path_metadata(Path::new("dir/c.bin")).await?;
+        assert!(meta.is_file);
+        assert_eq!(meta.size_bytes, big_file.len() as u64);
+
+        let mut stream = ds.read_range(Path::new("dir/c.bin"), 0, None).await?;
+        let mut data = vec![];
+        while let Some(chunk) = stream.next().await {
+            data.extend(chunk?);
+        }
+        assert_eq!(data, big_file);
+
+        let mut stream = ds
+            .read_range(Path::new("dir/c.bin"), 1000, Some(20))
+            .await?;
+        let mut data = vec![];
+        while let Some(chunk) = stream.next().await {
+            data.extend(chunk?);
+        }
+        assert_eq!(data, big_file[1000..1020]);
+
+        ds.request(Method::DELETE, root_url).send().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+//! Client API methods for the collection backup, restore, search reindex, search indexer,
+//! graph repair, graph degree statistics and graph export tasks.
+
+use hoover3_database::db_management::{DatabaseSpaceManager, S3DatabaseHandle};
+use hoover3_database::models::collection::query_search_indexing_lag;
+use hoover3_taskdef::TemporalioWorkflowDescriptor;
+use hoover3_types::collection::{CollectionBackupInfo, SearchIndexingLag};
+use hoover3_types::db_schema::{GraphExportFormat, GraphExportInfo, GraphExportRequest};
+use hoover3_types::identifier::{CollectionId, DatabaseIdentifier};
+use hoover3_types::tasks::UiWorkflowStatus;
+
+use crate::indexer::start_search_indexer;
+use crate::tasks::{
+    backup_collection_workflow, compute_graph_degrees_workflow, export_collection_graph_workflow,
+    reindex_collection_workflow, repair_graph_edges_workflow, restore_collection_workflow,
+};
+
+/// Client API method, lists the backup archives of a collection, sorted by name.
+pub async fn list_collection_backups(c: CollectionId) -> anyhow::Result<Vec<CollectionBackupInfo>> {
+    let prefix = format!("{}/", c);
+    let files = S3DatabaseHandle::global_session()
+        .await?
+        .list_backup_files(&prefix)
+        .await?;
+    let mut backups = files
+        .into_iter()
+        .filter_map(|(archive_key, size_bytes, last_modified)| {
+            let name = archive_key.strip_prefix(&prefix)?.strip_suffix(".tar.gz")?;
+            Some(CollectionBackupInfo {
+                collection_id: c.clone(),
+                backup_name: DatabaseIdentifier::new(name).ok()?,
+                archive_key,
+                size_bytes,
+                last_modified,
+            })
+        })
+        .collect::<Vec<_>>();
+    backups.sort();
+    Ok(backups)
+}
+
+/// Client API method, starts backing up a collection under the given backup name.
+pub async fn start_collection_backup(
+    (c, backup_name): (CollectionId, DatabaseIdentifier),
+) -> anyhow::Result<String> {
+    backup_collection_workflow::client_start(&(c, backup_name)).await
+}
+
+/// Client API method, returns the status of a collection backup.
+pub async fn get_collection_backup_status(
+    (c, backup_name): (CollectionId, DatabaseIdentifier),
+) -> anyhow::Result<UiWorkflowStatus> {
+    backup_collection_workflow::client_get_status(&(c, backup_name)).await
+}
+
+/// Client API method, starts restoring the backup of a collection into the target collection,
+/// which is created if it does not exist.
+pub async fn start_collection_restore(
+    (c, backup_name, target): (CollectionId, DatabaseIdentifier, CollectionId),
+) -> anyhow::Result<String> {
+    restore_collection_workflow::client_start(&(c, backup_name, target)).await
+}
+
+/// Client API method, returns the status of a backup restore.
+pub async fn get_collection_restore_status(
+    (c, backup_name, target): (CollectionId, DatabaseIdentifier, CollectionId),
+) -> anyhow::Result<UiWorkflowStatus> {
+    restore_collection_workflow::client_get_status(&(c, backup_name, target)).await
+}
+
+/// Client API method, starts rebuilding the search index of a collection from its rows.
+/// With `fresh_index`, the rows are indexed into a new index that replaces the old one at the end.
+/// Returns the workflow id; use it to get the progress from the workflow status tree.
+pub async fn start_collection_reindex(
+    (c, fresh_index): (CollectionId, bool),
+) -> anyhow::Result<String> {
+    let requested_at = chrono::Utc::now().timestamp_millis();
+    reindex_collection_workflow::client_start(&(c, fresh_index, requested_at)).await
+}
+
+/// Client API method, returns the search indexing lag of a collection:
+/// the documents written to Scylla but not yet confirmed by the search index.
+pub async fn get_search_indexing_lag(c: CollectionId) -> anyhow::Result<SearchIndexingLag> {
+    query_search_indexing_lag(c).await
+}
+
+/// Client API method, starts the search indexer of a collection, unless it is already running.
+/// Returns the workflow id.
+pub async fn start_collection_search_indexer(c: CollectionId) -> anyhow::Result<String> {
+    start_search_indexer(c).await
+}
+
+/// Client API method, starts removing the dangling graph edges of a collection:
+/// the edges that point to rows that no longer exist.
+/// Returns the workflow id; use it to get the progress from the workflow status tree.
+pub async fn start_collection_graph_repair(c: CollectionId) -> anyhow::Result<String> {
+    let requested_at = chrono::Utc::now().timestamp_millis();
+    repair_graph_edges_workflow::client_start(&(c, requested_at)).await
+}
+
+/// Client API method, starts computing the degree statistics of the graph of a collection,
+/// read afterwards with the graph degree distributions API.
+/// Returns the workflow id; use it to get the progress from the workflow status tree.
+pub async fn start_collection_graph_degrees(c: CollectionId) -> anyhow::Result<String> {
+    let requested_at = chrono::Utc::now().timestamp_millis();
+    compute_graph_degrees_workflow::client_start(&(c, requested_at)).await
+}
+
+/// Client API method, starts exporting the graph of a collection into a file
+/// in the object store, under the given export name.
+pub async fn start_collection_graph_export(
+    (c, export_name, request): (CollectionId, DatabaseIdentifier, GraphExportRequest),
+) -> anyhow::Result<String> {
+    export_collection_graph_workflow::client_start(&(c, export_name, request)).await
+}
+
+/// Client API method, returns the status of a graph export.
+pub async fn get_collection_graph_export_status(
+    (c, export_name, request): (CollectionId, DatabaseIdentifier, GraphExportRequest),
+) -> anyhow::Result<UiWorkflowStatus> {
+    export_collection_graph_workflow::client_get_status(&(c, export_name, request)).await
+}
+
+/// Client API method, lists the graph export files of a collection, sorted by name.
+pub async fn list_collection_graph_exports(
+    c: CollectionId,
+) -> anyhow::Result<Vec<GraphExportInfo>> {
+    let prefix = format!("{}/", c);
+    let files = S3DatabaseHandle::global_session()
+        .await?
+        .list_export_files(&prefix)
+        .await?;
+    let mut exports = files
+        .into_iter()
+        .filter_map(|(file_key, size_bytes, last_modified)| {
+            let (name, extension) = file_key.strip_prefix(&prefix)?.rsplit_once('.')?;
+            Some(GraphExportInfo {
+                collection_id: c.clone(),
+                export_name: DatabaseIdentifier::new(name).ok()?,
+                format: GraphExportFormat::from_file_extension(extension)?,
+                file_key,
+                size_bytes,
+                last_modified,
+            })
+        })
+        .collect::<Vec<_>>();
+    exports.sort();
+    Ok(exports)
+}
@@ -0,0 +1,158 @@
+//! Search indexer tasks: send the entries of the search indexing outbox of a collection
+//! to the search index, on a dedicated queue, so slow indexing does not hold up the tasks
+//! that write the rows.
+//!
+//! The indexer is started by the write path itself, through the [SearchOutboxNotifyStatic]
+//! registered here, and runs until the outbox stays empty.
+//! Notifications can be lost, so the worker of the search indexer queue also runs
+//! [sweep_search_outboxes] at startup and then periodically, to start the indexer of every collection with queued entries.
+
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use hoover3_database::client_query::collections::get_all_collections;
+use hoover3_database::models::collection::{
+    drain_search_outbox_round, search_outbox_has_entries, SearchOutboxNotifyStatic,
+};
+use hoover3_macro::{activity, workflow};
+use hoover3_taskdef::{
+    declare_task_queue, TemporalioActivityDescriptor, TemporalioWorkflowDescriptor, WfContext,
+    WfExitValue, WorkflowResult,
+};
+use hoover3_types::collection::SearchOutboxDrainResult;
+use hoover3_types::identifier::CollectionId;
+use tracing::{info, warn};
+
+declare_task_queue!(
+    SearchIndexerQueue,
+    "search_indexer",
+    4,    // concurrent workflows
+    4,    // max i/o threads
+    1024  // MB ram worker total
+);
+
+/// Maximum number of drain rounds in one workflow, to keep its history small.
+/// If entries are left, the workflow starts the next one before it stops.
+const SEARCH_INDEXER_MAX_ROUNDS: u32 = 1000;
+
+/// Time to wait for new entries before deciding that the outbox is empty.
+const SEARCH_INDEXER_IDLE_WAIT: Duration = Duration::from_secs(30);
+
+/// Time between two sweeps of the outboxes of all collections.
+const SEARCH_INDEXER_SWEEP_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Start the search indexer of a collection, unless it is already running.
+/// The workflow id includes the current minute, so a finished indexer is started again
+/// for entries queued after it stopped.
+pub async fn start_search_indexer(collection_id: CollectionId) -> anyhow::Result<String> {
+    let started_minute = chrono::Utc::now().timestamp() / 60;
+    drain_search_outbox_workflow::client_start(&(collection_id, started_minute)).await
+}
+
+/// Start the search indexer of every collection whose outbox has entries.
+/// Returns the number of indexers started; errors of single collections are only logged.
+pub async fn sweep_search_outboxes() -> anyhow::Result<u64> {
+    let mut started_count = 0;
+    for collection in get_all_collections(()).await? {
+        let c = collection.collection_id;
+        let started = async {
+            if !search_outbox_has_entries(&c).await? {
+                return anyhow::Ok(false);
+            }
+            start_search_indexer(c.clone()).await?;
+            Ok(true)
+        };
+        match started.await {
+            Ok(true) => started_count += 1,
+            Ok(false) => {}
+            Err(e) => warn!("search outbox sweep: collection {}: {:#}", c, e),
+        }
+    }
+    Ok(started_count)
+}
+
+/// Run [sweep_search_outboxes] now and then every [SEARCH_INDEXER_SWEEP_INTERVAL],
+/// on a background thread, for the life of the process.
+pub fn spawn_search_outbox_sweeper() -> anyhow::Result<()> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    std::thread::Builder::new()
+        .name("search_outbox_sweeper".to_string())
+        .spawn(move || {
+            rt.block_on(async {
+                loop {
+                    match sweep_search_outboxes().await {
+                        Ok(started_count) => {
+                            info!("search outbox sweep: {} indexers started", started_count)
+                        }
+                        Err(e) => warn!("search outbox sweep failed: {:#}", e),
+                    }
+                    tokio::time::sleep(SEARCH_INDEXER_SWEEP_INTERVAL).await;
+                }
+            })
+        })?;
+    Ok(())
+}
+
+fn notify_search_indexer(collection_id: CollectionId) -> BoxFuture<'static, anyhow::Result<()>> {
+    Box::pin(async move {
+        start_search_indexer(collection_id).await?;
+        Ok(())
+    })
+}
+
+hoover3_database::inventory::submit! {
+    SearchOutboxNotifyStatic {
+        notify: notify_search_indexer,
+    }
+}
+
+/// Workflow that drains the search indexing outbox of a collection, one round at a time,
+/// until it stays empty. The minute argument only makes the workflow id unique,
+/// see [start_search_indexer]. After [SEARCH_INDEXER_MAX_ROUNDS] rounds that all read entries,
+/// it starts the next indexer and stops.
+#[workflow(SearchIndexerQueue)]
+async fn drain_search_outbox(
+    wf_ctx: WfContext,
+    (collection_id, started_minute): (CollectionId, i64),
+) -> WorkflowResult<SearchOutboxDrainResult> {
+    let mut result = SearchOutboxDrainResult::default();
+    let mut drained = false;
+    for _ in 0..SEARCH_INDEXER_MAX_ROUNDS {
+        let round = run_search_outbox_round_activity::run(&wf_ctx, collection_id.clone()).await?;
+        result += round;
+        if round.read_count == 0 {
+            drained = true;
+            break;
+        }
+    }
+    if !drained {
+        start_next_search_indexer_activity::run(&wf_ctx, (collection_id, started_minute)).await?;
+    }
+    Ok(WfExitValue::Normal(result))
+}
+
+/// Start the indexer that continues the work of one that reached its round limit.
+/// Its id uses a later minute than the one of the stopping indexer, so it is a new workflow.
+#[activity(SearchIndexerQueue)]
+async fn start_next_search_indexer(
+    (collection_id, started_minute): (CollectionId, i64),
+) -> anyhow::Result<String> {
+    let next_minute = (chrono::Utc::now().timestamp() / 60).max(started_minute + 1);
+    drain_search_outbox_workflow::client_start(&(collection_id, next_minute)).await
+}
+
+/// Run one drain round on the outbox. If it is empty, wait a little and check again,
+/// so entries queued while the last round ran are not left behind.
+#[activity(SearchIndexerQueue, retries = 5, timeout = 1800)]
+async fn run_search_outbox_round(
+    collection_id: CollectionId,
+) -> anyhow::Result<SearchOutboxDrainResult> {
+    let result = drain_search_outbox_round(&collection_id).await?;
+    if result.read_count > 0 {
+        return Ok(result);
+    }
+    tokio::time::sleep(SEARCH_INDEXER_IDLE_WAIT).await;
+    drain_search_outbox_round(&collection_id).await
+}
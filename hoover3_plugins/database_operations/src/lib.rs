@@ -1,3 +1,5 @@
 //! This module has tasks to execute database operations: migrations, backup/restore, etc.
 
+pub mod api;
+pub mod indexer;
 pub mod tasks;
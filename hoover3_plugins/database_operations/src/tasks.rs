@@ -1,3 +1,574 @@
-//! Tasks to execute database operations: migrations, backup/restore, etc.
+//! Tasks to execute database operations: migrations, backup/restore, search reindex,
+//! graph repair and export, etc.
+//!
+//! A collection backup is a `.tar.gz` archive in the backup bucket of the object store.
+//! It holds a `manifest.json` file, with the collection details and search index settings,
+//! and one `scylla/<table>.jsonl` file for each collection table, graph tables included.
+//! The blobs stored in the collection bucket are not part of the archive.
 
-// TODO!
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use hoover3_database::client_query::collections::{
+    create_new_collection, get_single_collection, update_collection,
+};
+use hoover3_database::db_management::{
+    create_fresh_search_index, get_reindex_target_search_index, get_search_settings_json,
+    search_index_document_count, set_search_settings_json, DatabaseSpaceManager, S3DatabaseHandle,
+};
+use hoover3_database::migrate::backup::{
+    export_table_rows, import_table_rows, list_backup_table_names,
+};
+use hoover3_database::migrate::migrate_collection;
+use hoover3_database::models::collection::{
+    abandon_fresh_search_reindex, begin_fresh_search_reindex, compute_graph_degree_statistics,
+    finish_fresh_search_reindex, graph_export_to_file, graph_repair_edges_segment,
+    index_table_rows, rebuild_search_index, search_index_table_names, GRAPH_REPAIR_SEGMENT_COUNT,
+};
+use hoover3_macro::{activity, workflow};
+use hoover3_taskdef::{
+    activity_heartbeat, declare_task_queue, TemporalioActivityDescriptor,
+    TemporalioWorkflowDescriptor, WfContext, WfExitValue, WorkflowResult,
+    WORKER_TEMPDIR_ENV_VAR_BIG,
+};
+use hoover3_types::collection::{
+    CollectionBackupResult, CollectionRestoreResult, CollectionUiRow, SearchReindexResult,
+};
+use hoover3_types::db_schema::{GraphEdgeRepairResult, GraphExportRequest, GraphExportResult};
+use hoover3_types::identifier::{CollectionId, DatabaseIdentifier};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+declare_task_queue!(
+    DatabaseOperationsQueue,
+    "database_operations",
+    2,    // concurrent workflows
+    8,    // max i/o threads
+    1024  // MB ram worker total
+);
+
+/// Version of the archive layout, saved in the manifest.
+const BACKUP_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+const TABLES_DIR_NAME: &str = "scylla";
+
+/// Contents of the `manifest.json` file of a backup archive.
+#[derive(Serialize, Deserialize, Debug)]
+struct BackupManifest {
+    format_version: u32,
+    collection: CollectionUiRow,
+    created_at: chrono::DateTime<chrono::Utc>,
+    /// Row count for each table saved in the archive.
+    tables: BTreeMap<String, u64>,
+    search_settings: serde_json::Value,
+}
+
+/// Key of a backup archive in the backup bucket.
+pub fn backup_archive_key(c: &CollectionId, backup_name: &DatabaseIdentifier) -> String {
+    format!("{}/{}.tar.gz", c, backup_name)
+}
+
+/// Workflow that backs up the tables and search settings of a collection
+/// into an archive in the object store.
+#[workflow(DatabaseOperationsQueue)]
+async fn backup_collection(
+    wf_ctx: WfContext,
+    (collection_id, backup_name): (CollectionId, DatabaseIdentifier),
+) -> WorkflowResult<CollectionBackupResult> {
+    Ok(WfExitValue::Normal(
+        create_backup_archive_activity::run(&wf_ctx, (collection_id, backup_name)).await?,
+    ))
+}
+
+/// Export all the collection tables, then pack and upload them with the manifest.
+#[activity(DatabaseOperationsQueue, retries = 2, timeout = 6 * 3600)]
+async fn create_backup_archive(
+    (collection_id, backup_name): (CollectionId, DatabaseIdentifier),
+) -> anyhow::Result<CollectionBackupResult> {
+    let collection = get_single_collection(collection_id.clone()).await?;
+    let work_dir = new_work_dir("backup", &collection_id, &backup_name).await?;
+    let tables_dir = work_dir.join(TABLES_DIR_NAME);
+    tokio::fs::create_dir_all(&tables_dir).await?;
+
+    let created_at = chrono::Utc::now();
+    let mut tables = BTreeMap::new();
+    for table in list_backup_table_names() {
+        let path = tables_dir.join(format!("{}.jsonl", table));
+        let row_count = export_table_rows(&collection_id, &table, &path).await?;
+        info!("backup {} {}: {} rows", collection_id, table, row_count);
+        tables.insert(table, row_count);
+    }
+    let manifest = BackupManifest {
+        format_version: BACKUP_FORMAT_VERSION,
+        collection,
+        created_at,
+        tables,
+        search_settings: get_search_settings_json(&collection_id).await?,
+    };
+    tokio::fs::write(
+        work_dir.join(MANIFEST_FILE_NAME),
+        serde_json::to_vec_pretty(&manifest)?,
+    )
+    .await?;
+
+    let archive_path = archive_path(&work_dir);
+    let (pack_dir, pack_path) = (work_dir.clone(), archive_path.clone());
+    tokio::task::spawn_blocking(move || write_tar_gz(&pack_dir, &pack_path)).await??;
+    let size_bytes = tokio::fs::metadata(&archive_path).await?.len();
+
+    let archive_key = backup_archive_key(&collection_id, &backup_name);
+    S3DatabaseHandle::global_session()
+        .await?
+        .put_backup_file(&archive_key, &archive_path)
+        .await?;
+    info!("backup {} saved as {}", collection_id, archive_key);
+    remove_work_dir(&work_dir).await;
+
+    Ok(CollectionBackupResult {
+        collection_id,
+        backup_name,
+        archive_key,
+        table_count: manifest.tables.len() as u64,
+        row_count: manifest.tables.values().sum(),
+        size_bytes,
+    })
+}
+
+/// Workflow that restores a backup archive into a new or existing collection,
+/// then rebuilds the search index of that collection from the restored rows.
+/// Rows of an existing collection are kept, unless the backup has rows with the same key.
+#[workflow(DatabaseOperationsQueue)]
+async fn restore_collection(
+    wf_ctx: WfContext,
+    (source_collection_id, backup_name, collection_id): (
+        CollectionId,
+        DatabaseIdentifier,
+        CollectionId,
+    ),
+) -> WorkflowResult<CollectionRestoreResult> {
+    let (table_count, row_count) = restore_backup_archive_activity::run(
+        &wf_ctx,
+        (
+            source_collection_id.clone(),
+            backup_name.clone(),
+            collection_id.clone(),
+        ),
+    )
+    .await?;
+    let search_row_count =
+        rebuild_collection_search_index_activity::run(&wf_ctx, collection_id.clone()).await?;
+    Ok(WfExitValue::Normal(CollectionRestoreResult {
+        source_collection_id,
+        backup_name,
+        collection_id,
+        table_count,
+        row_count,
+        search_row_count,
+    }))
+}
+
+/// Download and unpack the archive, create or migrate the target collection,
+/// then import the tables and the search settings. Returns the table and row counts.
+#[activity(DatabaseOperationsQueue, retries = 2, timeout = 6 * 3600)]
+async fn restore_backup_archive(
+    (source_collection_id, backup_name, collection_id): (
+        CollectionId,
+        DatabaseIdentifier,
+        CollectionId,
+    ),
+) -> anyhow::Result<(u64, u64)> {
+    let work_dir = new_work_dir("restore", &collection_id, &backup_name).await?;
+    let archive_path = archive_path(&work_dir);
+    let archive_key = backup_archive_key(&source_collection_id, &backup_name);
+    S3DatabaseHandle::global_session()
+        .await?
+        .get_backup_file(&archive_key, &archive_path)
+        .await?;
+    let (unpack_dir, unpack_path) = (work_dir.clone(), archive_path.clone());
+    tokio::task::spawn_blocking(move || read_tar_gz(&unpack_path, &unpack_dir)).await??;
+
+    let manifest: BackupManifest =
+        serde_json::from_slice(&tokio::fs::read(work_dir.join(MANIFEST_FILE_NAME)).await?)?;
+    if manifest.format_version > BACKUP_FORMAT_VERSION {
+        anyhow::bail!(
+            "backup {} has format version {}, newer than the supported version {}",
+            archive_key,
+            manifest.format_version,
+            BACKUP_FORMAT_VERSION
+        );
+    }
+
+    if get_single_collection(collection_id.clone()).await.is_ok() {
+        migrate_collection(&collection_id).await?;
+    } else {
+        create_new_collection(collection_id.clone()).await?;
+        let now = chrono::Utc::now();
+        update_collection(CollectionUiRow {
+            collection_id: collection_id.clone(),
+            collection_title: manifest.collection.collection_title.clone(),
+            collection_description: manifest.collection.collection_description.clone(),
+            time_created: now,
+            time_modified: now,
+        })
+        .await?;
+    }
+
+    let known_tables = list_backup_table_names()
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    let mut table_count = 0;
+    let mut row_count = 0;
+    for (table, expected_row_count) in manifest.tables.iter() {
+        if !known_tables.contains(table) {
+            warn!("restore {}: skipping unknown table {}", archive_key, table);
+            continue;
+        }
+        let path = work_dir
+            .join(TABLES_DIR_NAME)
+            .join(format!("{}.jsonl", table));
+        let imported = import_table_rows(&collection_id, table, &path).await?;
+        if imported != *expected_row_count {
+            anyhow::bail!(
+                "restore {} table {}: read {} rows, manifest has {}",
+                archive_key,
+                table,
+                imported,
+                expected_row_count
+            );
+        }
+        info!("restore {} {}: {} rows", collection_id, table, imported);
+        table_count += 1;
+        row_count += imported;
+    }
+    set_search_settings_json(&collection_id, manifest.search_settings).await?;
+    remove_work_dir(&work_dir).await;
+
+    Ok((table_count, row_count))
+}
+
+/// Rebuild the search index of a collection from its rows.
+/// Returns the number of rows indexed.
+#[activity(DatabaseOperationsQueue, retries = 2, timeout = 6 * 3600)]
+async fn rebuild_collection_search_index(collection_id: CollectionId) -> anyhow::Result<u64> {
+    rebuild_search_index(&collection_id).await
+}
+
+/// Workflow that rebuilds the search index of a collection from the rows stored in Scylla,
+/// one activity per table, so the progress is visible in the workflow status tree.
+///
+/// The arguments are the collection, the `fresh_index` flag and the request time,
+/// which makes every reindex request a new workflow.
+/// With `fresh_index`, the rows are indexed into a new, empty index of this request,
+/// which is swapped with the collection index only if its document count matches the indexed
+/// row count. The changes applied to the collection index while the reindex runs are sent to
+/// the new index before the swap.
+/// Without it, the documents are written over the existing ones in the collection index,
+/// so documents of deleted rows are kept.
+#[workflow(DatabaseOperationsQueue)]
+async fn reindex_collection(
+    wf_ctx: WfContext,
+    (collection_id, fresh_index, requested_at): (CollectionId, bool, i64),
+) -> WorkflowResult<SearchReindexResult> {
+    let fresh_reindex_id = fresh_index.then_some(requested_at);
+    let reindexed = run_search_reindex(&wf_ctx, &collection_id, fresh_reindex_id).await;
+    let (tables, row_count, document_count) = match (reindexed, fresh_reindex_id) {
+        (Ok(reindexed), _) => reindexed,
+        (Err(e), Some(reindex_id)) => {
+            abandon_search_reindex_activity::run(&wf_ctx, (collection_id, reindex_id)).await?;
+            return Err(e);
+        }
+        (Err(e), None) => return Err(e),
+    };
+    Ok(WfExitValue::Normal(SearchReindexResult {
+        collection_id,
+        fresh_index,
+        tables,
+        row_count,
+        document_count,
+    }))
+}
+
+/// Run the activities of [reindex_collection]. Returns the row count of each table,
+/// the total row count and the document count of the index.
+async fn run_search_reindex(
+    wf_ctx: &WfContext,
+    collection_id: &CollectionId,
+    fresh_reindex_id: Option<i64>,
+) -> anyhow::Result<(Vec<(String, u64)>, u64, u64)> {
+    let table_names =
+        prepare_search_reindex_activity::run(wf_ctx, (collection_id.clone(), fresh_reindex_id))
+            .await?;
+    let mut tables = vec![];
+    for table_name in table_names {
+        let row_count = reindex_search_table_activity::run(
+            wf_ctx,
+            (collection_id.clone(), table_name.clone(), fresh_reindex_id),
+        )
+        .await?;
+        tables.push((table_name, row_count));
+    }
+    let row_count = tables.iter().map(|(_, count)| count).sum();
+    let document_count = finish_search_reindex_activity::run(
+        wf_ctx,
+        (collection_id.clone(), fresh_reindex_id, row_count),
+    )
+    .await?;
+    Ok((tables, row_count, document_count))
+}
+
+/// Create the fresh index, if requested, and list the tables to reindex.
+/// The fresh reindex id is the request time of the reindex.
+#[activity(DatabaseOperationsQueue, retries = 3, timeout = 300)]
+async fn prepare_search_reindex(
+    (collection_id, fresh_reindex_id): (CollectionId, Option<i64>),
+) -> anyhow::Result<Vec<String>> {
+    if let Some(reindex_id) = fresh_reindex_id {
+        create_fresh_search_index(&collection_id, reindex_id).await?;
+        begin_fresh_search_reindex(&collection_id, reindex_id).await?;
+    }
+    search_index_table_names()
+}
+
+/// Send all the rows of one table to the search index and wait for them to be indexed.
+/// Returns the number of rows indexed.
+#[activity(DatabaseOperationsQueue, retries = 2, timeout = 6 * 3600, heartbeat = 900)]
+async fn reindex_search_table(
+    (collection_id, table_name, fresh_reindex_id): (CollectionId, String, Option<i64>),
+) -> anyhow::Result<u64> {
+    let index = get_reindex_target_search_index(&collection_id, fresh_reindex_id).await?;
+    let row_count = index_table_rows(
+        &collection_id,
+        &table_name,
+        index,
+        Arc::new(|row_count| activity_heartbeat(&row_count)),
+    )
+    .await?;
+    info!(
+        "reindex {} {}: {} rows",
+        collection_id, table_name, row_count
+    );
+    Ok(row_count)
+}
+
+/// Check the document count of the reindexed search index. For a fresh index, also replay
+/// the changes made while the reindex ran, then swap it in; this is saved step by step,
+/// so a retry after the swap does not check the count again. Returns the document count.
+#[activity(DatabaseOperationsQueue, retries = 3, timeout = 3600)]
+async fn finish_search_reindex(
+    (collection_id, fresh_reindex_id, row_count): (CollectionId, Option<i64>, u64),
+) -> anyhow::Result<u64> {
+    if let Some(reindex_id) = fresh_reindex_id {
+        let document_count =
+            finish_fresh_search_reindex(&collection_id, reindex_id, row_count).await?;
+        info!("reindex {}: fresh index swapped in", collection_id);
+        return Ok(document_count);
+    }
+    let index = get_reindex_target_search_index(&collection_id, None).await?;
+    let document_count = search_index_document_count(&index).await?;
+    if document_count < row_count {
+        anyhow::bail!(
+            "reindex {}: index has {} documents, fewer than the {} rows indexed",
+            collection_id,
+            document_count,
+            row_count
+        );
+    }
+    Ok(document_count)
+}
+
+/// Drop the fresh index of a failed reindex, and stop keeping changes for it,
+/// unless it was already swapped in.
+#[activity(DatabaseOperationsQueue, retries = 3, timeout = 300)]
+async fn abandon_search_reindex(
+    (collection_id, reindex_id): (CollectionId, i64),
+) -> anyhow::Result<()> {
+    abandon_fresh_search_reindex(&collection_id, reindex_id).await
+}
+
+/// Workflow that removes the dangling stored graph edges of a collection: the edges
+/// with an endpoint missing from the graph node map or from its model table.
+/// The edge table is scanned one token range at a time, one activity per range.
+///
+/// The arguments are the collection and the request time, which makes every repair request
+/// a new workflow.
+#[workflow(DatabaseOperationsQueue)]
+async fn repair_graph_edges(
+    wf_ctx: WfContext,
+    (collection_id, _requested_at): (CollectionId, i64),
+) -> WorkflowResult<GraphEdgeRepairResult> {
+    let mut result = GraphEdgeRepairResult::default();
+    for segment in 0..GRAPH_REPAIR_SEGMENT_COUNT {
+        result +=
+            repair_graph_edges_segment_activity::run(&wf_ctx, (collection_id.clone(), segment))
+                .await?;
+    }
+    info!(
+        "graph repair {}: {} edge rows scanned, {} dangling edges, {} removed",
+        collection_id, result.scanned_count, result.dangling_count, result.removed_count
+    );
+    Ok(WfExitValue::Normal(result))
+}
+
+/// Remove the dangling stored graph edges in one token range of the edge table.
+#[activity(DatabaseOperationsQueue, retries = 3, timeout = 3600, heartbeat = 600)]
+async fn repair_graph_edges_segment(
+    (collection_id, segment): (CollectionId, u32),
+) -> anyhow::Result<GraphEdgeRepairResult> {
+    graph_repair_edges_segment(&collection_id, segment, |scanned_count| {
+        activity_heartbeat(&scanned_count)
+    })
+    .await
+}
+
+/// Workflow that computes the degree distributions and the most connected nodes of the stored
+/// graph edges of a collection, from a full read of the edge counters, and saves them for the
+/// degree statistics API.
+///
+/// The arguments are the collection and the request time, which makes every request
+/// a new workflow.
+#[workflow(DatabaseOperationsQueue)]
+async fn compute_graph_degrees(
+    wf_ctx: WfContext,
+    (collection_id, _requested_at): (CollectionId, i64),
+) -> WorkflowResult<u32> {
+    Ok(WfExitValue::Normal(
+        save_graph_degree_statistics_activity::run(&wf_ctx, collection_id).await?,
+    ))
+}
+
+/// Compute and save the degree statistics of a collection.
+/// Returns the number of edge types and directions with statistics.
+#[activity(DatabaseOperationsQueue, retries = 2, timeout = 6 * 3600, heartbeat = 900)]
+async fn save_graph_degree_statistics(collection_id: CollectionId) -> anyhow::Result<u32> {
+    let statistics =
+        compute_graph_degree_statistics(&collection_id, |row_count| activity_heartbeat(&row_count))
+            .await?;
+    Ok(statistics.len() as u32)
+}
+
+/// Key of a graph export file in the export bucket.
+pub fn graph_export_file_key(
+    c: &CollectionId,
+    export_name: &DatabaseIdentifier,
+    request: &GraphExportRequest,
+) -> String {
+    format!("{}/{}.{}", c, export_name, request.format.file_extension())
+}
+
+/// Workflow that exports the graph of a collection into a GraphML, GEXF or JSON Graph file
+/// in the object store, with the edge types and nodes selected by the request.
+#[workflow(DatabaseOperationsQueue)]
+async fn export_collection_graph(
+    wf_ctx: WfContext,
+    (collection_id, export_name, request): (CollectionId, DatabaseIdentifier, GraphExportRequest),
+) -> WorkflowResult<GraphExportResult> {
+    Ok(WfExitValue::Normal(
+        write_graph_export_file_activity::run(&wf_ctx, (collection_id, export_name, request))
+            .await?,
+    ))
+}
+
+/// Write the graph export file locally, then upload it into the export bucket.
+#[activity(DatabaseOperationsQueue, retries = 2, timeout = 6 * 3600, heartbeat = 900)]
+async fn write_graph_export_file(
+    (collection_id, export_name, request): (CollectionId, DatabaseIdentifier, GraphExportRequest),
+) -> anyhow::Result<GraphExportResult> {
+    let work_dir = new_work_dir("graph_export", &collection_id, &export_name).await?;
+    let path = work_dir.join(format!("graph.{}", request.format.file_extension()));
+    let (node_count, edge_count) =
+        graph_export_to_file(&collection_id, &request, &path, |edge_count| {
+            activity_heartbeat(&edge_count)
+        })
+        .await?;
+    let size_bytes = tokio::fs::metadata(&path).await?.len();
+
+    let file_key = graph_export_file_key(&collection_id, &export_name, &request);
+    S3DatabaseHandle::global_session()
+        .await?
+        .put_export_file(&file_key, &path)
+        .await?;
+    info!(
+        "graph export {} saved as {}: {} nodes, {} edges",
+        collection_id, file_key, node_count, edge_count
+    );
+    remove_work_dir(&work_dir).await;
+
+    Ok(GraphExportResult {
+        collection_id,
+        export_name,
+        file_key,
+        node_count,
+        edge_count,
+        size_bytes,
+    })
+}
+
+/// Create an empty local directory for a backup, restore or graph export, removing leftovers of earlier attempts.
+/// It is placed in the worker tempdir for big files, since it holds whole collection tables.
+async fn new_work_dir(
+    kind: &str,
+    c: &CollectionId,
+    name: &DatabaseIdentifier,
+) -> anyhow::Result<PathBuf> {
+    let tempdir = PathBuf::from(std::env::var(WORKER_TEMPDIR_ENV_VAR_BIG)?).canonicalize()?;
+    let work_dir = tempdir.join(format!("hoover3_{}_{}_{}", kind, c, name));
+    remove_work_dir(&work_dir).await;
+    tokio::fs::create_dir_all(&work_dir).await?;
+    Ok(work_dir)
+}
+
+/// The archive is kept next to the work directory, so it is not packed into itself.
+fn archive_path(work_dir: &Path) -> PathBuf {
+    let mut path = work_dir.as_os_str().to_owned();
+    path.push(".tar.gz");
+    PathBuf::from(path)
+}
+
+async fn remove_work_dir(work_dir: &Path) {
+    let _ = tokio::fs::remove_dir_all(work_dir).await;
+    let _ = tokio::fs::remove_file(archive_path(work_dir)).await;
+}
+
+fn write_tar_gz(dir: &Path, archive_path: &Path) -> anyhow::Result<()> {
+    let file = std::fs::File::create(archive_path)?;
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    builder.append_dir_all(".", dir)?;
+    builder.into_inner()?.finish()?.sync_all()?;
+    Ok(())
+}
+
+fn read_tar_gz(archive_path: &Path, dir: &Path) -> anyhow::Result<()> {
+    let file = std::fs::File::open(archive_path)?;
+    tar::Archive::new(flate2::read::GzDecoder::new(file)).unpack(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_backup_archive_round_trip() -> anyhow::Result<()> {
+    let work_dir = std::env::temp_dir().join("hoover3_test_backup_archive_round_trip");
+    let _ = std::fs::remove_dir_all(&work_dir);
+    std::fs::create_dir_all(work_dir.join(TABLES_DIR_NAME))?;
+    std::fs::write(work_dir.join(MANIFEST_FILE_NAME), b"{}")?;
+    std::fs::write(
+        work_dir.join(TABLES_DIR_NAME).join("t.jsonl"),
+        b"{\"a\":1}\n",
+    )?;
+
+    let archive = archive_path(&work_dir);
+    write_tar_gz(&work_dir, &archive)?;
+    std::fs::remove_dir_all(&work_dir)?;
+    std::fs::create_dir_all(&work_dir)?;
+    read_tar_gz(&archive, &work_dir)?;
+    assert_eq!(std::fs::read(work_dir.join(MANIFEST_FILE_NAME))?, b"{}");
+    assert_eq!(
+        std::fs::read(work_dir.join(TABLES_DIR_NAME).join("t.jsonl"))?,
+        b"{\"a\":1}\n"
+    );
+
+    std::fs::remove_dir_all(&work_dir)?;
+    std::fs::remove_file(&archive)?;
+    Ok(())
+}
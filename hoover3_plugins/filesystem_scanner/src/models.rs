@@ -93,6 +93,10 @@ pub struct FsDirectoryDbRow {
     /// Scan results for the directory's total contents, including all descendants
     #[model(search(facet))]
     pub scan_total: fs_directory_scan_result,
+
+    /// Timestamp of the rescan that found the directory missing from the datasource
+    #[model(search(facet))]
+    pub removed_at: Option<Timestamp>,
 }
 
 impl FsDirectoryDbRow {
@@ -120,6 +124,7 @@ impl FsDirectoryDbRow {
             fs_created: meta.created,
             scan_children: Default::default(),
             scan_total: Default::default(),
+            removed_at: None,
         }
     }
 }
@@ -153,6 +158,15 @@ pub struct FsFileDbRow {
     /// Timestamp of the file's creation
     #[model(search(facet))]
     pub fs_created: Option<Timestamp>,
+
+    /// The sha3-256 hash of the file content, set after the file is hashed.
+    /// Cleared when a rescan finds the file changed, so it is hashed again.
+    #[model(search(index))]
+    pub blob_sha3_256: Option<String>,
+
+    /// Timestamp of the rescan that found the file missing from the datasource
+    #[model(search(facet))]
+    pub removed_at: Option<Timestamp>,
 }
 
 impl FsFileDbRow {
@@ -177,8 +191,19 @@ impl FsFileDbRow {
             size_bytes: meta.size_bytes as i64,
             fs_modified: meta.modified,
             fs_created: meta.created,
+            blob_sha3_256: None,
+            removed_at: None,
         }
     }
+
+    /// Check if the file looks the same as when this row was saved, by size and modification time.
+    /// Times are compared in milliseconds, since that is what the database stores.
+    pub fn is_unchanged(&self, meta: &FsMetadataBasic) -> bool {
+        self.removed_at.is_none()
+            && self.size_bytes == meta.size_bytes as i64
+            && self.fs_modified.map(|t| t.timestamp_millis())
+                == meta.modified.map(|t| t.timestamp_millis())
+    }
 }
 
 declare_implicit_graph_edge!(
@@ -194,6 +219,34 @@ declare_implicit_graph_edge!(
     FsFileDbRow
 );
 
+/// Directory that holds a Maildir mailbox (has `cur`, `new` and `tmp` subfolders).
+/// Each message inside is a separate file, processed as an email blob.
+#[model]
+pub struct FsMaildirDbRow {
+    /// Unique identifier for the datasource
+    #[model(primary(partition))]
+    #[model(search(facet))]
+    pub datasource_id: String,
+
+    /// Path to the Maildir directory
+    #[model(primary(partition))]
+    #[model(search(index))]
+    pub path: String,
+}
+
+declare_implicit_graph_edge!(
+    FsDirectoryToMaildir,
+    "fs_directory_maildir",
+    FsDirectoryDbRow,
+    FsMaildirDbRow
+);
+declare_stored_graph_edge!(
+    FsMaildirToMessage,
+    "fs_maildir_message",
+    FsMaildirDbRow,
+    FsFileDbRow
+);
+
 /// Model for storing the different types of hashes for a blob.
 #[model]
 pub struct FsBlobHashesDbRow {
@@ -228,6 +281,15 @@ pub struct FsBlobHashesDbRow {
     pub file_name: String,
 }
 
+/// Blob that is no longer linked to some file, because the file was changed or removed.
+/// The blob garbage collector checks if anything else still references it.
+#[model]
+pub struct FsBlobGcCandidateDbRow {
+    /// The sha3-256 hash of the blob.
+    #[model(primary(partition))]
+    pub blob_sha3_256: String,
+}
+
 /// Model for storing the different types of hashes for a blob.
 #[model]
 pub struct FsBlobPlanPageDbRow {
@@ -254,10 +316,15 @@ pub struct FsBlobMimeTypeDbRow {
     pub magika_score: Option<f32>,
     /// The mime type of the blob, from tika.
     pub tika_mime: String,
+    /// The best mime type of the blob, picked from libmagic, magika and tika, without parameters.
+    #[model(search(facet))]
+    pub best_mime: Option<String>,
     /// Tika metadata extraction was successful.
     pub tika_metadata_success: bool,
     /// Tika Content extraction was successful.
     pub tika_content_success: bool,
+    /// OCR extraction was successful; `None` if OCR did not run for this blob.
+    pub ocr_success: Option<bool>,
 }
 
 declare_stored_graph_edge!(
@@ -4,8 +4,8 @@
 //! Save the results to the database in [FsBlobHashesDbRow].
 
 use anyhow::Context;
-use charybdis::{batch::ModelBatch, model::BaseModel};
-use futures::{pin_mut, StreamExt};
+use charybdis::model::BaseModel;
+use futures::{pin_mut, StreamExt, TryStreamExt};
 use hoover3_data_access::list_disk::read_file_to_stream;
 use hoover3_database::{
     constants::CQL_SELECT_BATCH_SIZE,
@@ -14,26 +14,24 @@ use hoover3_database::{
 };
 use hoover3_macro::{activity, workflow};
 use hoover3_taskdef::{
+    activity_heartbeat, activity_heartbeat_details, activity_is_cancelled,
     TemporalioActivityDescriptor, TemporalioWorkflowDescriptor, WfContext, WfExitValue,
     WorkflowResult,
 };
+use hoover3_tracing::tracing::info;
 use hoover3_types::{
     filesystem::FsScanHashesResult,
     identifier::{CollectionId, DatabaseIdentifier},
 };
 use serde::{Deserialize, Serialize};
-use std::{
-    collections::{BTreeMap, BTreeSet, HashMap},
-    sync::Arc,
-};
-use tokio::sync::Mutex;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use super::{
     hash_files_plan::{compute_file_hash_plan_activity, FileHashPlanChunk},
     FilesystemScannerQueue,
 };
 use crate::models::{
-    find_fs_blob_hashes_db_row, FsBlobHashesDbRow, FsFileHashPlanDbRow, FsFileToHashes,
+    find_fs_blob_hashes_db_row, FsBlobHashesDbRow, FsFileDbRow, FsFileHashPlanDbRow, FsFileToHashes,
 };
 
 /// Argument for hashing a file
@@ -145,7 +143,88 @@ mod md5_impl {
     }
 }
 
-async fn filter_out_existing_hashes(
+/// Hashes of a single blob, computed with all the supported algorithms.
+/// All values are lowercase hex strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobHashes {
+    /// The SHA3-256 hash.
+    pub sha3_256: String,
+    /// The SHA256 hash.
+    pub sha256: String,
+    /// The SHA1 hash.
+    pub sha1: String,
+    /// The md5 hash.
+    pub md5: String,
+}
+
+/// Computes all the blob hashes in one pass over the data.
+pub struct BlobHasher {
+    hash_functions: Vec<Box<dyn HashFunction + Send>>,
+}
+
+impl Default for BlobHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlobHasher {
+    /// Create a new hasher with all the supported hash functions.
+    pub fn new() -> Self {
+        Self {
+            hash_functions: vec![
+                Box::new(sha3_impl::sha3_256_new()),
+                Box::new(sha1_impl::sha1_new()),
+                Box::new(sha256_impl::sha256_new()),
+                Box::new(md5_impl::md5_new()),
+            ],
+        }
+    }
+
+    /// Feed a chunk of data into all hash functions.
+    pub fn update(&mut self, data: &[u8]) {
+        for hash_function in self.hash_functions.iter_mut() {
+            hash_function.h_update(data);
+        }
+    }
+
+    /// Finish hashing and return the hex values.
+    pub fn finalize(self) -> BlobHashes {
+        let mut finished_hashes = HashMap::new();
+        for hash_function in self.hash_functions.iter() {
+            finished_hashes.insert(hash_function.h_type(), hash_function.h_finalize());
+        }
+        BlobHashes {
+            sha3_256: to_hex(&finished_hashes[&HashType::Sha3_256]),
+            sha256: to_hex(&finished_hashes[&HashType::Sha256]),
+            sha1: to_hex(&finished_hashes[&HashType::Sha1]),
+            md5: to_hex(&finished_hashes[&HashType::Md5]),
+        }
+    }
+}
+
+/// Hash a local file with all the supported algorithms.
+/// Returns the hashes and the file size.
+pub async fn hash_local_file(path: &std::path::Path) -> anyhow::Result<(BlobHashes, u64)> {
+    use tokio::io::AsyncReadExt;
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = BlobHasher::new();
+    let mut buf = vec![0; 4 * 1024 * 1024];
+    let mut size = 0_u64;
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((hasher.finalize(), size))
+}
+
+/// Remove rows for blobs that are already present in the database.
+/// Also de-duplicates the input rows by `blob_sha3_256`.
+pub async fn filter_out_existing_hashes(
     session: &ScyllaDatabaseHandle,
     rows: Vec<FsBlobHashesDbRow>,
 ) -> anyhow::Result<Vec<FsBlobHashesDbRow>> {
@@ -177,13 +256,24 @@ async fn filter_out_existing_hashes(
         .collect())
 }
 
+/// Number of files hashed between saving the results and recording a checkpoint.
+const HASH_FILES_SAVE_EVERY: usize = 100;
+
+/// Progress of [fs_do_hash_files], sent with the activity heartbeats.
+/// A retried activity skips the files saved before the last checkpoint.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+struct HashFilesCheckpoint {
+    /// Number of files from the plan chunk hashed and saved to the database.
+    files_saved: u64,
+}
+
 /// Hash some fiels and save the results to the database in [FsBlobHashesDbRow].
 /// The files are hashed by this function one after the other, so it should receive
-/// batches of similar size (in file byte total)
-#[activity(FilesystemScannerQueue)]
+/// batches of similar size (in file byte total).
+/// Results are saved every few files and a heartbeat checkpoint is recorded,
+/// so a retry after a worker crash continues from the last checkpoint.
+#[activity(FilesystemScannerQueue, retries = 2, timeout = 4 * 3600, heartbeat = 300)]
 async fn fs_do_hash_files(args: HashFileArgs) -> anyhow::Result<FsScanHashesResult> {
-    let mut new_hashes = vec![];
-
     let session = ScyllaDatabaseHandle::collection_session(&args.collection_id).await?;
     let plan_chunk = FsFileHashPlanDbRow::find_by_datasource_id_and_plan_chunk_id(
         args.datasource_id.to_string(),
@@ -207,9 +297,19 @@ async fn fs_do_hash_files(args: HashFileArgs) -> anyhow::Result<FsScanHashesResu
     let file_count = scan_file_args.len() as u64;
     drop(session);
 
-    let mut edge_batch = FsFileToHashes::edge_batch(&args.collection_id);
+    let mut checkpoint = activity_heartbeat_details::<HashFilesCheckpoint>().unwrap_or_default();
+    if checkpoint.files_saved > 0 {
+        info!(
+            "fs_do_hash_files {}/{}: resuming after {} files",
+            args.datasource_id, args.plan_chunk_id, checkpoint.files_saved
+        );
+    }
 
-    for (dir, file_name, plan_file_size) in scan_file_args {
+    let mut new_hashes = vec![];
+    for (dir, file_name, plan_file_size) in scan_file_args
+        .into_iter()
+        .skip(checkpoint.files_saved as usize)
+    {
         let (file_size, chunks) = read_file_to_stream(
             args.collection_id.clone(),
             args.datasource_id.clone(),
@@ -221,65 +321,115 @@ async fn fs_do_hash_files(args: HashFileArgs) -> anyhow::Result<FsScanHashesResu
             anyhow::bail!("File size mismatch for file: {:?}", file_name);
         }
         pin_mut!(chunks);
-        let mut hash_functions: Vec<Arc<Mutex<dyn HashFunction + Send>>> = vec![
-            Arc::new(Mutex::new(sha3_impl::sha3_256_new())),
-            Arc::new(Mutex::new(sha1_impl::sha1_new())),
-            Arc::new(Mutex::new(sha256_impl::sha256_new())),
-            Arc::new(Mutex::new(md5_impl::md5_new())),
-        ];
-
+        let mut hasher = BlobHasher::new();
         while let Some(Ok(chunk)) = chunks.next().await {
-            for hash_function in hash_functions.iter_mut() {
-                hash_function.lock().await.h_update(&chunk);
-            }
-        }
-        let mut finished_hashes = HashMap::new();
-        for hash_function in hash_functions.into_iter() {
-            let l = hash_function.lock().await;
-            let v = l.h_finalize();
-            let k = l.h_type();
-            finished_hashes.insert(k, v);
+            hasher.update(&chunk);
+            // big files can take a while; show we are still alive
+            activity_heartbeat(&checkpoint);
         }
-        let hashes_row = FsBlobHashesDbRow {
-            blob_sha3_256: to_hex(&finished_hashes[&HashType::Sha3_256]),
-            blob_sha256: to_hex(&finished_hashes[&HashType::Sha256]),
-            blob_md5: to_hex(&finished_hashes[&HashType::Md5]),
-            blob_sha1: to_hex(&finished_hashes[&HashType::Sha1]),
+        let hashes = hasher.finalize();
+        new_hashes.push(FsBlobHashesDbRow {
+            blob_sha3_256: hashes.sha3_256,
+            blob_sha256: hashes.sha256,
+            blob_md5: hashes.md5,
+            blob_sha1: hashes.sha1,
             size_bytes: file_size as i64,
             datasource_id: args.datasource_id.to_string(),
-            parent_dir_path: dir.clone(),
-            file_name: file_name.clone(),
-        };
-        new_hashes.push(hashes_row.clone());
+            parent_dir_path: dir,
+            file_name,
+        });
+
+        if new_hashes.len() >= HASH_FILES_SAVE_EVERY {
+            save_file_hashes(&args, std::mem::take(&mut new_hashes), &mut checkpoint).await?;
+            if activity_is_cancelled() {
+                anyhow::bail!(
+                    "fs_do_hash_files {}/{} cancelled",
+                    args.datasource_id,
+                    args.plan_chunk_id
+                );
+            }
+        }
+    }
+    save_file_hashes(&args, new_hashes, &mut checkpoint).await?;
+
+    Ok(FsScanHashesResult {
+        file_count,
+        hash_count: file_count,
+    })
+}
+
+/// Save the hashes of some files: the new [FsBlobHashesDbRow], the file-to-hash edges,
+/// and the hash on the file rows. Then record a heartbeat checkpoint.
+async fn save_file_hashes(
+    args: &HashFileArgs,
+    hashes_rows: Vec<FsBlobHashesDbRow>,
+    checkpoint: &mut HashFilesCheckpoint,
+) -> anyhow::Result<()> {
+    if hashes_rows.is_empty() {
+        return Ok(());
+    }
+    let mut edge_batch = FsFileToHashes::edge_batch(&args.collection_id);
+    let mut file_hashes: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    for hashes_row in hashes_rows.iter() {
+        file_hashes
+            .entry(hashes_row.parent_dir_path.clone())
+            .or_default()
+            .insert(
+                hashes_row.file_name.clone(),
+                hashes_row.blob_sha3_256.clone(),
+            );
         edge_batch.add_edge_from_pk(
-            &(args.datasource_id.to_string(), dir, file_name),
+            &(
+                args.datasource_id.to_string(),
+                hashes_row.parent_dir_path.clone(),
+                hashes_row.file_name.clone(),
+            ),
             &hashes_row.primary_key_values(),
         );
     }
-    let hash_count = new_hashes.len() as u64;
+    let file_count = hashes_rows.len() as u64;
 
     let session = ScyllaDatabaseHandle::collection_session(&args.collection_id).await?;
-    let new_hashes: Vec<FsBlobHashesDbRow> = filter_out_existing_hashes(&session, new_hashes)
+    let new_hashes: Vec<FsBlobHashesDbRow> = filter_out_existing_hashes(&session, hashes_rows)
         .await
         .context("filter_out_existing_hashes")?;
     if !new_hashes.is_empty() {
-        for new_hashes in new_hashes.chunks(300) {
-            let mut batch = FsBlobHashesDbRow::batch();
-            batch.append_inserts(&new_hashes);
-            batch.execute(&session).await.context("batch execute")?;
-            DatabaseExtraCallbacks::new(&args.collection_id)
-                .await?
-                .insert(&new_hashes)
-                .await?;
-        }
+        DatabaseExtraCallbacks::new(&args.collection_id)
+            .await?
+            .insert_rows(&new_hashes, 300)
+            .await
+            .context("hashes insert")?;
     }
 
     edge_batch.execute().await.context("edge batch execute")?;
 
-    Ok(FsScanHashesResult {
-        file_count,
-        hash_count: hash_count,
-    })
+    // save the hashes on the file rows, so rescans only hash new or changed files
+    let mut hashed_files = vec![];
+    for (dir, hashes) in file_hashes {
+        let rows = FsFileDbRow::find_by_datasource_id_and_parent_dir_path(
+            args.datasource_id.to_string(),
+            dir,
+        )
+        .execute(&session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+        for mut row in rows {
+            if let Some(blob_sha3_256) = hashes.get(&row.file_name) {
+                row.blob_sha3_256 = Some(blob_sha3_256.clone());
+                hashed_files.push(row);
+            }
+        }
+    }
+    DatabaseExtraCallbacks::new(&args.collection_id)
+        .await?
+        .insert_rows(&hashed_files, 1024)
+        .await
+        .context("file rows insert")?;
+
+    checkpoint.files_saved += file_count;
+    activity_heartbeat(checkpoint);
+    Ok(())
 }
 
 /// Execute one hash file chunk.
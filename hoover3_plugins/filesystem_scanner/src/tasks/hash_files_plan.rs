@@ -8,6 +8,7 @@ use crate::models::{
 };
 use async_stream::try_stream;
 use charybdis::operations::InsertWithCallbacks;
+use futures::future::ready;
 use futures::{pin_mut, StreamExt, TryStreamExt};
 use hoover3_database::db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle};
 use hoover3_database::models::collection::{
@@ -20,7 +21,8 @@ use serde::{Deserialize, Serialize};
 
 use super::FilesystemScannerQueue;
 
-/// Compute the plan for hashing all files in a datasource. Write plan chunks to database.
+/// Compute the plan for hashing all files in a datasource that were not hashed yet,
+/// skipping the ones marked as removed. Write plan chunks to database.
 /// Returns a list of plan chunk ids.
 #[activity(FilesystemScannerQueue)]
 pub async fn compute_file_hash_plan(
@@ -81,7 +83,9 @@ async fn stream_file_hashing_plan(
     let edge_chain = chain_edges(FsDatasourceToDirectory, FsDirectoryToFile);
     let stream = edge_chain
         .list_target(&collection_id, &(datasource_id.to_string(),))
-        .await?;
+        .await?
+        .try_filter(|file| ready(file.removed_at.is_none() && file.blob_sha3_256.is_none()))
+        .boxed();
     let stream = chunk_by_size(stream, min_read_size, max_chunk_size, |file| {
         file.size_bytes
     })?;
@@ -14,4 +14,5 @@ declare_task_queue!(
 pub mod hash_files;
 pub mod hash_files_plan;
 pub mod process_plan;
+pub mod remove_datasource;
 pub mod scan_filesystem;
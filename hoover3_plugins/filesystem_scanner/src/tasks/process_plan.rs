@@ -1,11 +1,12 @@
 //! Plan for processing blobs that have been hashed.
 //! Splits the work into chunks of similar file size.
 
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 
 use crate::models::{
-    find_fs_blob_plan_page_db_row, BlobProcessingPlan, BlobProcessingPlanPageBlobs,
-    FsBlobHashesDbRow, FsBlobPlanPageDbRow,
+    find_blob_processing_plan, find_fs_blob_plan_page_db_row, BlobProcessingPlan,
+    BlobProcessingPlanPageBlobs, FsBlobHashesDbRow, FsBlobPlanPageDbRow, FsDatasourceToDirectory,
+    FsDirectoryToFile,
 };
 use anyhow::Context;
 use async_stream::try_stream;
@@ -14,12 +15,14 @@ use charybdis::operations::{Find, InsertWithCallbacks};
 use futures::{pin_mut, StreamExt, TryStreamExt};
 use hoover3_database::constants::CQL_SELECT_BATCH_SIZE;
 use hoover3_database::db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle};
-use hoover3_database::models::collection::{DatabaseExtraCallbacks, ResultStream};
+use hoover3_database::models::collection::{
+    chain_edges, DatabaseExtraCallbacks, GraphEdgeQuery, ResultStream,
+};
 use hoover3_macro::{activity, workflow};
 use hoover3_taskdef::{TemporalioActivityDescriptor, WfContext, WfExitValue, WorkflowResult};
 use hoover3_tracing::tracing::info;
 use hoover3_types::filesystem::ProcessingPlanResult;
-use hoover3_types::identifier::CollectionId;
+use hoover3_types::identifier::{CollectionId, DatabaseIdentifier};
 use tokio::time::Instant;
 
 use super::hash_files_plan::chunk_by_size;
@@ -133,6 +136,59 @@ async fn get_max_plan_page_id(collection_id: &CollectionId) -> anyhow::Result<i3
     Ok(max_id)
 }
 
+/// Stream the hashes of the blobs of the files in a datasource, in chunks of distinct hashes
+/// small enough for one `IN` query.
+pub async fn stream_datasource_blob_hashes(
+    collection_id: &CollectionId,
+    datasource_id: &DatabaseIdentifier,
+) -> anyhow::Result<ResultStream<Vec<String>>> {
+    let stream = chain_edges(FsDatasourceToDirectory, FsDirectoryToFile)
+        .list_target(collection_id, &(datasource_id.to_string(),))
+        .await?
+        .try_filter(|file| std::future::ready(file.removed_at.is_none()))
+        .try_filter_map(|file| std::future::ready(Ok(file.blob_sha3_256)))
+        .chunks(CQL_SELECT_BATCH_SIZE)
+        .map(|chunk| {
+            let chunk = chunk.into_iter().collect::<anyhow::Result<BTreeSet<_>>>()?;
+            Ok(chunk.into_iter().collect::<Vec<_>>())
+        });
+    Ok(stream.boxed())
+}
+
+/// Mark the plan pages holding any of the given blobs as not started,
+/// so the next processing run processes them again.
+pub async fn reset_blob_plan_pages(
+    collection_id: &CollectionId,
+    blob_hashes: &[String],
+) -> anyhow::Result<()> {
+    let session = ScyllaDatabaseHandle::collection_session(collection_id).await?;
+    let page_ids = find_fs_blob_plan_page_db_row!("blob_sha3_256 IN ?", (blob_hashes.to_vec(),))
+        .execute(&session)
+        .await?
+        .map_ok(|row| row.plan_page_id)
+        .try_collect::<BTreeSet<_>>()
+        .await?;
+    if page_ids.is_empty() {
+        return Ok(());
+    }
+    let page_ids = page_ids.into_iter().collect::<Vec<_>>();
+    let plans = find_blob_processing_plan!("plan_page_id IN ?", (page_ids,))
+        .execute(&session)
+        .await?
+        .try_filter(|plan| std::future::ready(plan.is_started))
+        .map_ok(|plan| BlobProcessingPlan {
+            is_started: false,
+            ..plan
+        })
+        .try_collect::<Vec<_>>()
+        .await?;
+    DatabaseExtraCallbacks::new(collection_id)
+        .await?
+        .insert_rows(&plans, 1024)
+        .await?;
+    Ok(())
+}
+
 // fn flatten_result<T, E>(r: Result<Result<T, E>, E>) -> Result<T, E> {
 //     match r {
 //         Ok(Ok(r)) => Ok(r),
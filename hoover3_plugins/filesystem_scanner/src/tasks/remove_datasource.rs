@@ -0,0 +1,218 @@
+//! Remove a datasource and all the rows the filesystem scanner derived from it:
+//! directories, files, Maildir folders and hashing plans, with their search documents and graph edges.
+//! The blobs found in the removed files are saved as [FsBlobGcCandidateDbRow],
+//! since other datasources or containers may still reference them.
+
+use charybdis::batch::ModelBatch;
+use futures::{StreamExt, TryStreamExt};
+use hoover3_data_access::api::{drop_datasource, purge_datasource};
+use hoover3_database::db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle};
+use hoover3_database::models::collection::DatabaseExtraCallbacks;
+use hoover3_macro::{activity, workflow};
+use hoover3_taskdef::{
+    TemporalioActivityDescriptor, TemporalioWorkflowDescriptor, WfContext, WfExitValue,
+    WorkflowResult,
+};
+use hoover3_types::filesystem::FsRemoveDatasourceResult;
+use hoover3_types::identifier::{CollectionId, DatabaseIdentifier};
+use std::collections::BTreeSet;
+
+use super::scan_filesystem::{remove_file_hash_edges, remove_maildir_message_edges};
+use super::FilesystemScannerQueue;
+use crate::models::{
+    FsBlobGcCandidateDbRow, FsDirectoryDbRow, FsFileDbRow, FsFileHashPlanDbRow,
+    FsFileHashPlanPageDbRow, FsMaildirDbRow,
+};
+
+/// Number of directories removed by a single batch.
+const REMOVE_DIRECTORY_BATCH_SIZE: usize = 256;
+
+/// Workflow for removing a datasource. Marks the datasource as deleted,
+/// then removes its directories in batches - one child workflow per batch,
+/// so the progress shows up in the task status tree - and finally its hashing plan.
+/// The datasource row itself is kept, so the blob garbage collector can run next.
+#[workflow(FilesystemScannerQueue)]
+async fn fs_remove_datasource(
+    wf_ctx: WfContext,
+    (collection_id, datasource_id): (CollectionId, DatabaseIdentifier),
+) -> WorkflowResult<FsRemoveDatasourceResult> {
+    fs_mark_datasource_deleted_activity::run(
+        &wf_ctx,
+        (collection_id.clone(), datasource_id.clone()),
+    )
+    .await?;
+
+    let mut result = FsRemoveDatasourceResult::default();
+    for batch_index in 0_i32.. {
+        let batch = fs_remove_directory_batch_workflow::run_as_child(
+            &wf_ctx,
+            (collection_id.clone(), datasource_id.clone(), batch_index),
+        )
+        .await?;
+        if batch.dir_count == 0 {
+            break;
+        }
+        result += batch;
+    }
+
+    fs_remove_hash_plan_activity::run(&wf_ctx, (collection_id, datasource_id)).await?;
+    Ok(WfExitValue::Normal(result))
+}
+
+/// Mark the datasource as deleted, so it is hidden from the datasource list.
+#[activity(FilesystemScannerQueue, retries = 5, timeout = 60)]
+async fn fs_mark_datasource_deleted(
+    (collection_id, datasource_id): (CollectionId, DatabaseIdentifier),
+) -> anyhow::Result<()> {
+    drop_datasource((collection_id, datasource_id)).await
+}
+
+/// Remove the datasource row, once everything derived from it is gone.
+#[activity(FilesystemScannerQueue, retries = 5, timeout = 60)]
+async fn fs_purge_datasource(
+    (collection_id, datasource_id): (CollectionId, DatabaseIdentifier),
+) -> anyhow::Result<()> {
+    purge_datasource((collection_id, datasource_id)).await
+}
+
+/// Workflow that removes one batch of directories.
+/// The batch index is only used to get a different workflow id for each batch.
+#[workflow(FilesystemScannerQueue)]
+async fn fs_remove_directory_batch(
+    wf_ctx: WfContext,
+    (collection_id, datasource_id, _batch_index): (CollectionId, DatabaseIdentifier, i32),
+) -> WorkflowResult<FsRemoveDatasourceResult> {
+    Ok(WfExitValue::Normal(
+        fs_do_remove_directory_batch_activity::run(&wf_ctx, (collection_id, datasource_id)).await?,
+    ))
+}
+
+/// Remove the first directories left in the datasource, together with their files and Maildir folders.
+/// Returns a zero directory count when nothing is left to remove.
+#[activity(FilesystemScannerQueue)]
+async fn fs_do_remove_directory_batch(
+    (collection_id, datasource_id): (CollectionId, DatabaseIdentifier),
+) -> anyhow::Result<FsRemoveDatasourceResult> {
+    let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+    let db_extra = DatabaseExtraCallbacks::new(&collection_id).await?;
+
+    let dirs = FsDirectoryDbRow::find_by_datasource_id(datasource_id.to_string())
+        .execute(&session)
+        .await?
+        .take(REMOVE_DIRECTORY_BATCH_SIZE)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let mut result = FsRemoveDatasourceResult::default();
+    for dir in dirs.iter() {
+        let files = FsFileDbRow::find_by_datasource_id_and_parent_dir_path(
+            dir.datasource_id.clone(),
+            dir.path.clone(),
+        )
+        .execute(&session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+        let blob_hashes = remove_file_hash_edges(&collection_id, &files).await?;
+        remove_maildir_message_edges(&collection_id, &files).await?;
+        result.blob_candidate_count += save_blob_gc_candidates(&session, blob_hashes).await?;
+
+        db_extra.delete(&files).await?;
+        FsFileDbRow::delete_by_datasource_id_and_parent_dir_path(
+            dir.datasource_id.clone(),
+            dir.path.clone(),
+        )
+        .execute(&session)
+        .await?;
+        result.file_count += files.len() as u64;
+
+        remove_maildir_row(&session, &db_extra, &dir.datasource_id, &dir.path).await?;
+    }
+
+    db_extra.delete(&dirs).await?;
+    FsDirectoryDbRow::batch()
+        .chunked_delete(&session, &dirs, 1024)
+        .await?;
+    result.dir_count = dirs.len() as u64;
+    Ok(result)
+}
+
+/// Remove the file hashing plan of the datasource.
+#[activity(FilesystemScannerQueue)]
+async fn fs_remove_hash_plan(
+    (collection_id, datasource_id): (CollectionId, DatabaseIdentifier),
+) -> anyhow::Result<()> {
+    let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+    let db_extra = DatabaseExtraCallbacks::new(&collection_id).await?;
+
+    let pages = FsFileHashPlanPageDbRow::find_by_datasource_id(datasource_id.to_string())
+        .execute(&session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let mut plans = vec![];
+    for page in pages.iter() {
+        FsFileHashPlanDbRow::delete_by_datasource_id_and_plan_chunk_id(
+            page.datasource_id.clone(),
+            page.plan_chunk_id,
+        )
+        .execute(&session)
+        .await?;
+        // only the primary key is needed to delete the search document
+        plans.push(FsFileHashPlanDbRow {
+            datasource_id: page.datasource_id.clone(),
+            plan_chunk_id: page.plan_chunk_id,
+            plan_data: String::new(),
+        });
+    }
+    FsFileHashPlanPageDbRow::delete_by_datasource_id(datasource_id.to_string())
+        .execute(&session)
+        .await?;
+    db_extra.delete(&plans).await?;
+    db_extra.delete(&pages).await?;
+    Ok(())
+}
+
+/// Remove the Maildir row for a directory, if the directory is a Maildir folder.
+pub(crate) async fn remove_maildir_row(
+    session: &ScyllaDatabaseHandle,
+    db_extra: &DatabaseExtraCallbacks,
+    datasource_id: &str,
+    path: &str,
+) -> anyhow::Result<()> {
+    let Some(maildir) = FsMaildirDbRow::maybe_find_first_by_datasource_id_and_path(
+        datasource_id.to_string(),
+        path.to_string(),
+    )
+    .execute(session)
+    .await?
+    else {
+        return Ok(());
+    };
+    FsMaildirDbRow::delete_by_datasource_id_and_path(
+        maildir.datasource_id.clone(),
+        maildir.path.clone(),
+    )
+    .execute(session)
+    .await?;
+    db_extra.delete(&[maildir]).await?;
+    Ok(())
+}
+
+/// Save blobs that lost a file reference, for the blob garbage collector to check.
+/// Returns the number of distinct blobs saved.
+pub(crate) async fn save_blob_gc_candidates(
+    session: &ScyllaDatabaseHandle,
+    blob_hashes: Vec<String>,
+) -> anyhow::Result<u64> {
+    let rows = blob_hashes
+        .into_iter()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|blob_sha3_256| FsBlobGcCandidateDbRow { blob_sha3_256 })
+        .collect::<Vec<_>>();
+    FsBlobGcCandidateDbRow::batch()
+        .chunked_insert(session, &rows, 1024)
+        .await?;
+    Ok(rows.len() as u64)
+}
@@ -2,31 +2,42 @@
 //! Scan results (files and directories) are saved to the database.
 
 use charybdis::batch::ModelBatch;
+use charybdis::model::BaseModel;
 use charybdis::operations::Find;
 use charybdis::operations::InsertWithCallbacks;
 use charybdis::operations::UpdateWithCallbacks;
-use hoover3_database::client_query::list_disk::get_path_metadata;
-use hoover3_database::client_query::list_disk::list_directory;
+use futures::TryStreamExt;
+use hoover3_data_access::list_disk::get_datasource_path_metadata;
+use hoover3_data_access::list_disk::list_datasource_directory;
 use hoover3_database::db_management::DatabaseSpaceManager;
 use hoover3_database::db_management::ScyllaDatabaseHandle;
+use hoover3_database::models::collection::edge_list_targets_pk;
+use hoover3_database::models::collection::DatabaseExtraCallbacks;
+use hoover3_database::models::collection::GraphEdgeInsert;
 use hoover3_taskdef::TemporalioWorkflowDescriptor;
 use hoover3_taskdef::{
     activity, anyhow, workflow, TemporalioActivityDescriptor, WfContext, WfExitValue,
     WorkflowResult,
 };
-use hoover3_types::datasource::DatasourceSettings;
 use hoover3_types::filesystem::FsScanDatasourceDirsResult;
 use hoover3_types::filesystem::FsScanResult;
 use hoover3_types::identifier::CollectionId;
 use hoover3_types::identifier::DatabaseIdentifier;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
 use std::path::PathBuf;
 
+use crate::models::find_fs_directory_db_row;
 use crate::models::FsDirectoryDbRow;
 use crate::models::FsFileDbRow;
+use crate::models::FsFileToHashes;
+use crate::models::FsMaildirDbRow;
+use crate::models::FsMaildirToMessage;
 
 use super::hash_files::hash_files_root_workflow;
 use super::process_plan::compute_blob_processing_plan_workflow;
+use super::remove_datasource::{remove_maildir_row, save_blob_gc_candidates};
 use super::FilesystemScannerQueue;
 
 /// Arguments for filesystem datasource scanning
@@ -38,6 +49,9 @@ pub struct ScanDatasourceArgs {
     pub datasource_id: DatabaseIdentifier,
     /// Optional path to scan, defaults to root if None
     pub path: Option<PathBuf>,
+    /// Compare with the rows saved by a previous scan, and only update what changed
+    #[serde(default)]
+    pub incremental: bool,
 }
 
 /// Workflow for scanning a filesystem datasource. Calls child workflows that:
@@ -48,30 +62,57 @@ async fn fs_scan_datasource(
     wf_ctx: WfContext,
     (collection_id, datasource_id): (CollectionId, DatabaseIdentifier),
 ) -> WorkflowResult<FsScanResult> {
+    let result = run_datasource_scan(&wf_ctx, collection_id, datasource_id, false).await?;
+    Ok(WfExitValue::Normal(result))
+}
+
+/// Workflow for rescanning a filesystem datasource that was scanned before.
+/// Files are compared with the saved rows by size and modification time:
+/// - only new and changed files are hashed again
+/// - files and directories that are gone are marked as removed
+/// - only blobs never seen before are added to the processing plan
+#[workflow(FilesystemScannerQueue)]
+async fn fs_rescan_datasource(
+    wf_ctx: WfContext,
+    (collection_id, datasource_id): (CollectionId, DatabaseIdentifier),
+) -> WorkflowResult<FsScanResult> {
+    let result = run_datasource_scan(&wf_ctx, collection_id, datasource_id, true).await?;
+    Ok(WfExitValue::Normal(result))
+}
+
+/// Run the child workflows that scan the directories, hash the files that were not hashed yet,
+/// and plan the processing for the new blobs.
+async fn run_datasource_scan(
+    wf_ctx: &WfContext,
+    collection_id: CollectionId,
+    datasource_id: DatabaseIdentifier,
+    incremental: bool,
+) -> anyhow::Result<FsScanResult> {
     let _scan_dir = fs_scan_datasource_dir_workflow::run_as_child(
-        &wf_ctx,
+        wf_ctx,
         ScanDatasourceArgs {
             collection_id: collection_id.clone(),
             datasource_id: datasource_id.clone(),
             path: None,
+            incremental,
         },
     )
     .await?;
 
     let _hash_files = hash_files_root_workflow::run_as_child(
-        &wf_ctx,
+        wf_ctx,
         (collection_id.clone(), datasource_id.clone()),
     )
     .await?;
 
     let _process_plan =
-        compute_blob_processing_plan_workflow::run_as_child(&wf_ctx, collection_id.clone()).await?;
+        compute_blob_processing_plan_workflow::run_as_child(wf_ctx, collection_id.clone()).await?;
 
-    Ok(WfExitValue::Normal(FsScanResult {
+    Ok(FsScanResult {
         dir_scan_result: _scan_dir,
         hash_scan_result: _hash_files,
         processing_plan_result: _process_plan,
-    }))
+    })
 }
 
 /// Workflow for scanning a filesystem datasource
@@ -89,6 +130,7 @@ async fn fs_scan_datasource_dir(
             collection_id: args.collection_id.clone(),
             datasource_id: args.datasource_id.clone(),
             path: Some(p),
+            incremental: args.incremental,
         })
         .collect::<Vec<_>>();
 
@@ -125,7 +167,7 @@ async fn fs_scan_datasource_dir(
 }
 
 /// Activity for saving directory scan results
-#[activity(FilesystemScannerQueue)]
+#[activity(FilesystemScannerQueue, retries = 5, timeout = 60)]
 async fn fs_save_dir_scan_total_result(
     (args, scan_result): (Vec<ScanDatasourceArgs>, FsScanDatasourceDirsResult),
 ) -> anyhow::Result<()> {
@@ -154,12 +196,9 @@ async fn fs_save_dir_scan_total_result(
     if dirs.is_empty() {
         return Ok(());
     }
-    FsDirectoryDbRow::batch()
-        .chunked_insert(&scylla_session, &dirs, 1024)
-        .await?;
     let db_extra =
         hoover3_database::models::collection::DatabaseExtraCallbacks::new(&collection_id).await?;
-    db_extra.insert(&dirs).await?;
+    db_extra.insert_rows(&dirs, 1024).await?;
     Ok(())
 }
 
@@ -195,14 +234,8 @@ async fn fs_do_scan_datasource(
     ))
     .await?;
 
-    let DatasourceSettings::LocalDisk { path: root_path } = &ds_row.datasource_settings else {
-        anyhow::bail!("Datasource is not a local disk");
-    };
-    let dir_path = root_path
-        .to_path_buf()
-        .join(arg.path.clone().unwrap_or(PathBuf::from("")));
-
-    let children = list_directory(dir_path.clone()).await?;
+    let dir_path = arg.path.clone().unwrap_or(PathBuf::from(""));
+    let children = list_datasource_directory(&ds_row.datasource_settings, &dir_path).await?;
     let mut files = vec![];
     let mut dirs = vec![];
     let mut next_paths = vec![];
@@ -224,7 +257,8 @@ async fn fs_do_scan_datasource(
             .await?
         }
         None => {
-            let root_meta = get_path_metadata(PathBuf::from("")).await?;
+            let root_meta =
+                get_datasource_path_metadata(&ds_row.datasource_settings, &dir_path).await?;
             let mut dir = FsDirectoryDbRow::from_basic_meta(&arg.datasource_id, &root_meta);
             FsDirectoryDbRow::insert_cb(&mut dir, &db_extra)
                 .execute(&scylla_session)
@@ -233,12 +267,37 @@ async fn fs_do_scan_datasource(
         }
     };
 
-    children.into_iter().for_each(|mut c| {
-        c.path = c.path.strip_prefix(root_path).unwrap().to_path_buf();
+    // on rescan, get the files saved last time, to skip the ones that did not change
+    let dir_path_str = dir_path.to_str().unwrap().to_string();
+    let mut old_files = BTreeMap::new();
+    if arg.incremental {
+        let rows = FsFileDbRow::find_by_datasource_id_and_parent_dir_path(
+            arg.datasource_id.to_string(),
+            dir_path_str.clone(),
+        )
+        .execute(&scylla_session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+        for row in rows {
+            old_files.insert(row.file_name.clone(), row);
+        }
+    }
+    let mut changed_files = vec![];
+    let mut unchanged_files = vec![];
+
+    children.into_iter().for_each(|c| {
         if c.is_file {
             let new_file = FsFileDbRow::from_basic_meta(&arg.datasource_id, &c);
 
-            files.push(new_file);
+            match old_files.remove(&new_file.file_name) {
+                Some(old_file) if old_file.is_unchanged(&c) => unchanged_files.push(old_file),
+                Some(old_file) => {
+                    changed_files.push(old_file);
+                    files.push(new_file);
+                }
+                None => files.push(new_file),
+            }
             file_count += 1;
             file_size_bytes += c.size_bytes;
         } else if c.is_dir {
@@ -250,15 +309,47 @@ async fn fs_do_scan_datasource(
         }
     });
 
-    FsFileDbRow::batch()
-        .chunked_insert(&scylla_session, &files, 1024)
-        .await?;
-    FsDirectoryDbRow::batch()
-        .chunked_insert(&scylla_session, &dirs, 1024)
+    db_extra.insert_rows(&files, 1024).await?;
+    db_extra.insert_rows(&dirs, 1024).await?;
+
+    if arg.incremental {
+        // the old content of changed files is no longer linked to them
+        let unlinked_blobs = remove_file_hash_edges(&arg.collection_id, &changed_files).await?;
+        save_blob_gc_candidates(&scylla_session, unlinked_blobs).await?;
+
+        let removed_files = old_files
+            .into_values()
+            .filter(|f| f.removed_at.is_none())
+            .collect::<Vec<_>>();
+        mark_files_removed(
+            &arg.collection_id,
+            &scylla_session,
+            &db_extra,
+            removed_files,
+        )
         .await?;
 
-    db_extra.insert(&files).await?;
-    db_extra.insert(&dirs).await?;
+        let removed_dirs =
+            find_child_directories(&scylla_session, &arg.datasource_id, &dir_path_str)
+                .await?
+                .into_iter()
+                .filter(|d| {
+                    d.removed_at.is_none() && !dirs.iter().any(|new_dir| new_dir.path == d.path)
+                })
+                .collect::<Vec<_>>();
+        mark_dirs_removed(&arg.collection_id, &scylla_session, &db_extra, removed_dirs).await?;
+    }
+
+    files.extend(unchanged_files);
+    save_maildir_rows(
+        &arg.collection_id,
+        &scylla_session,
+        &db_extra,
+        &parent_pk,
+        &dirs,
+        &files,
+    )
+    .await?;
 
     next_paths.sort();
     next_paths.dedup();
@@ -280,3 +371,291 @@ async fn fs_do_scan_datasource(
         next_paths,
     ))
 }
+
+/// Subfolders that every Maildir folder has.
+const MAILDIR_SUBFOLDERS: [&str; 3] = ["cur", "new", "tmp"];
+/// Maildir subfolders that hold delivered messages.
+const MAILDIR_MESSAGE_SUBFOLDERS: [&str; 2] = ["cur", "new"];
+
+/// Record Maildir folders found while scanning the directory `dir`.
+/// If `dir` is itself a Maildir, save it; if `dir` is the `cur` or `new` subfolder
+/// of a Maildir saved earlier, link the Maildir to the message files.
+async fn save_maildir_rows(
+    collection_id: &CollectionId,
+    scylla_session: &ScyllaDatabaseHandle,
+    db_extra: &DatabaseExtraCallbacks,
+    dir: &FsDirectoryDbRow,
+    child_dirs: &[FsDirectoryDbRow],
+    child_files: &[FsFileDbRow],
+) -> anyhow::Result<()> {
+    if is_maildir(child_dirs) {
+        let mut maildir = FsMaildirDbRow {
+            datasource_id: dir.datasource_id.clone(),
+            path: dir.path.clone(),
+        };
+        FsMaildirDbRow::insert_cb(&mut maildir, db_extra)
+            .execute(scylla_session)
+            .await?;
+    }
+
+    let dir_path = Path::new(&dir.path);
+    let Some(dir_name) = dir_path.file_name().and_then(|n| n.to_str()) else {
+        return Ok(());
+    };
+    if child_files.is_empty() || !MAILDIR_MESSAGE_SUBFOLDERS.contains(&dir_name) {
+        return Ok(());
+    }
+    let maildir_path = dir_path
+        .parent()
+        .and_then(|p| p.to_str())
+        .unwrap_or_default()
+        .to_string();
+    let Some(maildir) = FsMaildirDbRow::maybe_find_first_by_datasource_id_and_path(
+        dir.datasource_id.clone(),
+        maildir_path,
+    )
+    .execute(scylla_session)
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let mut edge_batch = FsMaildirToMessage::edge_batch(collection_id);
+    for file in child_files {
+        edge_batch.add_edge_from_pk(&maildir.primary_key_values(), &file.primary_key_values());
+    }
+    edge_batch.execute().await?;
+    Ok(())
+}
+
+/// A folder is a Maildir if it has all the `cur`, `new` and `tmp` subfolders.
+fn is_maildir(child_dirs: &[FsDirectoryDbRow]) -> bool {
+    MAILDIR_SUBFOLDERS.iter().all(|name| {
+        child_dirs
+            .iter()
+            .any(|d| Path::new(&d.path).file_name().and_then(|n| n.to_str()) == Some(*name))
+    })
+}
+
+/// Number of rows read by each query of [find_child_directories].
+const CHILD_DIRECTORIES_PAGE_SIZE: usize = 256;
+
+/// List the direct subdirectories of `dir_path`. Directories are sorted by path, so the
+/// subtree of a child directory follows it; each subtree is skipped over with a new range
+/// query starting after it, instead of being read.
+async fn find_child_directories(
+    scylla_session: &ScyllaDatabaseHandle,
+    datasource_id: &DatabaseIdentifier,
+    dir_path: &str,
+) -> anyhow::Result<Vec<FsDirectoryDbRow>> {
+    let prefix = match dir_path.is_empty() {
+        true => String::new(),
+        false => format!("{}/", dir_path),
+    };
+    let mut children = vec![];
+    // the root path "" is excluded; other directories start at "dir_path/"
+    let mut from = prefix.clone();
+    let mut from_inclusive = !dir_path.is_empty();
+    loop {
+        // the query LIMIT is CHILD_DIRECTORIES_PAGE_SIZE
+        let rows = match from_inclusive {
+            true => find_fs_directory_db_row!(
+                "datasource_id = ? AND path >= ? LIMIT 256",
+                (datasource_id.to_string(), from.clone())
+            ),
+            false => find_fs_directory_db_row!(
+                "datasource_id = ? AND path > ? LIMIT 256",
+                (datasource_id.to_string(), from.clone())
+            ),
+        }
+        .execute(scylla_session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+        let row_count = rows.len();
+        let mut skip_to = None;
+        for row in rows {
+            let Some(rest) = row.path.strip_prefix(prefix.as_str()) else {
+                return Ok(children);
+            };
+            match rest.split_once('/') {
+                None => {
+                    from = row.path.clone();
+                    from_inclusive = false;
+                    children.push(row);
+                }
+                // '0' is the character after '/', so this skips everything in "prefix/name/"
+                Some((name, _)) => {
+                    skip_to = Some(format!("{}{}0", prefix, name));
+                    break;
+                }
+            }
+        }
+        match skip_to {
+            Some(skip_to) => {
+                from = skip_to;
+                from_inclusive = true;
+            }
+            None if row_count < CHILD_DIRECTORIES_PAGE_SIZE => return Ok(children),
+            None => {}
+        }
+    }
+}
+
+/// List all the directories below `dir_path`, at any depth, using a range query on the path.
+async fn find_subdirectories(
+    scylla_session: &ScyllaDatabaseHandle,
+    datasource_id: &DatabaseIdentifier,
+    dir_path: &str,
+) -> anyhow::Result<Vec<FsDirectoryDbRow>> {
+    let rows = if dir_path.is_empty() {
+        find_fs_directory_db_row!(
+            "datasource_id = ? AND path > ?",
+            (datasource_id.to_string(), "")
+        )
+        .execute(scylla_session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?
+    } else {
+        // '0' is the character after '/', so this selects everything starting with "dir_path/"
+        find_fs_directory_db_row!(
+            "datasource_id = ? AND path >= ? AND path < ?",
+            (
+                datasource_id.to_string(),
+                format!("{}/", dir_path),
+                format!("{}0", dir_path)
+            )
+        )
+        .execute(scylla_session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?
+    };
+    Ok(rows)
+}
+
+/// Mark files that are gone from the datasource as removed.
+/// Their search documents are deleted, and so are their edges to blobs and Maildir folders.
+async fn mark_files_removed(
+    collection_id: &CollectionId,
+    scylla_session: &ScyllaDatabaseHandle,
+    db_extra: &DatabaseExtraCallbacks,
+    mut files: Vec<FsFileDbRow>,
+) -> anyhow::Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let removed_at = chrono::Utc::now();
+    for file in files.iter_mut() {
+        file.removed_at = Some(removed_at);
+    }
+    FsFileDbRow::batch()
+        .chunked_insert(scylla_session, &files, 1024)
+        .await?;
+    db_extra.delete(&files).await?;
+    let unlinked_blobs = remove_file_hash_edges(collection_id, &files).await?;
+    save_blob_gc_candidates(scylla_session, unlinked_blobs).await?;
+    remove_maildir_message_edges(collection_id, &files).await?;
+    Ok(())
+}
+
+/// Remove the edges from Maildir folders to the message files.
+pub(crate) async fn remove_maildir_message_edges(
+    collection_id: &CollectionId,
+    files: &[FsFileDbRow],
+) -> anyhow::Result<()> {
+    let mut maildir_edges = FsMaildirToMessage::edge_batch(collection_id);
+    for file in files.iter() {
+        let dir_path = Path::new(&file.parent_dir_path);
+        let dir_name = dir_path.file_name().and_then(|n| n.to_str());
+        if !dir_name.is_some_and(|n| MAILDIR_MESSAGE_SUBFOLDERS.contains(&n)) {
+            continue;
+        }
+        let maildir_path = dir_path
+            .parent()
+            .and_then(|p| p.to_str())
+            .unwrap_or_default()
+            .to_string();
+        maildir_edges.remove_edge_from_pk(
+            &(file.datasource_id.clone(), maildir_path),
+            &file.primary_key_values(),
+        );
+    }
+    maildir_edges.execute().await?;
+    Ok(())
+}
+
+/// Remove the edges from files to the blobs they contained.
+/// Returns the hashes of the blobs that were unlinked.
+pub(crate) async fn remove_file_hash_edges(
+    collection_id: &CollectionId,
+    files: &[FsFileDbRow],
+) -> anyhow::Result<Vec<String>> {
+    let mut edge_batch = FsFileToHashes::edge_batch(collection_id);
+    let mut blob_hashes = vec![];
+    for file in files {
+        let file_pk = file.primary_key_values();
+        if let Some(blob_sha3_256) = &file.blob_sha3_256 {
+            edge_batch.remove_edge_from_pk(&file_pk, &(blob_sha3_256.clone(),));
+            blob_hashes.push(blob_sha3_256.clone());
+            continue;
+        }
+        // file was hashed before the hash was saved on its row, so look at the edges
+        let blobs = edge_list_targets_pk::<FsFileToHashes>(collection_id, &file_pk)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        for blob_pk in blobs {
+            edge_batch.remove_edge_from_pk(&file_pk, &blob_pk);
+            blob_hashes.push(blob_pk.0);
+        }
+    }
+    edge_batch.execute().await?;
+    Ok(blob_hashes)
+}
+
+/// Mark directories that are gone from the datasource as removed,
+/// together with all the directories and files below them.
+async fn mark_dirs_removed(
+    collection_id: &CollectionId,
+    scylla_session: &ScyllaDatabaseHandle,
+    db_extra: &DatabaseExtraCallbacks,
+    dirs: Vec<FsDirectoryDbRow>,
+) -> anyhow::Result<()> {
+    if dirs.is_empty() {
+        return Ok(());
+    }
+    let mut removed_dirs = vec![];
+    for dir in dirs {
+        let datasource_id = DatabaseIdentifier::new(&dir.datasource_id)?;
+        removed_dirs.extend(find_subdirectories(scylla_session, &datasource_id, &dir.path).await?);
+        removed_dirs.push(dir);
+    }
+    removed_dirs.retain(|d| d.removed_at.is_none());
+
+    let removed_at = chrono::Utc::now();
+    for dir in removed_dirs.iter_mut() {
+        let files = FsFileDbRow::find_by_datasource_id_and_parent_dir_path(
+            dir.datasource_id.clone(),
+            dir.path.clone(),
+        )
+        .execute(scylla_session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?
+        .into_iter()
+        .filter(|f| f.removed_at.is_none())
+        .collect::<Vec<_>>();
+        mark_files_removed(collection_id, scylla_session, db_extra, files).await?;
+
+        remove_maildir_row(scylla_session, db_extra, &dir.datasource_id, &dir.path).await?;
+        dir.removed_at = Some(removed_at);
+    }
+
+    FsDirectoryDbRow::batch()
+        .chunked_insert(scylla_session, &removed_dirs, 1024)
+        .await?;
+    db_extra.delete(&removed_dirs).await?;
+    Ok(())
+}
@@ -1,9 +1,14 @@
 //! Test the filesystem scanner API
 use std::path::PathBuf;
 
+use hoover3_database::db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle};
 use hoover3_database::migrate::migrate_common;
+use hoover3_database::system_paths::get_data_root;
+use hoover3_filesystem_scanner::models::{FsBlobGcCandidateDbRow, FsDirectoryDbRow, FsFileDbRow};
 use hoover3_filesystem_scanner::tasks::{
-    scan_filesystem::fs_scan_datasource_workflow, FilesystemScannerQueue,
+    remove_datasource::fs_remove_datasource_workflow,
+    scan_filesystem::{fs_rescan_datasource_workflow, fs_scan_datasource_workflow},
+    FilesystemScannerQueue,
 };
 use hoover3_taskdef::TemporalioWorkflowDescriptor;
 use hoover3_types::{
@@ -11,6 +16,7 @@ use hoover3_types::{
     identifier::{CollectionId, DatabaseIdentifier},
 };
 
+use futures::TryStreamExt;
 use hoover3_data_access::api::{create_datasource, get_all_datasources};
 use hoover3_database::client_query::collections::{create_new_collection, drop_collection};
 use hoover3_types::tasks::UiWorkflowStatusCode;
@@ -52,3 +58,123 @@ async fn test_fs_do_scan_datasource_small() -> anyhow::Result<()> {
     drop_collection(collection_id.clone()).await?;
     Ok(())
 }
+
+#[tokio::test]
+async fn test_fs_rescan_datasource() -> anyhow::Result<()> {
+    migrate_common().await?;
+    let collection_id = CollectionId::new("test_fs_rescan_datasource")?;
+    drop_collection(collection_id.clone()).await?;
+    create_new_collection(collection_id.clone()).await?;
+    let datasource_id = DatabaseIdentifier::new("test_fs_rescan_datasource")?;
+    let relative_path = PathBuf::from("test-fs-rescan-datasource");
+    let dir = get_data_root().join(&relative_path);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(dir.join("sub"))?;
+    std::fs::write(dir.join("same.txt"), "same")?;
+    std::fs::write(dir.join("changed.txt"), "before")?;
+    std::fs::write(dir.join("sub").join("deleted.txt"), "deleted")?;
+    let settings = DatasourceSettings::LocalDisk {
+        path: relative_path,
+    };
+    create_datasource((collection_id.clone(), datasource_id.clone(), settings)).await?;
+
+    hoover3_taskdef::spawn_worker_on_thread(FilesystemScannerQueue);
+
+    let arg = (collection_id.clone(), datasource_id.clone());
+    fs_scan_datasource_workflow::client_start(&arg).await?;
+    let status = fs_scan_datasource_workflow::client_wait_for_completion(&arg).await?;
+    assert_eq!(status.dir_scan_result.file_count, 3);
+    assert_eq!(status.hash_scan_result.file_count, 3);
+
+    std::fs::write(dir.join("changed.txt"), "after the change")?;
+    std::fs::write(dir.join("new.txt"), "new")?;
+    std::fs::remove_dir_all(dir.join("sub"))?;
+
+    fs_rescan_datasource_workflow::client_start(&arg).await?;
+    let status = fs_rescan_datasource_workflow::client_wait_for_completion(&arg).await?;
+    assert_eq!(status.dir_scan_result.file_count, 3);
+    assert_eq!(status.dir_scan_result.dir_count, 0);
+    // only the changed and the new file are hashed again
+    assert_eq!(status.hash_scan_result.file_count, 2);
+    assert_eq!(status.hash_scan_result.hash_count, 2);
+    assert_eq!(status.processing_plan_result.total_blob_count, 2);
+
+    let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+    let deleted = FsFileDbRow::find_first_by_datasource_id_and_parent_dir_path_and_file_name(
+        datasource_id.to_string(),
+        "sub".to_string(),
+        "deleted.txt".to_string(),
+    )
+    .execute(&session)
+    .await?;
+    assert!(deleted.removed_at.is_some());
+    let same = FsFileDbRow::find_first_by_datasource_id_and_parent_dir_path_and_file_name(
+        datasource_id.to_string(),
+        "".to_string(),
+        "same.txt".to_string(),
+    )
+    .execute(&session)
+    .await?;
+    assert!(same.removed_at.is_none());
+    assert!(same.blob_sha3_256.is_some());
+
+    std::fs::remove_dir_all(&dir)?;
+    drop_collection(collection_id.clone()).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fs_remove_datasource() -> anyhow::Result<()> {
+    migrate_common().await?;
+    let collection_id = CollectionId::new("test_fs_remove_datasource")?;
+    drop_collection(collection_id.clone()).await?;
+    create_new_collection(collection_id.clone()).await?;
+    let datasource_id = DatabaseIdentifier::new("test_fs_remove_datasource")?;
+    let relative_path = PathBuf::from("test-fs-remove-datasource");
+    let dir = get_data_root().join(&relative_path);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(dir.join("sub"))?;
+    std::fs::write(dir.join("a.txt"), "a")?;
+    std::fs::write(dir.join("sub").join("b.txt"), "b")?;
+    std::fs::write(dir.join("sub").join("b_copy.txt"), "b")?;
+    let settings = DatasourceSettings::LocalDisk {
+        path: relative_path,
+    };
+    create_datasource((collection_id.clone(), datasource_id.clone(), settings)).await?;
+
+    hoover3_taskdef::spawn_worker_on_thread(FilesystemScannerQueue);
+
+    let arg = (collection_id.clone(), datasource_id.clone());
+    fs_scan_datasource_workflow::client_start(&arg).await?;
+    fs_scan_datasource_workflow::client_wait_for_completion(&arg).await?;
+
+    fs_remove_datasource_workflow::client_start(&arg).await?;
+    let result = fs_remove_datasource_workflow::client_wait_for_completion(&arg).await?;
+    assert_eq!(result.dir_count, 2);
+    assert_eq!(result.file_count, 3);
+    assert_eq!(result.blob_candidate_count, 2);
+    // the datasource is hidden, but kept until the blobs are collected
+    assert!(get_all_datasources(collection_id.clone()).await?.is_empty());
+
+    let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+    let dirs = FsDirectoryDbRow::find_by_datasource_id(datasource_id.to_string())
+        .execute(&session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert!(dirs.is_empty());
+    let candidates = FsBlobGcCandidateDbRow::find_all()
+        .execute(&session)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(candidates.len(), 2);
+
+    std::fs::remove_dir_all(&dir)?;
+    drop_collection(collection_id.clone()).await?;
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! Test the filesystem scanner workflows on the local task backend, without workers,
+//! followed by the processing of the scanned blobs.
+use std::path::PathBuf;
+
+use hoover3_data_access::api::create_datasource;
+use hoover3_database::client_query::collections::{create_new_collection, drop_collection};
+use hoover3_database::migrate::migrate_common;
+use hoover3_filesystem_scanner::tasks::scan_filesystem::fs_scan_datasource_workflow;
+use hoover3_processing::tasks::run_collection_processing_workflow;
+use hoover3_taskdef::{
+    set_task_backend, TaskBackend, TemporalioWorkflowDescriptor, WORKER_TEMPDIR_ENV_VAR_BIG,
+    WORKER_TEMPDIR_ENV_VAR_SMALL,
+};
+use hoover3_types::{
+    datasource::DatasourceSettings,
+    identifier::{CollectionId, DatabaseIdentifier},
+    tasks::UiWorkflowStatusCode,
+};
+
+#[tokio::test]
+async fn test_fs_scan_datasource_local_backend() -> anyhow::Result<()> {
+    let state_dir = std::env::temp_dir().join(format!(
+        "hoover3_local_tasks_fs_scan_{}",
+        std::process::id()
+    ));
+    if state_dir.exists() {
+        std::fs::remove_dir_all(&state_dir)?;
+    }
+    set_task_backend(TaskBackend::Local(state_dir.clone()))?;
+    for (env_var, dir) in [
+        (WORKER_TEMPDIR_ENV_VAR_SMALL, "small"),
+        (WORKER_TEMPDIR_ENV_VAR_BIG, "big"),
+    ] {
+        let tempdir = state_dir.join("worker_temp").join(dir);
+        std::fs::create_dir_all(&tempdir)?;
+        std::env::set_var(env_var, tempdir);
+    }
+
+    migrate_common().await?;
+    let collection_id = CollectionId::new("test_fs_scan_datasource_local_backend")?;
+    drop_collection(collection_id.clone()).await?;
+    create_new_collection(collection_id.clone()).await?;
+    let datasource_id = DatabaseIdentifier::new("test_fs_scan_datasource_local_backend")?;
+    let settings = DatasourceSettings::LocalDisk {
+        path: PathBuf::from("hoover-testdata/data/disk-files/long-filenames"),
+    };
+    create_datasource((collection_id.clone(), datasource_id.clone(), settings)).await?;
+
+    let arg = (collection_id.clone(), datasource_id.clone());
+    fs_scan_datasource_workflow::client_start(&arg).await?;
+    let status = fs_scan_datasource_workflow::client_wait_for_completion(&arg).await?;
+    assert_eq!(status.dir_scan_result.file_count, 3);
+    assert_eq!(status.dir_scan_result.errors, 0);
+    assert_eq!(status.hash_scan_result.hash_count, 3);
+    let status = fs_scan_datasource_workflow::client_get_status(&arg).await?;
+    assert_eq!(status.task_status, UiWorkflowStatusCode::Completed);
+
+    // the scan planned the blobs; process them all, in one round
+    run_collection_processing_workflow::client_start(&collection_id).await?;
+    let result =
+        run_collection_processing_workflow::client_wait_for_completion(&collection_id).await?;
+    assert_eq!(result.small_page_count + result.large_page_count, 1);
+    let items = result.small_page_results.item_count + result.large_page_results.item_count;
+    assert_eq!(items, 3);
+    let status = run_collection_processing_workflow::client_get_status(&collection_id).await?;
+    assert_eq!(status.task_status, UiWorkflowStatusCode::Completed);
+    drop_collection(collection_id.clone()).await?;
+    Ok(())
+}
@@ -0,0 +1,486 @@
+//! Processing settings management - per-collection configuration for the processing plugin.
+
+use charybdis::batch::ModelBatch;
+use charybdis::operations::InsertWithCallbacks;
+use futures::TryStreamExt;
+use hoover3_database::db_management::redis::{drop_redis_cache, with_redis_cache};
+use hoover3_database::db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle};
+use hoover3_database::models::collection::DatabaseExtraCallbacks;
+use hoover3_filesystem_scanner::tasks::process_plan::{
+    reset_blob_plan_pages, stream_datasource_blob_hashes,
+};
+use hoover3_types::identifier::{CollectionId, DatabaseIdentifier};
+use hoover3_types::processing::{
+    OcrSettings, ProcessingFailureCount, ProcessingFailureCountPage, ProcessingFailurePage,
+    ProcessingFailureUiRow,
+};
+use std::collections::BTreeMap;
+use tracing::info;
+
+use crate::models::{
+    find_blob_processing_failure_db_row, BlobProcessingFailureDbRow, ProcessingSettingsDbRow,
+};
+
+const OCR_SETTINGS_ID: &str = "ocr";
+const GENERATION_SETTINGS_ID: &str = "generation";
+
+/// Maximum number of rows read by [list_processing_failures].
+const PROCESSING_FAILURES_LIST_LIMIT: i32 = 1000;
+/// Maximum number of rows read by [count_processing_failures].
+const PROCESSING_FAILURES_COUNT_LIMIT: i32 = 10000;
+
+/// Mime type reported for failures of blobs whose mime type was not detected.
+const UNKNOWN_MIME_TYPE: &str = "unknown";
+
+/// Client API method, returns the OCR settings for the collection.
+/// Collections without saved settings have OCR disabled.
+/// Cached for 1min. Cache gets dumped on MODIFY.
+pub async fn get_ocr_settings(c: CollectionId) -> anyhow::Result<OcrSettings> {
+    with_redis_cache("get_ocr_settings", 60, _get_ocr_settings, &c).await
+}
+
+async fn _get_ocr_settings(c: CollectionId) -> anyhow::Result<OcrSettings> {
+    let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+    let row = ProcessingSettingsDbRow::maybe_find_first_by_settings_id(OCR_SETTINGS_ID.to_string())
+        .execute(&session)
+        .await?;
+    Ok(match row {
+        Some(row) => serde_json::from_str(&row.settings_json)?,
+        None => OcrSettings::default(),
+    })
+}
+
+/// Client API method, saves the OCR settings for the collection.
+/// The settings are used by the next processing run; blobs already OCR-ed are not processed again.
+pub async fn set_ocr_settings(
+    (c, settings): (CollectionId, OcrSettings),
+) -> anyhow::Result<OcrSettings> {
+    tokio::spawn(async move {
+        info!("set_ocr_settings collection={c:?} settings={settings:?}");
+        if settings.enabled && settings.languages.is_empty() {
+            anyhow::bail!("at least one OCR language is needed");
+        }
+        if let Some(bad) = settings
+            .languages
+            .iter()
+            .find(|l| l.is_empty() || !l.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+        {
+            anyhow::bail!("invalid OCR language: {bad:?}");
+        }
+        let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+        let mut row = ProcessingSettingsDbRow {
+            settings_id: OCR_SETTINGS_ID.to_string(),
+            settings_json: serde_json::to_string(&settings)?,
+        };
+        let cb_info = DatabaseExtraCallbacks::new(&c).await?;
+        ProcessingSettingsDbRow::insert_cb(&mut row, &cb_info)
+            .execute(&session)
+            .await?;
+        drop_redis_cache("get_ocr_settings", &c).await?;
+        Ok(settings)
+    })
+    .await?
+}
+
+/// Read a counter stored in the settings table; `None` if it was never saved.
+async fn get_counter_setting(c: &CollectionId, settings_id: &str) -> anyhow::Result<Option<u32>> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let row = ProcessingSettingsDbRow::maybe_find_first_by_settings_id(settings_id.to_string())
+        .execute(&session)
+        .await?;
+    Ok(match row {
+        Some(row) => Some(serde_json::from_str(&row.settings_json)?),
+        None => None,
+    })
+}
+
+/// Save a counter into the settings table.
+async fn set_counter_setting(
+    c: &CollectionId,
+    settings_id: &str,
+    value: u32,
+) -> anyhow::Result<()> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let cb_info = DatabaseExtraCallbacks::new(c).await?;
+    let mut row = ProcessingSettingsDbRow {
+        settings_id: settings_id.to_string(),
+        settings_json: serde_json::to_string(&value)?,
+    };
+    ProcessingSettingsDbRow::insert_cb(&mut row, &cb_info)
+        .execute(&session)
+        .await?;
+    Ok(())
+}
+
+/// Serializes the read-increment-write of the generation counter.
+static GENERATION_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Client API method, returns the last processing generation taken in the collection.
+pub async fn get_processing_generation(c: CollectionId) -> anyhow::Result<u32> {
+    Ok(get_counter_setting(&c, GENERATION_SETTINGS_ID)
+        .await?
+        .unwrap_or(0))
+}
+
+/// Take a new processing generation in the collection, for starting a workflow again
+/// with new workflow ids, instead of returning the results of the old workflows.
+/// See [hoover3_taskdef::with_workflow_generation]. Returns the new generation.
+pub async fn next_processing_generation(c: CollectionId) -> anyhow::Result<u32> {
+    let _lock = GENERATION_LOCK.lock().await;
+    let generation = get_processing_generation(c.clone()).await? + 1;
+    info!("next_processing_generation collection={c:?} generation={generation}");
+    set_counter_setting(&c, GENERATION_SETTINGS_ID, generation).await?;
+    Ok(generation)
+}
+
+fn workflow_generation_settings_id(workflow_name: &str) -> String {
+    format!("{GENERATION_SETTINGS_ID}/{workflow_name}")
+}
+
+/// Returns the generation a collection workflow was last started in,
+/// or `None` if it was never started.
+pub async fn get_workflow_generation(
+    c: &CollectionId,
+    workflow_name: &str,
+) -> anyhow::Result<Option<u32>> {
+    get_counter_setting(c, &workflow_generation_settings_id(workflow_name)).await
+}
+
+/// Save the generation a collection workflow is started in.
+pub async fn set_workflow_generation(
+    c: &CollectionId,
+    workflow_name: &str,
+    generation: u32,
+) -> anyhow::Result<()> {
+    set_counter_setting(
+        c,
+        &workflow_generation_settings_id(workflow_name),
+        generation,
+    )
+    .await
+}
+
+fn datasource_generation_settings_id(workflow_name: &str, ds: &DatabaseIdentifier) -> String {
+    format!("{GENERATION_SETTINGS_ID}/{workflow_name}/{ds}")
+}
+
+/// Returns the generation a datasource workflow was last started in,
+/// or `None` if it was never started.
+pub async fn get_datasource_generation(
+    c: &CollectionId,
+    ds: &DatabaseIdentifier,
+    workflow_name: &str,
+) -> anyhow::Result<Option<u32>> {
+    get_counter_setting(c, &datasource_generation_settings_id(workflow_name, ds)).await
+}
+
+/// Save the generation a datasource workflow is started in.
+pub async fn set_datasource_generation(
+    c: &CollectionId,
+    ds: &DatabaseIdentifier,
+    workflow_name: &str,
+    generation: u32,
+) -> anyhow::Result<()> {
+    set_counter_setting(
+        c,
+        &datasource_generation_settings_id(workflow_name, ds),
+        generation,
+    )
+    .await
+}
+
+/// Forget the generation a datasource workflow was started in, so the next start of
+/// a datasource with the same id runs in a new generation.
+pub async fn clear_datasource_generation(
+    c: &CollectionId,
+    ds: &DatabaseIdentifier,
+    workflow_name: &str,
+) -> anyhow::Result<()> {
+    let session = ScyllaDatabaseHandle::collection_session(c).await?;
+    let settings_id = datasource_generation_settings_id(workflow_name, ds);
+    if let Some(row) = ProcessingSettingsDbRow::maybe_find_first_by_settings_id(settings_id.clone())
+        .execute(&session)
+        .await?
+    {
+        ProcessingSettingsDbRow::delete_by_settings_id(settings_id)
+            .execute(&session)
+            .await?;
+        DatabaseExtraCallbacks::new(c).await?.delete(&[row]).await?;
+    }
+    Ok(())
+}
+
+/// Marks the processing plan pages holding blobs of the datasource as not started,
+/// so they are processed again, and removes the failure records of those blobs.
+/// Other blobs sharing these pages are processed again too.
+pub async fn reset_datasource_processing(
+    c: CollectionId,
+    ds: DatabaseIdentifier,
+) -> anyhow::Result<()> {
+    tokio::spawn(async move {
+        info!("reset_datasource_processing collection={c:?} datasource={ds}");
+        let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+        let cb_info = DatabaseExtraCallbacks::new(&c).await?;
+        let mut blob_chunks = stream_datasource_blob_hashes(&c, &ds).await?;
+        while let Some(blob_hashes) = blob_chunks.try_next().await? {
+            if blob_hashes.is_empty() {
+                continue;
+            }
+            reset_blob_plan_pages(&c, &blob_hashes).await?;
+            let failures =
+                find_blob_processing_failure_db_row!("blob_sha3_256 IN ?", (blob_hashes,))
+                    .execute(&session)
+                    .await?
+                    .try_collect::<Vec<_>>()
+                    .await?;
+            BlobProcessingFailureDbRow::batch()
+                .chunked_delete(&session, &failures, 1024)
+                .await?;
+            cb_info.delete(&failures).await?;
+        }
+        Ok(())
+    })
+    .await?
+}
+
+/// Read a page of failure rows, whole blobs at a time, starting after the blob `after`
+/// in partition order. Returns the rows, and the blob to continue after if there are more.
+async fn read_failure_page(
+    session: &ScyllaDatabaseHandle,
+    after: Option<String>,
+    limit: i32,
+) -> anyhow::Result<(Vec<BlobProcessingFailureDbRow>, Option<String>)> {
+    let mut rows = match after {
+        None => {
+            find_blob_processing_failure_db_row!(
+                "token(blob_sha3_256) >= ? LIMIT ?",
+                (i64::MIN, limit)
+            )
+            .execute(session)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+        }
+        Some(after) => {
+            find_blob_processing_failure_db_row!(
+                "token(blob_sha3_256) > token(?) LIMIT ?",
+                (after, limit)
+            )
+            .execute(session)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?
+        }
+    };
+    if rows.len() < limit as usize {
+        return Ok((rows, None));
+    }
+    // the last blob may have more rows after the limit; read it again with the next page
+    let last = rows.last().map(|row| row.blob_sha3_256.clone());
+    if rows
+        .iter()
+        .any(|row| Some(&row.blob_sha3_256) != last.as_ref())
+    {
+        rows.retain(|row| Some(&row.blob_sha3_256) != last.as_ref());
+    }
+    let next_page = rows.last().map(|row| row.blob_sha3_256.clone());
+    Ok((rows, next_page))
+}
+
+/// Client API method, lists a page of the blobs that failed processing, in partition order.
+/// If a mime type is given, only blobs of that mime type are listed; use "unknown"
+/// for the blobs whose mime type was not detected. Each page reads at most 1000 rows;
+/// pass the `next_page` of a page to get the following one.
+pub async fn list_processing_failures(
+    (c, mime_type, after): (CollectionId, Option<String>, Option<String>),
+) -> anyhow::Result<ProcessingFailurePage> {
+    let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+    let (rows, next_page) =
+        read_failure_page(&session, after, PROCESSING_FAILURES_LIST_LIMIT).await?;
+    let rows = rows
+        .into_iter()
+        .filter(|row| {
+            let row_mime = row.mime_type.as_deref().unwrap_or(UNKNOWN_MIME_TYPE);
+            mime_type.as_deref().is_none_or(|m| m == row_mime)
+        })
+        .map(|row| ProcessingFailureUiRow {
+            blob_sha3_256: row.blob_sha3_256,
+            stage: row.stage,
+            mime_type: row.mime_type,
+            error: row.error,
+            attempt_count: row.attempt_count,
+            plan_page_id: row.plan_page_id,
+            failed_at: row.failed_at,
+        })
+        .collect();
+    Ok(ProcessingFailurePage { rows, next_page })
+}
+
+/// Client API method, counts the failed blobs of a page of the failure table by mime type
+/// and stage. Each page reads at most 10000 rows; the counts of the collection are the sum
+/// of the counts of all the pages, following `next_page` until it is `None`.
+pub async fn count_processing_failures(
+    (c, after): (CollectionId, Option<String>),
+) -> anyhow::Result<ProcessingFailureCountPage> {
+    let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+    let (rows, next_page) =
+        read_failure_page(&session, after, PROCESSING_FAILURES_COUNT_LIMIT).await?;
+    let mut counts = BTreeMap::<(String, String), i64>::new();
+    for row in rows {
+        let mime_type = row.mime_type.unwrap_or(UNKNOWN_MIME_TYPE.to_string());
+        *counts.entry((mime_type, row.stage)).or_default() += 1;
+    }
+    let counts = counts
+        .into_iter()
+        .map(|((mime_type, stage), count)| ProcessingFailureCount {
+            mime_type,
+            stage,
+            count,
+        })
+        .collect();
+    Ok(ProcessingFailureCountPage { counts, next_page })
+}
+
+#[tokio::test]
+async fn test_ocr_settings() -> anyhow::Result<()> {
+    use hoover3_database::client_query::collections::{create_new_collection, drop_collection};
+    use hoover3_database::migrate::migrate_common;
+
+    migrate_common().await?;
+    let c = CollectionId::new("test_ocr_settings")?;
+    drop_collection(c.clone()).await?;
+    create_new_collection(c.clone()).await?;
+
+    assert_eq!(get_ocr_settings(c.clone()).await?, OcrSettings::default());
+    let settings = OcrSettings {
+        enabled: true,
+        languages: vec!["eng".to_string(), "deu".to_string()],
+    };
+    set_ocr_settings((c.clone(), settings.clone())).await?;
+    assert_eq!(get_ocr_settings(c.clone()).await?, settings);
+    assert_eq!(settings.tesseract_languages(), "eng+deu");
+
+    let bad = OcrSettings {
+        enabled: true,
+        languages: vec!["eng; rm -rf".to_string()],
+    };
+    assert!(set_ocr_settings((c.clone(), bad)).await.is_err());
+
+    drop_collection(c.clone()).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_processing_generation() -> anyhow::Result<()> {
+    use hoover3_database::client_query::collections::{create_new_collection, drop_collection};
+    use hoover3_database::migrate::migrate_common;
+
+    migrate_common().await?;
+    let c = CollectionId::new("test_processing_generation")?;
+    drop_collection(c.clone()).await?;
+    create_new_collection(c.clone()).await?;
+
+    assert_eq!(get_processing_generation(c.clone()).await?, 0);
+    assert_eq!(next_processing_generation(c.clone()).await?, 1);
+    assert_eq!(next_processing_generation(c.clone()).await?, 2);
+    assert_eq!(get_processing_generation(c.clone()).await?, 2);
+
+    let ds = DatabaseIdentifier::new("test_ds")?;
+    assert_eq!(get_datasource_generation(&c, &ds, "scan").await?, None);
+    set_datasource_generation(&c, &ds, "scan", 2).await?;
+    assert_eq!(get_datasource_generation(&c, &ds, "scan").await?, Some(2));
+    assert_eq!(get_datasource_generation(&c, &ds, "remove").await?, None);
+    clear_datasource_generation(&c, &ds, "scan").await?;
+    assert_eq!(get_datasource_generation(&c, &ds, "scan").await?, None);
+
+    assert_eq!(get_workflow_generation(&c, "retry").await?, None);
+    set_workflow_generation(&c, "retry", 3).await?;
+    assert_eq!(get_workflow_generation(&c, "retry").await?, Some(3));
+
+    drop_collection(c.clone()).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_processing_failures_listing() -> anyhow::Result<()> {
+    use hoover3_database::client_query::collections::{create_new_collection, drop_collection};
+    use hoover3_database::migrate::migrate_common;
+
+    migrate_common().await?;
+    let c = CollectionId::new("test_processing_failures_listing")?;
+    drop_collection(c.clone()).await?;
+    create_new_collection(c.clone()).await?;
+
+    let session = ScyllaDatabaseHandle::collection_session(&c).await?;
+    let row = |blob: &str, stage: &str, mime_type: Option<&str>| BlobProcessingFailureDbRow {
+        blob_sha3_256: blob.to_string(),
+        stage: stage.to_string(),
+        mime_type: mime_type.map(str::to_string),
+        error: "broken".to_string(),
+        attempt_count: 1,
+        plan_page_id: 0,
+        failed_at: chrono::Utc::now(),
+    };
+    let rows = vec![
+        row("a", "tika", Some("application/pdf")),
+        row("b", "tika", Some("application/pdf")),
+        row("b", "unpack_archive", Some("application/pdf")),
+        row("c", "download", None),
+    ];
+    BlobProcessingFailureDbRow::batch()
+        .chunked_insert(&session, &rows, 64)
+        .await?;
+
+    let page = count_processing_failures((c.clone(), None)).await?;
+    assert_eq!(page.next_page, None);
+    let counts = page
+        .counts
+        .into_iter()
+        .map(|x| (x.mime_type, x.stage, x.count))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        counts,
+        vec![
+            ("application/pdf".to_string(), "tika".to_string(), 2),
+            (
+                "application/pdf".to_string(),
+                "unpack_archive".to_string(),
+                1
+            ),
+            ("unknown".to_string(), "download".to_string(), 1),
+        ]
+    );
+    let page = list_processing_failures((c.clone(), None, None)).await?;
+    assert_eq!(page.rows.len(), 4);
+    let unknown = list_processing_failures((c.clone(), Some("unknown".to_string()), None)).await?;
+    assert_eq!(unknown.rows.len(), 1);
+    assert_eq!(unknown.rows[0].blob_sha3_256, "c");
+
+    // small pages hold whole blobs, and together have every row once
+    let mut paged = vec![];
+    let mut after = None;
+    loop {
+        let (rows, next_page) = read_failure_page(&session, after, 2).await?;
+        assert!(!rows.is_empty());
+        paged.extend(rows.into_iter().map(|r| (r.blob_sha3_256, r.stage)));
+        after = next_page;
+        if after.is_none() {
+            break;
+        }
+    }
+    paged.sort();
+    let mut expected = rows
+        .iter()
+        .map(|r| (r.blob_sha3_256.clone(), r.stage.clone()))
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(paged, expected);
+
+    // resetting a datasource without these blobs keeps their failures
+    reset_datasource_processing(c.clone(), DatabaseIdentifier::new("other_ds")?).await?;
+    let page = list_processing_failures((c.clone(), None, None)).await?;
+    assert_eq!(page.rows.len(), 4);
+
+    drop_collection(c.clone()).await?;
+    Ok(())
+}
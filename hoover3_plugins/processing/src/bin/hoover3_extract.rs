@@ -0,0 +1,9 @@
+//! Runs one extraction job in a separate process, so that a malformed file
+//! cannot crash or hang the worker. Takes the same arguments as a worker executable
+//! started for a job, and is used instead of it when `HOOVER3_EXTRACT_BIN` points to it;
+//! see [hoover3_processing::tasks::sandbox].
+
+fn main() -> anyhow::Result<()> {
+    hoover3_processing::tasks::sandbox::run_sandbox_subcommand()
+        .unwrap_or_else(|| anyhow::bail!("usage: hoover3_extract hoover3_extract <job JSON>"))
+}
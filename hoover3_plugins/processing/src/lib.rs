@@ -1,6 +1,10 @@
 //! Base processing module plugin - works on de-duplicated blobs of data.
 //!
 
+pub use inventory;
+
+pub mod api;
 pub mod models;
+pub mod processor;
 pub mod tasks;
 pub(crate) mod utf8_utils;
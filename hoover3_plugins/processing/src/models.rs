@@ -1,8 +1,87 @@
 //! Models for the processing plugin.
 
 #![allow(missing_docs)]
+use hoover3_database::declare_implicit_graph_edge;
+use hoover3_database::declare_stored_graph_edge;
+use hoover3_filesystem_scanner::models::FsBlobHashesDbRow;
 use hoover3_macro::model;
 
+/// Processing settings for the collection, stored as JSON.
+/// Each kind of settings has its own row, e.g. "ocr" for [hoover3_types::processing::OcrSettings].
+#[model]
+pub struct ProcessingSettingsDbRow {
+    /// The kind of settings stored in this row.
+    #[model(primary(partition))]
+    pub settings_id: String,
+
+    /// The settings, serialized as JSON.
+    pub settings_json: String,
+}
+
+/// Model for storing the result of each blob processor that ran on a blob.
+/// See [crate::processor::BlobProcessor].
+#[model]
+pub struct BlobProcessorStatusDbRow {
+    /// The sha3-256 hash of the blob.
+    #[model(primary(partition))]
+    #[model(search(index))]
+    pub blob_sha3_256: String,
+
+    /// The name of the processor.
+    #[model(primary(clustering))]
+    #[model(search(facet))]
+    pub processor: String,
+
+    /// If the processor ran without errors.
+    #[model(search(facet))]
+    pub success: bool,
+
+    /// The errors, with their chains of causes, if the processor failed.
+    pub error: Option<String>,
+
+    /// How long the processor ran, in milliseconds.
+    #[model(search(facet))]
+    pub duration_ms: i64,
+
+    /// When the processor ran.
+    #[model(search(facet))]
+    pub processed_at: Timestamp,
+}
+
+/// Model for storing the processing stages that failed for a blob, with the last error.
+/// Rows are replaced when the stage fails again, and removed when a retry succeeds.
+#[model]
+pub struct BlobProcessingFailureDbRow {
+    /// The sha3-256 hash of the blob.
+    #[model(primary(partition))]
+    #[model(search(index))]
+    pub blob_sha3_256: String,
+
+    /// The processing stage that failed, e.g. "download", "tika".
+    #[model(primary(clustering))]
+    #[model(search(facet))]
+    pub stage: String,
+
+    /// The mime type of the blob, if it was detected before the failure.
+    #[model(search(facet))]
+    pub mime_type: Option<String>,
+
+    /// The error, with its chain of causes.
+    #[model(search(index))]
+    pub error: String,
+
+    /// How many times the stage failed for this blob.
+    #[model(search(facet))]
+    pub attempt_count: i32,
+
+    /// The plan page the blob was processed in.
+    pub plan_page_id: i32,
+
+    /// When the stage failed the last time.
+    #[model(search(facet))]
+    pub failed_at: Timestamp,
+}
+
 /// Model for storing metadata extracted from a blob.
 #[model]
 pub struct BlobExtractedMetadataRow {
@@ -57,3 +136,146 @@ pub struct BlobExtractedContentRow {
     #[model(search(facet))]
     pub content_length: i32,
 }
+
+/// Model for storing the members found when unpacking a container blob (archive, email).
+#[model]
+pub struct BlobContainerMemberDbRow {
+    /// The sha3-256 hash of the container blob.
+    #[model(primary(partition))]
+    #[model(search(index))]
+    pub container_blob_sha3_256: String,
+
+    /// Path of the member inside the container.
+    #[model(primary(clustering))]
+    #[model(search(index))]
+    pub member_path: String,
+
+    /// The sha3-256 hash of the member blob.
+    #[model(search(index))]
+    pub member_blob_sha3_256: String,
+
+    /// The size of the member blob in bytes.
+    #[model(search(facet))]
+    pub size_bytes: i64,
+
+    /// The mime type of the container, e.g. "application/zip"
+    #[model(search(facet))]
+    pub container_mime: String,
+}
+
+/// Model for blobs that were extracted from other blobs.
+/// Their data is kept in the collection object store, not in a datasource.
+#[model]
+pub struct BlobObjectStoreDbRow {
+    /// The sha3-256 hash of the blob.
+    #[model(primary(partition))]
+    pub blob_sha3_256: String,
+
+    /// How many containers deep this blob was found.
+    pub container_depth: i32,
+
+    /// The size of the blob in bytes.
+    pub size_bytes: i64,
+}
+
+declare_stored_graph_edge!(
+    BlobContainerToMember,
+    "blob_container_member",
+    FsBlobHashesDbRow,
+    FsBlobHashesDbRow
+);
+
+/// Model for storing the headers of an email message.
+#[model]
+pub struct EmailHeadersDbRow {
+    /// The sha3-256 hash of the email blob.
+    #[model(primary(partition))]
+    #[model(search(index))]
+    pub blob_sha3_256: String,
+
+    /// The "From" header, as written in the message.
+    #[model(search(index))]
+    pub from_header: Option<String>,
+
+    /// The "To" header, as written in the message.
+    #[model(search(index))]
+    pub to_header: Option<String>,
+
+    /// The "Cc" header, as written in the message.
+    #[model(search(index))]
+    pub cc_header: Option<String>,
+
+    /// The "Subject" header.
+    #[model(search(index))]
+    pub subject: Option<String>,
+
+    /// The "Date" header.
+    #[model(search(facet))]
+    pub date: Option<Timestamp>,
+
+    /// The "Message-ID" header.
+    #[model(search(index))]
+    pub message_id: Option<String>,
+
+    /// The "In-Reply-To" header.
+    #[model(search(index))]
+    pub in_reply_to: Option<String>,
+
+    /// The number of attachments found in the message.
+    #[model(search(facet))]
+    pub attachment_count: i32,
+}
+
+/// Model for storing the addresses found in the headers of an email message.
+#[model]
+pub struct EmailAddressDbRow {
+    /// The sha3-256 hash of the email blob.
+    #[model(primary(partition))]
+    #[model(search(index))]
+    pub blob_sha3_256: String,
+
+    /// The header the address was found in, e.g. "from", "to", "cc"
+    #[model(primary(clustering))]
+    #[model(search(facet))]
+    pub header: String,
+
+    /// Entry index
+    #[model(primary(clustering))]
+    pub list_index: i32,
+
+    /// The email address, lowercase.
+    #[model(search(facet))]
+    pub address: String,
+
+    /// The display name for the address.
+    #[model(search(index))]
+    pub display_name: Option<String>,
+}
+
+declare_implicit_graph_edge!(
+    BlobToEmailHeaders,
+    "blob_email_headers",
+    FsBlobHashesDbRow,
+    EmailHeadersDbRow
+);
+
+declare_implicit_graph_edge!(
+    EmailHeadersToAddresses,
+    "email_headers_addresses",
+    EmailHeadersDbRow,
+    EmailAddressDbRow
+);
+
+declare_stored_graph_edge!(
+    EmailToAttachment,
+    "email_attachment",
+    FsBlobHashesDbRow,
+    FsBlobHashesDbRow
+);
+
+declare_stored_graph_edge!(
+    MailboxToMessage,
+    "mailbox_message",
+    FsBlobHashesDbRow,
+    FsBlobHashesDbRow
+);
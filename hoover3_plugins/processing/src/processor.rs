@@ -0,0 +1,387 @@
+//! Blob processor registry - each extraction step (unpacking archives, parsing emails, tika, ...)
+//! implements [BlobProcessor] and registers itself with [declare_blob_processor].
+//! The page processing detects the mime type of every blob, then routes it to the processors
+//! that accept its mime type and size, in dependency order, and writes their output rows in batches.
+
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use charybdis::model::{BaseModel, Model};
+use futures::future::BoxFuture;
+use hoover3_database::db_management::ScyllaDatabaseHandle;
+use hoover3_database::models::collection::{DatabaseExtraCallbacks, GraphEdgeInsert, PKValue};
+use hoover3_filesystem_scanner::models::FsBlobHashesDbRow;
+use hoover3_types::identifier::CollectionId;
+
+use crate::tasks::get_mime_type::strip_mime_parameters;
+use crate::tasks::unpack_archive::UnpackedMember;
+
+/// An extraction step that runs on the blobs routed to it.
+/// Failures are recorded against the blob, under the processor name, and do not stop the page.
+pub trait BlobProcessor: Send + Sync {
+    /// Unique name of the processor. Used for dependencies, the processor status table,
+    /// the failure records, and as the source of the extracted metadata and content.
+    fn name(&self) -> &'static str;
+
+    /// Mime types (without parameters) routed to this processor.
+    /// Entries ending with "/" match all the subtypes, e.g. "image/". Empty means all blobs.
+    fn mime_types(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Blobs larger than this are not routed to this processor. `None` means no limit.
+    fn max_size_bytes(&self) -> Option<i64> {
+        None
+    }
+
+    /// Names of the processors that must succeed on the blob before this one runs.
+    /// Their outputs are available in [BlobProcessorInput::dependencies].
+    fn depends_on(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Check if a blob with this libmagic mime type and size is routed to this processor.
+    fn accepts(&self, mime_type: &str, size_bytes: i64) -> bool {
+        if self.max_size_bytes().is_some_and(|max| size_bytes > max) {
+            return false;
+        }
+        let mime_type = strip_mime_parameters(mime_type);
+        let mime_types = self.mime_types();
+        mime_types.is_empty()
+            || mime_types.iter().any(|m| match m.ends_with('/') {
+                true => mime_type.starts_with(m),
+                false => mime_type == *m,
+            })
+    }
+
+    /// Process one blob.
+    fn process<'a>(
+        &'a self,
+        input: &'a BlobProcessorInput<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<BlobProcessorOutput>>;
+
+    /// The graph edges linking a blob to the child blobs found by this processor,
+    /// given as (parent, child) blob hashes. Processors that find child blobs override this.
+    fn child_blob_edges(
+        &self,
+        _collection_id: &CollectionId,
+        _edges: Vec<(String, String)>,
+    ) -> Option<Box<dyn ProcessorRows>> {
+        None
+    }
+
+    /// Check if some blob links to this one with the edges of [BlobProcessor::child_blob_edges].
+    /// Used by the blob garbage collection; see [crate::tasks::blob_gc].
+    fn has_parent_blob<'a>(
+        &'a self,
+        _collection_id: &'a CollectionId,
+        _blob_sha3_256: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<bool>> {
+        Box::pin(async { Ok(false) })
+    }
+
+    /// Remove the edges of [BlobProcessor::child_blob_edges] from a removed blob to its children.
+    /// Returns the hashes of the unlinked child blobs.
+    fn unlink_child_blobs<'a>(
+        &'a self,
+        _collection_id: &'a CollectionId,
+        _blob_sha3_256: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<String>>> {
+        Box::pin(async { Ok(vec![]) })
+    }
+
+    /// Delete the rows this processor saved in [BlobProcessorOutput::rows] for a removed blob,
+    /// with their search documents. The rest of the processor output is removed
+    /// by the blob garbage collection itself.
+    fn remove_blob_rows<'a>(
+        &'a self,
+        _session: &'a ScyllaDatabaseHandle,
+        _db_extra: &'a DatabaseExtraCallbacks,
+        _blob_sha3_256: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// A blob downloaded into the worker tempdir, as given to the processors.
+pub struct BlobProcessorInput<'a> {
+    /// The collection of the blob.
+    pub collection_id: &'a CollectionId,
+    /// The hashes row of the blob.
+    pub blob: &'a FsBlobHashesDbRow,
+    /// The libmagic mime type of the blob, with parameters.
+    pub mime_type: &'a str,
+    /// Local path of the blob data.
+    pub file_path: &'a Path,
+    /// Tempdir for the blob; removed after the blob is saved.
+    pub temp_dir: &'a Path,
+    /// How many containers deep this blob was found; 0 for blobs read from datasources.
+    pub container_depth: i32,
+    /// Blobs this deep should not be unpacked further.
+    pub max_container_depth: i32,
+    /// Outputs of the processors that ran before this one, by processor name.
+    /// All the processors this one depends on are here, and have succeeded.
+    pub dependencies: &'a BTreeMap<&'static str, BlobProcessorOutput>,
+}
+
+/// Output of a processor for one blob.
+#[derive(Default)]
+pub struct BlobProcessorOutput {
+    /// Content type reported by the processor; used when choosing the best mime type.
+    pub content_type: Option<String>,
+    /// Metadata, saved with the processor name as provider.
+    pub metadata: BTreeMap<String, Vec<String>>,
+    /// Path to a text file with the extracted content, saved with the processor name as source.
+    pub content_path: Option<PathBuf>,
+    /// Child blobs (archive members, attachments, ...), to be saved and processed later.
+    pub child_blobs: Vec<UnpackedMember>,
+    /// Other database rows produced by the processor.
+    pub rows: Vec<Box<dyn ProcessorRows>>,
+    /// Errors that did not stop the processor; its output is still saved,
+    /// but the blob is recorded as failed for this processor.
+    pub errors: Vec<anyhow::Error>,
+}
+
+/// Database rows produced by processors. Rows of the same type
+/// from all the blobs in a page are gathered and written together.
+pub trait ProcessorRows: Send + Sync + 'static {
+    /// The type of the rows; only rows of the same type are appended together.
+    fn rows_type_id(&self) -> TypeId {
+        TypeId::of::<Self>()
+    }
+    /// Number of rows waiting to be written.
+    fn len(&self) -> usize;
+    /// Check if there are no rows waiting to be written.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Move the rows of another value of the same type into this one.
+    fn append(&mut self, other: Box<dyn Any + Send>);
+    /// Convert into [Any], for [ProcessorRows::append].
+    fn into_any(self: Box<Self>) -> Box<dyn Any + Send>;
+    /// Write the rows to the database, and clear them.
+    fn write<'a>(
+        &'a mut self,
+        session: &'a ScyllaDatabaseHandle,
+        extra: &'a DatabaseExtraCallbacks,
+    ) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+/// Model rows produced by a processor.
+pub struct ModelRows<T>(pub Vec<T>);
+
+impl<T> ProcessorRows for ModelRows<T>
+where
+    T: Model + serde::Serialize + Send + Sync + 'static,
+    <T as BaseModel>::PrimaryKey: PKValue,
+{
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+    fn append(&mut self, other: Box<dyn Any + Send>) {
+        if let Ok(other) = other.downcast::<Self>() {
+            self.0.extend(other.0);
+        }
+    }
+    fn into_any(self: Box<Self>) -> Box<dyn Any + Send> {
+        self
+    }
+    fn write<'a>(
+        &'a mut self,
+        session: &'a ScyllaDatabaseHandle,
+        extra: &'a DatabaseExtraCallbacks,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            extra.insert_rows(&self.0, 300).await?;
+            self.0.clear();
+            Ok(())
+        })
+    }
+}
+
+/// Graph edges between two blobs, given by their hashes, produced by a processor.
+pub struct BlobEdgeRows<E> {
+    collection_id: CollectionId,
+    edges: Vec<(String, String)>,
+    _ph: std::marker::PhantomData<E>,
+}
+
+impl<E> BlobEdgeRows<E> {
+    /// Edges from the (source, target) blob hashes.
+    pub fn new(collection_id: &CollectionId, edges: Vec<(String, String)>) -> Self {
+        Self {
+            collection_id: collection_id.clone(),
+            edges,
+            _ph: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<E> ProcessorRows for BlobEdgeRows<E>
+where
+    E: GraphEdgeInsert,
+    E::SourceType: BaseModel<PrimaryKey = (String,)> + Send + Sync,
+    E::DestType: BaseModel<PrimaryKey = (String,)> + Send + Sync,
+    <E::SourceType as BaseModel>::PartitionKey: PKValue,
+    <E::DestType as BaseModel>::PartitionKey: PKValue,
+{
+    fn len(&self) -> usize {
+        self.edges.len()
+    }
+    fn append(&mut self, other: Box<dyn Any + Send>) {
+        if let Ok(other) = other.downcast::<Self>() {
+            self.edges.extend(other.edges);
+        }
+    }
+    fn into_any(self: Box<Self>) -> Box<dyn Any + Send> {
+        self
+    }
+    fn write<'a>(
+        &'a mut self,
+        _session: &'a ScyllaDatabaseHandle,
+        _extra: &'a DatabaseExtraCallbacks,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut batch = E::edge_batch(&self.collection_id);
+            for (source, target) in self.edges.drain(..) {
+                batch.add_edge_from_pk(&(source,), &(target,));
+            }
+            batch.execute().await?;
+            Ok(())
+        })
+    }
+}
+
+/// Inventory blob processor definition.
+pub struct BlobProcessorStatic {
+    /// The processor implementation.
+    pub processor: &'static dyn BlobProcessor,
+}
+
+inventory::collect!(BlobProcessorStatic);
+
+/// Declare a blob processor in the inventory.
+/// Arguments:
+/// - the processor value (usually a unit struct) implementing [BlobProcessor]
+#[macro_export]
+macro_rules! declare_blob_processor {
+    ($processor:expr) => {
+        $crate::inventory::submit!($crate::processor::BlobProcessorStatic {
+            processor: &$processor,
+        });
+    };
+}
+pub use declare_blob_processor;
+
+/// List all blob processors compiled into this binary.
+pub fn list_blob_processors() -> impl Iterator<Item = &'static dyn BlobProcessor> {
+    inventory::iter::<BlobProcessorStatic>().map(|p| p.processor)
+}
+
+/// List all blob processors, each one after the processors it depends on.
+pub fn sorted_blob_processors() -> anyhow::Result<Vec<&'static dyn BlobProcessor>> {
+    sort_blob_processors(list_blob_processors().collect())
+}
+
+/// Sort the processors so that each one comes after its dependencies;
+/// otherwise, by name. Errors on duplicate names, unknown dependencies and cycles.
+fn sort_blob_processors(
+    processors: Vec<&'static dyn BlobProcessor>,
+) -> anyhow::Result<Vec<&'static dyn BlobProcessor>> {
+    let mut pending = BTreeMap::new();
+    for processor in processors {
+        if pending.insert(processor.name(), processor).is_some() {
+            anyhow::bail!("Duplicate blob processor: {}", processor.name());
+        }
+    }
+    for processor in pending.values() {
+        for dependency in processor.depends_on() {
+            if !pending.contains_key(dependency) {
+                anyhow::bail!(
+                    "Blob processor {} depends on unknown processor {}",
+                    processor.name(),
+                    dependency
+                );
+            }
+        }
+    }
+    let mut done = BTreeSet::new();
+    let mut sorted = vec![];
+    while !pending.is_empty() {
+        let ready = pending
+            .values()
+            .filter(|p| p.depends_on().iter().all(|d| done.contains(d)))
+            .map(|p| p.name())
+            .collect::<Vec<_>>();
+        if ready.is_empty() {
+            anyhow::bail!(
+                "Blob processor dependency cycle between: {:?}",
+                pending.keys().collect::<Vec<_>>()
+            );
+        }
+        for name in ready {
+            sorted.extend(pending.remove(name));
+            done.insert(name);
+        }
+    }
+    Ok(sorted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestProcessor(&'static str, &'static [&'static str]);
+    impl BlobProcessor for TestProcessor {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+        fn depends_on(&self) -> &'static [&'static str] {
+            self.1
+        }
+        fn process<'a>(
+            &'a self,
+            _input: &'a BlobProcessorInput<'a>,
+        ) -> BoxFuture<'a, anyhow::Result<BlobProcessorOutput>> {
+            Box::pin(async { Ok(BlobProcessorOutput::default()) })
+        }
+    }
+
+    static A: TestProcessor = TestProcessor("a", &["c"]);
+    static B: TestProcessor = TestProcessor("b", &[]);
+    static C: TestProcessor = TestProcessor("c", &["b"]);
+    static D: TestProcessor = TestProcessor("d", &["x"]);
+    static E: TestProcessor = TestProcessor("e", &["f"]);
+    static F: TestProcessor = TestProcessor("f", &["e"]);
+
+    fn names(processors: Vec<&'static dyn BlobProcessor>) -> Vec<&'static str> {
+        processors.into_iter().map(|p| p.name()).collect()
+    }
+
+    #[test]
+    fn test_sort_blob_processors() -> anyhow::Result<()> {
+        assert_eq!(
+            names(sort_blob_processors(vec![&A, &B, &C])?),
+            ["b", "c", "a"]
+        );
+        assert!(sort_blob_processors(vec![&A, &B, &C, &D]).is_err());
+        assert!(sort_blob_processors(vec![&E, &F]).is_err());
+        assert!(sort_blob_processors(vec![&B, &B]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_blob_processors_registered() -> anyhow::Result<()> {
+        assert_eq!(
+            names(sorted_blob_processors()?),
+            ["parse_email", "tika", "unpack_archive", "unpack_mailbox"]
+        );
+        let archive = list_blob_processors()
+            .find(|p| p.name() == "unpack_archive")
+            .unwrap();
+        assert!(archive.accepts("application/zip; charset=binary", 1024));
+        assert!(!archive.accepts("text/plain", 1024));
+        Ok(())
+    }
+}
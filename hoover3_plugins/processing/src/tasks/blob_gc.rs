@@ -0,0 +1,327 @@
+//! Blob garbage collection - remove the blobs that nothing references anymore,
+//! together with everything extracted from them during processing.
+//! The blobs to check are the [FsBlobGcCandidateDbRow] rows, saved when files are changed or removed.
+//! A blob is still referenced if some file links to it, or some container, email or mailbox blob,
+//! through the child blob edges of its [crate::processor::BlobProcessor].
+
+use charybdis::batch::ModelBatch;
+use charybdis::operations::InsertWithCallbacks;
+use futures::{StreamExt, TryStreamExt};
+use hoover3_database::db_management::{
+    DatabaseSpaceManager, S3DatabaseHandle, ScyllaDatabaseHandle,
+};
+use hoover3_database::models::collection::{
+    edge_list_source_pk, edge_list_targets_pk, DatabaseExtraCallbacks, GraphEdge, GraphEdgeInsert,
+};
+use hoover3_filesystem_scanner::models::{
+    find_blob_processing_plan_page_blobs, find_fs_blob_hashes_db_row,
+    find_fs_blob_mime_type_db_row, find_fs_blob_plan_page_db_row, BlobProcessingPlanPageBlobs,
+    FsBlobGcCandidateDbRow, FsBlobHashesDbRow, FsBlobMimeTypeDbRow, FsBlobPlanPageDbRow,
+    FsFileDbRow, FsFileToHashes,
+};
+use hoover3_macro::{activity, workflow};
+use hoover3_taskdef::{
+    TemporalioActivityDescriptor, TemporalioWorkflowDescriptor, WfContext, WfExitValue,
+    WorkflowResult,
+};
+use hoover3_types::identifier::CollectionId;
+use hoover3_types::processing::BlobGcResult;
+
+use super::ProcessingTasksQueue;
+use crate::models::{
+    find_blob_container_member_db_row, find_blob_extracted_content_row,
+    find_blob_extracted_metadata_row, find_blob_object_store_db_row,
+    find_blob_processing_failure_db_row, find_blob_processor_status_db_row,
+    BlobContainerMemberDbRow, BlobExtractedContentRow, BlobExtractedMetadataRow,
+    BlobObjectStoreDbRow, BlobProcessingFailureDbRow, BlobProcessorStatusDbRow,
+};
+use crate::processor::list_blob_processors;
+
+/// Number of candidate blobs checked by a single batch.
+const BLOB_GC_BATCH_SIZE: usize = 256;
+
+/// Workflow that checks all the garbage collection candidates of a collection,
+/// in batches - one child workflow per batch, so the progress shows up in the task status tree.
+#[workflow(ProcessingTasksQueue)]
+async fn gc_unreferenced_blobs(
+    wf_ctx: WfContext,
+    collection_id: CollectionId,
+) -> WorkflowResult<BlobGcResult> {
+    let mut result = BlobGcResult::default();
+    for batch_index in 0_i32.. {
+        let batch =
+            gc_blob_batch_workflow::run_as_child(&wf_ctx, (collection_id.clone(), batch_index))
+                .await?;
+        if batch.checked_count == 0 {
+            break;
+        }
+        result += batch;
+    }
+    Ok(WfExitValue::Normal(result))
+}
+
+/// Workflow that checks one batch of candidates.
+/// The batch index is only used to get a different workflow id for each batch.
+#[workflow(ProcessingTasksQueue)]
+async fn gc_blob_batch(
+    wf_ctx: WfContext,
+    (collection_id, _batch_index): (CollectionId, i32),
+) -> WorkflowResult<BlobGcResult> {
+    Ok(WfExitValue::Normal(
+        gc_do_blob_batch_activity::run(&wf_ctx, collection_id).await?,
+    ))
+}
+
+/// Check the first candidates left, and remove the blobs that are no longer referenced.
+/// The members of removed container blobs become candidates themselves.
+/// Returns a zero checked count when no candidates are left.
+#[activity(ProcessingTasksQueue)]
+async fn gc_do_blob_batch(collection_id: CollectionId) -> anyhow::Result<BlobGcResult> {
+    let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+    let db_extra = DatabaseExtraCallbacks::new(&collection_id).await?;
+
+    let candidates = FsBlobGcCandidateDbRow::find_all()
+        .execute(&session)
+        .await?
+        .take(BLOB_GC_BATCH_SIZE)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let mut result = BlobGcResult::default();
+    for candidate in candidates.iter() {
+        result.checked_count += 1;
+        let blob_sha3_256 = candidate.blob_sha3_256.clone();
+        if !is_blob_referenced(&collection_id, &session, &db_extra, &blob_sha3_256).await? {
+            let members = remove_blob(&collection_id, &session, &db_extra, &blob_sha3_256).await?;
+            let members = members
+                .into_iter()
+                .map(|blob_sha3_256| FsBlobGcCandidateDbRow { blob_sha3_256 })
+                .collect::<Vec<_>>();
+            FsBlobGcCandidateDbRow::batch()
+                .chunked_insert(&session, &members, 1024)
+                .await?;
+            result.removed_count += 1;
+        }
+    }
+    FsBlobGcCandidateDbRow::batch()
+        .chunked_delete(&session, &candidates, 1024)
+        .await?;
+    Ok(result)
+}
+
+/// Check if some file or parent blob still links to this blob.
+/// If the blob is still found in some file, but the file it was first found in is gone,
+/// the blob row is changed to point to the remaining file, so it can still be read.
+async fn is_blob_referenced(
+    collection_id: &CollectionId,
+    session: &ScyllaDatabaseHandle,
+    db_extra: &DatabaseExtraCallbacks,
+    blob_sha3_256: &str,
+) -> anyhow::Result<bool> {
+    let blob_pk = (blob_sha3_256.to_string(),);
+    let mut files = edge_list_source_pk::<FsFileToHashes>(collection_id, &blob_pk).await?;
+    if let Some((datasource_id, parent_dir_path, file_name)) = files.try_next().await? {
+        let blob = find_fs_blob_hashes_db_row!("blob_sha3_256 = ?", (blob_sha3_256,))
+            .execute(session)
+            .await?
+            .try_next()
+            .await?;
+        if let Some(mut blob) = blob {
+            let first_file =
+                FsFileDbRow::maybe_find_first_by_datasource_id_and_parent_dir_path_and_file_name(
+                    blob.datasource_id.clone(),
+                    blob.parent_dir_path.clone(),
+                    blob.file_name.clone(),
+                )
+                .execute(session)
+                .await?;
+            if !first_file.is_some_and(|f| f.removed_at.is_none()) {
+                blob.datasource_id = datasource_id;
+                blob.parent_dir_path = parent_dir_path;
+                blob.file_name = file_name;
+                FsBlobHashesDbRow::insert_cb(&mut blob, db_extra)
+                    .execute(session)
+                    .await?;
+            }
+        }
+        return Ok(true);
+    }
+
+    for processor in list_blob_processors() {
+        if processor
+            .has_parent_blob(collection_id, blob_sha3_256)
+            .await?
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Check if some blob links to this one, using edge `E`.
+pub(crate) async fn has_parent_blob<E>(
+    collection_id: &CollectionId,
+    blob_sha3_256: &str,
+) -> anyhow::Result<bool>
+where
+    E: GraphEdge<SourceType = FsBlobHashesDbRow, DestType = FsBlobHashesDbRow>,
+{
+    let mut parents =
+        edge_list_source_pk::<E>(collection_id, &(blob_sha3_256.to_string(),)).await?;
+    Ok(parents.try_next().await?.is_some())
+}
+
+/// Remove the links from this blob to the blobs found inside it, using edge `E`.
+/// Returns the hashes of the unlinked blobs.
+pub(crate) async fn unlink_member_blobs<E>(
+    collection_id: &CollectionId,
+    blob_sha3_256: &str,
+) -> anyhow::Result<Vec<String>>
+where
+    E: GraphEdgeInsert + GraphEdge<SourceType = FsBlobHashesDbRow, DestType = FsBlobHashesDbRow>,
+{
+    let blob_pk = (blob_sha3_256.to_string(),);
+    let members = edge_list_targets_pk::<E>(collection_id, &blob_pk)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    let mut edge_batch = E::edge_batch(collection_id);
+    for member in members.iter() {
+        edge_batch.remove_edge_from_pk(&blob_pk, member);
+    }
+    edge_batch.execute().await?;
+    Ok(members.into_iter().map(|m| m.0).collect())
+}
+
+/// Find all the rows of a table for a blob, then delete them and their search documents.
+macro_rules! delete_blob_rows {
+    ($find:ident, $model:ty, $query:literal, $values:expr, $session:expr, $db_extra:expr) => {{
+        let rows: Vec<$model> = $find!($query, $values)
+            .execute($session)
+            .await?
+            .try_collect()
+            .await?;
+        $db_extra.delete(&rows).await?;
+        <$model>::batch()
+            .chunked_delete($session, &rows, 1024)
+            .await?;
+        rows
+    }};
+}
+pub(crate) use delete_blob_rows;
+
+/// Remove a blob: the rows extracted from it during processing - including the rows
+/// each blob processor removes itself - its status and failure rows, its hashes and plan entries,
+/// and its copy in the object store. Returns the hashes of the blobs found inside it.
+async fn remove_blob(
+    collection_id: &CollectionId,
+    session: &ScyllaDatabaseHandle,
+    db_extra: &DatabaseExtraCallbacks,
+    blob_sha3_256: &str,
+) -> anyhow::Result<Vec<String>> {
+    let mut members = vec![];
+    for processor in list_blob_processors() {
+        members.extend(
+            processor
+                .unlink_child_blobs(collection_id, blob_sha3_256)
+                .await?,
+        );
+        processor
+            .remove_blob_rows(session, db_extra, blob_sha3_256)
+            .await?;
+    }
+
+    let sha = blob_sha3_256.to_string();
+    delete_blob_rows!(
+        find_blob_extracted_metadata_row,
+        BlobExtractedMetadataRow,
+        "blob_sha3_256 = ?",
+        (sha.clone(),),
+        session,
+        db_extra
+    );
+    delete_blob_rows!(
+        find_blob_extracted_content_row,
+        BlobExtractedContentRow,
+        "blob_sha3_256 = ?",
+        (sha.clone(),),
+        session,
+        db_extra
+    );
+    delete_blob_rows!(
+        find_blob_container_member_db_row,
+        BlobContainerMemberDbRow,
+        "container_blob_sha3_256 = ?",
+        (sha.clone(),),
+        session,
+        db_extra
+    );
+    delete_blob_rows!(
+        find_blob_processor_status_db_row,
+        BlobProcessorStatusDbRow,
+        "blob_sha3_256 = ?",
+        (sha.clone(),),
+        session,
+        db_extra
+    );
+    delete_blob_rows!(
+        find_blob_processing_failure_db_row,
+        BlobProcessingFailureDbRow,
+        "blob_sha3_256 = ?",
+        (sha.clone(),),
+        session,
+        db_extra
+    );
+    delete_blob_rows!(
+        find_fs_blob_mime_type_db_row,
+        FsBlobMimeTypeDbRow,
+        "blob_sha3_256 = ?",
+        (sha.clone(),),
+        session,
+        db_extra
+    );
+
+    let plan_pages = delete_blob_rows!(
+        find_fs_blob_plan_page_db_row,
+        FsBlobPlanPageDbRow,
+        "blob_sha3_256 = ?",
+        (sha.clone(),),
+        session,
+        db_extra
+    );
+    for plan_page in plan_pages {
+        delete_blob_rows!(
+            find_blob_processing_plan_page_blobs,
+            BlobProcessingPlanPageBlobs,
+            "plan_page_id = ? AND blob_sha3_256 = ?",
+            (plan_page.plan_page_id, sha.clone()),
+            session,
+            db_extra
+        );
+    }
+
+    let stored = delete_blob_rows!(
+        find_blob_object_store_db_row,
+        BlobObjectStoreDbRow,
+        "blob_sha3_256 = ?",
+        (sha.clone(),),
+        session,
+        db_extra
+    );
+    if !stored.is_empty() {
+        let s3 = S3DatabaseHandle::collection_session(collection_id).await?;
+        s3.delete_blob(collection_id, blob_sha3_256).await?;
+    }
+
+    delete_blob_rows!(
+        find_fs_blob_hashes_db_row,
+        FsBlobHashesDbRow,
+        "blob_sha3_256 = ?",
+        (sha.clone(),),
+        session,
+        db_extra
+    );
+    members.sort();
+    members.dedup();
+    Ok(members)
+}
@@ -0,0 +1,355 @@
+//! Parse RFC 822 / MIME email messages.
+//! Headers are stored in [EmailHeadersDbRow] and [EmailAddressDbRow];
+//! attachments are written into the item tempdir and become new blobs,
+//! linked to the email through [crate::models::EmailToAttachment].
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use charybdis::batch::ModelBatch;
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+use hoover3_database::db_management::ScyllaDatabaseHandle;
+use hoover3_database::models::collection::DatabaseExtraCallbacks;
+use hoover3_filesystem_scanner::models::FsBlobHashesDbRow;
+use hoover3_types::identifier::CollectionId;
+use mail_parser::{Address, HeaderValue, Message, MessageParser, MimeHeaders};
+
+use super::blob_gc::{delete_blob_rows, has_parent_blob, unlink_member_blobs};
+use super::failures::STAGE_PARSE_EMAIL;
+use super::unpack_archive::{hash_member_files, sanitize_member_path, UnpackedMember};
+use crate::models::{
+    find_email_address_db_row, find_email_headers_db_row, EmailAddressDbRow, EmailHeadersDbRow,
+    EmailToAttachment,
+};
+use crate::processor::{
+    declare_blob_processor, BlobEdgeRows, BlobProcessor, BlobProcessorInput, BlobProcessorOutput,
+    ModelRows, ProcessorRows,
+};
+
+/// Emails larger than this are not parsed.
+const EMAIL_MAX_SIZE_BYTES: u64 = 256 * 1024 * 1024; // 256 MB
+
+/// Check if the libmagic mime type is an email message.
+pub fn is_email_mime_type(mime_type: &str) -> bool {
+    let mime_type = mime_type.split(';').next().unwrap_or_default().trim();
+    matches!(mime_type, "message/rfc822" | "message/news")
+}
+
+/// Result of parsing a single email message.
+pub(crate) struct ParsedEmail {
+    pub headers_row: EmailHeadersDbRow,
+    pub address_rows: Vec<EmailAddressDbRow>,
+    pub attachments: Vec<UnpackedMember>,
+}
+
+/// Processor that parses emails: saves their headers and addresses,
+/// and extracts the attachments as new blobs, linked through [EmailToAttachment].
+/// Attachments are not extracted from emails at the maximum container depth.
+pub struct ParseEmailProcessor;
+
+impl BlobProcessor for ParseEmailProcessor {
+    fn name(&self) -> &'static str {
+        STAGE_PARSE_EMAIL
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &["message/rfc822", "message/news"]
+    }
+
+    fn process<'a>(
+        &'a self,
+        input: &'a BlobProcessorInput<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<BlobProcessorOutput>> {
+        Box::pin(async move {
+            let email = parse_email(
+                input.blob,
+                input.mime_type,
+                input.container_depth,
+                input.container_depth < input.max_container_depth,
+                input.file_path.to_path_buf(),
+                input.temp_dir.to_path_buf(),
+            )
+            .await?;
+            Ok(BlobProcessorOutput {
+                child_blobs: email.attachments,
+                rows: vec![
+                    Box::new(ModelRows(vec![email.headers_row])),
+                    Box::new(ModelRows(email.address_rows)),
+                ],
+                ..Default::default()
+            })
+        })
+    }
+
+    fn child_blob_edges(
+        &self,
+        collection_id: &CollectionId,
+        edges: Vec<(String, String)>,
+    ) -> Option<Box<dyn ProcessorRows>> {
+        Some(Box::new(BlobEdgeRows::<EmailToAttachment>::new(
+            collection_id,
+            edges,
+        )))
+    }
+
+    fn has_parent_blob<'a>(
+        &'a self,
+        collection_id: &'a CollectionId,
+        blob_sha3_256: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<bool>> {
+        Box::pin(has_parent_blob::<EmailToAttachment>(
+            collection_id,
+            blob_sha3_256,
+        ))
+    }
+
+    fn unlink_child_blobs<'a>(
+        &'a self,
+        collection_id: &'a CollectionId,
+        blob_sha3_256: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<String>>> {
+        Box::pin(unlink_member_blobs::<EmailToAttachment>(
+            collection_id,
+            blob_sha3_256,
+        ))
+    }
+
+    fn remove_blob_rows<'a>(
+        &'a self,
+        session: &'a ScyllaDatabaseHandle,
+        db_extra: &'a DatabaseExtraCallbacks,
+        blob_sha3_256: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let sha = blob_sha3_256.to_string();
+            delete_blob_rows!(
+                find_email_address_db_row,
+                EmailAddressDbRow,
+                "blob_sha3_256 = ?",
+                (sha.clone(),),
+                session,
+                db_extra
+            );
+            delete_blob_rows!(
+                find_email_headers_db_row,
+                EmailHeadersDbRow,
+                "blob_sha3_256 = ?",
+                (sha.clone(),),
+                session,
+                db_extra
+            );
+            Ok(())
+        })
+    }
+}
+
+declare_blob_processor!(ParseEmailProcessor);
+
+/// Parse an email: read the headers and extract the attachments into the tempdir.
+/// `container_depth` is the depth of the email blob; attachments get `container_depth + 1`.
+/// If `extract_attachments` is false, only the headers are read.
+pub(crate) async fn parse_email(
+    email: &FsBlobHashesDbRow,
+    mime_type: &str,
+    container_depth: i32,
+    extract_attachments: bool,
+    file_path: PathBuf,
+    temp_dir: PathBuf,
+) -> anyhow::Result<ParsedEmail> {
+    let output_dir = temp_dir.join("attachments");
+    let blob_sha3_256 = email.blob_sha3_256.clone();
+    let _output_dir = output_dir.clone();
+    let (headers_row, address_rows, attachment_paths) = tokio::task::spawn_blocking(move || {
+        run_parse_email(blob_sha3_256, file_path, _output_dir, extract_attachments)
+    })
+    .await??;
+    let attachments =
+        hash_member_files(email, mime_type, container_depth, attachment_paths).await?;
+
+    Ok(ParsedEmail {
+        headers_row,
+        address_rows,
+        attachments,
+    })
+}
+
+fn run_parse_email(
+    blob_sha3_256: String,
+    file_path: PathBuf,
+    output_dir: PathBuf,
+    extract_attachments: bool,
+) -> anyhow::Result<(
+    EmailHeadersDbRow,
+    Vec<EmailAddressDbRow>,
+    Vec<(String, PathBuf)>,
+)> {
+    if std::fs::metadata(&file_path)?.len() > EMAIL_MAX_SIZE_BYTES {
+        anyhow::bail!("email is too large to parse: {:?}", file_path);
+    }
+    let data = std::fs::read(&file_path)?;
+    let message = MessageParser::default()
+        .parse(&data)
+        .context("failed to parse email message")?;
+
+    let mut attachment_paths = vec![];
+    if extract_attachments {
+        attachment_paths = write_attachments(&message, &output_dir)?;
+    }
+    let headers_row = get_headers_row(&blob_sha3_256, &message, attachment_paths.len());
+    let address_rows = get_address_rows(&blob_sha3_256, &message);
+    Ok((headers_row, address_rows, attachment_paths))
+}
+
+fn get_headers_row(
+    blob_sha3_256: &str,
+    message: &Message,
+    attachment_count: usize,
+) -> EmailHeadersDbRow {
+    EmailHeadersDbRow {
+        blob_sha3_256: blob_sha3_256.to_string(),
+        from_header: message.header_raw("From").map(|h| h.trim().to_string()),
+        to_header: message.header_raw("To").map(|h| h.trim().to_string()),
+        cc_header: message.header_raw("Cc").map(|h| h.trim().to_string()),
+        subject: message.subject().map(|s| s.to_string()),
+        date: message
+            .date()
+            .and_then(|d| chrono::DateTime::from_timestamp(d.to_timestamp(), 0)),
+        message_id: message.message_id().map(|s| s.to_string()),
+        in_reply_to: header_text(message.in_reply_to()),
+        attachment_count: attachment_count as i32,
+    }
+}
+
+/// Headers like In-Reply-To can hold a single id or a list of ids.
+fn header_text(value: &HeaderValue) -> Option<String> {
+    if let Some(list) = value.as_text_list() {
+        return Some(list.join(" "));
+    }
+    value.as_text().map(|s| s.to_string())
+}
+
+fn get_address_rows(blob_sha3_256: &str, message: &Message) -> Vec<EmailAddressDbRow> {
+    let mut rows = vec![];
+    for (header, address) in [
+        ("from", message.from()),
+        ("to", message.to()),
+        ("cc", message.cc()),
+    ] {
+        let Some(address) = address else {
+            continue;
+        };
+        rows.extend(address_rows_for_header(blob_sha3_256, header, address));
+    }
+    rows
+}
+
+fn address_rows_for_header(
+    blob_sha3_256: &str,
+    header: &str,
+    address: &Address,
+) -> Vec<EmailAddressDbRow> {
+    address
+        .iter()
+        .filter_map(|addr| {
+            let email = addr.address.as_ref()?.trim().to_lowercase();
+            if email.is_empty() {
+                return None;
+            }
+            Some((email, addr.name.as_ref().map(|n| n.trim().to_string())))
+        })
+        .enumerate()
+        .map(|(i, (address, display_name))| EmailAddressDbRow {
+            blob_sha3_256: blob_sha3_256.to_string(),
+            header: header.to_string(),
+            list_index: i as i32,
+            address,
+            display_name,
+        })
+        .collect()
+}
+
+/// Write all attachments to disk, each one in its own numbered folder,
+/// so that attachments with the same file name don't collide.
+fn write_attachments(
+    message: &Message,
+    output_dir: &Path,
+) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let mut paths = vec![];
+    for (i, part) in message.attachments().enumerate() {
+        let file_name = part
+            .attachment_name()
+            .and_then(|name| sanitize_member_path(Path::new(name)))
+            .and_then(|name| name.file_name().map(|f| f.to_string_lossy().to_string()))
+            .unwrap_or_else(|| format!("attachment_{}", i));
+        let member_path = format!("attachment_{}/{}", i, file_name);
+        let local_path = output_dir.join(&member_path);
+        std::fs::create_dir_all(local_path.parent().context("no parent")?)?;
+        std::fs::write(&local_path, part.contents())?;
+        paths.push((member_path, local_path));
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_EMAIL: &str = "From: Alice Example <Alice@example.com>\r
+To: bob@example.com, Carol <carol@example.com>\r
+Subject: Quarterly report\r
+Date: Tue, 1 Oct 2024 10:00:00 +0000\r
+Message-ID: <msg-2@example.com>\r
+In-Reply-To: <msg-1@example.com>\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"XXX\"\r
+\r
+--XXX\r
+Content-Type: text/plain\r
+\r
+See attached.\r
+--XXX\r
+Content-Type: text/plain; name=\"../report.txt\"\r
+Content-Disposition: attachment; filename=\"../report.txt\"\r
+\r
+The numbers are up.\r
+--XXX--\r
+";
+
+    #[test]
+    fn test_parse_email() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let email_path = temp_dir.path().join("test.eml");
+        std::fs::write(&email_path, TEST_EMAIL)?;
+        let output_dir = temp_dir.path().join("attachments");
+
+        let (headers, addresses, attachments) =
+            run_parse_email("abcdef".to_string(), email_path, output_dir, true)?;
+        assert_eq!(headers.subject.as_deref(), Some("Quarterly report"));
+        assert_eq!(headers.message_id.as_deref(), Some("msg-2@example.com"));
+        assert_eq!(headers.in_reply_to.as_deref(), Some("msg-1@example.com"));
+        assert_eq!(headers.attachment_count, 1);
+        assert!(headers.date.is_some());
+
+        let addresses = addresses
+            .iter()
+            .map(|a| (a.header.as_str(), a.address.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            addresses,
+            vec![
+                ("from", "alice@example.com"),
+                ("to", "bob@example.com"),
+                ("to", "carol@example.com"),
+            ]
+        );
+
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].0, "attachment_0/report.txt");
+        assert_eq!(
+            std::fs::read_to_string(&attachments[0].1)?,
+            "The numbers are up."
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+//! Processing failure records, and the workflow that processes the failed blobs again.
+//!
+//! The page processing saves a [BlobProcessingFailureDbRow] for every blob and stage that failed.
+//! After fixing the cause (a missing file, a broken extractor), [retry_failed_blobs_workflow]
+//! runs the failed blobs through the same pipeline, grouped by their plan page.
+//! Stages that succeed on the retry have their failure rows removed.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use charybdis::batch::ModelBatch;
+use futures::TryStreamExt;
+use hoover3_database::{
+    charybdis::operations::Find,
+    constants::CQL_SELECT_BATCH_SIZE,
+    db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle},
+    models::collection::DatabaseExtraCallbacks,
+};
+use hoover3_filesystem_scanner::{
+    models::{find_fs_blob_hashes_db_row, BlobProcessingPlan},
+    tasks::process_plan::do_compute_blob_processing_plan_activity,
+};
+use hoover3_taskdef::{
+    activity, anyhow, workflow, TemporalioActivityDescriptor, TemporalioWorkflowDescriptor,
+    WfContext, WfExitValue, WorkflowResult,
+};
+use hoover3_types::{
+    identifier::CollectionId,
+    processing::{ProcessPageResult, RetryFailedBlobsResult},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ocr::{clear_failed_ocr_activity, run_collection_ocr_workflow},
+    process_group::{
+        get_plan_page_ids_activity, process_pages_group_workflow, ProcessPageArgs, SMALL_THRESHOLD,
+    },
+    process_page::{retry_big_page_blobs_activity, retry_small_page_blobs_activity},
+    unpack_archive::ARCHIVE_MAX_DEPTH_LIMIT,
+    ProcessingTasksQueue,
+};
+use crate::models::BlobProcessingFailureDbRow;
+use crate::processor::BlobProcessorOutput;
+
+/// Reading or downloading the blob data.
+pub const STAGE_DOWNLOAD: &str = "download";
+/// Detecting the mime type with libmagic and Magika.
+pub const STAGE_MIME_TYPE: &str = "mime_type";
+/// Running OCR on scanned PDFs and images, after the page processing; see [super::ocr].
+pub const STAGE_OCR: &str = "ocr";
+// The other stages are the blob processors, named after them; see [crate::processor].
+/// Unpacking an archive.
+pub const STAGE_UNPACK_ARCHIVE: &str = "unpack_archive";
+/// Unpacking a mailbox.
+pub const STAGE_UNPACK_MAILBOX: &str = "unpack_mailbox";
+/// Parsing an email message.
+pub const STAGE_PARSE_EMAIL: &str = "parse_email";
+/// Extracting metadata and text with Tika.
+pub const STAGE_TIKA: &str = "tika";
+
+/// Maximum number of blobs retried by one activity.
+const RETRY_BLOBS_PER_ACTIVITY: usize = 500;
+
+/// A processing stage that failed for a blob.
+#[derive(Debug)]
+pub(super) struct ItemFailure {
+    pub(super) stage: &'static str,
+    pub(super) mime_type: Option<String>,
+    pub(super) error: String,
+}
+
+impl ItemFailure {
+    pub(super) fn new(stage: &'static str, mime_type: Option<&str>, error: &anyhow::Error) -> Self {
+        Self {
+            stage,
+            mime_type: mime_type.map(str::to_string),
+            error: format!("{error:#}"),
+        }
+    }
+}
+
+/// The failure of a processor for a blob, if the processor failed or its output has errors.
+/// A processor with output errors still has its output saved - e.g. Tika found the metadata,
+/// but failed to extract the content - and is recorded as failed, so the blob is retried.
+pub(super) fn processor_failure(
+    stage: &'static str,
+    result: &anyhow::Result<BlobProcessorOutput>,
+) -> Option<ItemFailure> {
+    let errors = match result {
+        Ok(output) => output.errors.iter().collect::<Vec<_>>(),
+        Err(e) => vec![e],
+    };
+    if errors.is_empty() {
+        return None;
+    }
+    let error = errors
+        .iter()
+        .map(|e| format!("{e:#}"))
+        .collect::<Vec<_>>()
+        .join("; ");
+    Some(ItemFailure::new(stage, None, &anyhow::anyhow!(error)))
+}
+
+/// Save the failures found for the given blobs, and remove the failure rows of the stages
+/// that did not fail this time. Blobs processed for the first time only have their failures saved.
+pub(super) async fn save_item_failures(
+    session: &ScyllaDatabaseHandle,
+    extra: &DatabaseExtraCallbacks,
+    plan_page_id: i32,
+    items: Vec<(String, Vec<ItemFailure>)>,
+    is_retry: bool,
+) -> anyhow::Result<()> {
+    let now = chrono::Utc::now();
+    let mut new_rows = vec![];
+    let mut old_rows = vec![];
+    for (blob_sha3_256, failures) in items {
+        if failures.is_empty() && !is_retry {
+            continue;
+        }
+        let mut previous = BlobProcessingFailureDbRow::find_by_blob_sha3_256(blob_sha3_256.clone())
+            .execute(session)
+            .await?
+            .map_ok(|row| (row.stage.clone(), row))
+            .try_collect::<HashMap<_, _>>()
+            .await?;
+        for failure in failures {
+            let attempt_count = previous
+                .remove(failure.stage)
+                .map(|row| row.attempt_count)
+                .unwrap_or(0)
+                + 1;
+            new_rows.push(BlobProcessingFailureDbRow {
+                blob_sha3_256: blob_sha3_256.clone(),
+                stage: failure.stage.to_string(),
+                mime_type: failure.mime_type,
+                error: failure.error,
+                attempt_count,
+                plan_page_id,
+                failed_at: now,
+            });
+        }
+        old_rows.extend(previous.into_values());
+    }
+    extra.insert_rows(&new_rows, 256).await?;
+    BlobProcessingFailureDbRow::batch()
+        .chunked_delete(session, &old_rows, 256)
+        .await?;
+    extra.delete(&old_rows).await?;
+    Ok(())
+}
+
+/// Save the outcome of a stage that runs apart from the page processing, e.g. OCR:
+/// its failure row is saved if it failed, and removed if it succeeded.
+/// The failure rows of the other stages are kept.
+pub(super) async fn save_stage_failure(
+    session: &ScyllaDatabaseHandle,
+    extra: &DatabaseExtraCallbacks,
+    plan_page_id: i32,
+    blob_sha3_256: &str,
+    stage: &'static str,
+    failure: Option<ItemFailure>,
+) -> anyhow::Result<()> {
+    let previous = BlobProcessingFailureDbRow::maybe_find_first_by_blob_sha3_256_and_stage(
+        blob_sha3_256.to_string(),
+        stage.to_string(),
+    )
+    .execute(session)
+    .await?;
+    match (failure, previous) {
+        (Some(failure), previous) => {
+            let row = BlobProcessingFailureDbRow {
+                blob_sha3_256: blob_sha3_256.to_string(),
+                stage: stage.to_string(),
+                mime_type: failure.mime_type,
+                error: failure.error,
+                attempt_count: previous.map(|row| row.attempt_count).unwrap_or(0) + 1,
+                plan_page_id,
+                failed_at: chrono::Utc::now(),
+            };
+            extra.insert_rows(&[row], 1).await?;
+        }
+        (None, Some(previous)) => {
+            let old_rows = vec![previous];
+            BlobProcessingFailureDbRow::batch()
+                .chunked_delete(session, &old_rows, 1)
+                .await?;
+            extra.delete(&old_rows).await?;
+        }
+        (None, None) => {}
+    }
+    Ok(())
+}
+
+/// Failed blobs of a plan page, to be processed again.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RetryPageBlobsArgs {
+    /// The plan page the blobs were processed in.
+    pub page: ProcessPageArgs,
+    /// The failed blobs, sorted.
+    pub blobs: Vec<String>,
+}
+
+/// Workflow for processing the failed blobs of a collection again.
+/// Each retry request starts it in a new generation; see [hoover3_taskdef::with_workflow_generation].
+/// New blobs found in retried containers are planned and processed afterwards,
+/// and OCR runs again on the blobs that failed it.
+#[workflow(ProcessingTasksQueue)]
+async fn retry_failed_blobs(
+    ctx: WfContext,
+    collection_id: CollectionId,
+) -> WorkflowResult<RetryFailedBlobsResult> {
+    let ocr_blob_count = clear_failed_ocr_activity::run(&ctx, collection_id.clone()).await?;
+    let pages = get_failed_blob_pages_activity::run(&ctx, collection_id.clone()).await?;
+    let mut result = RetryFailedBlobsResult {
+        failed_blob_count: pages.iter().map(|p| p.blobs.len() as i32).sum(),
+        ..Default::default()
+    };
+    for chunk in pages.chunks(300) {
+        for (_arg, res) in retry_page_blobs_workflow::run_parallel(&ctx, chunk.to_vec()).await? {
+            result.retry_results += res?;
+        }
+    }
+
+    for _round in 0..ARCHIVE_MAX_DEPTH_LIMIT {
+        let plan =
+            do_compute_blob_processing_plan_activity::run(&ctx, collection_id.clone()).await?;
+        if plan.new_page_count == 0 {
+            break;
+        }
+        let (small_pages, large_pages) =
+            get_plan_page_ids_activity::run(&ctx, collection_id.clone()).await?;
+        for (pages, is_small) in [(small_pages, true), (large_pages, false)] {
+            if !pages.is_empty() {
+                result.new_page_results += process_pages_group_workflow::run_as_child(
+                    &ctx,
+                    (collection_id.clone(), pages, is_small),
+                )
+                .await?;
+            }
+        }
+    }
+
+    if ocr_blob_count > 0 {
+        result.ocr_results =
+            run_collection_ocr_workflow::run_as_child(&ctx, collection_id.clone()).await?;
+    }
+    Ok(WfExitValue::Normal(result))
+}
+
+/// Activity for listing the failed blobs, grouped by plan page.
+/// Blobs removed since they failed, e.g. by the blob garbage collection, are skipped.
+/// OCR failures are left to the OCR run; see [clear_failed_ocr_activity].
+#[activity(ProcessingTasksQueue)]
+async fn get_failed_blob_pages(
+    collection_id: CollectionId,
+) -> anyhow::Result<Vec<RetryPageBlobsArgs>> {
+    let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+    let pages = BlobProcessingFailureDbRow::find_all()
+        .execute(&session)
+        .await?
+        .try_fold(
+            BTreeMap::<i32, BTreeSet<String>>::new(),
+            |mut pages, row| {
+                if row.stage != STAGE_OCR {
+                    pages
+                        .entry(row.plan_page_id)
+                        .or_default()
+                        .insert(row.blob_sha3_256);
+                }
+                std::future::ready(Ok(pages))
+            },
+        )
+        .await?;
+
+    let mut result = vec![];
+    for (plan_page_id, blobs) in pages {
+        let page_is_small = match BlobProcessingPlan::maybe_find_first_by_plan_page_id(plan_page_id)
+            .execute(&session)
+            .await?
+        {
+            Some(plan) => plan.size_bytes < SMALL_THRESHOLD,
+            None => false,
+        };
+        let blobs = existing_blobs(&session, blobs).await?;
+        for chunk in blobs.chunks(RETRY_BLOBS_PER_ACTIVITY) {
+            result.push(RetryPageBlobsArgs {
+                page: ProcessPageArgs {
+                    collection_id: collection_id.clone(),
+                    plan_page_id,
+                    page_is_small,
+                },
+                blobs: chunk.to_vec(),
+            });
+        }
+    }
+    Ok(result)
+}
+
+/// Keep only the blobs that still have their [hoover3_filesystem_scanner::models::FsBlobHashesDbRow].
+async fn existing_blobs(
+    session: &ScyllaDatabaseHandle,
+    blobs: BTreeSet<String>,
+) -> anyhow::Result<Vec<String>> {
+    let blobs = blobs.into_iter().collect::<Vec<_>>();
+    let mut existing = BTreeSet::new();
+    for chunk in blobs.chunks(CQL_SELECT_BATCH_SIZE) {
+        let found = find_fs_blob_hashes_db_row!("blob_sha3_256 IN ?", (chunk.to_vec(),))
+            .execute(session)
+            .await?
+            .map_ok(|row| row.blob_sha3_256)
+            .try_collect::<Vec<_>>()
+            .await?;
+        existing.extend(found);
+    }
+    Ok(blobs
+        .into_iter()
+        .filter(|blob| existing.contains(blob))
+        .collect())
+}
+
+/// Process the failed blobs of one plan page again.
+#[workflow(ProcessingTasksQueue)]
+async fn retry_page_blobs(
+    ctx: WfContext,
+    args: RetryPageBlobsArgs,
+) -> WorkflowResult<ProcessPageResult> {
+    if args.page.page_is_small {
+        Ok(WfExitValue::Normal(
+            retry_small_page_blobs_activity::run(&ctx, args).await?,
+        ))
+    } else {
+        Ok(WfExitValue::Normal(
+            retry_big_page_blobs_activity::run(&ctx, args).await?,
+        ))
+    }
+}
+
+#[test]
+fn test_item_failure_keeps_error_chain() {
+    let error = anyhow::anyhow!("zip header broken").context("unpacking archive");
+    let failure = ItemFailure::new(STAGE_UNPACK_ARCHIVE, Some("application/zip"), &error);
+    assert_eq!(failure.error, "unpacking archive: zip header broken");
+    assert_eq!(failure.mime_type.as_deref(), Some("application/zip"));
+}
+
+#[test]
+fn test_processor_failure_with_output_errors() {
+    let output = BlobProcessorOutput {
+        metadata: [("author".to_string(), vec!["me".to_string()])].into(),
+        errors: vec![anyhow::anyhow!("content extraction failed")],
+        ..Default::default()
+    };
+    let failure = processor_failure(STAGE_TIKA, &Ok(output)).expect("tika failure");
+    assert_eq!(failure.stage, STAGE_TIKA);
+    assert_eq!(failure.error, "content extraction failed");
+    assert!(processor_failure(STAGE_TIKA, &Ok(BlobProcessorOutput::default())).is_none());
+    let failure = processor_failure(STAGE_TIKA, &Err(anyhow::anyhow!("tika is down")));
+    assert_eq!(failure.map(|f| f.error), Some("tika is down".to_string()));
+}
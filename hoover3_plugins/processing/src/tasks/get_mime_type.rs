@@ -1,6 +1,8 @@
 //! Task definitions for the processing plugin.
 use std::path::PathBuf;
+use std::sync::Mutex;
 
+use anyhow::Context;
 use hoover3_taskdef::anyhow;
 use magic::cookie::Flags;
 
@@ -25,52 +27,112 @@ pub struct MagikaResult {
 }
 
 /// Get the mime type of a file using the magic libraries.
+/// Both run on CPU, in the blocking thread pool.
 pub async fn magic_get_mime_type(path: PathBuf) -> anyhow::Result<MimeTypeResult> {
-    // let magika_result = run_magika(path.clone()).await?;
+    let _path = path.clone();
+    let magika_result = tokio::task::spawn_blocking(move || run_magika(_path)).await??;
     let mime_type = tokio::task::spawn_blocking(move || run_magic(path)).await??;
 
     Ok(MimeTypeResult {
         magic_mime_type: mime_type,
-        magika_result: MagikaResult {
-            magika_ruled_mime_type: None,
+        magika_result,
+    })
+}
+
+lazy_static::lazy_static! {
+    /// Magika sessions are expensive to create, so they are kept for reuse by the next
+    /// processing tasks. A session runs one inference at a time, so each blocking task
+    /// takes one out of the pool, or creates one if none is free.
+    /// The ONNX model is bundled in the `magika` crate.
+    static ref MAGIKA_SESSIONS: Mutex<Vec<magika::Session>> = Mutex::new(Vec::new());
+}
+
+fn run_magika(path: PathBuf) -> anyhow::Result<MagikaResult> {
+    let session = MAGIKA_SESSIONS
+        .lock()
+        .map_err(|e| anyhow::anyhow!("magika session lock poisoned: {e}"))?
+        .pop();
+    let mut session = match session {
+        Some(session) => session,
+        None => magika::Session::new().context("create magika session")?,
+    };
+    let file_type = session.identify_file_sync(&path);
+    MAGIKA_SESSIONS
+        .lock()
+        .map_err(|e| anyhow::anyhow!("magika session lock poisoned: {e}"))?
+        .push(session);
+    let file_type = file_type?;
+
+    let result = match file_type {
+        magika::FileType::Directory => MagikaResult {
+            magika_ruled_mime_type: Some("inode/directory".to_string()),
             magika_inferred_mime_type: None,
             magika_score: None,
         },
-    })
+        magika::FileType::Symlink => MagikaResult {
+            magika_ruled_mime_type: Some("inode/symlink".to_string()),
+            magika_inferred_mime_type: None,
+            magika_score: None,
+        },
+        magika::FileType::Inferred(inferred) => MagikaResult {
+            magika_ruled_mime_type: None,
+            magika_inferred_mime_type: Some(inferred.content_type.info().mime_type.to_string()),
+            magika_score: Some(inferred.score),
+        },
+        magika::FileType::Ruled(ruled) => MagikaResult {
+            magika_ruled_mime_type: Some(ruled.content_type.info().mime_type.to_string()),
+            magika_inferred_mime_type: ruled
+                .overruled
+                .as_ref()
+                .map(|inferred| inferred.content_type.info().mime_type.to_string()),
+            magika_score: ruled.overruled.map(|inferred| inferred.score),
+        },
+    };
+
+    Ok(result)
 }
 
-// async fn run_magika(path: PathBuf) -> anyhow::Result<MagikaResult> {
-//     let cookie = magika::Session::new()?;
-//     let mime_type = cookie.identify_file_async(&path).await?;
-
-//     let mime_type = match mime_type {
-//         magika::FileType::Directory => MagikaResult {
-//             magika_ruled_mime_type: Some("inode/directory".to_string()),
-//             magika_inferred_mime_type: None,
-//             magika_score: None,
-//         },
-//         magika::FileType::Symlink => MagikaResult {
-//             magika_ruled_mime_type: Some("inode/symlink".to_string()),
-//             magika_inferred_mime_type: None,
-//             magika_score: None,
-//         },
-//         magika::FileType::Inferred(inferred) => MagikaResult {
-//             magika_ruled_mime_type: None,
-//             magika_inferred_mime_type: Some(inferred.content_type.info().mime_type.to_string()),
-//             magika_score: Some(inferred.score),
-//         },
-//         magika::FileType::Ruled(ruled) => MagikaResult {
-//             magika_ruled_mime_type: Some(ruled.content_type.info().mime_type.to_string()),
-//             magika_inferred_mime_type: ruled
-//                 .overruled
-//                 .as_ref()
-//                 .map(|inferred| inferred.content_type.info().mime_type.to_string()),
-//             magika_score: ruled.overruled.map(|inferred| inferred.score),
-//         },
-//     };
-
-//     Ok(mime_type)
-// }
+/// Minimum Magika model score for its inferred mime type to be trusted.
+const MAGIKA_MIN_SCORE: f32 = 0.9;
+
+/// Pick the best mime type from all the detections: the first specific one out of
+/// Magika rules, the Magika model (if confident), tika and libmagic.
+/// Parameters like "; charset=binary" are removed.
+pub fn best_mime_type(
+    magic_mime: &str,
+    magika_result: &MagikaResult,
+    tika_mime: Option<&str>,
+) -> String {
+    let confident_magika = magika_result
+        .magika_inferred_mime_type
+        .as_deref()
+        .filter(|_| magika_result.magika_score.unwrap_or_default() >= MAGIKA_MIN_SCORE);
+    [
+        magika_result.magika_ruled_mime_type.as_deref(),
+        confident_magika,
+        tika_mime,
+        Some(magic_mime),
+    ]
+    .into_iter()
+    .flatten()
+    .map(strip_mime_parameters)
+    .find(|m| !is_generic_mime_type(m))
+    .or_else(|| Some(strip_mime_parameters(magic_mime)).filter(|m| !m.is_empty()))
+    .unwrap_or("application/octet-stream")
+    .to_string()
+}
+
+/// Remove the parameters from a mime type, e.g. "text/plain; charset=utf-8" becomes "text/plain".
+pub fn strip_mime_parameters(mime_type: &str) -> &str {
+    mime_type.split(';').next().unwrap_or_default().trim()
+}
+
+fn is_generic_mime_type(mime_type: &str) -> bool {
+    matches!(
+        mime_type,
+        "" | "application/octet-stream" | "text/plain" | "inode/x-empty"
+    )
+}
 
 fn run_magic(path: PathBuf) -> Result<String, anyhow::Error> {
     let cookie = magic::Cookie::open(Flags::ERROR | Flags::MIME_TYPE | Flags::MIME_ENCODING)?;
@@ -101,6 +163,70 @@ mod tests {
             Some("application/pdf".to_string())
         );
         assert!(magic.magika_result.magika_score.unwrap() > 0.9);
+        assert_eq!(
+            best_mime_type(&magic.magic_mime_type, &magic.magika_result, None),
+            "application/pdf"
+        );
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_magika_parallel_sessions() -> anyhow::Result<()> {
+        let data_dir = get_data_root();
+        let path = PathBuf::from(data_dir).join("hoover-testdata/data/no-extension/file_pdf");
+        let results =
+            futures::future::try_join_all((0..4).map(|_| magic_get_mime_type(path.clone())))
+                .await?;
+        for result in results {
+            assert_eq!(
+                result.magika_result.magika_inferred_mime_type,
+                Some("application/pdf".to_string())
+            );
+        }
+        // the sessions go back to the pool after use
+        assert!(!MAGIKA_SESSIONS.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_best_mime_type() {
+        let no_magika = MagikaResult {
+            magika_ruled_mime_type: None,
+            magika_inferred_mime_type: None,
+            magika_score: None,
+        };
+        assert_eq!(
+            best_mime_type("application/zip; charset=binary", &no_magika, None),
+            "application/zip"
+        );
+        assert_eq!(
+            best_mime_type(
+                "application/zip; charset=binary",
+                &no_magika,
+                Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document")
+            ),
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+        );
+        let unsure_magika = MagikaResult {
+            magika_ruled_mime_type: None,
+            magika_inferred_mime_type: Some("text/x-python".to_string()),
+            magika_score: Some(0.5),
+        };
+        assert_eq!(
+            best_mime_type("text/plain; charset=us-ascii", &unsure_magika, Some("")),
+            "text/plain"
+        );
+        let sure_magika = MagikaResult {
+            magika_score: Some(0.99),
+            ..unsure_magika
+        };
+        assert_eq!(
+            best_mime_type("text/plain; charset=us-ascii", &sure_magika, None),
+            "text/x-python"
+        );
+        assert_eq!(
+            best_mime_type("", &no_magika, None),
+            "application/octet-stream"
+        );
+    }
 }
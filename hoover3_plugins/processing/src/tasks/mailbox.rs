@@ -0,0 +1,298 @@
+//! Split mailbox blobs (mbox, Outlook PST) into individual message blobs.
+//! The messages are then processed as emails in the next processing round,
+//! and linked to their mailbox through [crate::models::MailboxToMessage].
+//!
+//! Maildir folders are not blobs; they are detected by the filesystem scanner.
+
+use std::{
+    io::{BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use hoover3_filesystem_scanner::models::FsBlobHashesDbRow;
+use hoover3_taskdef::anyhow;
+use hoover3_tracing::tracing::info;
+use hoover3_types::identifier::CollectionId;
+
+use super::blob_gc::{has_parent_blob, unlink_member_blobs};
+use super::failures::STAGE_UNPACK_MAILBOX;
+use super::unpack_archive::{hash_member_files, list_unpacked_files, UnpackedMember};
+use crate::models::MailboxToMessage;
+use crate::processor::{
+    declare_blob_processor, BlobEdgeRows, BlobProcessor, BlobProcessorInput, BlobProcessorOutput,
+    ProcessorRows,
+};
+
+/// Max run time for the external PST converter.
+const PST_UNPACK_TIMEOUT: Duration = Duration::from_secs(4 * 3600);
+
+/// Mailbox formats that we know how to split into messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailboxType {
+    /// Unix mbox file - messages separated by "From " lines
+    Mbox,
+    /// Outlook PST/OST file
+    Pst,
+}
+
+impl MailboxType {
+    /// Get the mailbox type from the libmagic mime type.
+    /// Returns `None` for formats that are not mailboxes.
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        let mime_type = mime_type.split(';').next().unwrap_or_default().trim();
+        MAILBOX_MIME_TYPES
+            .iter()
+            .find(|(m, _)| *m == mime_type)
+            .map(|(_, mailbox_type)| *mailbox_type)
+    }
+}
+
+/// Mime types of the mailbox formats that we know how to split.
+const MAILBOX_MIME_TYPES: &[(&str, MailboxType)] = &[
+    ("application/mbox", MailboxType::Mbox),
+    ("application/vnd.ms-outlook", MailboxType::Pst),
+    ("application/vnd.ms-outlook-pst", MailboxType::Pst),
+];
+
+lazy_static::lazy_static! {
+    /// The mime types handled by [UnpackMailboxProcessor], from [MAILBOX_MIME_TYPES].
+    static ref MAILBOX_PROCESSOR_MIME_TYPES: Vec<&'static str> =
+        MAILBOX_MIME_TYPES.iter().map(|(m, _)| *m).collect();
+}
+
+/// Processor that splits mailboxes into message blobs,
+/// linked to the mailbox through [MailboxToMessage].
+pub struct UnpackMailboxProcessor;
+
+impl BlobProcessor for UnpackMailboxProcessor {
+    fn name(&self) -> &'static str {
+        STAGE_UNPACK_MAILBOX
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &MAILBOX_PROCESSOR_MIME_TYPES
+    }
+
+    fn process<'a>(
+        &'a self,
+        input: &'a BlobProcessorInput<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<BlobProcessorOutput>> {
+        Box::pin(async move {
+            let Some(mailbox_type) = MailboxType::from_mime_type(input.mime_type) else {
+                return Ok(BlobProcessorOutput::default());
+            };
+            if input.container_depth >= input.max_container_depth {
+                info!(
+                    "Not unpacking mailbox {}: depth {} over limit {}",
+                    input.blob.blob_sha3_256, input.container_depth, input.max_container_depth
+                );
+                return Ok(BlobProcessorOutput::default());
+            }
+            let child_blobs = unpack_mailbox(
+                mailbox_type,
+                input.blob,
+                input.mime_type,
+                input.container_depth,
+                input.file_path.to_path_buf(),
+                input.temp_dir.to_path_buf(),
+            )
+            .await?;
+            Ok(BlobProcessorOutput {
+                child_blobs,
+                ..Default::default()
+            })
+        })
+    }
+
+    fn child_blob_edges(
+        &self,
+        collection_id: &CollectionId,
+        edges: Vec<(String, String)>,
+    ) -> Option<Box<dyn ProcessorRows>> {
+        Some(Box::new(BlobEdgeRows::<MailboxToMessage>::new(
+            collection_id,
+            edges,
+        )))
+    }
+
+    fn has_parent_blob<'a>(
+        &'a self,
+        collection_id: &'a CollectionId,
+        blob_sha3_256: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<bool>> {
+        Box::pin(has_parent_blob::<MailboxToMessage>(
+            collection_id,
+            blob_sha3_256,
+        ))
+    }
+
+    fn unlink_child_blobs<'a>(
+        &'a self,
+        collection_id: &'a CollectionId,
+        blob_sha3_256: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<String>>> {
+        Box::pin(unlink_member_blobs::<MailboxToMessage>(
+            collection_id,
+            blob_sha3_256,
+        ))
+    }
+}
+
+declare_blob_processor!(UnpackMailboxProcessor);
+
+/// Split a mailbox into message files inside the tempdir, and hash them.
+/// `container_depth` is the depth of the mailbox; messages get `container_depth + 1`.
+pub(crate) async fn unpack_mailbox(
+    mailbox_type: MailboxType,
+    mailbox: &FsBlobHashesDbRow,
+    mime_type: &str,
+    container_depth: i32,
+    file_path: PathBuf,
+    temp_dir: PathBuf,
+) -> anyhow::Result<Vec<UnpackedMember>> {
+    let output_dir = temp_dir.join("mailbox");
+    tokio::fs::create_dir_all(&output_dir).await?;
+
+    match mailbox_type {
+        MailboxType::Mbox => {
+            let _output_dir = output_dir.clone();
+            tokio::task::spawn_blocking(move || split_mbox(&file_path, &_output_dir)).await??;
+        }
+        MailboxType::Pst => run_readpst(&file_path, &output_dir).await?,
+    }
+
+    let _output_dir = output_dir.clone();
+    let member_paths =
+        tokio::task::spawn_blocking(move || list_unpacked_files(&_output_dir)).await??;
+    hash_member_files(mailbox, mime_type, container_depth, member_paths).await
+}
+
+/// Convert the PST using `readpst` (from libpst), writing each message as a separate `.eml` file.
+async fn run_readpst(file_path: &Path, output_dir: &Path) -> anyhow::Result<()> {
+    let mut command = tokio::process::Command::new("readpst");
+    command
+        .arg("-e") // one file per message, with .eml extension
+        .arg("-D") // include deleted items
+        .arg("-b") // don't save the RTF body as attachment
+        .arg("-q") // quiet
+        .arg("-o")
+        .arg(output_dir)
+        .arg(file_path)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true);
+    let output = tokio::time::timeout(PST_UNPACK_TIMEOUT, command.output())
+        .await
+        .map_err(|_| anyhow::anyhow!("readpst timed out for {:?}", file_path))??;
+    if !output.status.success() {
+        anyhow::bail!(
+            "readpst failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+/// Split a mbox file into one file per message.
+/// Messages start with a "From " line after an empty line (or at the start of the file);
+/// mboxrd-quoted ">From " lines in the body are un-quoted.
+fn split_mbox(file_path: &Path, output_dir: &Path) -> anyhow::Result<()> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(file_path)?);
+    let mut current: Option<BufWriter<std::fs::File>> = None;
+    let mut message_count = 0;
+    let mut previous_line_blank = true;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if previous_line_blank && line.starts_with(b"From ") {
+            if let Some(mut message) = current.take() {
+                message.flush()?;
+            }
+            let message_path = output_dir.join(format!("message_{:06}.eml", message_count));
+            current = Some(BufWriter::new(std::fs::File::create(message_path)?));
+            message_count += 1;
+            previous_line_blank = false;
+            continue;
+        }
+        previous_line_blank = line == b"\n" || line == b"\r\n";
+        if let Some(message) = current.as_mut() {
+            message.write_all(unquote_mbox_line(&line))?;
+        }
+    }
+    if let Some(mut message) = current.take() {
+        message.flush()?;
+    }
+    Ok(())
+}
+
+fn unquote_mbox_line(line: &[u8]) -> &[u8] {
+    let quote_count = line.iter().take_while(|c| **c == b'>').count();
+    if quote_count > 0 && line[quote_count..].starts_with(b"From ") {
+        &line[1..]
+    } else {
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_mbox() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let mbox_path = temp_dir.path().join("test.mbox");
+        std::fs::write(
+            &mbox_path,
+            "From alice@example.com Tue Oct  1 10:00:00 2024\n\
+             From: alice@example.com\n\
+             Subject: one\n\
+             \n\
+             >From the start.\n\
+             \n\
+             From bob@example.com Tue Oct  1 11:00:00 2024\n\
+             From: bob@example.com\n\
+             Subject: two\n\
+             \n\
+             body line\n\
+             From here, not a separator.\n",
+        )?;
+        let output_dir = temp_dir.path().join("out");
+        std::fs::create_dir_all(&output_dir)?;
+        split_mbox(&mbox_path, &output_dir)?;
+
+        let files = list_unpacked_files(&output_dir)?;
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].0, "message_000000.eml");
+        assert_eq!(
+            std::fs::read_to_string(&files[0].1)?,
+            "From: alice@example.com\nSubject: one\n\nFrom the start.\n\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&files[1].1)?,
+            "From: bob@example.com\nSubject: two\n\nbody line\nFrom here, not a separator.\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mailbox_type_from_mime_type() {
+        assert_eq!(
+            MailboxType::from_mime_type("application/mbox; charset=us-ascii"),
+            Some(MailboxType::Mbox)
+        );
+        assert_eq!(
+            MailboxType::from_mime_type("application/vnd.ms-outlook; charset=binary"),
+            Some(MailboxType::Pst)
+        );
+        assert_eq!(MailboxType::from_mime_type("message/rfc822"), None);
+        for mime_type in UnpackMailboxProcessor.mime_types() {
+            assert!(MailboxType::from_mime_type(mime_type).is_some());
+        }
+    }
+}
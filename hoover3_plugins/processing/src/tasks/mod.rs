@@ -1,16 +1,29 @@
 //! Task definitions for the processing plugin.
 
+pub mod blob_gc;
+pub mod email;
+pub mod failures;
 pub mod get_mime_type;
+pub mod mailbox;
+pub mod ocr;
 mod process_group;
 mod process_page;
-mod tika;
+pub mod sandbox;
+pub mod tika;
+pub mod unpack_archive;
 
+use hoover3_filesystem_scanner::tasks::process_plan::do_compute_blob_processing_plan_activity;
 use hoover3_taskdef::{
     declare_task_queue, workflow, TemporalioActivityDescriptor, TemporalioWorkflowDescriptor,
     WfContext, WfExitValue, WorkflowResult,
 };
-use hoover3_types::{identifier::CollectionId, processing::CollectionProcessingResult};
+use hoover3_types::{
+    identifier::CollectionId,
+    processing::{CollectionProcessingResult, ProcessPageResult},
+};
+use ocr::run_collection_ocr_workflow;
 use process_group::{get_plan_page_ids_activity, process_pages_group_workflow};
+use unpack_archive::ARCHIVE_MAX_DEPTH_LIMIT;
 
 declare_task_queue!(
     ProcessingTasksQueue,
@@ -36,33 +49,71 @@ declare_task_queue!(
     4096  // MB ram worker total
 );
 
-/// Workflow for computing the processing plan for all blobs.
+declare_task_queue!(
+    ProcessingQueueOcr,
+    "processing_ocr",
+    2,    // concurrent workflows
+    4,    // max i/o threads
+    4096  // MB ram worker total
+);
+
+/// Workflow for processing all the planned blobs.
+/// Blobs unpacked from archives are planned into new pages and processed
+/// in the next round, until no new blobs are found.
 #[workflow(ProcessingTasksQueue)]
 async fn run_collection_processing(
     ctx: WfContext,
     collection_id: CollectionId,
 ) -> WorkflowResult<CollectionProcessingResult> {
-    let (small_pages, large_pages) =
-        get_plan_page_ids_activity::run(&ctx, collection_id.clone()).await?;
-    let small_page_cnt = small_pages.len() as i32;
-    let large_page_cnt = large_pages.len() as i32;
+    let mut small_page_cnt = 0;
+    let mut large_page_cnt = 0;
+    let mut small_page_results = ProcessPageResult::default();
+    let mut large_page_results = ProcessPageResult::default();
+
+    // the depth limit is enforced when unpacking; this only bounds the number of rounds.
+    for round in 0..=ARCHIVE_MAX_DEPTH_LIMIT {
+        if round > 0 {
+            let plan =
+                do_compute_blob_processing_plan_activity::run(&ctx, collection_id.clone()).await?;
+            if plan.new_page_count == 0 {
+                break;
+            }
+        }
+        let (small_pages, large_pages) =
+            get_plan_page_ids_activity::run(&ctx, collection_id.clone()).await?;
+        if small_pages.is_empty() && large_pages.is_empty() {
+            break;
+        }
+        small_page_cnt += small_pages.len() as i32;
+        large_page_cnt += large_pages.len() as i32;
+
+        // skip empty groups: their workflow id would be the same in every round
+        if !small_pages.is_empty() {
+            small_page_results += process_pages_group_workflow::run_as_child(
+                &ctx,
+                (collection_id.clone(), small_pages, true),
+            )
+            .await?;
+        }
+        if !large_pages.is_empty() {
+            large_page_results += process_pages_group_workflow::run_as_child(
+                &ctx,
+                (collection_id.clone(), large_pages, false),
+            )
+            .await?;
+        }
+    }
 
-    let _r1 = process_pages_group_workflow::run_as_child(
-        &ctx,
-        (collection_id.clone(), small_pages, true),
-    )
-    .await?;
-    let _r2 = process_pages_group_workflow::run_as_child(
-        &ctx,
-        (collection_id.clone(), large_pages, false),
-    )
-    .await?;
+    // OCR runs last, on its own queue; it does nothing unless enabled for the collection.
+    let ocr_results =
+        run_collection_ocr_workflow::run_as_child(&ctx, collection_id.clone()).await?;
 
     Ok(WfExitValue::Normal(CollectionProcessingResult {
         collection_id,
         small_page_count: small_page_cnt,
         large_page_count: large_page_cnt,
-        small_page_results: _r1,
-        large_page_results: _r2,
+        small_page_results,
+        large_page_results,
+        ocr_results,
     }))
 }
@@ -0,0 +1,249 @@
+//! OCR for scanned PDFs and images, using Tesseract through extractous.
+//! Runs after the regular processing, on its own task queue, so the slow OCR
+//! does not hold up the other processing workers.
+//! The OCR text is stored as [BlobExtractedContentRow] with the "ocr" content source.
+
+use std::path::PathBuf;
+
+use futures::{pin_mut, StreamExt, TryStreamExt};
+use hoover3_database::{
+    charybdis::operations::Find,
+    constants::CQL_SELECT_BATCH_SIZE,
+    db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle},
+    models::collection::DatabaseExtraCallbacks,
+};
+use hoover3_filesystem_scanner::models::{
+    find_fs_blob_mime_type_db_row, BlobProcessingPlan, BlobProcessingPlanPageBlobs,
+    FsBlobMimeTypeDbRow,
+};
+use hoover3_taskdef::{
+    activity, anyhow, workflow, ActivityHeartbeat, TemporalioActivityDescriptor,
+    TemporalioWorkflowDescriptor, WfContext, WfExitValue, WorkflowResult,
+    WORKER_TEMPDIR_ENV_VAR_BIG,
+};
+use hoover3_tracing::tracing::{info, warn};
+use hoover3_types::{identifier::CollectionId, processing::ProcessPageResult};
+
+use super::{
+    failures::{save_stage_failure, ItemFailure, STAGE_OCR},
+    get_mime_type::strip_mime_parameters,
+    process_group::ProcessPageArgs,
+    process_page::{download_item, read_content_rows},
+    tika::extract_ocr_content,
+    ProcessingQueueOcr, ProcessingTasksQueue,
+};
+use crate::{
+    api::get_ocr_settings,
+    models::{BlobExtractedContentRow, BlobProcessingFailureDbRow},
+};
+
+/// The content source for OCR text, in [BlobExtractedContentRow].
+pub const OCR_CONTENT_SOURCE: &str = "ocr";
+
+/// Time between heartbeats while a page is going through OCR.
+const OCR_HEARTBEAT_TICK: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Check if OCR should run on blobs with this mime type.
+pub fn is_ocr_mime_type(mime_type: &str) -> bool {
+    let mime_type = strip_mime_parameters(mime_type);
+    mime_type == "application/pdf" || mime_type.starts_with("image/")
+}
+
+/// Workflow for running OCR on all the processed blobs of a collection.
+/// Does nothing if OCR is not enabled for the collection.
+#[workflow(ProcessingTasksQueue)]
+async fn run_collection_ocr(
+    ctx: WfContext,
+    collection_id: CollectionId,
+) -> WorkflowResult<ProcessPageResult> {
+    let pages = get_ocr_page_ids_activity::run(&ctx, collection_id.clone()).await?;
+    let mut total = ProcessPageResult::default();
+    for chunk in pages.chunks(300) {
+        let args = chunk
+            .iter()
+            .map(|plan_page_id| (collection_id.clone(), *plan_page_id))
+            .collect::<Vec<_>>();
+        for (_arg, res) in ocr_page_workflow::run_parallel(&ctx, args).await? {
+            total += res?;
+        }
+    }
+    Ok(WfExitValue::Normal(total))
+}
+
+/// Activity for fetching the plan pages to OCR.
+/// Returns no pages if OCR is not enabled for the collection.
+#[activity(ProcessingTasksQueue)]
+async fn get_ocr_page_ids(collection_id: CollectionId) -> anyhow::Result<Vec<i32>> {
+    if !get_ocr_settings(collection_id.clone()).await?.enabled {
+        return Ok(vec![]);
+    }
+    let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+    let pages = BlobProcessingPlan::find_all()
+        .execute(&session)
+        .await?
+        .map_ok(|page| page.plan_page_id)
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(pages)
+}
+
+/// Run OCR on one processing plan page.
+#[workflow(ProcessingTasksQueue)]
+async fn ocr_page(
+    ctx: WfContext,
+    (collection_id, plan_page_id): (CollectionId, i32),
+) -> WorkflowResult<ProcessPageResult> {
+    Ok(WfExitValue::Normal(
+        ocr_page_blobs_activity::run(&ctx, (collection_id, plan_page_id)).await?,
+    ))
+}
+
+/// Activity for running OCR on the PDFs and images of a plan page.
+/// Blobs that went through OCR before are skipped; failed blobs are recorded
+/// with the [STAGE_OCR] stage, and run again by [super::failures::retry_failed_blobs_workflow].
+#[activity(ProcessingQueueOcr, retries = 1, timeout = 8 * 3600, heartbeat = 600)]
+async fn ocr_page_blobs(
+    (collection_id, plan_page_id): (CollectionId, i32),
+) -> anyhow::Result<ProcessPageResult> {
+    // OCR on a single blob can take up to an hour, so heartbeat while it runs
+    let heartbeat = ActivityHeartbeat::current();
+    let heartbeat_task = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(OCR_HEARTBEAT_TICK).await;
+            heartbeat.record(&plan_page_id);
+        }
+    });
+    let result = do_ocr_page_blobs(collection_id, plan_page_id).await;
+    heartbeat_task.abort();
+    result
+}
+
+async fn do_ocr_page_blobs(
+    collection_id: CollectionId,
+    plan_page_id: i32,
+) -> anyhow::Result<ProcessPageResult> {
+    info!("OCR {} page: {}", collection_id, plan_page_id);
+    let languages = get_ocr_settings(collection_id.clone())
+        .await?
+        .tesseract_languages();
+    let tempdir = PathBuf::from(std::env::var(WORKER_TEMPDIR_ENV_VAR_BIG)?).canonicalize()?;
+    let tempdir = tempdir
+        .join("ocr_tmp")
+        .join(collection_id.to_string())
+        .join(plan_page_id.to_string());
+    tokio::fs::create_dir_all(&tempdir).await?;
+
+    let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+    let extra = DatabaseExtraCallbacks::new(&collection_id).await?;
+    let args = ProcessPageArgs {
+        collection_id: collection_id.clone(),
+        plan_page_id,
+        page_is_small: false,
+    };
+
+    let mut result = ProcessPageResult::default();
+    let blobs = BlobProcessingPlanPageBlobs::find_by_plan_page_id(plan_page_id)
+        .execute(&session)
+        .await?;
+    pin_mut!(blobs);
+    while let Some(blob) = blobs.next().await {
+        let blob_sha3_256 = blob?.blob_sha3_256;
+        let Some(mut mime_type_row) =
+            FsBlobMimeTypeDbRow::maybe_find_first_by_blob_sha3_256(blob_sha3_256.clone())
+                .execute(&session)
+                .await?
+        else {
+            continue;
+        };
+        let best_mime = mime_type_row
+            .best_mime
+            .clone()
+            .unwrap_or_else(|| mime_type_row.magic_mime.clone());
+        if mime_type_row.ocr_success.is_some() || !is_ocr_mime_type(&best_mime) {
+            continue;
+        }
+
+        result.item_count += 1;
+        let blob_tempdir = tempdir.join(&blob_sha3_256);
+        tokio::fs::create_dir_all(&blob_tempdir).await?;
+        let rows = ocr_blob(&args, &blob_sha3_256, blob_tempdir.clone(), &languages).await;
+        tokio::fs::remove_dir_all(&blob_tempdir).await?;
+        let failure = match rows {
+            Ok(rows) => {
+                extra.insert_rows(&rows, 1).await?;
+                result.item_success += 1;
+                mime_type_row.ocr_success = Some(true);
+                None
+            }
+            Err(e) => {
+                warn!("Error running OCR on {}: {:?}", blob_sha3_256, e);
+                result.item_errors += 1;
+                mime_type_row.ocr_success = Some(false);
+                Some(ItemFailure::new(STAGE_OCR, Some(best_mime.as_str()), &e))
+            }
+        };
+        save_stage_failure(
+            &session,
+            &extra,
+            plan_page_id,
+            &blob_sha3_256,
+            STAGE_OCR,
+            failure,
+        )
+        .await?;
+        extra.insert_rows(&[mime_type_row], 1).await?;
+    }
+
+    tokio::fs::remove_dir_all(&tempdir).await?;
+    info!("OCR {}/{}: done", collection_id, plan_page_id);
+    Ok(result)
+}
+
+/// Activity for clearing the OCR result of the blobs that failed OCR,
+/// so the next OCR run processes them again. Returns the number of blobs cleared.
+#[activity(ProcessingTasksQueue)]
+async fn clear_failed_ocr(collection_id: CollectionId) -> anyhow::Result<i32> {
+    let session = ScyllaDatabaseHandle::collection_session(&collection_id).await?;
+    let extra = DatabaseExtraCallbacks::new(&collection_id).await?;
+    let blobs = BlobProcessingFailureDbRow::find_all()
+        .execute(&session)
+        .await?
+        .try_filter(|row| std::future::ready(row.stage == STAGE_OCR))
+        .map_ok(|row| row.blob_sha3_256)
+        .try_collect::<Vec<_>>()
+        .await?;
+    for chunk in blobs.chunks(CQL_SELECT_BATCH_SIZE) {
+        let mut rows = find_fs_blob_mime_type_db_row!("blob_sha3_256 IN ?", (chunk.to_vec(),))
+            .execute(&session)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        for row in rows.iter_mut() {
+            row.ocr_success = None;
+        }
+        extra.insert_rows(&rows, 256).await?;
+    }
+    Ok(blobs.len() as i32)
+}
+
+async fn ocr_blob(
+    args: &ProcessPageArgs,
+    blob_sha3_256: &str,
+    tempdir: PathBuf,
+    languages: &str,
+) -> anyhow::Result<Vec<BlobExtractedContentRow>> {
+    let item = download_item(args.clone(), blob_sha3_256.to_string(), tempdir.clone()).await?;
+    let content_path =
+        extract_ocr_content(item.file_path.clone(), tempdir, languages.to_string()).await?;
+    read_content_rows(content_path, blob_sha3_256.to_string(), OCR_CONTENT_SOURCE)
+        .try_collect()
+        .await
+}
+
+#[test]
+fn test_is_ocr_mime_type() {
+    assert!(is_ocr_mime_type("application/pdf"));
+    assert!(is_ocr_mime_type("image/tiff"));
+    assert!(is_ocr_mime_type("application/pdf; charset=binary"));
+    assert!(!is_ocr_mime_type("text/plain"));
+}
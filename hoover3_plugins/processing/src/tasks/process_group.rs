@@ -16,7 +16,7 @@ use super::{
     ProcessingTasksQueue,
 };
 
-const SMALL_THRESHOLD: i64 = 100 * 1024 * 1024; // 100MB
+pub(super) const SMALL_THRESHOLD: i64 = 100 * 1024 * 1024; // 100MB
 
 /// Activity for fetching the plan pages for a collection.
 #[activity(ProcessingTasksQueue)]
@@ -84,10 +84,7 @@ async fn process_pages_group(
 
         let mut total = ProcessPageResult::default();
         for (_arg, res) in process_page_one_workflow::run_parallel(&ctx, args).await? {
-            let _res = res?;
-            total.item_count += _res.item_count;
-            total.item_success += _res.item_success;
-            total.item_errors += _res.item_errors;
+            total += res?;
         }
         Ok(WfExitValue::Normal(total))
     } else {
@@ -97,10 +94,7 @@ async fn process_pages_group(
             .collect::<Vec<_>>();
         let mut total = ProcessPageResult::default();
         for (_arg, res) in process_pages_group_workflow::run_parallel(&ctx, chunks).await? {
-            let _res = res?;
-            total.item_count += _res.item_count;
-            total.item_success += _res.item_success;
-            total.item_errors += _res.item_errors;
+            total += res?;
         }
         Ok(WfExitValue::Normal(total))
     }
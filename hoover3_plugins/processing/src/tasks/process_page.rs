@@ -1,50 +1,129 @@
-use std::{path::PathBuf, time::Instant};
+use std::{
+    any::TypeId,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
-use charybdis::batch::ModelBatch;
 use futures::{pin_mut, Stream, StreamExt};
 use hoover3_data_access::list_disk::read_file_to_stream;
 use hoover3_database::{
     charybdis::operations::Update,
-    db_management::{DatabaseSpaceManager, ScyllaDatabaseHandle},
+    db_management::{DatabaseSpaceManager, S3DatabaseHandle, ScyllaDatabaseHandle},
     models::collection::DatabaseExtraCallbacks,
 };
-use hoover3_filesystem_scanner::models::{
-    BlobProcessingPlan, BlobProcessingPlanPageBlobs, FsBlobHashesDbRow, FsBlobMimeTypeDbRow,
+use hoover3_filesystem_scanner::{
+    models::{
+        find_blob_processing_plan_page_blobs, BlobProcessingPlan, FsBlobHashesDbRow,
+        FsBlobMimeTypeDbRow,
+    },
+    tasks::hash_files::filter_out_existing_hashes,
+};
+use hoover3_taskdef::{
+    activity, anyhow, ActivityHeartbeat, WORKER_TEMPDIR_ENV_VAR_BIG, WORKER_TEMPDIR_ENV_VAR_SMALL,
 };
-use hoover3_taskdef::{activity, anyhow, WORKER_TEMPDIR_ENV_VAR_BIG, WORKER_TEMPDIR_ENV_VAR_SMALL};
 use hoover3_tracing::tracing::{info, warn};
 use hoover3_types::{
     identifier::{CollectionId, DatabaseIdentifier},
     processing::ProcessPageResult,
 };
+use serde::{Deserialize, Serialize};
 use tokio::io::AsyncWriteExt;
 
 use crate::{
-    models::{BlobExtractedContentRow, BlobExtractedMetadataRow},
+    models::{
+        BlobContainerMemberDbRow, BlobExtractedContentRow, BlobExtractedMetadataRow,
+        BlobObjectStoreDbRow, BlobProcessorStatusDbRow,
+    },
+    processor::{
+        sorted_blob_processors, BlobProcessor, BlobProcessorInput, BlobProcessorOutput,
+        ProcessorRows,
+    },
     utf8_utils::read_utf8_file_paragraphs,
 };
 
 use super::{
-    get_mime_type::magic_get_mime_type, process_group::ProcessPageArgs, ProcessingQueueBigPage,
-    ProcessingQueueSmallPage,
+    failures::{
+        processor_failure, save_item_failures, ItemFailure, RetryPageBlobsArgs, STAGE_DOWNLOAD,
+        STAGE_MIME_TYPE, STAGE_TIKA,
+    },
+    get_mime_type::{best_mime_type, magic_get_mime_type},
+    process_group::ProcessPageArgs,
+    unpack_archive::{archive_max_depth, UnpackedMember},
+    ProcessingQueueBigPage, ProcessingQueueSmallPage,
 };
 
+/// Number of items processed between writing the results and recording a checkpoint.
+const PROCESS_PAGE_SAVE_EVERY: i32 = 100;
+
+/// Time between heartbeats while an item is being processed, well under the heartbeat timeouts,
+/// since a single big item (e.g. OCR of a long scan) can take longer than them.
+const PROCESS_PAGE_HEARTBEAT_TICK: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Processor rows of one type are written when this many are waiting.
+const PROCESSOR_ROWS_WRITE_EVERY: usize = 300;
+
+/// Progress of a page, sent with the activity heartbeats.
+/// A retried activity continues after the last blob saved by the previous attempt.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+struct ProcessPageCheckpoint {
+    /// The last blob whose results were written to the database.
+    /// Blobs are processed in plan page order, so all the blobs before it are done too.
+    last_saved_blob_sha3_256: Option<String>,
+    /// The counts for the blobs up to and including the last saved one.
+    result: ProcessPageResult,
+}
+
 /// Activity for processing a page.
-#[activity(ProcessingQueueSmallPage)]
+#[activity(ProcessingQueueSmallPage, retries = 2, timeout = 3600, heartbeat = 600)]
 async fn process_small_page(_args: ProcessPageArgs) -> anyhow::Result<ProcessPageResult> {
     process_page(_args).await
 }
 
 /// Activity for processing a big page.
-#[activity(ProcessingQueueBigPage)]
+#[activity(ProcessingQueueBigPage, retries = 1, timeout = 6 * 3600, heartbeat = 1800)]
 async fn process_big_page(_args: ProcessPageArgs) -> anyhow::Result<ProcessPageResult> {
     process_page(_args).await
 }
 
+/// Activity for processing the failed blobs of a page again.
+#[activity(ProcessingQueueSmallPage, retries = 2, timeout = 3600, heartbeat = 600)]
+async fn retry_small_page_blobs(args: RetryPageBlobsArgs) -> anyhow::Result<ProcessPageResult> {
+    process_page_blobs(args.page, PageBlobs::Only(args.blobs)).await
+}
+
+/// Activity for processing the failed blobs of a big page again.
+#[activity(ProcessingQueueBigPage, retries = 1, timeout = 6 * 3600, heartbeat = 1800)]
+async fn retry_big_page_blobs(args: RetryPageBlobsArgs) -> anyhow::Result<ProcessPageResult> {
+    process_page_blobs(args.page, PageBlobs::Only(args.blobs)).await
+}
+
+/// The blobs of a plan page to process.
+enum PageBlobs {
+    /// All the blobs in the page.
+    All,
+    /// Only the given blobs, sorted - used for processing failed blobs again.
+    Only(Vec<String>),
+}
+
 async fn process_page(args: ProcessPageArgs) -> anyhow::Result<ProcessPageResult> {
+    let session = ScyllaDatabaseHandle::collection_session(&args.collection_id).await?;
+    let mut plan = BlobProcessingPlan::find_by_plan_page_id(args.plan_page_id)
+        .execute(&session)
+        .await?;
+    plan.is_started = true;
+    plan.update().execute(&session).await?;
+    process_page_blobs(args, PageBlobs::All).await
+}
+
+async fn process_page_blobs(
+    args: ProcessPageArgs,
+    blobs: PageBlobs,
+) -> anyhow::Result<ProcessPageResult> {
+    let is_retry = matches!(blobs, PageBlobs::Only(_));
     info!(
-        "Processing {} page: {}",
-        args.collection_id, args.plan_page_id
+        "Processing {} page: {} (retry: {})",
+        args.collection_id, args.plan_page_id, is_retry
     );
     let tempdir_env = match args.page_is_small {
         true => WORKER_TEMPDIR_ENV_VAR_SMALL,
@@ -52,36 +131,68 @@ async fn process_page(args: ProcessPageArgs) -> anyhow::Result<ProcessPageResult
     };
     let tempdir = std::env::var(tempdir_env)?;
     let tempdir = PathBuf::from(tempdir).canonicalize()?;
+    // the retries of one page are split in chunks that run in parallel,
+    // so each chunk has its own dir, named after its first blob
+    let page_dir = match &blobs {
+        PageBlobs::All => args.plan_page_id.to_string(),
+        PageBlobs::Only(blobs) => format!(
+            "{}_{}",
+            args.plan_page_id,
+            blobs.first().map(String::as_str).unwrap_or_default()
+        ),
+    };
     let tempdir = tempdir
-        .join("processing_tmp")
+        .join(match is_retry {
+            true => "processing_retry_tmp",
+            false => "processing_tmp",
+        })
         .join(args.collection_id.to_string())
-        .join(args.plan_page_id.to_string());
+        .join(page_dir);
     tokio::fs::create_dir_all(&tempdir).await?;
 
     let session = ScyllaDatabaseHandle::collection_session(&args.collection_id).await?;
-    let mut plan = BlobProcessingPlan::find_by_plan_page_id(args.plan_page_id)
-        .execute(&session)
-        .await?;
-    plan.is_started = true;
-    plan.update().execute(&session).await?;
+
+    // the pipeline runs on spawned tasks, so take the activity handle with us
+    let heartbeat = ActivityHeartbeat::current();
+    let checkpoint = heartbeat
+        .last_details::<ProcessPageCheckpoint>()
+        .unwrap_or_default();
+    if let Some(last_saved) = &checkpoint.last_saved_blob_sha3_256 {
+        info!(
+            "Processing {} page {}: resuming after blob {}, {} items done",
+            args.collection_id, args.plan_page_id, last_saved, checkpoint.result.item_count
+        );
+    }
 
     let (model_tx, mut model_rx) = tokio::sync::mpsc::channel(16);
     let _tempdir = tempdir.clone();
     let _args = args.clone();
+    let start_after = checkpoint
+        .last_saved_blob_sha3_256
+        .clone()
+        .unwrap_or_default();
     let _model_reader_task = async move {
-        let model_stream = BlobProcessingPlanPageBlobs::find_by_plan_page_id(_args.plan_page_id)
-            .execute(&session)
-            .await?;
-        pin_mut!(model_stream);
-        while let Some(model) = model_stream.next().await {
-            let model = model?;
-            let blob_sha3_256 = model.blob_sha3_256.clone();
-            let segment1 = &blob_sha3_256[0..3];
-            let segment2 = &blob_sha3_256[3..6];
-            let tempdir = _tempdir.join(segment1).join(segment2).join(&blob_sha3_256);
-            tokio::fs::create_dir_all(&tempdir).await?;
-            let tempdir = tokio::fs::canonicalize(&tempdir).await?;
-            model_tx.send((model, tempdir)).await?;
+        match blobs {
+            PageBlobs::All => {
+                let model_stream = find_blob_processing_plan_page_blobs!(
+                    "plan_page_id = ? AND blob_sha3_256 > ?",
+                    (_args.plan_page_id, start_after)
+                )
+                .execute(&session)
+                .await?;
+                pin_mut!(model_stream);
+                while let Some(model) = model_stream.next().await {
+                    let model = model?;
+                    let tempdir = make_blob_tempdir(&_tempdir, &model.blob_sha3_256).await?;
+                    model_tx.send((model.blob_sha3_256, tempdir)).await?;
+                }
+            }
+            PageBlobs::Only(blobs) => {
+                for blob_sha3_256 in blobs.into_iter().filter(|b| *b > start_after) {
+                    let tempdir = make_blob_tempdir(&_tempdir, &blob_sha3_256).await?;
+                    model_tx.send((blob_sha3_256, tempdir)).await?;
+                }
+            }
         }
         drop(model_tx);
         anyhow::Ok(())
@@ -90,52 +201,99 @@ async fn process_page(args: ProcessPageArgs) -> anyhow::Result<ProcessPageResult
     let (download_tx, mut download_rx) = tokio::sync::mpsc::channel(2);
     let _args = args.clone();
     let _download_task = async move {
-        while let Some((model, tempdir)) = model_rx.recv().await {
-            let filepath =
-                download_item(_args.clone(), model.blob_sha3_256.clone(), tempdir.clone()).await?;
-            download_tx.send((model, tempdir, filepath)).await?;
+        while let Some((blob_sha3_256, tempdir)) = model_rx.recv().await {
+            let item = download_item(_args.clone(), blob_sha3_256.clone(), tempdir.clone())
+                .await
+                .map_err(|e| ItemFailure::new(STAGE_DOWNLOAD, None, &e));
+            download_tx.send((blob_sha3_256, tempdir, item)).await?;
         }
         drop(download_tx);
         anyhow::Ok(())
     };
 
     let (item_result_tx, mut item_result_rx) = tokio::sync::mpsc::channel(2);
+    let max_container_depth = archive_max_depth();
+    let processors = sorted_blob_processors()?;
+    let collection_id = args.collection_id.clone();
     let _item_process_task = async move {
-        while let Some((model, tempdir, filepath)) = download_rx.recv().await {
-            let r = process_item(
-                model.blob_sha3_256.clone(),
-                filepath.clone(),
-                tempdir.clone(),
-            )
-            .await;
-            item_result_tx.send((r, tempdir)).await?;
-            tokio::fs::remove_file(&filepath).await?;
+        while let Some((blob_sha3_256, tempdir, item)) = download_rx.recv().await {
+            let r = match item {
+                Ok(item) => {
+                    let filepath = item.file_path.clone();
+                    let r = process_item(
+                        &collection_id,
+                        &processors,
+                        item,
+                        tempdir.clone(),
+                        max_container_depth,
+                    )
+                    .await;
+                    tokio::fs::remove_file(&filepath).await?;
+                    r
+                }
+                Err(failure) => Err(failure),
+            };
+            item_result_tx.send((blob_sha3_256, r, tempdir)).await?;
         }
         drop(item_result_tx);
         anyhow::Ok(())
     };
 
+    // keep sending the last checkpoint while an item is in flight
+    let (checkpoint_tx, mut checkpoint_rx) = tokio::sync::watch::channel(checkpoint.clone());
+    let _heartbeat = heartbeat.clone();
+    let _heartbeat_task = async move {
+        loop {
+            tokio::time::sleep(PROCESS_PAGE_HEARTBEAT_TICK).await;
+            // the save task is done once it drops the sender
+            if checkpoint_rx.has_changed().is_err() {
+                break;
+            }
+            _heartbeat.record(&*checkpoint_rx.borrow_and_update());
+        }
+    };
+
     let _args = args.clone();
     let _item_save_task = async move {
         let session = ScyllaDatabaseHandle::collection_session(&_args.collection_id).await?;
         let extra = DatabaseExtraCallbacks::new(&_args.collection_id).await?;
-        let mut process_page_results = ProcessPageResult::default();
-        let mut batches =
-            ProcessItemsWriteBatches::new(&_args.collection_id, session, extra).await?;
+        let mut checkpoint = checkpoint;
+        let mut process_page_results = checkpoint.result;
+        let mut batches = ProcessItemsWriteBatches::new(&_args, is_retry, session, extra).await?;
 
-        while let Some((r, tempdir)) = item_result_rx.recv().await {
+        while let Some((blob_sha3_256, r, tempdir)) = item_result_rx.recv().await {
             process_page_results.item_count += 1;
             match r {
                 Ok(_r) => {
                     process_page_results.item_success += 1;
                     batches.accept(_r).await?;
                 }
-                Err(_e) => {
-                    warn!("Error processing item: {:?}", _e);
+                Err(failure) => {
+                    warn!("Error processing item {}: {:?}", blob_sha3_256, failure);
                     process_page_results.item_errors += 1;
+                    batches.accept_failures(blob_sha3_256.clone(), vec![failure]);
                 }
             }
             tokio::fs::remove_dir_all(&tempdir).await?;
+
+            let unsaved = process_page_results.item_count - checkpoint.result.item_count;
+            if unsaved >= PROCESS_PAGE_SAVE_EVERY {
+                batches.finalize().await?;
+                checkpoint = ProcessPageCheckpoint {
+                    last_saved_blob_sha3_256: Some(blob_sha3_256),
+                    result: process_page_results,
+                };
+                // stop on a checkpoint, so the results written so far match the checkpoint
+                if heartbeat.is_cancelled() {
+                    anyhow::bail!(
+                        "processing {}/{} cancelled",
+                        _args.collection_id,
+                        _args.plan_page_id
+                    );
+                }
+            }
+            heartbeat.record(&checkpoint);
+            checkpoint_tx.send_replace(checkpoint.clone());
         }
         batches.finalize().await?;
         anyhow::Ok(process_page_results)
@@ -145,11 +303,14 @@ async fn process_page(args: ProcessPageArgs) -> anyhow::Result<ProcessPageResult
     let _download_task = tokio::spawn(_download_task);
     let _item_process_task = tokio::spawn(_item_process_task);
     let _item_save_task = tokio::spawn(_item_save_task);
+    let _heartbeat_task = tokio::spawn(_heartbeat_task);
 
     let _ = _model_reader_task.await?;
     let _ = _download_task.await?;
     let _ = _item_process_task.await?;
-    let process_page_results = _item_save_task.await??;
+    let process_page_results = _item_save_task.await;
+    _heartbeat_task.abort();
+    let process_page_results = process_page_results??;
 
     tokio::fs::remove_dir_all(&tempdir).await?;
     info!(
@@ -159,28 +320,61 @@ async fn process_page(args: ProcessPageArgs) -> anyhow::Result<ProcessPageResult
     Ok(process_page_results)
 }
 
+/// Create the tempdir for a blob, inside the page tempdir.
+async fn make_blob_tempdir(page_tempdir: &Path, blob_sha3_256: &str) -> anyhow::Result<PathBuf> {
+    let segment1 = &blob_sha3_256[0..3];
+    let segment2 = &blob_sha3_256[3..6];
+    let tempdir = page_tempdir
+        .join(segment1)
+        .join(segment2)
+        .join(blob_sha3_256);
+    tokio::fs::create_dir_all(&tempdir).await?;
+    Ok(tokio::fs::canonicalize(&tempdir).await?)
+}
+
 struct ProcessItemsWriteBatches {
     collection_id: CollectionId,
+    plan_page_id: i32,
+    is_retry: bool,
+    item_failures: Vec<(String, Vec<ItemFailure>)>,
     mime_type_rows: Vec<FsBlobMimeTypeDbRow>,
-    tika_meta_rows: Vec<BlobExtractedMetadataRow>,
-    tika_content_rows: Vec<BlobExtractedContentRow>,
-    tika_content_total_size: i32,
+    processor_status_rows: Vec<BlobProcessorStatusDbRow>,
+    meta_rows: Vec<BlobExtractedMetadataRow>,
+    content_rows: Vec<BlobExtractedContentRow>,
+    content_total_size: i32,
+    member_hashes_rows: Vec<FsBlobHashesDbRow>,
+    member_object_store_rows: Vec<BlobObjectStoreDbRow>,
+    member_rows: Vec<BlobContainerMemberDbRow>,
+    member_hashes_pending: HashSet<String>,
+    /// Other rows produced by the processors, by row type.
+    processor_rows: HashMap<TypeId, Box<dyn ProcessorRows>>,
     extra: DatabaseExtraCallbacks,
     session: std::sync::Arc<ScyllaDatabaseHandle>,
 }
 
 impl ProcessItemsWriteBatches {
     async fn new(
-        collection_id: &CollectionId,
+        args: &ProcessPageArgs,
+        is_retry: bool,
         session: std::sync::Arc<ScyllaDatabaseHandle>,
         extra: DatabaseExtraCallbacks,
     ) -> anyhow::Result<Self> {
+        let collection_id = &args.collection_id;
         Ok(Self {
             collection_id: collection_id.clone(),
+            plan_page_id: args.plan_page_id,
+            is_retry,
+            item_failures: vec![],
             mime_type_rows: vec![],
-            tika_meta_rows: vec![],
-            tika_content_rows: vec![],
-            tika_content_total_size: 0,
+            processor_status_rows: vec![],
+            meta_rows: vec![],
+            content_rows: vec![],
+            content_total_size: 0,
+            member_hashes_rows: vec![],
+            member_object_store_rows: vec![],
+            member_rows: vec![],
+            member_hashes_pending: HashSet::new(),
+            processor_rows: HashMap::new(),
             extra,
             session,
         })
@@ -191,8 +385,21 @@ impl ProcessItemsWriteBatches {
             self.collection_id
         );
         self.write_mime_type_rows().await?;
-        self.write_tika_meta_rows().await?;
-        self.write_tika_content_rows().await?;
+        self.write_processor_status_rows().await?;
+        self.write_meta_rows().await?;
+        self.write_content_rows().await?;
+        self.write_member_rows().await?;
+        for rows in self.processor_rows.values_mut() {
+            rows.write(&self.session, &self.extra).await?;
+        }
+        save_item_failures(
+            &self.session,
+            &self.extra,
+            self.plan_page_id,
+            std::mem::take(&mut self.item_failures),
+            self.is_retry,
+        )
+        .await?;
         anyhow::Ok(())
     }
 
@@ -206,82 +413,226 @@ impl ProcessItemsWriteBatches {
             self.mime_type_rows.len(),
             self.collection_id
         );
-        let mut batch = FsBlobMimeTypeDbRow::batch();
-        batch.append_inserts(&self.mime_type_rows);
-        batch.execute(&self.session).await?;
-        self.extra.insert(&self.mime_type_rows).await?;
+        self.extra
+            .insert_rows(&self.mime_type_rows, self.mime_type_rows.len())
+            .await?;
         self.mime_type_rows.clear();
         info!("ProcessItemsWriteBatches: write_mime_type_rows: {} items, collection_id: {}, time: {:?}",self.mime_type_rows.len(), self.collection_id, t0.elapsed());
         anyhow::Ok(())
     }
-    async fn write_tika_meta_rows(&mut self) -> anyhow::Result<()> {
-        if self.tika_meta_rows.is_empty() {
+    async fn write_processor_status_rows(&mut self) -> anyhow::Result<()> {
+        if self.processor_status_rows.is_empty() {
+            return anyhow::Ok(());
+        }
+        self.extra
+            .insert_rows(&self.processor_status_rows, 500)
+            .await?;
+        self.processor_status_rows.clear();
+        anyhow::Ok(())
+    }
+    async fn write_meta_rows(&mut self) -> anyhow::Result<()> {
+        if self.meta_rows.is_empty() {
             return anyhow::Ok(());
         }
         let t0 = Instant::now();
         info!(
-            "ProcessItemsWriteBatches: write_tika_meta_rows: {} items, collection_id: {}",
-            self.tika_meta_rows.len(),
+            "ProcessItemsWriteBatches: write_meta_rows: {} items, collection_id: {}",
+            self.meta_rows.len(),
             self.collection_id
         );
-        let mut batch = BlobExtractedMetadataRow::batch();
-        batch.append_inserts(&self.tika_meta_rows);
-        batch.execute(&self.session).await?;
-        self.extra.insert(&self.tika_meta_rows).await?;
-        self.tika_meta_rows.clear();
-        info!("ProcessItemsWriteBatches: write_tika_meta_rows: {} items, collection_id: {}, time: {:?}",self.tika_meta_rows.len(), self.collection_id, t0.elapsed());
+        self.extra
+            .insert_rows(&self.meta_rows, self.meta_rows.len())
+            .await?;
+        self.meta_rows.clear();
+        info!(
+            "ProcessItemsWriteBatches: write_meta_rows: {} items, collection_id: {}, time: {:?}",
+            self.meta_rows.len(),
+            self.collection_id,
+            t0.elapsed()
+        );
         anyhow::Ok(())
     }
-    async fn write_tika_content_rows(&mut self) -> anyhow::Result<()> {
-        if self.tika_content_rows.is_empty() {
+    async fn write_content_rows(&mut self) -> anyhow::Result<()> {
+        if self.content_rows.is_empty() {
             return anyhow::Ok(());
         }
         let t0 = Instant::now();
         info!(
-            "ProcessItemsWriteBatches: write_tika_content_rows: {} items, collection_id: {}",
-            self.tika_content_rows.len(),
+            "ProcessItemsWriteBatches: write_content_rows: {} items, collection_id: {}",
+            self.content_rows.len(),
             self.collection_id
         );
         info!(
             "batch size: {} rows = {} bytes",
-            self.tika_content_rows.len(),
-            self.tika_content_total_size
+            self.content_rows.len(),
+            self.content_total_size
+        );
+        self.extra.insert_rows(&self.content_rows, 1).await?;
+        self.content_rows.clear();
+        self.content_total_size = 0;
+        info!(
+            "ProcessItemsWriteBatches: write_content_rows: {} items, collection_id: {}, time: {:?}",
+            self.content_rows.len(),
+            self.collection_id,
+            t0.elapsed()
+        );
+        anyhow::Ok(())
+    }
+
+    async fn write_member_rows(&mut self) -> anyhow::Result<()> {
+        if self.member_rows.is_empty() {
+            return anyhow::Ok(());
+        }
+        let t0 = Instant::now();
+        info!(
+            "ProcessItemsWriteBatches: write_member_rows: {} items, {} new blobs, collection_id: {}",
+            self.member_rows.len(),
+            self.member_hashes_rows.len(),
+            self.collection_id
         );
-        let batch = BlobExtractedContentRow::batch();
-        batch
-            .chunked_insert(&self.session, &self.tika_content_rows, 1)
+        // the object store rows go in first, so that the new blobs can be downloaded
+        // as soon as they are visible in the hashes table.
+        self.extra
+            .insert_rows(
+                &self.member_object_store_rows,
+                self.member_object_store_rows.len(),
+            )
+            .await?;
+        self.extra
+            .insert_rows(&self.member_hashes_rows, self.member_hashes_rows.len())
+            .await?;
+        self.extra
+            .insert_rows(&self.member_rows, self.member_rows.len())
             .await?;
-        self.extra.insert(&self.tika_content_rows).await?;
-        self.tika_content_rows.clear();
-        self.tika_content_total_size = 0;
-        info!("ProcessItemsWriteBatches: write_tika_content_rows: {} items, collection_id: {}, time: {:?}",self.tika_content_rows.len(), self.collection_id, t0.elapsed());
+        self.member_object_store_rows.clear();
+        self.member_hashes_rows.clear();
+        self.member_rows.clear();
+        self.member_hashes_pending.clear();
+        info!(
+            "ProcessItemsWriteBatches: write_member_rows: collection_id: {}, time: {:?}",
+            self.collection_id,
+            t0.elapsed()
+        );
+        anyhow::Ok(())
+    }
+
+    /// Upload the new child blobs into the object store, and queue their rows.
+    /// Children that are already known in the collection are only linked to the parent.
+    /// Returns the (parent, child) blob hash pairs, to be saved as graph edges.
+    async fn accept_child_blobs(
+        &mut self,
+        members: Vec<UnpackedMember>,
+    ) -> anyhow::Result<Vec<(String, String)>> {
+        if members.is_empty() {
+            return anyhow::Ok(vec![]);
+        }
+        let new_hashes = filter_out_existing_hashes(
+            &self.session,
+            members.iter().map(|m| m.hashes_row.clone()).collect(),
+        )
+        .await?
+        .into_iter()
+        .map(|r| r.blob_sha3_256)
+        .collect::<HashSet<_>>();
+        let s3 = S3DatabaseHandle::collection_session(&self.collection_id).await?;
+        let mut edges = vec![];
+        for member in members {
+            let blob_sha3_256 = member.hashes_row.blob_sha3_256.clone();
+            if new_hashes.contains(&blob_sha3_256)
+                && self.member_hashes_pending.insert(blob_sha3_256.clone())
+            {
+                s3.put_blob_file(&self.collection_id, &blob_sha3_256, &member.local_path)
+                    .await?;
+                self.member_hashes_rows.push(member.hashes_row);
+                self.member_object_store_rows.push(member.object_store_row);
+            }
+            edges.push((
+                member.member_row.container_blob_sha3_256.clone(),
+                blob_sha3_256,
+            ));
+            self.member_rows.push(member.member_row);
+        }
+        if self.member_rows.len() >= 300 {
+            self.write_member_rows().await?;
+        }
+        anyhow::Ok(edges)
+    }
+
+    /// Queue the failed stages of a blob. On a retry, blobs without failures are queued too,
+    /// so their old failure rows get removed.
+    fn accept_failures(&mut self, blob_sha3_256: String, failures: Vec<ItemFailure>) {
+        if !failures.is_empty() || self.is_retry {
+            self.item_failures.push((blob_sha3_256, failures));
+        }
+    }
+
+    /// Queue rows produced by a processor, together with the rows of the same type.
+    async fn accept_processor_rows(&mut self, rows: Box<dyn ProcessorRows>) -> anyhow::Result<()> {
+        let queued = match self.processor_rows.entry(rows.rows_type_id()) {
+            Entry::Occupied(queued) => {
+                let queued = queued.into_mut();
+                queued.append(rows.into_any());
+                queued
+            }
+            Entry::Vacant(entry) => entry.insert(rows),
+        };
+        if queued.len() >= PROCESSOR_ROWS_WRITE_EVERY {
+            queued.write(&self.session, &self.extra).await?;
+        }
         anyhow::Ok(())
     }
 
-    async fn accept(&mut self, item: ProcessItemResultRows) -> anyhow::Result<()> {
+    async fn accept(&mut self, mut item: ProcessItemResultRows) -> anyhow::Result<()> {
+        let failures = std::mem::take(&mut item.failures);
+        self.accept_failures(item.blob_sha3_256.clone(), failures);
+        self.processor_status_rows
+            .extend(std::mem::take(&mut item.processor_status_rows));
         self.mime_type_rows.push(item.mime_type_row);
         if self.mime_type_rows.len() >= 500 {
             self.write_mime_type_rows().await?;
         }
-        self.tika_meta_rows.extend(item.tika_meta_rows);
-        if self.tika_meta_rows.len() >= 300 {
-            self.write_tika_meta_rows().await?;
-        }
-        if let Some(tika_content_path) = item.tika_content_path {
-            let paragraphs =
-                read_tika_content_rows(tika_content_path.clone(), item.blob_sha3_256.clone());
-            pin_mut!(paragraphs);
-            while let Some(row) = paragraphs.next().await {
-                let row = row?;
-                self.tika_content_total_size += row.content_length + 1024;
-                self.tika_content_rows.push(row);
-                if self.tika_content_rows.len() >= 100
-                    || self.tika_content_total_size >= 50 * 1024 * 1024
-                {
-                    self.write_tika_content_rows().await?;
+        for (processor, output) in item.outputs {
+            let edges = self.accept_child_blobs(output.child_blobs).await?;
+            if !edges.is_empty() {
+                if let Some(rows) = processor.child_blob_edges(&self.collection_id, edges) {
+                    self.accept_processor_rows(rows).await?;
+                }
+            }
+            for rows in output.rows {
+                self.accept_processor_rows(rows).await?;
+            }
+            for (key, values) in output.metadata {
+                for (i, value) in values.into_iter().enumerate() {
+                    self.meta_rows.push(BlobExtractedMetadataRow {
+                        blob_sha3_256: item.blob_sha3_256.clone(),
+                        meta_provider: processor.name().to_string(),
+                        meta_key: key.clone(),
+                        list_index: i as i32,
+                        value,
+                    });
                 }
             }
-            tokio::fs::remove_file(tika_content_path).await?;
+            if self.meta_rows.len() >= 300 {
+                self.write_meta_rows().await?;
+            }
+            if let Some(content_path) = output.content_path {
+                let paragraphs = read_content_rows(
+                    content_path.clone(),
+                    item.blob_sha3_256.clone(),
+                    processor.name(),
+                );
+                pin_mut!(paragraphs);
+                while let Some(row) = paragraphs.next().await {
+                    let row = row?;
+                    self.content_total_size += row.content_length + 1024;
+                    self.content_rows.push(row);
+                    if self.content_rows.len() >= 100 || self.content_total_size >= 50 * 1024 * 1024
+                    {
+                        self.write_content_rows().await?;
+                    }
+                }
+                tokio::fs::remove_file(content_path).await?;
+            }
         }
         anyhow::Ok(())
     }
@@ -289,25 +640,57 @@ impl ProcessItemsWriteBatches {
 struct ProcessItemResultRows {
     blob_sha3_256: String,
     mime_type_row: FsBlobMimeTypeDbRow,
-    tika_meta_rows: Vec<BlobExtractedMetadataRow>,
-    tika_content_path: Option<PathBuf>,
+    /// Outputs of the processors that ran on the blob, in the order they ran.
+    outputs: Vec<(&'static dyn BlobProcessor, BlobProcessorOutput)>,
+    processor_status_rows: Vec<BlobProcessorStatusDbRow>,
+    /// Stages that failed without failing the whole item.
+    failures: Vec<ItemFailure>,
+}
+
+/// A blob downloaded into the worker tempdir.
+pub(super) struct DownloadedItem {
+    pub(super) file_path: PathBuf,
+    blob: FsBlobHashesDbRow,
+    /// How many containers deep this blob was found; 0 for blobs read from datasources.
+    container_depth: i32,
 }
 
-async fn download_item(
+pub(super) async fn download_item(
     args: ProcessPageArgs,
     blob_sha3_256: String,
     tempdir: PathBuf,
-) -> anyhow::Result<PathBuf> {
+) -> anyhow::Result<DownloadedItem> {
     let session = ScyllaDatabaseHandle::collection_session(&args.collection_id).await?;
     let blob = FsBlobHashesDbRow::find_by_blob_sha3_256(blob_sha3_256.clone())
         .execute(&session)
         .await?;
+    let stored = BlobObjectStoreDbRow::maybe_find_first_by_blob_sha3_256(blob_sha3_256.clone())
+        .execute(&session)
+        .await?;
     drop(session);
 
     let temp_file_path = tempdir.join("the_file");
+    if let Some(stored) = stored {
+        // blob was extracted from a container - read it from the object store
+        let s3 = S3DatabaseHandle::collection_session(&args.collection_id).await?;
+        let downloaded_size = s3
+            .get_blob_file(&args.collection_id, &blob_sha3_256, &temp_file_path)
+            .await?;
+        if downloaded_size as i64 != blob.size_bytes {
+            anyhow::bail!(
+                "File size mismatch for object store blob: {:?}",
+                blob.file_name
+            );
+        }
+        return Ok(DownloadedItem {
+            file_path: temp_file_path,
+            blob,
+            container_depth: stored.container_depth,
+        });
+    }
     let mut file = tokio::fs::File::create(&temp_file_path).await?;
     // let file = FsFileDbRow::find_by_datasource_id_and_parent_dir_path_and_file_name(blob.datasource_id.clone(), blob.parent_dir_path.clone(), blob.file_name.clone()).execute(&session).await?;
-    let ds = DatabaseIdentifier::new(blob.datasource_id)?;
+    let ds = DatabaseIdentifier::new(&blob.datasource_id)?;
 
     let (file_size, stream) = read_file_to_stream(
         args.collection_id.clone(),
@@ -335,33 +718,104 @@ async fn download_item(
             blob.file_name
         );
     }
-    Ok(temp_file_path)
+    Ok(DownloadedItem {
+        file_path: temp_file_path,
+        blob,
+        container_depth: 0,
+    })
 }
 
 async fn process_item(
-    blob_sha3_256: String,
-    file_path: PathBuf,
+    collection_id: &CollectionId,
+    processors: &[&'static dyn BlobProcessor],
+    item: DownloadedItem,
     temp_dir: PathBuf,
-) -> anyhow::Result<ProcessItemResultRows> {
-    let magic_mime_type = magic_get_mime_type(file_path.clone()).await?;
-    let tika_result = crate::tasks::tika::extract_metadata(file_path, temp_dir).await;
-    let tika_metadata = tika_result
-        .as_ref()
-        .ok()
-        .map(|r| r.metadata.clone())
-        .unwrap_or_default();
-    let tika_type = tika_result
-        .as_ref()
-        .ok()
-        .map(|r| r.content_type.clone())
-        .flatten();
-    let tika_metadata_success = tika_result.is_ok();
-    let tika_content_path = match tika_result {
-        Ok(r) => r.extracted_content,
-        Err(_e) => Err(_e),
-    };
-    let tika_content_success = tika_content_path.is_ok();
+    max_container_depth: i32,
+) -> Result<ProcessItemResultRows, ItemFailure> {
+    let blob_sha3_256 = item.blob.blob_sha3_256.clone();
+    let file_path = item.file_path.clone();
+    let magic_mime_type = magic_get_mime_type(file_path.clone())
+        .await
+        .map_err(|e| ItemFailure::new(STAGE_MIME_TYPE, None, &e))?;
+    let mut failures = vec![];
+    let mut processor_status_rows = vec![];
 
+    // processors come sorted by dependencies, so the outputs they need are already here
+    let mut outputs = BTreeMap::new();
+    let mut succeeded = HashSet::new();
+    let mut order = vec![];
+    for processor in processors {
+        if !processor.accepts(&magic_mime_type.magic_mime_type, item.blob.size_bytes) {
+            continue;
+        }
+        let t0 = Instant::now();
+        let missing = processor
+            .depends_on()
+            .iter()
+            .filter(|d| !succeeded.contains(*d))
+            .collect::<Vec<_>>();
+        let result = if !missing.is_empty() {
+            Err(anyhow::anyhow!(
+                "dependencies did not succeed: {:?}",
+                missing
+            ))
+        } else {
+            let input = BlobProcessorInput {
+                collection_id,
+                blob: &item.blob,
+                mime_type: &magic_mime_type.magic_mime_type,
+                file_path: &file_path,
+                temp_dir: &temp_dir,
+                container_depth: item.container_depth,
+                max_container_depth,
+                dependencies: &outputs,
+            };
+            processor.process(&input).await
+        };
+        let error = match processor_failure(processor.name(), &result) {
+            None => None,
+            Some(failure) => {
+                warn!(
+                    "Error in processor {} for {}: {}",
+                    processor.name(),
+                    blob_sha3_256,
+                    failure.error
+                );
+                let error = failure.error.clone();
+                failures.push(failure);
+                Some(error)
+            }
+        };
+        processor_status_rows.push(BlobProcessorStatusDbRow {
+            blob_sha3_256: blob_sha3_256.clone(),
+            processor: processor.name().to_string(),
+            success: error.is_none(),
+            error,
+            duration_ms: t0.elapsed().as_millis() as i64,
+            processed_at: chrono::Utc::now(),
+        });
+        // a processor with errors still has its output saved, but does not count as succeeded
+        if let Ok(output) = result {
+            if output.errors.is_empty() {
+                succeeded.insert(processor.name());
+            }
+            outputs.insert(processor.name(), output);
+            order.push(*processor);
+        }
+    }
+
+    let tika_metadata_success = outputs.contains_key(STAGE_TIKA);
+    let tika_content_success = succeeded.contains(STAGE_TIKA);
+    let reported_mime = order
+        .iter()
+        .filter_map(|p| outputs.get(p.name()))
+        .find_map(|o| o.content_type.clone())
+        .unwrap_or_default();
+    let best_mime = best_mime_type(
+        &magic_mime_type.magic_mime_type,
+        &magic_mime_type.magika_result,
+        Some(&reported_mime),
+    );
     let mime_type_row = FsBlobMimeTypeDbRow {
         blob_sha3_256: blob_sha3_256.clone(),
         magic_mime: magic_mime_type.magic_mime_type,
@@ -370,34 +824,32 @@ async fn process_item(
         magika_score: magic_mime_type.magika_result.magika_score,
         tika_metadata_success,
         tika_content_success,
-        tika_mime: tika_type.unwrap_or_default(),
+        tika_mime: reported_mime,
+        best_mime: Some(best_mime.clone()),
+        ocr_success: None,
     };
-    // write tika metadata to table
-    let mut tika_meta_rows = vec![];
-    for (key, values) in tika_metadata.iter() {
-        for (i, value) in values.iter().enumerate() {
-            let tika_metadata_row = BlobExtractedMetadataRow {
-                blob_sha3_256: blob_sha3_256.clone(),
-                meta_provider: "tika".to_string(),
-                meta_key: key.to_string(),
-                list_index: i as i32,
-                value: value.to_string(),
-            };
-            tika_meta_rows.push(tika_metadata_row);
-        }
+    for failure in failures.iter_mut() {
+        failure.mime_type = Some(best_mime.clone());
     }
 
-    anyhow::Ok(ProcessItemResultRows {
+    let outputs = order
+        .into_iter()
+        .filter_map(|p| outputs.remove(p.name()).map(|o| (p, o)))
+        .collect();
+    Ok(ProcessItemResultRows {
         blob_sha3_256,
         mime_type_row,
-        tika_meta_rows,
-        tika_content_path: tika_content_path.ok(),
+        outputs,
+        processor_status_rows,
+        failures,
     })
 }
 
-fn read_tika_content_rows(
+/// Split the content extracted into a file into rows, for the given content source.
+pub(super) fn read_content_rows(
     content_path: PathBuf,
     blob_sha3_256: String,
+    content_source: &'static str,
 ) -> impl Stream<Item = anyhow::Result<BlobExtractedContentRow>> {
     async_stream::try_stream! {
         let content_stream = read_utf8_file_paragraphs(content_path, 768 * 1024);
@@ -410,7 +862,7 @@ fn read_tika_content_rows(
             }
             let row = BlobExtractedContentRow {
                 blob_sha3_256: blob_sha3_256.clone(),
-                content_source: "tika".to_string(),
+                content_source: content_source.to_string(),
                 list_index,
                 content_length: chunk.len() as i32,
                 content: chunk,
@@ -0,0 +1,285 @@
+//! Crash-isolated extraction: runs the extractous (tika) extractors in a supervised subprocess.
+//! A malformed file that hangs the extractor, exhausts its memory or writes unbounded output
+//! only takes down its own extraction process. The error is returned for that blob,
+//! and the page processing carries on with the other items.
+//!
+//! The subprocess is the current executable started again with [EXTRACT_SUBCOMMAND];
+//! executables that run processing tasks call [run_sandbox_subcommand] first thing in `main`.
+
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::tika::{run_extract_metadata, run_extract_ocr_content, TikaSandboxResult};
+
+/// First argument that makes an executable run one extraction job instead of its usual work.
+pub const EXTRACT_SUBCOMMAND: &str = "hoover3_extract";
+
+/// Environment variable for the executable that runs the extraction jobs,
+/// e.g. the `hoover3_extract` binary. By default, it is the current executable.
+pub const EXTRACT_BIN_ENV_VAR: &str = "HOOVER3_EXTRACT_BIN";
+
+/// Environment variable for the wall-clock timeout of metadata extraction, in seconds.
+pub const EXTRACT_TIMEOUT_ENV_VAR: &str = "HOOVER3_EXTRACT_TIMEOUT_SECS";
+
+/// Environment variable for the wall-clock timeout of OCR extraction, in seconds.
+pub const EXTRACT_OCR_TIMEOUT_ENV_VAR: &str = "HOOVER3_EXTRACT_OCR_TIMEOUT_SECS";
+
+/// Environment variable for the memory limit of the extraction process, in MB. 0 disables it.
+pub const EXTRACT_MEMORY_ENV_VAR: &str = "HOOVER3_EXTRACT_MEMORY_MB";
+
+/// Environment variable for the maximum size of the extracted output, in MB.
+pub const EXTRACT_MAX_OUTPUT_ENV_VAR: &str = "HOOVER3_EXTRACT_MAX_OUTPUT_MB";
+
+const EXTRACT_TIMEOUT_DEFAULT_SECS: u64 = 300;
+const EXTRACT_OCR_TIMEOUT_DEFAULT_SECS: u64 = 3600;
+const EXTRACT_MEMORY_DEFAULT_MB: u64 = 4096;
+const EXTRACT_MAX_OUTPUT_DEFAULT_MB: u64 = 512;
+
+/// Only the end of the stderr is kept for the error message.
+const STDERR_MAX_BYTES: usize = 16 * 1024;
+
+/// Limits for one extraction subprocess.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandboxLimits {
+    /// Wall-clock timeout, after which the process is killed.
+    pub timeout: Duration,
+    /// Memory limit (data segment) of the process, in MB. 0 means no limit.
+    pub memory_mb: u64,
+    /// Maximum size of any file written by the process, and of its JSON result.
+    pub max_output_bytes: u64,
+}
+
+impl SandboxLimits {
+    /// Read the limits for the job from the environment, with defaults.
+    pub(crate) fn from_env(job: &SandboxJob) -> Self {
+        let timeout = match job {
+            SandboxJob::Metadata { .. } => {
+                env_u64(EXTRACT_TIMEOUT_ENV_VAR, EXTRACT_TIMEOUT_DEFAULT_SECS)
+            }
+            SandboxJob::Ocr { .. } => env_u64(
+                EXTRACT_OCR_TIMEOUT_ENV_VAR,
+                EXTRACT_OCR_TIMEOUT_DEFAULT_SECS,
+            ),
+        };
+        Self {
+            timeout: Duration::from_secs(timeout),
+            memory_mb: env_u64(EXTRACT_MEMORY_ENV_VAR, EXTRACT_MEMORY_DEFAULT_MB),
+            max_output_bytes: env_u64(EXTRACT_MAX_OUTPUT_ENV_VAR, EXTRACT_MAX_OUTPUT_DEFAULT_MB)
+                * 1024
+                * 1024,
+        }
+    }
+}
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(default)
+}
+
+/// An extraction job, sent to the subprocess as JSON.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) enum SandboxJob {
+    /// Extract the metadata and text content of a file.
+    Metadata {
+        /// The file to extract.
+        path: PathBuf,
+        /// Directory for the extracted content.
+        temp_dir: PathBuf,
+    },
+    /// OCR a scanned PDF or image.
+    Ocr {
+        /// The file to OCR.
+        path: PathBuf,
+        /// Directory for the OCR text.
+        temp_dir: PathBuf,
+        /// Tesseract languages, e.g. "eng+deu".
+        languages: String,
+    },
+}
+
+/// The result of an extraction job, printed by the subprocess as JSON.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) enum SandboxResponse {
+    /// Result of [SandboxJob::Metadata].
+    Metadata(TikaSandboxResult),
+    /// Path to the OCR text, for [SandboxJob::Ocr].
+    Ocr(PathBuf),
+    /// The job failed; the error with its chain of causes.
+    Error(String),
+}
+
+/// Run an extraction job in a subprocess, within the given limits.
+/// Errors if the job fails, or if the process times out, is killed or exceeds the output limit.
+pub(crate) async fn run_sandboxed(
+    job: &SandboxJob,
+    limits: SandboxLimits,
+) -> anyhow::Result<SandboxResponse> {
+    let exe = find_extract_bin()?;
+    let mut command = tokio::process::Command::new(&exe);
+    command
+        .arg(EXTRACT_SUBCOMMAND)
+        .arg(serde_json::to_string(job)?)
+        .env(EXTRACT_MEMORY_ENV_VAR, limits.memory_mb.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    {
+        let memory_bytes = limits.memory_mb * 1024 * 1024;
+        let max_output_bytes = limits.max_output_bytes;
+        // SAFETY: set_child_rlimits only calls setrlimit, which is async-signal-safe,
+        // and does not allocate in the forked child.
+        unsafe {
+            command.pre_exec(move || set_child_rlimits(memory_bytes, max_output_bytes));
+        }
+    }
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("failed to start {}", exe.display()))?;
+    let stdout = child.stdout.take().context("no stdout")?;
+    let stderr = child.stderr.take().context("no stderr")?;
+
+    let run = async {
+        let (status, stdout, stderr) = tokio::try_join!(
+            async { Ok::<_, anyhow::Error>(child.wait().await?) },
+            read_capped(stdout, limits.max_output_bytes),
+            read_tail(stderr),
+        )?;
+        anyhow::Ok((status, stdout, stderr))
+    };
+    let (status, stdout, stderr) = match tokio::time::timeout(limits.timeout, run).await {
+        Ok(result) => result?,
+        Err(_) => {
+            let _ = child.kill().await;
+            anyhow::bail!("extraction timed out after {}s", limits.timeout.as_secs());
+        }
+    };
+
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr);
+        #[cfg(unix)]
+        if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+            anyhow::bail!(
+                "extraction killed by signal {} (memory limit {} MB, output limit {} MB): {}",
+                signal,
+                limits.memory_mb,
+                limits.max_output_bytes / 1024 / 1024,
+                stderr
+            );
+        }
+        anyhow::bail!("extraction failed with {}: {}", status, stderr);
+    }
+    let response: SandboxResponse =
+        serde_json::from_slice(&stdout).context("invalid extraction output")?;
+    if let SandboxResponse::Error(e) = response {
+        anyhow::bail!(e);
+    }
+    Ok(response)
+}
+
+fn find_extract_bin() -> anyhow::Result<PathBuf> {
+    if let Ok(path) = std::env::var(EXTRACT_BIN_ENV_VAR) {
+        return Ok(PathBuf::from(path));
+    }
+    Ok(std::env::current_exe()?)
+}
+
+/// Set the resource limits of the extraction process. Runs in the forked child of a
+/// multi-threaded process, so it must not allocate: it only calls `setrlimit`.
+#[cfg(unix)]
+fn set_child_rlimits(memory_bytes: u64, max_output_bytes: u64) -> std::io::Result<()> {
+    // the resource type differs between platforms, so it is left to inference
+    let set_rlimit = |resource, limit: u64| {
+        let rlimit = libc::rlimit {
+            rlim_cur: limit as libc::rlim_t,
+            rlim_max: limit as libc::rlim_t,
+        };
+        if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    };
+    set_rlimit(libc::RLIMIT_FSIZE, max_output_bytes)?;
+    if memory_bytes > 0 {
+        set_rlimit(libc::RLIMIT_DATA, memory_bytes)?;
+    }
+    Ok(())
+}
+
+/// Read the whole stream, failing if it is longer than `max_bytes`.
+async fn read_capped(stream: impl AsyncRead + Unpin, max_bytes: u64) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    stream.take(max_bytes + 1).read_to_end(&mut buf).await?;
+    if buf.len() as u64 > max_bytes {
+        anyhow::bail!("extraction output over the limit of {} bytes", max_bytes);
+    }
+    Ok(buf)
+}
+
+/// Read the whole stream, keeping only its end.
+async fn read_tail(mut stream: impl AsyncRead + Unpin) -> anyhow::Result<Vec<u8>> {
+    let mut tail = Vec::new();
+    let mut chunk = vec![0; 8192];
+    loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        tail.extend_from_slice(&chunk[..n]);
+        if tail.len() > STDERR_MAX_BYTES {
+            tail.drain(..tail.len() - STDERR_MAX_BYTES);
+        }
+    }
+    Ok(tail)
+}
+
+/// If the process was started with [EXTRACT_SUBCOMMAND] as its first argument,
+/// run the job given as JSON in the second argument, print the [SandboxResponse]
+/// as JSON on stdout, and return the outcome; the caller should exit right after.
+/// Returns `None` otherwise.
+pub fn run_sandbox_subcommand() -> Option<anyhow::Result<()>> {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() != Some(EXTRACT_SUBCOMMAND) {
+        return None;
+    }
+    Some(sandbox_main(args.next()))
+}
+
+fn sandbox_main(job: Option<String>) -> anyhow::Result<()> {
+    let job = job.context("missing job argument")?;
+    let job: SandboxJob = serde_json::from_str(&job)?;
+    let memory_mb = env_u64(EXTRACT_MEMORY_ENV_VAR, 0);
+    if memory_mb > 0 {
+        hoover3_tracing::set_process_memory_limit(memory_mb.min(u32::MAX as u64) as u32)?;
+    }
+    let response = match job {
+        SandboxJob::Metadata { path, temp_dir } => {
+            run_extract_metadata(path, temp_dir).map(SandboxResponse::Metadata)
+        }
+        SandboxJob::Ocr {
+            path,
+            temp_dir,
+            languages,
+        } => run_extract_ocr_content(path, temp_dir, languages).map(SandboxResponse::Ocr),
+    };
+    let response = response.unwrap_or_else(|e| SandboxResponse::Error(format!("{e:#}")));
+    serde_json::to_writer(std::io::stdout().lock(), &response)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_read_capped_limits_output() -> anyhow::Result<()> {
+    assert_eq!(read_capped(&b"0123456789"[..], 10).await?.len(), 10);
+    assert!(read_capped(&b"0123456789"[..], 9).await.is_err());
+    let long = vec![b'x'; STDERR_MAX_BYTES * 3];
+    assert_eq!(read_tail(&long[..]).await?.len(), STDERR_MAX_BYTES);
+    Ok(())
+}
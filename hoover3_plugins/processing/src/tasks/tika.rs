@@ -1,7 +1,18 @@
+//! Metadata, text and OCR extraction with extractous (tika).
+//! The extraction runs in a sandboxed subprocess, see [super::sandbox].
+
 use std::{collections::BTreeMap, path::PathBuf};
 
 use anyhow::Context;
-use extractous::{Extractor, PdfOcrStrategy, PdfParserConfig, StreamReader};
+use extractous::{Extractor, PdfOcrStrategy, PdfParserConfig, StreamReader, TesseractOcrConfig};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use super::failures::STAGE_TIKA;
+use super::sandbox::{run_sandboxed, SandboxJob, SandboxLimits, SandboxResponse};
+use crate::processor::{
+    declare_blob_processor, BlobProcessor, BlobProcessorInput, BlobProcessorOutput,
+};
 
 /// Result of the tika extraction for a single file.
 pub struct TikaResult {
@@ -15,12 +26,66 @@ pub struct TikaResult {
     pub extracted_content: Result<PathBuf, anyhow::Error>,
 }
 
-/// Use extractous (tika) to extract metadata from a file.
+/// Result of the tika extraction, as sent back by the extraction subprocess.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct TikaSandboxResult {
+    metadata: BTreeMap<String, Vec<String>>,
+    content_length: Option<u64>,
+    content_type: Option<String>,
+    extracted_content: Result<PathBuf, String>,
+}
+
+/// Processor that extracts metadata and text content from all the blobs with tika.
+/// The content type reported by tika is used for choosing the best mime type.
+pub struct TikaProcessor;
+
+impl BlobProcessor for TikaProcessor {
+    fn name(&self) -> &'static str {
+        STAGE_TIKA
+    }
+
+    fn process<'a>(
+        &'a self,
+        input: &'a BlobProcessorInput<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<BlobProcessorOutput>> {
+        Box::pin(async move {
+            let result =
+                extract_metadata(input.file_path.to_path_buf(), input.temp_dir.to_path_buf())
+                    .await?;
+            let mut output = BlobProcessorOutput {
+                content_type: result.content_type,
+                metadata: result.metadata,
+                ..Default::default()
+            };
+            match result.extracted_content {
+                Ok(path) => output.content_path = Some(path),
+                Err(e) => output.errors.push(e),
+            }
+            Ok(output)
+        })
+    }
+}
+
+declare_blob_processor!(TikaProcessor);
+
+/// Use extractous (tika) to extract metadata from a file, in a sandboxed subprocess.
 pub async fn extract_metadata(path: PathBuf, temp_dir: PathBuf) -> anyhow::Result<TikaResult> {
-    tokio::task::spawn_blocking(move || run_extract_metadata(path, temp_dir)).await?
+    let job = SandboxJob::Metadata { path, temp_dir };
+    match run_sandboxed(&job, SandboxLimits::from_env(&job)).await? {
+        SandboxResponse::Metadata(r) => Ok(TikaResult {
+            metadata: r.metadata,
+            _original_content_length: r.content_length,
+            content_type: r.content_type,
+            extracted_content: r.extracted_content.map_err(anyhow::Error::msg),
+        }),
+        other => anyhow::bail!("unexpected extraction result: {:?}", other),
+    }
 }
 
-fn run_extract_metadata(path: PathBuf, temp_dir: PathBuf) -> anyhow::Result<TikaResult> {
+pub(super) fn run_extract_metadata(
+    path: PathBuf,
+    temp_dir: PathBuf,
+) -> anyhow::Result<TikaSandboxResult> {
     let path = path.to_str().context("invalid path")?;
     let extractor = Extractor::new()
         .set_pdf_config(PdfParserConfig::new().set_ocr_strategy(PdfOcrStrategy::NO_OCR));
@@ -47,14 +112,49 @@ fn run_extract_metadata(path: PathBuf, temp_dir: PathBuf) -> anyhow::Result<Tika
         })
         .collect();
 
-    Ok(TikaResult {
+    Ok(TikaSandboxResult {
         metadata,
-        extracted_content: download_content(temp_dir, content),
-        _original_content_length: content_length,
+        extracted_content: download_content(temp_dir, content).map_err(|e| format!("{e:#}")),
+        content_length,
         content_type,
     })
 }
 
+/// Use extractous (tika) with Tesseract to OCR a scanned PDF or an image, in a sandboxed subprocess.
+/// The languages are given in the Tesseract format, e.g. "eng+deu".
+/// Returns a path to the OCR text.
+pub async fn extract_ocr_content(
+    path: PathBuf,
+    temp_dir: PathBuf,
+    languages: String,
+) -> anyhow::Result<PathBuf> {
+    let job = SandboxJob::Ocr {
+        path,
+        temp_dir,
+        languages,
+    };
+    match run_sandboxed(&job, SandboxLimits::from_env(&job)).await? {
+        SandboxResponse::Ocr(path) => Ok(path),
+        other => anyhow::bail!("unexpected extraction result: {:?}", other),
+    }
+}
+
+pub(super) fn run_extract_ocr_content(
+    path: PathBuf,
+    temp_dir: PathBuf,
+    languages: String,
+) -> anyhow::Result<PathBuf> {
+    let path = path.to_str().context("invalid path")?;
+    let extractor = Extractor::new()
+        .set_ocr_config(TesseractOcrConfig::new().set_language(&languages))
+        .set_pdf_config(PdfParserConfig::new().set_ocr_strategy(PdfOcrStrategy::OCR_ONLY));
+    let (mut content, _metadata) = extractor.extract_file(&path)?;
+    let file_path = temp_dir.join("tika_output_ocr");
+    let mut file = std::fs::File::create(&file_path)?;
+    std::io::copy(&mut content, &mut file)?;
+    Ok(file_path)
+}
+
 fn truncate_utf8_string(s: String, max_length: usize) -> String {
     if s.len() <= max_length {
         return s;
@@ -0,0 +1,581 @@
+//! Unpack container blobs (archives) into new member blobs.
+//! Members are extracted into the item tempdir and hashed here;
+//! the page save task uploads them into the collection object store
+//! and links them to their container through [crate::models::BlobContainerToMember].
+
+use std::{
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use futures::future::BoxFuture;
+use hoover3_filesystem_scanner::{models::FsBlobHashesDbRow, tasks::hash_files::hash_local_file};
+use hoover3_taskdef::anyhow;
+use hoover3_tracing::tracing::{info, warn};
+use hoover3_types::identifier::CollectionId;
+
+use super::blob_gc::{has_parent_blob, unlink_member_blobs};
+use super::failures::STAGE_UNPACK_ARCHIVE;
+use crate::models::{BlobContainerMemberDbRow, BlobContainerToMember, BlobObjectStoreDbRow};
+use crate::processor::{
+    declare_blob_processor, BlobEdgeRows, BlobProcessor, BlobProcessorInput, BlobProcessorOutput,
+    ProcessorRows,
+};
+
+/// Environment variable for the maximum nesting depth of unpacked archives.
+/// Blobs found this many containers deep are still processed, but not unpacked further.
+pub const ARCHIVE_MAX_DEPTH_ENV_VAR: &str = "HOOVER3_ARCHIVE_MAX_DEPTH";
+
+/// Default maximum nesting depth of unpacked archives.
+pub const ARCHIVE_MAX_DEPTH_DEFAULT: i32 = 5;
+
+/// Hard limit for the nesting depth, regardless of configuration.
+/// Also bounds the number of processing rounds run by the collection workflow.
+pub const ARCHIVE_MAX_DEPTH_LIMIT: i32 = 32;
+
+/// Maximum total size of the members unpacked from a single archive.
+const ARCHIVE_MAX_UNPACKED_BYTES: u64 = 64 * 1024 * 1024 * 1024; // 64 GB
+
+/// Get the configured maximum nesting depth of unpacked archives.
+pub fn archive_max_depth() -> i32 {
+    std::env::var(ARCHIVE_MAX_DEPTH_ENV_VAR)
+        .ok()
+        .and_then(|v| v.trim().parse::<i32>().ok())
+        .unwrap_or(ARCHIVE_MAX_DEPTH_DEFAULT)
+        .clamp(0, ARCHIVE_MAX_DEPTH_LIMIT)
+}
+
+/// Container formats that we know how to unpack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveType {
+    /// ZIP archive
+    Zip,
+    /// TAR archive (uncompressed)
+    Tar,
+    /// 7-Zip archive
+    SevenZip,
+    /// RAR archive
+    Rar,
+    /// GZIP compressed file - has a single member
+    Gzip,
+}
+
+impl ArchiveType {
+    /// Get the archive type from the libmagic mime type.
+    /// Returns `None` for formats that are not containers.
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        // libmagic appends the encoding, e.g. "application/zip; charset=binary"
+        let mime_type = mime_type.split(';').next().unwrap_or_default().trim();
+        ARCHIVE_MIME_TYPES
+            .iter()
+            .find(|(m, _)| *m == mime_type)
+            .map(|(_, archive_type)| *archive_type)
+    }
+}
+
+/// Mime types of the container formats that we know how to unpack.
+const ARCHIVE_MIME_TYPES: &[(&str, ArchiveType)] = &[
+    ("application/zip", ArchiveType::Zip),
+    ("application/x-zip-compressed", ArchiveType::Zip),
+    ("application/x-tar", ArchiveType::Tar),
+    ("application/x-gtar", ArchiveType::Tar),
+    ("application/x-ustar", ArchiveType::Tar),
+    ("application/x-7z-compressed", ArchiveType::SevenZip),
+    ("application/x-rar", ArchiveType::Rar),
+    ("application/x-rar-compressed", ArchiveType::Rar),
+    ("application/vnd.rar", ArchiveType::Rar),
+    ("application/gzip", ArchiveType::Gzip),
+    ("application/x-gzip", ArchiveType::Gzip),
+];
+
+lazy_static::lazy_static! {
+    /// The mime types handled by [UnpackArchiveProcessor], from [ARCHIVE_MIME_TYPES].
+    static ref ARCHIVE_PROCESSOR_MIME_TYPES: Vec<&'static str> =
+        ARCHIVE_MIME_TYPES.iter().map(|(m, _)| *m).collect();
+}
+
+/// A member blob extracted from a container blob, hashed and waiting to be saved.
+pub struct UnpackedMember {
+    /// Local path of the extracted member, inside the item tempdir.
+    pub local_path: PathBuf,
+    /// Hashes row for the member, pointing to its path inside the container.
+    pub hashes_row: FsBlobHashesDbRow,
+    /// Row linking the member to the container.
+    pub member_row: BlobContainerMemberDbRow,
+    /// Row marking the member as stored in the object store.
+    pub object_store_row: BlobObjectStoreDbRow,
+}
+
+/// Processor that unpacks archives into member blobs,
+/// linked to the archive through [BlobContainerToMember].
+pub struct UnpackArchiveProcessor;
+
+impl BlobProcessor for UnpackArchiveProcessor {
+    fn name(&self) -> &'static str {
+        STAGE_UNPACK_ARCHIVE
+    }
+
+    fn mime_types(&self) -> &'static [&'static str] {
+        &ARCHIVE_PROCESSOR_MIME_TYPES
+    }
+
+    fn process<'a>(
+        &'a self,
+        input: &'a BlobProcessorInput<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<BlobProcessorOutput>> {
+        Box::pin(async move {
+            let Some(archive_type) = ArchiveType::from_mime_type(input.mime_type) else {
+                return Ok(BlobProcessorOutput::default());
+            };
+            if input.container_depth >= input.max_container_depth {
+                info!(
+                    "Not unpacking archive {}: depth {} over limit {}",
+                    input.blob.blob_sha3_256, input.container_depth, input.max_container_depth
+                );
+                return Ok(BlobProcessorOutput::default());
+            }
+            let child_blobs = unpack_archive(
+                archive_type,
+                input.blob,
+                input.mime_type,
+                input.container_depth,
+                input.file_path.to_path_buf(),
+                input.temp_dir.to_path_buf(),
+            )
+            .await?;
+            Ok(BlobProcessorOutput {
+                child_blobs,
+                ..Default::default()
+            })
+        })
+    }
+
+    fn child_blob_edges(
+        &self,
+        collection_id: &CollectionId,
+        edges: Vec<(String, String)>,
+    ) -> Option<Box<dyn ProcessorRows>> {
+        Some(Box::new(BlobEdgeRows::<BlobContainerToMember>::new(
+            collection_id,
+            edges,
+        )))
+    }
+
+    fn has_parent_blob<'a>(
+        &'a self,
+        collection_id: &'a CollectionId,
+        blob_sha3_256: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<bool>> {
+        Box::pin(has_parent_blob::<BlobContainerToMember>(
+            collection_id,
+            blob_sha3_256,
+        ))
+    }
+
+    fn unlink_child_blobs<'a>(
+        &'a self,
+        collection_id: &'a CollectionId,
+        blob_sha3_256: &'a str,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<String>>> {
+        Box::pin(unlink_member_blobs::<BlobContainerToMember>(
+            collection_id,
+            blob_sha3_256,
+        ))
+    }
+}
+
+declare_blob_processor!(UnpackArchiveProcessor);
+
+/// Extract all members of an archive into the tempdir and hash them.
+/// `container_depth` is the depth of the container itself; members get `container_depth + 1`.
+pub(crate) async fn unpack_archive(
+    archive_type: ArchiveType,
+    container: &FsBlobHashesDbRow,
+    container_mime: &str,
+    container_depth: i32,
+    file_path: PathBuf,
+    temp_dir: PathBuf,
+) -> anyhow::Result<Vec<UnpackedMember>> {
+    let output_dir = temp_dir.join("unpacked");
+    tokio::fs::create_dir_all(&output_dir).await?;
+
+    let _output_dir = output_dir.clone();
+    let _file_name = container.file_name.clone();
+    tokio::task::spawn_blocking(move || {
+        run_unpack_archive(archive_type, file_path, _output_dir, _file_name)
+    })
+    .await??;
+
+    let _output_dir = output_dir.clone();
+    let member_paths =
+        tokio::task::spawn_blocking(move || list_unpacked_files(&_output_dir)).await??;
+
+    hash_member_files(container, container_mime, container_depth, member_paths).await
+}
+
+/// Hash member files extracted from a container and build their database rows.
+/// Takes pairs of (member path inside the container, local path).
+pub async fn hash_member_files(
+    container: &FsBlobHashesDbRow,
+    container_mime: &str,
+    container_depth: i32,
+    member_paths: Vec<(String, PathBuf)>,
+) -> anyhow::Result<Vec<UnpackedMember>> {
+    let container_path = PathBuf::from(&container.parent_dir_path).join(&container.file_name);
+    let container_mime = container_mime
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let mut members = vec![];
+    for (member_path, local_path) in member_paths {
+        let (hashes, size_bytes) = hash_local_file(&local_path).await?;
+        let full_path = container_path.join(&member_path);
+        let hashes_row = FsBlobHashesDbRow {
+            blob_sha3_256: hashes.sha3_256.clone(),
+            blob_sha256: hashes.sha256,
+            blob_md5: hashes.md5,
+            blob_sha1: hashes.sha1,
+            size_bytes: size_bytes as i64,
+            datasource_id: container.datasource_id.clone(),
+            parent_dir_path: full_path
+                .parent()
+                .unwrap_or(container_path.as_path())
+                .to_string_lossy()
+                .to_string(),
+            file_name: full_path
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        };
+        members.push(UnpackedMember {
+            local_path,
+            member_row: BlobContainerMemberDbRow {
+                container_blob_sha3_256: container.blob_sha3_256.clone(),
+                member_path,
+                member_blob_sha3_256: hashes.sha3_256.clone(),
+                size_bytes: size_bytes as i64,
+                container_mime: container_mime.clone(),
+            },
+            object_store_row: BlobObjectStoreDbRow {
+                blob_sha3_256: hashes.sha3_256,
+                container_depth: container_depth + 1,
+                size_bytes: size_bytes as i64,
+            },
+            hashes_row,
+        });
+    }
+    Ok(members)
+}
+
+fn run_unpack_archive(
+    archive_type: ArchiveType,
+    archive_path: PathBuf,
+    output_dir: PathBuf,
+    container_file_name: String,
+) -> anyhow::Result<()> {
+    match archive_type {
+        ArchiveType::Zip => unpack_zip(&archive_path, &output_dir),
+        ArchiveType::Tar => unpack_tar(&archive_path, &output_dir),
+        ArchiveType::Gzip => unpack_gzip(&archive_path, &output_dir, &container_file_name),
+        ArchiveType::SevenZip => unpack_7z(&archive_path, &output_dir),
+        ArchiveType::Rar => unpack_rar(&archive_path, &output_dir),
+    }
+}
+
+fn unpack_7z(archive_path: &Path, output_dir: &Path) -> anyhow::Result<()> {
+    let mut archive = sevenz_rust::SevenZReader::open(archive_path, sevenz_rust::Password::empty())
+        .map_err(|e| anyhow::anyhow!("7z open error: {:?}", e))?;
+    let mut total_size = 0;
+    // errors of our own can't go through the 7z error type; keep them and stop reading
+    let mut copy_error = None;
+    archive
+        .for_each_entries(|entry, reader| {
+            if entry.is_directory() {
+                return Ok(true);
+            }
+            let Some(member_path) = sanitize_member_path(Path::new(entry.name())) else {
+                return Ok(true);
+            };
+            match copy_member(reader, &output_dir.join(member_path), total_size) {
+                Ok(written) => {
+                    total_size += written;
+                    Ok(true)
+                }
+                Err(e) => {
+                    copy_error = Some(e);
+                    Ok(false)
+                }
+            }
+        })
+        .map_err(|e| anyhow::anyhow!("7z decompress error: {:?}", e))?;
+    match copy_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn unpack_rar(archive_path: &Path, output_dir: &Path) -> anyhow::Result<()> {
+    let mut archive = unrar::Archive::new(archive_path).open_for_processing()?;
+    let mut total_size = 0;
+    while let Some(header) = archive.read_header()? {
+        let member_path = match header.entry().is_file() {
+            true => sanitize_member_path(&header.entry().filename),
+            false => None,
+        };
+        let Some(member_path) = member_path else {
+            archive = header.skip()?;
+            continue;
+        };
+        // unrar writes the member file itself, so the limit is checked with the declared size
+        // before extracting, then with the size actually written
+        let declared_size = header.entry().unpacked_size;
+        total_size = add_unpacked_size(total_size, declared_size)?;
+        let dest = output_dir.join(member_path);
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        archive = header.extract_to(&dest)?;
+        let written = std::fs::metadata(&dest)?.len();
+        total_size = add_unpacked_size(total_size, written.saturating_sub(declared_size))?;
+    }
+    Ok(())
+}
+
+fn unpack_zip(archive_path: &Path, output_dir: &Path) -> anyhow::Result<()> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(archive_path)?)?;
+    let mut total_size = 0;
+    for i in 0..archive.len() {
+        let mut entry = match archive.by_index(i) {
+            Ok(entry) => entry,
+            Err(e) => {
+                // encrypted or unsupported entries - skip them, keep the rest
+                warn!(
+                    "unpack_zip: skipping entry {} of {:?}: {}",
+                    i, archive_path, e
+                );
+                continue;
+            }
+        };
+        if !entry.is_file() {
+            continue;
+        }
+        let Some(member_path) = entry.enclosed_name() else {
+            continue;
+        };
+        total_size += copy_member(&mut entry, &output_dir.join(member_path), total_size)?;
+    }
+    Ok(())
+}
+
+fn unpack_tar(archive_path: &Path, output_dir: &Path) -> anyhow::Result<()> {
+    let mut archive = tar::Archive::new(std::fs::File::open(archive_path)?);
+    let mut total_size = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(member_path) = sanitize_member_path(&entry.path()?) else {
+            continue;
+        };
+        total_size += copy_member(&mut entry, &output_dir.join(member_path), total_size)?;
+    }
+    Ok(())
+}
+
+fn unpack_gzip(
+    archive_path: &Path,
+    output_dir: &Path,
+    container_file_name: &str,
+) -> anyhow::Result<()> {
+    let member_name = match container_file_name.rsplit_once('.') {
+        Some((stem, "tgz")) => format!("{}.tar", stem),
+        Some((stem, "gz")) | Some((stem, "gzip")) if !stem.is_empty() => stem.to_string(),
+        _ => format!("{}.uncompressed", container_file_name),
+    };
+    let mut decoder = flate2::read::MultiGzDecoder::new(std::fs::File::open(archive_path)?);
+    copy_member(&mut decoder, &output_dir.join(member_name), 0)?;
+    Ok(())
+}
+
+/// Copy one member to disk, failing if the archive grows over the size limit.
+/// Returns the number of bytes written.
+fn copy_member(reader: &mut dyn Read, dest: &Path, total_size: u64) -> anyhow::Result<u64> {
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::File::create(dest)?;
+    let remaining = ARCHIVE_MAX_UNPACKED_BYTES.saturating_sub(total_size);
+    let written = std::io::copy(&mut reader.take(remaining + 1), &mut file)?;
+    if written > remaining {
+        anyhow::bail!(
+            "archive unpacked size is over the limit of {} bytes",
+            ARCHIVE_MAX_UNPACKED_BYTES
+        );
+    }
+    file.flush()?;
+    Ok(written)
+}
+
+/// Add a member size to the unpacked size of an archive, failing if it goes over the limit.
+fn add_unpacked_size(total_size: u64, size: u64) -> anyhow::Result<u64> {
+    match total_size.checked_add(size) {
+        Some(total_size) if total_size <= ARCHIVE_MAX_UNPACKED_BYTES => Ok(total_size),
+        _ => anyhow::bail!(
+            "archive unpacked size is over the limit of {} bytes",
+            ARCHIVE_MAX_UNPACKED_BYTES
+        ),
+    }
+}
+
+/// Keep only the normal components of a member path, so it can't escape the output dir.
+pub(crate) fn sanitize_member_path(path: &Path) -> Option<PathBuf> {
+    let path = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(c) => Some(c),
+            _ => None,
+        })
+        .collect::<PathBuf>();
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+/// List all regular files under the output dir.
+/// Returns the member path (relative to the output dir) and the local path.
+pub(crate) fn list_unpacked_files(output_dir: &Path) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let mut files = vec![];
+    let mut dirs = vec![output_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                let path = entry.path();
+                let member_path = path_to_string(path.strip_prefix(output_dir)?);
+                files.push((member_path, path));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn path_to_string(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_type_from_mime_type() {
+        assert_eq!(
+            ArchiveType::from_mime_type("application/zip; charset=binary"),
+            Some(ArchiveType::Zip)
+        );
+        assert_eq!(
+            ArchiveType::from_mime_type("application/x-7z-compressed"),
+            Some(ArchiveType::SevenZip)
+        );
+        assert_eq!(
+            ArchiveType::from_mime_type("application/gzip; charset=binary"),
+            Some(ArchiveType::Gzip)
+        );
+        assert_eq!(
+            ArchiveType::from_mime_type("application/pdf; charset=binary"),
+            None
+        );
+        for mime_type in UnpackArchiveProcessor.mime_types() {
+            assert!(ArchiveType::from_mime_type(mime_type).is_some());
+        }
+    }
+
+    #[test]
+    fn test_sanitize_member_path() {
+        assert_eq!(
+            sanitize_member_path(Path::new("../../etc/passwd")),
+            Some(PathBuf::from("etc/passwd"))
+        );
+        assert_eq!(
+            sanitize_member_path(Path::new("/a/./b.txt")),
+            Some(PathBuf::from("a/b.txt"))
+        );
+        assert_eq!(sanitize_member_path(Path::new("..")), None);
+    }
+
+    #[test]
+    fn test_add_unpacked_size() -> anyhow::Result<()> {
+        assert_eq!(add_unpacked_size(1, 2)?, 3);
+        assert_eq!(
+            add_unpacked_size(0, ARCHIVE_MAX_UNPACKED_BYTES)?,
+            ARCHIVE_MAX_UNPACKED_BYTES
+        );
+        assert!(add_unpacked_size(ARCHIVE_MAX_UNPACKED_BYTES, 1).is_err());
+        assert!(add_unpacked_size(1, u64::MAX).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_tar_gz() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let tar_path = temp_dir.path().join("test.tar");
+        {
+            let mut builder = tar::Builder::new(std::fs::File::create(&tar_path)?);
+            let data = b"hello from inside the archive";
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, "some/dir/hello.txt", &data[..])?;
+            builder.finish()?;
+        }
+        let gz_path = temp_dir.path().join("test.tar.gz");
+        {
+            let mut encoder = flate2::write::GzEncoder::new(
+                std::fs::File::create(&gz_path)?,
+                flate2::Compression::default(),
+            );
+            std::io::copy(&mut std::fs::File::open(&tar_path)?, &mut encoder)?;
+            encoder.finish()?;
+        }
+
+        let gz_out = temp_dir.path().join("gz_out");
+        run_unpack_archive(
+            ArchiveType::Gzip,
+            gz_path,
+            gz_out.clone(),
+            "test.tar.gz".to_string(),
+        )?;
+        let files = list_unpacked_files(&gz_out)?;
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, "test.tar");
+
+        let tar_out = temp_dir.path().join("tar_out");
+        run_unpack_archive(
+            ArchiveType::Tar,
+            files[0].1.clone(),
+            tar_out.clone(),
+            "test.tar".to_string(),
+        )?;
+        let files = list_unpacked_files(&tar_out)?;
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, "some/dir/hello.txt");
+        assert_eq!(
+            std::fs::read_to_string(&files[0].1)?,
+            "hello from inside the archive"
+        );
+        Ok(())
+    }
+}
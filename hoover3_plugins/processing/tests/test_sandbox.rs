@@ -0,0 +1,20 @@
+//! Test the extraction sandbox with the standalone `hoover3_extract` binary,
+//! since the test executable cannot run the extraction subcommand itself.
+
+use hoover3_processing::tasks::sandbox::{EXTRACT_BIN_ENV_VAR, EXTRACT_TIMEOUT_ENV_VAR};
+use hoover3_processing::tasks::tika::extract_metadata;
+
+#[tokio::test]
+async fn test_sandbox_reports_failures() -> anyhow::Result<()> {
+    std::env::set_var(EXTRACT_BIN_ENV_VAR, env!("CARGO_BIN_EXE_hoover3_extract"));
+    let temp_dir = std::env::temp_dir();
+    let missing_file = temp_dir.join("hoover3_extract_missing_file");
+    assert!(extract_metadata(missing_file.clone(), temp_dir.clone())
+        .await
+        .is_err());
+
+    std::env::set_var(EXTRACT_TIMEOUT_ENV_VAR, "0");
+    let err = extract_metadata(missing_file, temp_dir).await.unwrap_err();
+    assert!(format!("{err:#}").contains("timed out"));
+    Ok(())
+}
@@ -1,13 +1,23 @@
 //! Server API functions
 
+use hoover3_processing::api::{
+    clear_datasource_generation, get_datasource_generation, get_workflow_generation,
+    next_processing_generation, reset_datasource_processing, set_datasource_generation,
+    set_workflow_generation,
+};
+use hoover3_processing::tasks::failures::retry_failed_blobs_workflow;
 use hoover3_taskdef::anyhow;
+use hoover3_taskdef::with_workflow_generation;
+use hoover3_taskdef::TemporalioDescriptorName;
 use hoover3_taskdef::TemporalioWorkflowDescriptor;
 use hoover3_types::identifier::CollectionId;
 use hoover3_types::identifier::DatabaseIdentifier;
 use hoover3_types::processing::ProcessDatasourceTaskResult;
-use hoover3_types::tasks::UiWorkflowStatus;
+use hoover3_types::tasks::{UiWorkflowStatus, UiWorkflowStatusCode};
 
 use crate::tasks::process_datasource_workflow;
+use crate::tasks::remove_datasource_workflow;
+use crate::tasks::reprocess_datasource_workflow;
 
 /// API method to get the current memory usage and limit for the server process, in MB
 pub async fn get_server_memory_usage(_: ()) -> anyhow::Result<(u32, u32)> {
@@ -17,24 +27,230 @@ pub async fn get_server_memory_usage(_: ()) -> anyhow::Result<(u32, u32)> {
     ))
 }
 
+/// Get the generation a datasource workflow was last started in, for querying or controlling it.
+async fn started_generation<
+    W: TemporalioWorkflowDescriptor<Arg = (CollectionId, DatabaseIdentifier)>,
+>(
+    (c_id, ds_id): &(CollectionId, DatabaseIdentifier),
+) -> anyhow::Result<u32> {
+    Ok(get_datasource_generation(c_id, ds_id, W::name())
+        .await?
+        .unwrap_or(0))
+}
+
+/// Start a datasource workflow in the given generation, and save the generation
+/// for querying and controlling the workflow later.
+async fn start_in_generation<
+    W: TemporalioWorkflowDescriptor<Arg = (CollectionId, DatabaseIdentifier)> + 'static,
+>(
+    arg: &(CollectionId, DatabaseIdentifier),
+    generation: u32,
+) -> anyhow::Result<String> {
+    set_datasource_generation(&arg.0, &arg.1, W::name(), generation).await?;
+    with_workflow_generation(generation, W::client_start(arg)).await
+}
+
+/// Start a datasource workflow in a new generation, so it runs again with all its child
+/// workflows, instead of returning the results of the previous run.
+/// If the workflow is still running, returns its workflow id instead.
+async fn start_again<
+    W: TemporalioWorkflowDescriptor<Arg = (CollectionId, DatabaseIdentifier)> + 'static,
+>(
+    arg: &(CollectionId, DatabaseIdentifier),
+) -> anyhow::Result<String> {
+    if let Some(generation) = get_datasource_generation(&arg.0, &arg.1, W::name()).await? {
+        let status = with_workflow_generation(generation, W::client_get_status(arg)).await?;
+        if status.task_status == UiWorkflowStatusCode::Running {
+            return Ok(W::workflow_id_for_generation(arg, generation));
+        }
+    }
+    let generation = next_processing_generation(arg.0.clone()).await?;
+    start_in_generation::<W>(arg, generation).await
+}
+
+/// Start a datasource workflow in the generation it was started in before,
+/// or in a new generation if it was never started.
+async fn start_once<
+    W: TemporalioWorkflowDescriptor<Arg = (CollectionId, DatabaseIdentifier)> + 'static,
+>(
+    arg: &(CollectionId, DatabaseIdentifier),
+) -> anyhow::Result<String> {
+    let generation = match get_datasource_generation(&arg.0, &arg.1, W::name()).await? {
+        Some(generation) => generation,
+        None => next_processing_generation(arg.0.clone()).await?,
+    };
+    start_in_generation::<W>(arg, generation).await
+}
+
 /// Initiates a filesystem scan operation
 pub async fn start_processing(
     (c_id, ds_id): (CollectionId, DatabaseIdentifier),
 ) -> Result<String, anyhow::Error> {
-    let w = process_datasource_workflow::client_start(&(c_id, ds_id)).await?;
-    Ok(w)
+    start_once::<process_datasource_workflow>(&(c_id, ds_id)).await
+}
+
+/// Processes the datasource again from scratch in a new generation, instead of returning
+/// the results of the previous run. The processing plan pages of the datasource are marked
+/// as not started, and the failure records of its blobs are removed.
+pub async fn restart_processing(
+    (c_id, ds_id): (CollectionId, DatabaseIdentifier),
+) -> Result<String, anyhow::Error> {
+    reset_datasource_processing(c_id.clone(), ds_id.clone()).await?;
+    let generation = next_processing_generation(c_id.clone()).await?;
+    start_in_generation::<process_datasource_workflow>(&(c_id, ds_id), generation).await
 }
 
 /// Waits for and returns filesystem scan results
 pub async fn wait_for_processing_results(
     (c_id, ds_id): (CollectionId, DatabaseIdentifier),
 ) -> Result<ProcessDatasourceTaskResult, anyhow::Error> {
-    process_datasource_workflow::client_wait_for_completion(&(c_id, ds_id)).await
+    let arg = (c_id, ds_id);
+    let generation = started_generation::<process_datasource_workflow>(&arg).await?;
+    with_workflow_generation(
+        generation,
+        process_datasource_workflow::client_wait_for_completion(&arg),
+    )
+    .await
 }
 
 /// Retrieves current filesystem scan status
 pub async fn get_processing_status(
     (c_id, ds_id): (CollectionId, DatabaseIdentifier),
 ) -> Result<UiWorkflowStatus, anyhow::Error> {
-    process_datasource_workflow::client_get_status(&(c_id, ds_id)).await
+    let arg = (c_id, ds_id);
+    let generation = started_generation::<process_datasource_workflow>(&arg).await?;
+    with_workflow_generation(
+        generation,
+        process_datasource_workflow::client_get_status(&arg),
+    )
+    .await
+}
+
+/// Initiates an incremental rescan of a datasource that was processed before.
+pub async fn start_reprocessing(
+    (c_id, ds_id): (CollectionId, DatabaseIdentifier),
+) -> Result<String, anyhow::Error> {
+    start_again::<reprocess_datasource_workflow>(&(c_id, ds_id)).await
+}
+
+/// Retrieves current incremental rescan status
+pub async fn get_reprocessing_status(
+    (c_id, ds_id): (CollectionId, DatabaseIdentifier),
+) -> Result<UiWorkflowStatus, anyhow::Error> {
+    let arg = (c_id, ds_id);
+    let generation = started_generation::<reprocess_datasource_workflow>(&arg).await?;
+    with_workflow_generation(
+        generation,
+        reprocess_datasource_workflow::client_get_status(&arg),
+    )
+    .await
+}
+
+/// Initiates the removal of a datasource, with everything found in it.
+/// The removal runs in a new generation, and the generations of the processing workflows
+/// are forgotten, so a datasource created again with the same id starts over.
+pub async fn start_datasource_removal(
+    (c_id, ds_id): (CollectionId, DatabaseIdentifier),
+) -> Result<String, anyhow::Error> {
+    let arg = (c_id, ds_id);
+    let w = start_again::<remove_datasource_workflow>(&arg).await?;
+    for workflow_name in [
+        process_datasource_workflow::name(),
+        reprocess_datasource_workflow::name(),
+    ] {
+        clear_datasource_generation(&arg.0, &arg.1, workflow_name).await?;
+    }
+    Ok(w)
+}
+
+/// Retrieves current datasource removal status
+pub async fn get_datasource_removal_status(
+    (c_id, ds_id): (CollectionId, DatabaseIdentifier),
+) -> Result<UiWorkflowStatus, anyhow::Error> {
+    let arg = (c_id, ds_id);
+    let generation = started_generation::<remove_datasource_workflow>(&arg).await?;
+    with_workflow_generation(
+        generation,
+        remove_datasource_workflow::client_get_status(&arg),
+    )
+    .await
+}
+
+/// Cancels a running datasource processing, together with its scan and processing child workflows.
+/// Returns the number of workflows cancelled.
+pub async fn cancel_processing(
+    (c_id, ds_id): (CollectionId, DatabaseIdentifier),
+) -> Result<u32, anyhow::Error> {
+    let arg = (c_id, ds_id);
+    let generation = started_generation::<process_datasource_workflow>(&arg).await?;
+    with_workflow_generation(generation, process_datasource_workflow::client_cancel(&arg)).await
+}
+
+/// Terminates a running datasource processing, together with its child workflows,
+/// without waiting for them to stop. Use when cancelling does not stop it.
+/// Returns the number of workflows terminated.
+pub async fn terminate_processing(
+    (c_id, ds_id): (CollectionId, DatabaseIdentifier),
+) -> Result<u32, anyhow::Error> {
+    let arg = (c_id, ds_id);
+    let generation = started_generation::<process_datasource_workflow>(&arg).await?;
+    with_workflow_generation(
+        generation,
+        process_datasource_workflow::client_terminate(&arg),
+    )
+    .await
+}
+
+/// Pauses a running datasource processing - no new activities or child workflows are started
+/// until it is resumed. Returns the number of workflows paused.
+pub async fn pause_processing(
+    (c_id, ds_id): (CollectionId, DatabaseIdentifier),
+) -> Result<u32, anyhow::Error> {
+    let arg = (c_id, ds_id);
+    let generation = started_generation::<process_datasource_workflow>(&arg).await?;
+    with_workflow_generation(generation, process_datasource_workflow::client_pause(&arg)).await
+}
+
+/// Resumes a paused datasource processing. Returns the number of workflows resumed.
+pub async fn resume_processing(
+    (c_id, ds_id): (CollectionId, DatabaseIdentifier),
+) -> Result<u32, anyhow::Error> {
+    let arg = (c_id, ds_id);
+    let generation = started_generation::<process_datasource_workflow>(&arg).await?;
+    with_workflow_generation(generation, process_datasource_workflow::client_resume(&arg)).await
+}
+
+/// Starts processing the blobs that failed processing again, in a new generation.
+/// If a retry is still running, returns its workflow id instead.
+pub async fn start_retry_failed(c_id: CollectionId) -> Result<String, anyhow::Error> {
+    let workflow_name = retry_failed_blobs_workflow::name();
+    if let Some(generation) = get_workflow_generation(&c_id, workflow_name).await? {
+        let status = with_workflow_generation(
+            generation,
+            retry_failed_blobs_workflow::client_get_status(&c_id),
+        )
+        .await?;
+        if status.task_status == UiWorkflowStatusCode::Running {
+            return Ok(retry_failed_blobs_workflow::workflow_id_for_generation(
+                &c_id, generation,
+            ));
+        }
+    }
+    let generation = next_processing_generation(c_id.clone()).await?;
+    set_workflow_generation(&c_id, workflow_name, generation).await?;
+    with_workflow_generation(generation, retry_failed_blobs_workflow::client_start(&c_id)).await
+}
+
+/// Retrieves the status of the last retry of the failed blobs.
+pub async fn get_retry_failed_status(
+    c_id: CollectionId,
+) -> Result<UiWorkflowStatus, anyhow::Error> {
+    let generation = get_workflow_generation(&c_id, retry_failed_blobs_workflow::name())
+        .await?
+        .unwrap_or(0);
+    with_workflow_generation(
+        generation,
+        retry_failed_blobs_workflow::client_get_status(&c_id),
+    )
+    .await
 }
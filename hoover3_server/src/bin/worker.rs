@@ -2,14 +2,26 @@
 
 use std::path::PathBuf;
 
+use hoover3_database_operations::indexer::{spawn_search_outbox_sweeper, SearchIndexerQueue};
 use hoover3_taskdef::{
-    task_inventory::TaskQueue, WORKER_TEMPDIR_ENV_VAR_BIG, WORKER_TEMPDIR_ENV_VAR_SMALL,
+    task_inventory::{TaskQueue, TaskQueueConst},
+    TaskBackend, WORKER_TEMPDIR_ENV_VAR_BIG, WORKER_TEMPDIR_ENV_VAR_SMALL,
 };
 use hoover3_tracing::tracing::{error, info, warn};
 
 fn main() -> anyhow::Result<()> {
+    // extraction jobs run in this same executable, started again with a subcommand
+    if let Some(result) = hoover3_server::run_sandbox_subcommand() {
+        return result;
+    }
     hoover3_tracing::init_tracing();
     hoover3_server::init_server_plugins()?;
+    if let TaskBackend::Local(_) = hoover3_taskdef::task_backend() {
+        warn!(
+            "Local task backend selected: tasks run inside the server process, no workers needed."
+        );
+        return Ok(());
+    }
     let arg = std::env::args().nth(1).unwrap_or_default();
     if arg.is_empty() {
         info!("\n\nRunning all workers as subprocesses\n\n");
@@ -122,6 +134,11 @@ fn run_worker_directly(arg: String) -> anyhow::Result<()> {
         return Err(anyhow::anyhow!("Multiple matching queues found"));
     }
     let queue = matching_queues.first().cloned().unwrap();
+    // the search indexer worker also starts the indexers that lost their notification,
+    // now and then periodically
+    if queue.queue_name() == <SearchIndexerQueue as TaskQueueConst>::QUEUE_NAME {
+        spawn_search_outbox_sweeper()?;
+    }
     hoover3_taskdef::tasks::run_worker(queue)?;
     Ok(())
 }
@@ -7,6 +7,10 @@
 /// Re-export the function to migrate all databases;
 pub use hoover3_database::migrate::migrate_all;
 
+/// Re-export the entry point of the extraction subprocesses, started from the worker
+/// and server executables;
+pub use hoover3_processing::tasks::sandbox::run_sandbox_subcommand;
+
 mod init;
 pub use init::init_server_plugins;
 
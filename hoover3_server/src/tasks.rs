@@ -1,11 +1,12 @@
 //!
 use hoover3_macro::workflow;
 use hoover3_taskdef::{
-    declare_task_queue, TemporalioWorkflowDescriptor, WfContext, WfExitValue, WorkflowResult,
+    declare_task_queue, TemporalioActivityDescriptor, TemporalioWorkflowDescriptor, WfContext,
+    WfExitValue, WorkflowResult,
 };
 use hoover3_types::{
     identifier::{CollectionId, DatabaseIdentifier},
-    processing::ProcessDatasourceTaskResult,
+    processing::{ProcessDatasourceTaskResult, RemoveDatasourceTaskResult},
 };
 declare_task_queue!(ServerTaskQueue, "server_task_queue", 4, 4, 256);
 
@@ -28,3 +29,49 @@ async fn process_datasource(
         process,
     }))
 }
+
+/// Rescan a data source that was processed before, and process only the new blobs.
+#[workflow(ServerTaskQueue)]
+async fn reprocess_datasource(
+    wf_ctx: WfContext,
+    (collection_id, datasource_id): (CollectionId, DatabaseIdentifier),
+) -> WorkflowResult<ProcessDatasourceTaskResult> {
+    let scan = hoover3_filesystem_scanner::tasks::scan_filesystem::fs_rescan_datasource_workflow::run_as_child(&wf_ctx, (collection_id.clone(), datasource_id.clone())).await?;
+    let process = hoover3_processing::tasks::run_collection_processing_workflow::run_as_child(
+        &wf_ctx,
+        collection_id.clone(),
+    )
+    .await?;
+    Ok(WfExitValue::Normal(ProcessDatasourceTaskResult {
+        collection_id,
+        datasource_id,
+        scan,
+        process,
+    }))
+}
+
+/// Remove a data source and everything found in it, then remove the blobs
+/// no other data source references anymore. Uses the "scan" and "process" plugins.
+#[workflow(ServerTaskQueue)]
+async fn remove_datasource(
+    wf_ctx: WfContext,
+    (collection_id, datasource_id): (CollectionId, DatabaseIdentifier),
+) -> WorkflowResult<RemoveDatasourceTaskResult> {
+    let remove = hoover3_filesystem_scanner::tasks::remove_datasource::fs_remove_datasource_workflow::run_as_child(&wf_ctx, (collection_id.clone(), datasource_id.clone())).await?;
+    let blob_gc = hoover3_processing::tasks::blob_gc::gc_unreferenced_blobs_workflow::run_as_child(
+        &wf_ctx,
+        collection_id.clone(),
+    )
+    .await?;
+    hoover3_filesystem_scanner::tasks::remove_datasource::fs_purge_datasource_activity::run(
+        &wf_ctx,
+        (collection_id.clone(), datasource_id.clone()),
+    )
+    .await?;
+    Ok(WfExitValue::Normal(RemoveDatasourceTaskResult {
+        collection_id,
+        datasource_id,
+        remove,
+        blob_gc,
+    }))
+}